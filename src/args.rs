@@ -1,18 +1,1048 @@
-use clap::{Parser, Subcommand};
+use std::{net::SocketAddr, path::PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+
+use crate::{
+    dates::{parse_due_arg, parse_span, Span},
+    todo::{Label, Priority, TodoRef},
+};
+
+const AFTER_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Nothing matched a search, or nothing changed with --fail-empty
+  2  Invalid usage or input, e.g. a bad index or an empty title
+  3  The database is busy or locked by another process
+  4  IO or config path failure
+  5  Unexpected failure, e.g. a damaged database or a failed hook with --strict-hooks
+
+Environment:
+  TODO_DB      Path of the database file
+  TODO_CONFIG  Path of the config file
+  NO_COLOR     Disable colors when --color is auto";
+
+// Rendered into the man pages, each page shows the examples of its own subcommand.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    (
+        "todo-cli add --separate \"buy milk\" \"call mom\"",
+        "Add two todos to the default list, without --separate it would be one.",
+    ),
+    (
+        "todo-cli add \"fix the build\" -t work -p high -d 2024-05-01",
+        "Add a tagged, high priority todo with a due date.",
+    ),
+    (
+        "todo-cli",
+        "Print the todos of the default list with their indexes.",
+    ),
+    (
+        "todo-cli done 0 2",
+        "Mark the todos at indexes 0 and 2 as done.",
+    ),
+    (
+        "todo-cli undone 2",
+        "Mark the todo at index 2 as pending again.",
+    ),
+    ("todo-cli remove 1", "Remove the todo at index 1."),
+    ("todo-cli clear", "Remove every done todo from the list."),
+    ("todo-cli next", "Print the pending todo to work on next."),
+    (
+        "todo-cli search milk --all-lists",
+        "Find todos mentioning milk in every list.",
+    ),
+    (
+        "todo-cli -l work print --json",
+        "Print the work list as JSON.",
+    ),
+    (
+        "todo-cli stats --by-tag",
+        "Count done and pending todos per tag.",
+    ),
+    (
+        "todo-cli completions bash > /etc/bash_completion.d/todo-cli",
+        "Install the bash completion script.",
+    ),
+    (
+        "todo-cli man --output /usr/share/man/man1",
+        "Write one man page per subcommand.",
+    ),
+];
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = AFTER_HELP)]
 pub struct Args {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// The list to work with [default: default]
+    #[arg(short, long, global = true)]
+    pub list: Option<String>,
+
+    /// Print what's happening to stderr, repeat for more detail
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Don't ask for confirmation before destructive changes
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Ask for confirmation when removing more todos than this [default: 10]
+    #[arg(long, global = true, value_name = "N")]
+    pub confirm_threshold: Option<usize>,
+
+    /// Don't print the list after changing it
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Fail when a change finds no todo to act on, like done on one that's already done
+    #[arg(long, global = true)]
+    pub fail_empty: bool,
+
+    /// Print the list after changing it to stderr, keeping stdout for the command's own output
+    #[arg(long, global = true)]
+    pub reprint_stderr: bool,
+
+    /// Read numbers naming todos as database ids, as shown by print --ids, instead of indexes
+    #[arg(long, global = true)]
+    pub by_id: bool,
+
+    /// Number todos from N instead of 0, both in what's printed and in the indexes commands read
+    #[arg(long, global = true, value_name = "N")]
+    pub line_numbers_from: Option<usize>,
+
+    /// Use a throwaway in-memory database instead of the real one
+    #[arg(long, global = true)]
+    pub ephemeral: bool,
+
+    /// Open the database read-only and refuse to change it
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    /// Append a line with the time, list and title to this file for every todo marked done
+    #[arg(long, global = true, value_name = "PATH")]
+    pub archive_file: Option<PathBuf>,
+
+    /// Where backup writes its copies, the database's directory by default
+    #[arg(long, global = true, value_name = "DIR")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Exit with an error when a post-command hook fails
+    #[arg(long, global = true)]
+    pub strict_hooks: bool,
+
+    /// When to color the output, auto honors NO_COLOR
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t)]
+    pub color: ColorChoice,
+
+    /// Describe status in words instead of colors and symbols, for screen readers
+    #[arg(long, global = true)]
+    pub accessible: bool,
+
+    /// Draw markers in plain ASCII, for terminals that show emoji as boxes
+    #[arg(long, global = true)]
+    pub no_emoji: bool,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    Add { titles: Vec<String> },
-    Done { ids: Vec<usize> },
-    Undone { ids: Vec<usize> },
-    Remove { ids: Vec<usize> },
+    /// Add a todo to the list, the words join into one title unless --separate is given
+    ///
+    /// Without titles they are read one per line from stdin, with a prompt at a terminal where
+    /// an empty line or Ctrl-D finishes and Ctrl-C aborts without adding anything.
+    Add {
+        titles: Vec<String>,
+
+        /// Add the todos of this template from the config instead
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["titles", "separate"])]
+        template: Option<String>,
+
+        /// Add one todo per argument, which add used to do by default
+        #[arg(short, long)]
+        separate: bool,
+
+        /// Add one todo per non-empty line of the clipboard
+        #[arg(long, conflicts_with_all = ["titles", "template", "separate"])]
+        from_clipboard: bool,
+
+        /// Add the whole clipboard as a single todo
+        #[arg(long, requires = "from_clipboard")]
+        single: bool,
+
+        /// Don't print the list after adding
+        #[arg(short, long, alias = "no-reprint-on-add")]
+        quiet: bool,
+
+        #[command(flatten)]
+        options: AddOptions,
+    },
+    /// Mark todos as done, without indexes the pending one added last
+    Done {
+        /// Indexes or UUID prefixes of the todos
+        ids: Vec<TodoRef>,
+
+        /// Then remove every done todo, all in one operation
+        #[arg(long)]
+        and_clear: bool,
+    },
+    /// Mark todos as pending, without indexes the one done last
+    Undone {
+        /// Indexes or UUID prefixes of the todos
+        ids: Vec<TodoRef>,
+    },
+    /// Move todos to the trash, where restore brings them back from
+    #[command(visible_alias = "rm")]
+    Remove {
+        /// Indexes or UUID prefixes of the todos, or filters to remove every match
+        #[arg(conflicts_with = "FilterOptions")]
+        ids: Vec<TodoRef>,
+
+        #[command(flatten)]
+        filter: FilterOptions,
+    },
+    /// Print the names of the add templates in the config
+    Templates,
+    /// Print every list with its pending and done counts, * marks the active one
+    Lists {
+        /// Rename a list, along with the config default when it names that list
+        #[arg(long, num_args = 2, value_names = ["OLD", "NEW"])]
+        rename: Option<Vec<String>>,
+
+        /// Delete a list by moving every todo in it to the trash
+        #[arg(long, value_name = "LIST", conflicts_with = "rename")]
+        delete: Option<String>,
+
+        /// Move the todos into NEW when a list by that name already exists
+        #[arg(long, requires = "rename")]
+        merge: bool,
+
+        /// Print the lists as JSON
+        #[arg(long, conflicts_with_all = ["rename", "delete"])]
+        json: bool,
+    },
+    /// Rename a tag on the todos of every list, merged into the new tag where both are set
+    RenameTag {
+        /// The tag to rename
+        from: String,
+
+        /// Its new name
+        to: String,
+    },
+    /// Remove a tag from the todos of every list
+    RemoveTag { tag: String },
+    /// Run a read-only SELECT against the database and print the rows tab-separated
+    Sql {
+        /// The query, like "SELECT title FROM todos WHERE done"
+        query: String,
+    },
+    /// Change the title, priority or label of a todo
+    Edit {
+        /// Index or UUID prefix of the todo to edit
+        id: TodoRef,
+
+        #[command(flatten)]
+        options: EditOptions,
+    },
+    /// Print everything about a todo, its UUID included
+    Show {
+        /// Index or UUID prefix of the todo
+        id: TodoRef,
+    },
+    /// Remove the done todos from the list
     Clear,
-    Print,
+    /// Go through the done todos one at a time, choosing which to keep
+    Clean,
+    /// Print the todos, the default command
+    #[command(visible_alias = "ls")]
+    Print {
+        /// Show todos from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        /// Print a JSON document per line whenever the todos change
+        #[arg(long, requires = "json")]
+        watch: bool,
+
+        #[command(flatten)]
+        filter: FilterOptions,
+
+        /// Mark with a * the todos added since you last printed with this flag
+        #[arg(long, conflicts_with = "watch")]
+        since_last_run: bool,
+
+        /// Only print the last N todos, keeping their indexes
+        #[arg(long, value_name = "N")]
+        tail: Option<usize>,
+
+        /// Print the pending todos before the done ones, otherwise in list order and keeping their
+        /// indexes
+        #[arg(long)]
+        group_done_last: bool,
+
+        /// Number the todos by their distance from the first pending one, which keeps its index
+        #[arg(
+            long,
+            conflicts_with_all = ["json", "table", "columns", "oneline", "tree"]
+        )]
+        relative: bool,
+
+        /// Finish with a bar showing how much of the list is done
+        #[arg(long, conflicts_with_all = ["json", "oneline", "done", "pending"])]
+        progress: bool,
+
+        /// Print as a JSON array, or as ndjson with one JSON object per line
+        #[arg(
+            long,
+            value_enum,
+            conflicts_with_all = [
+                "json", "watch", "table", "columns", "progress", "oneline", "tree", "relative"
+            ]
+        )]
+        format: Option<PrintFormat>,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Print the number of todos
+    Count {
+        /// Count todos from every list
+        #[arg(long)]
+        all_lists: bool,
+    },
+    /// Print the todos whose title contains the text
+    Search {
+        text: String,
+
+        /// Search todos from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        /// Print nothing, only exit with 0 when something matched and 1 otherwise
+        #[arg(short, long)]
+        quiet: bool,
+
+        #[command(flatten)]
+        filter: FilterOptions,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Print a shell completion script
+    Completions { shell: Shell },
+    /// Print man pages
+    Man {
+        /// Write one page per subcommand into this directory instead of printing
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        kind: CompletionKind,
+
+        #[arg(default_value = "")]
+        prefix: String,
+    },
+    /// Print the pending todo to work on next
+    Next {
+        /// Show this many todos instead of one
+        #[arg(long, value_name = "N")]
+        count: Option<usize>,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Keep printing the todos whenever the database changes
+    Watch {
+        /// Show todos from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        /// Seconds between checks of the database file
+        #[arg(long, value_name = "SECONDS", default_value_t = 1)]
+        interval: u64,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Print the pending todos past their due date, most overdue first
+    Overdue {
+        /// Show overdue todos from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Print what is overdue, due today or high priority
+    Today {
+        /// Show the agenda of every list
+        #[arg(long)]
+        all_lists: bool,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Print the todos due over the next seven days
+    Week {
+        /// Show the week of every list
+        #[arg(long)]
+        all_lists: bool,
+
+        /// Also list pending todos without a due date
+        #[arg(long)]
+        no_date: bool,
+
+        /// Show days with nothing due
+        #[arg(long)]
+        show_empty: bool,
+
+        #[command(flatten)]
+        options: PrintOptions,
+    },
+    /// Open the list full-screen to browse and change it with the keyboard
+    #[command(visible_alias = "ui")]
+    Tui,
+    /// Read commands line by line on one open database, written as they would be after todo-cli
+    Shell,
+    /// Serve the list as JSON over HTTP: GET /todos, POST /todos and POST /todos/<id>/done
+    Serve {
+        /// Address to listen on, only this machine can reach the default
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        addr: SocketAddr,
+    },
+    /// Fill the database with sample todos, e.g. with --ephemeral to try things out
+    Demo {
+        /// Add them even when the database already has todos
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print todos as a JSON array that import reads back
+    Export {
+        /// Only todos created on or after this day, or completed with --by completed
+        #[arg(long, value_parser = parse_due_arg)]
+        since: Option<NaiveDate>,
+
+        /// Only todos created on or before this day, or completed with --by completed
+        #[arg(long, value_parser = parse_due_arg)]
+        until: Option<NaiveDate>,
+
+        /// Which date --since and --until look at
+        #[arg(long, value_enum, default_value_t)]
+        by: ExportBy,
+
+        /// Export todos from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        #[command(flatten)]
+        filter: FilterOptions,
+    },
+    /// Add todos from a JSON array shaped like the output of print --json
+    ///
+    /// Todos whose UUID is already known are merged: unchanged ones are skipped and changed ones
+    /// resolved with --prefer.
+    Import {
+        /// The file to read, - for stdin
+        file: PathBuf,
+
+        /// Which side wins when a known todo differs. newer compares the last edit times and
+        /// picks the imported todo when either is missing
+        #[arg(long, value_enum, default_value_t)]
+        prefer: Prefer,
+
+        /// Show what would be added and updated without writing anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Move every todo of the list to the trash first, after asking unless --yes is given
+        #[arg(long, conflicts_with_all = ["prefer", "no_fsync"])]
+        replace: bool,
+
+        /// Import only the first of the todos in the file that share a title within a list,
+        /// or the first done one when they differ
+        #[arg(long)]
+        dedup: bool,
+
+        /// Don't wait for the disk while importing. Much faster for big imports, but a crash or
+        /// power loss midway can corrupt the database
+        #[arg(long)]
+        no_fsync: bool,
+    },
+    /// Apply a file of title edits, one "<index>: <title>" per line, all or nothing
+    Bulk {
+        /// The file to read, - for stdin
+        file: PathBuf,
+    },
+    /// Move a todo to another position in the list
+    #[command(visible_alias = "mv")]
+    Move {
+        /// Index or UUID prefix of the todo to move
+        id: TodoRef,
+
+        /// Index it should end up at
+        #[arg(required_unless_present_any = ["top", "bottom"])]
+        to: Option<usize>,
+
+        /// Move it to the top of the list
+        #[arg(long, conflicts_with_all = ["to", "bottom"])]
+        top: bool,
+
+        /// Move it to the bottom of the list
+        #[arg(long, conflicts_with = "to")]
+        bottom: bool,
+    },
+    /// Move todos to the bottom of another list, which is created when missing
+    MoveTo {
+        /// The list to move them to
+        #[arg(value_name = "LIST")]
+        target: String,
+
+        /// Indexes or UUID prefixes of the todos
+        #[arg(required = true)]
+        ids: Vec<TodoRef>,
+    },
+    /// Copy todos to the bottom of another list, as pending ones unless --keep-done is given
+    CopyTo {
+        /// The list to copy them to
+        #[arg(value_name = "LIST")]
+        target: String,
+
+        /// Indexes or UUID prefixes of the todos
+        #[arg(required = true)]
+        ids: Vec<TodoRef>,
+
+        /// Copy done todos as done
+        #[arg(long)]
+        keep_done: bool,
+    },
+    /// Push the due dates of todos back
+    #[command(allow_missing_positional = true)]
+    Postpone {
+        /// Indexes or UUID prefixes of the todos to postpone
+        #[arg(required_unless_present = "overdue")]
+        ids: Vec<TodoRef>,
+
+        /// How far to push them, like 1d, 2w or 3 months
+        #[arg(value_parser = parse_span)]
+        duration: Span,
+
+        /// Postpone every overdue todo
+        #[arg(long, conflicts_with = "ids")]
+        overdue: bool,
+
+        /// Print what would move without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Raise a desktop notification for each todo coming due
+    Notify {
+        /// Also notify about todos due this far ahead, like 1d [default: 0d]
+        #[arg(long, value_parser = parse_span, value_name = "DURATION")]
+        within: Option<Span>,
+
+        /// Notify again about todos already notified about
+        #[arg(long)]
+        force: bool,
+    },
+    /// Encrypt the database with a passphrase
+    #[cfg(feature = "encryption")]
+    Encrypt,
+    /// Decrypt the database back to plain SQLite
+    #[cfg(feature = "encryption")]
+    Decrypt,
+    /// Print where the database lives and what is in it
+    Info {
+        /// Print the details as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a one-line summary for status bars and prompts
+    Status,
+    /// Renumber the database ids to 1..N in list order, the only command that changes them
+    Reindex,
+    /// Write a timestamped copy of the database to the backup directory
+    Backup,
+    /// Print the removed todos of every list, numbered for restore
+    Trash {
+        /// Delete the trashed todos for good, after asking
+        #[arg(long)]
+        empty: bool,
+    },
+    /// Bring todos back from the trash to the bottom of their lists
+    Restore {
+        /// Numbers of the todos, as printed by trash
+        #[arg(required = true)]
+        ids: Vec<usize>,
+    },
+    /// Print the most recent changes
+    History {
+        /// Show at most this many changes
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+
+        /// Show changes from every list
+        #[arg(long)]
+        all_lists: bool,
+
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print done and pending counts
+    Stats {
+        /// Break the counts down by tag
+        #[arg(long)]
+        by_tag: bool,
+
+        /// How many days, ending today, the daily counts of --json or --cycle-time cover
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 7,
+            value_parser = clap::value_parser!(u32).range(1..),
+            conflicts_with = "by_tag"
+        )]
+        days: u32,
+
+        /// Also count the todos done within this long, like 7d
+        #[arg(
+            long,
+            value_parser = parse_span,
+            value_name = "DURATION",
+            conflicts_with = "by_tag"
+        )]
+        recent: Option<Span>,
+
+        /// Report how long todos took from added to done
+        #[arg(long, conflicts_with_all = ["by_tag", "recent"])]
+        cycle_time: bool,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Chart how many todos were open on each day, to see whether the list grows or shrinks
+    Burndown {
+        /// How many days, ending today, the chart covers
+        #[arg(
+            long,
+            value_name = "N",
+            default_value_t = 14,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        days: u32,
+
+        /// Print the open count of each day as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum PrintFormat {
+    Json,
+    Ndjson,
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum Prefer {
+    #[default]
+    Newer,
+    Local,
+    Remote,
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum ExportBy {
+    #[default]
+    Created,
+    Completed,
+}
+
+#[derive(ValueEnum, Debug, Default, Clone, Copy, PartialEq)]
+pub enum TagLogic {
+    And,
+    #[default]
+    Or,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum CompletionKind {
+    Done,
+    Undone,
+    Remove,
+    Tag,
+    List,
+}
+
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct AddOptions {
+    /// Tag the added todos, can be repeated
+    #[arg(short, long = "tag")]
+    pub tags: Vec<String>,
+
+    /// Priority of the added todos: high, medium, low or h, m, l [default: medium]
+    #[arg(short, long)]
+    pub priority: Option<Priority>,
+
+    /// Due date of the added todos, like 2024-07-04, tomorrow, friday, in 3 days or jul 4
+    #[arg(short, long, value_parser = parse_due_arg)]
+    pub due: Option<NaiveDate>,
+
+    /// Attach a note to the added todos
+    #[arg(short, long)]
+    pub note: Option<String>,
+
+    /// Color label of the added todos: red, yellow, green, blue, magenta or cyan
+    #[arg(long)]
+    pub label: Option<Label>,
+
+    /// Keep the whitespace of the titles as typed
+    #[arg(long)]
+    pub no_trim: bool,
+
+    /// Add the todos as subtasks of this one, by index or UUID prefix
+    #[arg(long, value_name = "TODO")]
+    pub parent: Option<TodoRef>,
+
+    /// Insert the todos at this index instead of the end, moving the todos from there down
+    #[arg(long, value_name = "INDEX")]
+    pub at: Option<usize>,
+}
+
+#[derive(clap::Args, Debug, Default, Clone)]
+#[group(required = true, multiple = true)]
+pub struct EditOptions {
+    /// New title
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// New priority: high, medium, low or h, m, l
+    #[arg(short, long)]
+    pub priority: Option<Priority>,
+
+    /// New color label: red, yellow, green, blue, magenta or cyan
+    #[arg(long)]
+    pub label: Option<Label>,
+
+    /// Remove the color label
+    #[arg(long, conflicts_with = "label")]
+    pub no_label: bool,
+}
+
+// Shared by the commands that pick todos, so the filters combine the same way everywhere.
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct FilterOptions {
+    /// Only done todos
+    #[arg(long)]
+    pub done: bool,
+
+    /// Only pending todos
+    #[arg(
+        long,
+        visible_alias = "undone",
+        alias = "exclude-done",
+        conflicts_with = "done"
+    )]
+    pub pending: bool,
+
+    /// Only todos with this tag, repeat for several
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+
+    /// Whether a todo needs all of the tags given or any of them
+    #[arg(long, value_enum, default_value_t, value_name = "LOGIC")]
+    pub tag_logic: TagLogic,
+
+    /// Only todos whose title contains this
+    #[arg(long, value_name = "TEXT")]
+    pub search: Option<String>,
+
+    /// Only todos due before this day
+    #[arg(long, value_name = "DATE", value_parser = parse_due_arg)]
+    pub due_before: Option<NaiveDate>,
+
+    /// Hide todos below this priority
+    #[arg(long, value_name = "LEVEL")]
+    pub min_priority: Option<Priority>,
+
+    /// Only todos whose title starts with this, like Project/ for subtasks
+    #[arg(long, value_name = "TEXT")]
+    pub prefix: Option<String>,
+}
+
+impl FilterOptions {
+    pub fn is_empty(&self) -> bool {
+        !self.done
+            && !self.pending
+            && self.tag.is_empty()
+            && self.search.is_none()
+            && self.due_before.is_none()
+            && self.min_priority.is_none()
+            && self.prefix.is_none()
+    }
+}
+
+#[derive(clap::Args, Debug, Default, Clone)]
+pub struct PrintOptions {
+    /// Show the database id next to the index, for use with --by-id
+    #[arg(long, visible_alias = "ids")]
+    pub show_id: bool,
+
+    /// Print todos as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Show the first line of each note next to the title
+    #[arg(long)]
+    pub show_note: bool,
+
+    /// Truncate the shown notes to this many characters
+    #[arg(long, value_name = "N", default_value_t = 40, requires = "show_note")]
+    pub note_width: usize,
+
+    /// Print todos as a table with a header row
+    #[arg(long, conflicts_with = "json")]
+    pub table: bool,
+
+    /// Comma separated table columns, in order, implies --table
+    #[arg(
+        long,
+        value_parser = parse_column,
+        value_delimiter = ',',
+        conflicts_with = "json"
+    )]
+    pub columns: Vec<Column>,
+
+    // Set by print --progress.
+    #[arg(skip)]
+    pub progress: bool,
+
+    /// Print one plain "<index> <title>" line per todo, for pickers like fzf
+    #[arg(
+        long,
+        conflicts_with_all = ["json", "table", "columns", "show_note", "show_id"]
+    )]
+    pub oneline: bool,
+
+    // Print only the last N todos, set by print --tail.
+    #[arg(skip)]
+    pub tail: Option<usize>,
+
+    // Set by print --group-done-last.
+    #[arg(skip)]
+    pub group_done_last: bool,
+
+    // Set by print --relative.
+    #[arg(skip)]
+    pub relative: bool,
+
+    /// Show subtasks indented under their parents
+    #[arg(long, conflicts_with_all = ["json", "table", "columns", "oneline"])]
+    pub tree: bool,
+
+    // Todos created after this are marked new, print --since-last-run fills it in.
+    #[arg(skip)]
+    pub new_since: Option<DateTime<Utc>>,
+
+    // One JSON object per todo and line, set by print --format ndjson.
+    #[arg(skip)]
+    pub ndjson: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Column {
+    Index,
+    Id,
+    List,
+    Done,
+    Priority,
+    Due,
+    Title,
+    Tags,
+    Note,
+    Label,
+}
+
+pub const COLUMNS: &[(&str, Column)] = &[
+    ("index", Column::Index),
+    ("id", Column::Id),
+    ("list", Column::List),
+    ("done", Column::Done),
+    ("priority", Column::Priority),
+    ("due", Column::Due),
+    ("title", Column::Title),
+    ("tags", Column::Tags),
+    ("note", Column::Note),
+    ("label", Column::Label),
+];
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Unknown column '{0}', valid columns are {names}", names = column_names())]
+pub struct ParseColumnError(String);
+
+fn column_names() -> String {
+    let names: Vec<&str> = COLUMNS.iter().map(|(name, _)| *name).collect();
+    names.join(", ")
+}
+
+pub fn parse_column(input: &str) -> Result<Column, ParseColumnError> {
+    let name = input.trim().to_lowercase();
+    COLUMNS
+        .iter()
+        .find(|(column, _)| *column == name)
+        .map(|(_, column)| *column)
+        .ok_or(ParseColumnError(input.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn test_args_are_consistent() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_conflicting_print_filters_fail_to_parse() {
+        let parse = |args: &[&str]| Args::try_parse_from(["todo-cli", "print"].iter().chain(args));
+
+        for args in [
+            &["--done", "--undone"][..],
+            &["--done", "--exclude-done"],
+            &["--undone", "--progress"],
+            &["--done", "--progress"],
+            &["--json", "--progress"],
+            &["--watch"],
+        ] {
+            let error = parse(args)
+                .err()
+                .unwrap_or_else(|| panic!("{:?} parsed", args));
+            assert!(
+                matches!(
+                    error.kind(),
+                    clap::error::ErrorKind::ArgumentConflict
+                        | clap::error::ErrorKind::MissingRequiredArgument
+                ),
+                "{:?}: {}",
+                args,
+                error
+            );
+        }
+
+        for args in [
+            &["--done", "--min-priority", "high", "--prefix", "a/"][..],
+            &["--undone", "--all-lists", "--json"],
+            &["--watch", "--json", "--undone"],
+        ] {
+            assert!(parse(args).is_ok(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_command_aliases() {
+        let parse = |args: &[&str]| {
+            Args::try_parse_from(["todo-cli"].iter().chain(args))
+                .unwrap()
+                .command
+        };
+
+        assert!(matches!(
+            parse(&["rm", "0", "1"]),
+            Some(Commands::Remove { ids, .. }) if ids.len() == 2
+        ));
+        assert!(matches!(
+            parse(&["mv", "2", "--top"]),
+            Some(Commands::Move { top: true, .. })
+        ));
+        assert!(matches!(
+            parse(&["ls", "--done"]),
+            Some(Commands::Print { filter, .. }) if filter.done
+        ));
+    }
+
+    #[test]
+    fn test_remove_state_filters_conflict_with_ids() {
+        let parse = |args: &[&str]| Args::try_parse_from(["todo-cli", "remove"].iter().chain(args));
+
+        for args in [
+            &["0", "--done"][..],
+            &["--pending", "1"],
+            &["--done", "--pending"],
+            &["0", "--tag", "work"],
+        ] {
+            let error = parse(args)
+                .err()
+                .unwrap_or_else(|| panic!("{:?} parsed", args));
+            assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+        assert!(parse(&["--pending"]).is_ok());
+        assert!(parse(&["0", "1"]).is_ok());
+    }
+
+    #[test]
+    fn test_filters_compose() {
+        let filters = [
+            "--pending",
+            "--tag",
+            "work",
+            "--search",
+            "deploy",
+            "--due-before",
+            "friday",
+        ];
+        for command in ["print", "search deploy", "export", "remove"] {
+            let args = ["todo-cli"]
+                .into_iter()
+                .chain(command.split_whitespace())
+                .chain(filters);
+            assert!(Args::try_parse_from(args).is_ok(), "{}", command);
+        }
+
+        let args = Args::parse_from(["todo-cli", "print", "--tag", "a", "--tag", "b", "--undone"]);
+        let Some(Commands::Print { filter, .. }) = args.command else {
+            panic!("expected the print command");
+        };
+        assert_eq!(filter.tag, vec!["a", "b"]);
+        assert_eq!(filter.tag_logic, TagLogic::Or);
+        assert!(filter.pending && !filter.is_empty());
+
+        let args = Args::parse_from(["todo-cli", "print", "--tag", "a", "--tag-logic", "and"]);
+        let Some(Commands::Print { filter, .. }) = args.command else {
+            panic!("expected the print command");
+        };
+        assert_eq!(filter.tag_logic, TagLogic::And);
+    }
+
+    #[test]
+    fn test_print_only_options() {
+        let parse = |args: &[&str]| Args::try_parse_from(std::iter::once(&"todo-cli").chain(args));
+
+        for flag in [
+            &["--tail", "3"][..],
+            &["--group-done-last"],
+            &["--relative"],
+            &["--progress"],
+        ] {
+            for command in ["today", "week", "next", "overdue", "search x", "watch"] {
+                let args: Vec<&str> = command.split(' ').chain(flag.iter().copied()).collect();
+                assert!(parse(&args).is_err(), "{:?} parsed", args);
+            }
+            let args: Vec<&str> = std::iter::once("print")
+                .chain(flag.iter().copied())
+                .collect();
+            assert!(parse(&args).is_ok(), "{:?}", args);
+        }
+    }
 }
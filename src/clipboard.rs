@@ -0,0 +1,115 @@
+use std::process::{Command, Stdio};
+
+// Tried in order. The display variable keeps a reader from being started in a session it can't
+// reach, the rest fail right away when there's no clipboard.
+const READERS: &[(&str, &[&str], Option<&str>)] = &[
+    ("wl-paste", &["--no-newline"], Some("WAYLAND_DISPLAY")),
+    (
+        "xclip",
+        &["-selection", "clipboard", "-out"],
+        Some("DISPLAY"),
+    ),
+    ("xsel", &["--clipboard", "--output"], Some("DISPLAY")),
+    ("pbpaste", &[], None),
+    (
+        "powershell",
+        &["-NoProfile", "-Command", "Get-Clipboard -Raw"],
+        None,
+    ),
+];
+
+const BULLETS: &[&str] = &["- ", "* ", "+ ", "• "];
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadClipboardError {
+    #[error(
+        "No clipboard is available, reading it takes wl-paste, xclip, xsel, pbpaste or powershell \
+        in a session with a display"
+    )]
+    Unavailable,
+
+    #[error("The clipboard doesn't hold text")]
+    NotText(#[from] std::string::FromUtf8Error),
+
+    #[error("The clipboard is empty")]
+    Empty,
+}
+
+pub fn read_clipboard_titles(single: bool) -> Result<Vec<String>, ReadClipboardError> {
+    let titles = clipboard_titles(&read_clipboard()?, single);
+    if titles.is_empty() {
+        return Err(ReadClipboardError::Empty);
+    }
+    Ok(titles)
+}
+
+fn read_clipboard() -> Result<String, ReadClipboardError> {
+    for (program, args, display) in READERS {
+        if display.is_some_and(|display| std::env::var_os(display).is_none()) {
+            continue;
+        }
+        let Ok(output) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        else {
+            continue;
+        };
+        if output.status.success() {
+            log::debug!("Read the clipboard with {}", program);
+            return Ok(String::from_utf8(output.stdout)?);
+        }
+        log::debug!("{} exited with {}", program, output.status);
+    }
+    Err(ReadClipboardError::Unavailable)
+}
+
+// A copied bullet list becomes one todo per item, without the bullets.
+fn clipboard_titles(text: &str, single: bool) -> Vec<String> {
+    if single {
+        let text = text.trim();
+        return if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![text.to_string()]
+        };
+    }
+
+    text.lines()
+        .map(|line| {
+            let line = line.trim();
+            BULLETS
+                .iter()
+                .find_map(|bullet| line.strip_prefix(bullet))
+                .unwrap_or(line)
+                .trim()
+        })
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipboard_titles() {
+        let text = "Action items:\r\n\n  - call the vendor\n* book a room  \n• send notes\n-\n";
+
+        assert_eq!(
+            clipboard_titles(text, false),
+            vec![
+                "Action items:",
+                "call the vendor",
+                "book a room",
+                "send notes",
+                "-"
+            ]
+        );
+        assert_eq!(clipboard_titles(" a\nb \n", true), vec!["a\nb"]);
+        assert!(clipboard_titles(" \n\t\n", false).is_empty());
+        assert!(clipboard_titles(" \n", true).is_empty());
+    }
+}
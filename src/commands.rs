@@ -1,180 +1,7211 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::{BufRead, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Days, Duration, Local, NaiveDate, NaiveTime, SecondsFormat, Utc};
 use rusqlite::Connection;
+use serde::Serialize;
+use uuid::Uuid;
 
 use crate::{
+    args::{AddOptions, Column, EditOptions, ExportBy, Prefer, PrintOptions, COLUMNS},
+    config::{get_backup_dir, GetDbPathError, StatusConfig, TemplateConfig, TemplateTodo},
+    confirm::{confirm_above_threshold, Confirm, ConfirmError},
+    dates::{DateFormat, Span},
     db::{
-        add_todos, get_todos, remove_todos, update_todos, AddTodosError, CreateTableError,
-        GetTodosError, RemoveTodoError, UpdateTodosError,
+        add_todos, backup_database, count_completed_since, count_todos, empty_trash, for_each_todo,
+        get_completions, get_daily_stats, get_history, get_latest_index, get_list_names,
+        get_list_stats, get_metadata, get_open_counts, get_stats, get_status, get_tag_stats,
+        get_todos, get_todos_by_uuid, get_trash, in_savepoint, insert_todos, move_to_list,
+        prune_done_todos, query_next_todos, query_overdue_todos, query_scheduled_todos,
+        query_todos, record_history, reindex_todos, remove_tag, remove_todos, rename_list,
+        rename_tag, reorder_todos, restore_todos, run_select, set_metadata, trash_todos,
+        update_todos, without_fsync, AddTodosError, BackupError, Completion, CreateTableError,
+        DayStats, EmptyTrashError, GetCandidatesError, GetHistoryError, GetStatsError,
+        GetTodosError, GetTrashError, InsertTodosError, ListStats, MetadataError, MoveToListError,
+        OpenCount, RecordHistoryError, ReindexTodosError, RemoveTodoError, RenameListError,
+        ReorderTodosError, RepositoryError, RestoreTodosError, RunSelectError, Stats, Status,
+        TodoQuery, TodoRepository, TrashTodosError, TrashedTodo, UpdateTagsError, UpdateTodosError,
     },
-    terminal::strikethrough,
-    todo::Todo,
+    terminal::{escape_control, Style},
+    todo::{EmptyTitleError, Priority, Todo, TodoDto, TodoList, TodoRef},
 };
 
 #[derive(thiserror::Error, Debug)]
-pub enum AddCommandError {
+#[error("There is no todo at index {0}")]
+pub struct InvalidIndexError(pub usize);
+
+fn select_by_indexes<T>(
+    todos: impl Into<Vec<T>>,
+    indexes: &[usize],
+) -> Result<Vec<T>, InvalidIndexError> {
+    let todos = todos.into();
+    if let Some(&index) = indexes.iter().find(|&&index| index >= todos.len()) {
+        return Err(InvalidIndexError(index));
+    }
+
+    Ok(todos
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| indexes.contains(i))
+        .map(|(_, todo)| todo)
+        .collect())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ResolveRefsError {
+    #[error("No todo in the list has a UUID starting with {0}")]
+    NoMatch(String),
+
+    #[error("Several todos have a UUID starting with {0}, give more of it")]
+    Ambiguous(String),
+
+    #[error("No todo in the list has id {0}")]
+    NoSuchId(usize),
+
+    #[error("There is no todo at index {0}")]
+    NoSuchIndex(usize),
+
     #[error(transparent)]
-    AddTodos(#[from] AddTodosError),
+    GetTodos(#[from] GetTodosError),
+}
+
+// Turns UUID prefixes into indexes, indexes pass through to be checked by the command. With
+// `by_id` every number is a database id instead, this being the only place numbers are read
+// so one invocation can't mix the two. Indexes counted from another `base` are checked here,
+// so an error names the number that was given rather than the one it became.
+pub fn resolve_refs(
+    connection: &Connection,
+    list: &str,
+    refs: Vec<TodoRef>,
+    by_id: bool,
+    base: usize,
+) -> Result<Vec<usize>, ResolveRefsError> {
+    let todos = if by_id || base > 0 || refs.iter().any(|r| matches!(r, TodoRef::Uuid(_))) {
+        get_todos(connection, list)?
+    } else {
+        TodoList::default()
+    };
+
+    refs.into_iter()
+        .map(|r| match r {
+            TodoRef::Index(id) if by_id => todos
+                .iter()
+                .position(|todo| todo.id == id)
+                .ok_or(ResolveRefsError::NoSuchId(id)),
+            TodoRef::Index(index) if base > 0 => index
+                .checked_sub(base)
+                .filter(|&i| i < todos.len())
+                .ok_or(ResolveRefsError::NoSuchIndex(index)),
+            TodoRef::Index(index) => Ok(index),
+            TodoRef::Uuid(prefix) => {
+                let mut matches = todos
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, todo)| todo.uuid.starts_with(&prefix));
+                match (matches.next(), matches.next()) {
+                    (Some((index, _)), None) => Ok(index),
+                    (None, _) => Err(ResolveRefsError::NoMatch(prefix)),
+                    (Some(_), Some(_)) => Err(ResolveRefsError::Ambiguous(prefix)),
+                }
+            }
+        })
+        .collect()
+}
+
+// Everyone sharing the database has a marker of their own. Returns the marker it replaced, so
+// the first run marks nothing.
+pub fn mark_viewed(
+    connection: &Connection,
+    user: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, MetadataError> {
+    let key = format!("last_viewed:{}", user);
+    let previous = get_metadata(connection, &key)?;
+    set_metadata(connection, &key, now)?;
+    Ok(previous)
+}
 
+#[derive(thiserror::Error, Debug)]
+pub enum AutoPruneError {
     #[error(transparent)]
-    CreateTable(#[from] CreateTableError),
+    Metadata(#[from] MetadataError),
+
+    #[error("Fail to prune done todos")]
+    Prune(#[from] RemoveTodoError),
 }
 
-pub fn add_command(
-    connection: &mut Connection,
-    titles: Vec<String>,
-) -> Result<(), AddCommandError> {
-    let todos = titles.into_iter().map(Todo::new).collect();
-    add_todos(connection, todos)?;
+const LAST_PRUNED_KEY: &str = "last_pruned";
+const PRUNE_NOTICED_KEY: &str = "prune_noticed";
+
+// At most once a day, so most runs only read a single metadata row. Only the first removal is
+// announced, so nobody is left wondering where their todos went.
+pub fn auto_prune(
+    connection: &Connection,
+    after: Span,
+    now: DateTime<Utc>,
+) -> Result<usize, AutoPruneError> {
+    let last_pruned: Option<DateTime<Utc>> = get_metadata(connection, LAST_PRUNED_KEY)?;
+    if last_pruned.is_some_and(|last_pruned| now - last_pruned < Duration::days(1)) {
+        return Ok(0);
+    }
+
+    let pruned = match after.before(now) {
+        Some(done_before) => prune_done_todos(connection, done_before)?,
+        None => 0,
+    };
+    set_metadata(connection, LAST_PRUNED_KEY, now)?;
+
+    if pruned > 0 {
+        let noticed: Option<DateTime<Utc>> = get_metadata(connection, PRUNE_NOTICED_KEY)?;
+        if noticed.is_none() {
+            eprintln!(
+                "Removed {} old done todos, set auto_prune_done_after = \"never\" in the config to keep them",
+                pruned
+            );
+            set_metadata(connection, PRUNE_NOTICED_KEY, now)?;
+        }
+        log::info!("Auto-pruned {} done todos", pruned);
+    }
+    Ok(pruned)
+}
+
+pub fn sql_command(connection: &Connection, query: &str) -> Result<(), RunSelectError> {
+    let (columns, rows) = run_select(connection, query)?;
+    println!("{}", columns.join("\t"));
+    for row in rows {
+        println!("{}", row.join("\t"));
+    }
     Ok(())
 }
 
 #[derive(thiserror::Error, Debug)]
-pub enum SetDoneCommandError {
+pub enum ShowCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
     #[error(transparent)]
     GetTodos(#[from] GetTodosError),
+}
+
+pub fn show_command(
+    connection: &Connection,
+    list: &str,
+    index: usize,
+    date_format: &DateFormat,
+) -> Result<(), ShowCommandError> {
+    let todo = select_by_indexes(get_todos(connection, list)?, &[index])?.remove(0);
+    let or_none = |value: Option<String>| value.unwrap_or_else(|| "none".into());
+    let now = Local::now();
+
+    // Continuation lines line up under the first one.
+    let title: Vec<String> = todo.title.lines().map(escape_control).collect();
+    println!("Title:    {}", title.join("\n          "));
+    println!("List:     {}", escape_control(&todo.list));
+    println!("Status:   {}", if todo.done { "done" } else { "pending" });
+    println!("Priority: {}", todo.priority);
+    let left = todo
+        .time_to_due(now)
+        .filter(|_| !todo.done)
+        .map(|left| format!(" ({})", time_left_phrase(left)))
+        .unwrap_or_default();
+    println!(
+        "Due:      {}{}",
+        or_none(todo.due.map(|due| date_format.date(due))),
+        left
+    );
+    println!(
+        "Tags:     {}",
+        or_none((!todo.tags.is_empty()).then(|| escape_control(&todo.tags.join(", "))))
+    );
+    let note = todo
+        .note
+        .as_deref()
+        .map(|note| note.lines().map(escape_control).collect::<Vec<_>>());
+    println!(
+        "Note:     {}",
+        or_none(note.map(|note| note.join("\n          ")))
+    );
+    println!(
+        "Label:    {}",
+        or_none(todo.label.map(|label| label.to_string()))
+    );
+    if let (Some(created_at), Some(age)) = (todo.created_at, todo.age(now)) {
+        println!(
+            "Created:  {} ({})",
+            date_format.timestamp(created_at),
+            age_phrase(age)
+        );
+    }
+    if let Some(done_at) = todo.done_at {
+        println!("Done:     {}", date_format.timestamp(done_at));
+    }
+    println!("UUID:     {}", todo.uuid);
+    println!("Id:       {}", todo.id);
+    Ok(())
+}
+
+// In whole days, so something added this morning reads "today" rather than "0 days ago".
+fn age_phrase(age: Duration) -> String {
+    match age.num_days().max(0) {
+        0 => "today".into(),
+        1 => "1 day ago".into(),
+        days => format!("{} days ago", days),
+    }
+}
+
+fn time_left_phrase(left: Duration) -> String {
+    if left < Duration::zero() {
+        return "overdue".into();
+    }
+    match left.num_days() {
+        0 => "due today".into(),
+        1 => "1 day left".into(),
+        days => format!("{} days left", days),
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EditCommandError {
+    #[error("Todo title can't be empty")]
+    EmptyTitle,
 
     #[error(transparent)]
-    UpdateTodos(#[from] UpdateTodosError),
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to edit atomically")]
+    Savepoint(#[from] rusqlite::Error),
 }
 
-pub fn set_done_command(
+pub fn edit_command(
     connection: &mut Connection,
-    ids: Vec<usize>,
-    done: bool,
-) -> Result<(), SetDoneCommandError> {
-    let todos = get_todos(&connection)?
-        .into_iter()
-        .enumerate()
-        .filter(|(i, _)| ids.contains(&i))
-        .map(|(_, todo)| Todo { done, ..todo })
-        .collect();
+    list: &str,
+    index: usize,
+    options: &EditOptions,
+) -> Result<Todo, EditCommandError> {
+    in_savepoint(connection, |connection| {
+        let edited = edit_todo(connection, list, index, options)?;
+        record_history(connection, list, &format!("edited {}", index))?;
+        Ok(edited)
+    })
+}
 
-    update_todos(connection, todos)?;
-    Ok(())
+fn edit_todo(
+    repository: &mut dyn TodoRepository,
+    list: &str,
+    index: usize,
+    options: &EditOptions,
+) -> Result<Todo, EditCommandError> {
+    if options
+        .title
+        .as_ref()
+        .is_some_and(|title| title.trim().is_empty())
+    {
+        return Err(EditCommandError::EmptyTitle);
+    }
+
+    let todo = select_by_indexes(repository.all(list)?, &[index])?.remove(0);
+    let label = if options.no_label {
+        None
+    } else {
+        options.label.or(todo.label)
+    };
+    let edited = Todo {
+        title: options
+            .title
+            .as_deref()
+            .map_or(todo.title.clone(), normalize_title),
+        priority: options.priority.unwrap_or(todo.priority),
+        label,
+        ..todo
+    };
+
+    repository.update(vec![edited.clone()])?;
+    Ok(edited)
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Malformed line {line}, {reason}")]
+pub struct ParseBulkError {
+    line: usize,
+    reason: &'static str,
+}
+
+// Lines look like "2: new title", blank lines are skipped.
+fn parse_bulk(content: &str) -> Result<Vec<(usize, String)>, ParseBulkError> {
+    let mut edits: Vec<(usize, String)> = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let error = |reason| ParseBulkError {
+            line: i + 1,
+            reason,
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (index, title) = line
+            .split_once(':')
+            .ok_or_else(|| error("expected '<index>: <title>'"))?;
+        let index: usize = index
+            .trim()
+            .parse()
+            .map_err(|_| error("expected '<index>: <title>'"))?;
+        let title = normalize_title(title);
+        if title.is_empty() {
+            return Err(error("the title is empty"));
+        }
+        if edits.iter().any(|(edited, _)| *edited == index) {
+            return Err(error("the todo is already updated on an earlier line"));
+        }
+        edits.push((index, title));
+    }
+
+    Ok(edits)
 }
 
 #[derive(thiserror::Error, Debug)]
-pub enum RemoveCommandError {
+pub enum TagCommandError {
+    #[error("Tag can't be empty")]
+    EmptyTag,
+
+    #[error(transparent)]
+    UpdateTags(#[from] UpdateTagsError),
+}
+
+pub fn rename_tag_command(
+    connection: &mut Connection,
+    from: &str,
+    to: &str,
+) -> Result<usize, TagCommandError> {
+    let to = to.trim();
+    if to.is_empty() {
+        return Err(TagCommandError::EmptyTag);
+    }
+    Ok(rename_tag(connection, from, to)?)
+}
+
+pub fn remove_tag_command(connection: &Connection, tag: &str) -> Result<usize, TagCommandError> {
+    Ok(remove_tag(connection, tag)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BulkCommandError {
+    #[error("Fail to read the bulk file")]
+    Read(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Parse(#[from] ParseBulkError),
+
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
     #[error(transparent)]
     GetTodos(#[from] GetTodosError),
 
     #[error(transparent)]
-    RemoveTodos(#[from] RemoveTodoError),
+    UpdateTodos(#[from] UpdateTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error("Fail to update atomically")]
+    Savepoint(#[from] rusqlite::Error),
 }
 
-pub fn remove_command(
-    connection: &Connection,
-    indexes: Vec<usize>,
-) -> Result<(), RemoveCommandError> {
-    let ids = get_todos(&connection)?
-        .into_iter()
-        .enumerate()
-        .filter(|(i, _)| indexes.contains(&i))
-        .map(|(_, todo)| todo.id)
-        .collect();
+// Every line is checked before anything is written, so a bad file changes nothing.
+pub fn bulk_command(
+    connection: &mut Connection,
+    list: &str,
+    input: &mut dyn Read,
+    base: usize,
+) -> Result<usize, BulkCommandError> {
+    let mut content = String::new();
+    input.read_to_string(&mut content)?;
+    let edits = parse_bulk(&content)?;
 
-    remove_todos(&connection, ids)?;
-    Ok(())
+    in_savepoint(connection, |connection| {
+        let todos = get_todos(connection, list)?;
+        let updated = edits
+            .into_iter()
+            .map(|(index, title)| {
+                let todo = index
+                    .checked_sub(base)
+                    .and_then(|i| todos.by_index(i))
+                    .ok_or(InvalidIndexError(index))?;
+                Ok(Todo {
+                    title,
+                    ..todo.clone()
+                })
+            })
+            .collect::<Result<Vec<_>, BulkCommandError>>()?;
+
+        let count = update_todos(connection, updated)?;
+        record_history(connection, list, &format!("bulk updated {}", count))?;
+        Ok(count)
+    })
 }
 
 #[derive(thiserror::Error, Debug)]
-pub enum ClearCommandError {
+pub enum AddCommandError {
+    #[error("Todo title can't be empty")]
+    EmptyTitle,
+
+    #[error(transparent)]
+    ResolveParent(#[from] ResolveRefsError),
+
+    #[error(transparent)]
+    InvalidParent(#[from] InvalidIndexError),
+
     #[error(transparent)]
     GetTodos(#[from] GetTodosError),
 
     #[error(transparent)]
-    RemoveTodos(#[from] RemoveTodoError),
+    AddTodos(#[from] AddTodosError),
+
+    #[error("Can't add at index {at}, the list has {}", todos_count(*.count))]
+    InvalidPosition { at: usize, count: usize },
+
+    #[error(transparent)]
+    InsertTodos(#[from] InsertTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error(transparent)]
+    CreateTable(#[from] CreateTableError),
+
+    #[error("Fail to read the titles")]
+    ReadTitles(#[source] std::io::Error),
+
+    #[error("Unknown template '{name}', {known}")]
+    UnknownTemplate { name: String, known: String },
+    #[error("Fail to add atomically")]
+    Savepoint(#[from] rusqlite::Error),
 }
 
-pub fn clear_command(connection: &Connection) -> Result<(), ClearCommandError> {
-    let ids = get_todos(&connection)?
-        .into_iter()
-        .filter(|todo| todo.done)
-        .map(|todo| todo.id)
+// One title per line. At a terminal each line gets a prompt and an empty one ends the input,
+// piped input is read to the end with blank lines skipped. Nothing is added before it ends,
+// so Ctrl-C midway leaves the list as it was.
+pub fn read_titles(
+    input: &mut dyn BufRead,
+    mut prompt: Option<&mut dyn Write>,
+) -> Result<Vec<String>, AddCommandError> {
+    let mut titles = Vec::new();
+    loop {
+        if let Some(prompt) = prompt.as_mut() {
+            write!(prompt, "> ")
+                .and_then(|()| prompt.flush())
+                .map_err(AddCommandError::ReadTitles)?;
+        }
+
+        let mut line = String::new();
+        if input
+            .read_line(&mut line)
+            .map_err(AddCommandError::ReadTitles)?
+            == 0
+        {
+            break;
+        }
+
+        let line = line.trim_end_matches(['\n', '\r']);
+        if line.trim().is_empty() {
+            if prompt.is_some() {
+                break;
+            }
+            continue;
+        }
+        titles.push(line.to_string());
+    }
+    Ok(titles)
+}
+
+pub fn add_command(
+    connection: &mut Connection,
+    list: &str,
+    titles: Vec<String>,
+    options: &AddOptions,
+) -> Result<Vec<Todo>, AddCommandError> {
+    if titles.iter().any(|title| title.trim().is_empty()) {
+        return Err(AddCommandError::EmptyTitle);
+    }
+
+    let parent = parent_uuid(connection, list, options)?;
+    let todos = titles
+        .iter()
+        .map(|title| new_todo(list, title, options, parent.clone()))
         .collect();
+    in_savepoint(connection, |connection| {
+        let added = insert_at(connection, list, todos, options.at)?;
+        record_history(connection, list, &format!("added {}", added.len()))?;
+        Ok(added)
+    })
+}
 
-    remove_todos(&connection, ids)?;
-    Ok(())
+// Right after the last todo is where the list ends, so that's the furthest it can go.
+fn insert_at(
+    connection: &mut Connection,
+    list: &str,
+    todos: Vec<Todo>,
+    at: Option<usize>,
+) -> Result<Vec<Todo>, AddCommandError> {
+    let Some(at) = at else {
+        return Ok(add_todos(connection, todos)?);
+    };
+    let count = count_todos(connection, &TodoQuery::list(list))?;
+    if at > count {
+        return Err(AddCommandError::InvalidPosition { at, count });
+    }
+    Ok(insert_todos(connection, list, at, todos)?)
+}
+
+// The template's own tags come first and its priorities win over --priority, the other options
+// apply to every todo it adds.
+pub fn add_template_command(
+    connection: &mut Connection,
+    list: &str,
+    templates: &BTreeMap<String, TemplateConfig>,
+    name: &str,
+    options: &AddOptions,
+) -> Result<Vec<Todo>, AddCommandError> {
+    let template = templates
+        .get(name)
+        .ok_or_else(|| AddCommandError::UnknownTemplate {
+            name: name.into(),
+            known: if templates.is_empty() {
+                "there are none in the config".into()
+            } else {
+                let names: Vec<&str> = templates.keys().map(String::as_str).collect();
+                format!("expected one of {}", names.join(", "))
+            },
+        })?;
+    if template
+        .todos
+        .iter()
+        .any(|todo| todo.title().trim().is_empty())
+    {
+        return Err(AddCommandError::EmptyTitle);
+    }
+
+    let parent = parent_uuid(connection, list, options)?;
+    let todos = template
+        .todos
+        .iter()
+        .map(|entry| {
+            let todo = new_todo(list, entry.title(), options, parent.clone());
+            let TemplateTodo::Table(table) = entry else {
+                return todo;
+            };
+            let mut tags = table.tags.clone();
+            tags.extend(
+                todo.tags
+                    .into_iter()
+                    .filter(|tag| !table.tags.contains(tag)),
+            );
+            Todo {
+                tags,
+                priority: table.priority.unwrap_or(todo.priority),
+                ..todo
+            }
+        })
+        .collect();
+    in_savepoint(connection, |connection| {
+        let added = insert_at(connection, list, todos, options.at)?;
+        record_history(
+            connection,
+            list,
+            &format!("added {} from template {}", added.len(), name),
+        )?;
+        Ok(added)
+    })
+}
+
+pub fn templates_command(templates: &BTreeMap<String, TemplateConfig>) {
+    for (name, template) in templates {
+        let titles: Vec<&str> = template.todos.iter().map(TemplateTodo::title).collect();
+        println!("{}: {}", name, titles.join(", "));
+    }
+}
+
+fn parent_uuid(
+    connection: &Connection,
+    list: &str,
+    options: &AddOptions,
+) -> Result<Option<String>, AddCommandError> {
+    let Some(parent) = &options.parent else {
+        return Ok(None);
+    };
+    let index = resolve_refs(connection, list, vec![parent.clone()], false, 0)?[0];
+    let todo = select_by_indexes(get_todos(connection, list)?, &[index])?.remove(0);
+    Ok(Some(todo.uuid))
+}
+
+fn new_todo(list: &str, title: &str, options: &AddOptions, parent: Option<String>) -> Todo {
+    let title = if options.no_trim {
+        title.to_string()
+    } else {
+        normalize_title(title)
+    };
+    Todo {
+        list: list.into(),
+        tags: options.tags.clone(),
+        priority: options.priority.unwrap_or_default(),
+        due: options.due,
+        note: options.note.clone(),
+        label: options.label,
+        parent,
+        ..Todo::new(title)
+    }
+}
+
+fn normalize_title(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
 #[derive(thiserror::Error, Debug)]
-pub enum PrintCommandError {
+pub enum ImportCommandError {
+    #[error("Fail to read the todos to import")]
+    Read(#[from] std::io::Error),
+
+    #[error("Fail to parse the todos to import")]
+    Parse(#[from] serde_json::Error),
+
     #[error(transparent)]
-    CreateTable(#[from] CreateTableError),
+    EmptyTitle(#[from] EmptyTitleError),
+
+    #[error(transparent)]
+    AddTodos(#[from] AddTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error(transparent)]
+    UpdateTodos(#[from] UpdateTodosError),
 
     #[error(transparent)]
     GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    Confirm(#[from] ConfirmError),
+
+    #[error(transparent)]
+    TrashTodos(#[from] TrashTodosError),
+
+    #[error("Fail to import atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Merge {
+    Added,
+    Updated,
+    Skipped,
+    // The title or done state differs, `kept_local` tells which side --prefer picked.
+    Conflicted { kept_local: bool },
 }
 
-pub fn print_command(connection: &Connection) -> Result<(), PrintCommandError> {
-    let todos = get_todos(&connection)?;
+impl Merge {
+    fn writes(self) -> bool {
+        !matches!(
+            self,
+            Merge::Skipped | Merge::Conflicted { kept_local: true }
+        )
+    }
+}
 
-    for (i, todo) in todos.iter().enumerate() {
-        if todo.done {
-            println!("{}: {}", i, strikethrough(&todo.title));
-        } else {
-            println!("{}: {}", i, &todo.title);
+// Pairs each imported todo with what merging it does. Known todos take the id of the local one.
+fn plan_import(
+    todos: Vec<Todo>,
+    mut existing: HashMap<String, Todo>,
+    prefer: Prefer,
+) -> Vec<(Todo, Merge)> {
+    todos
+        .into_iter()
+        .map(|todo| {
+            let Some(local) = existing.remove(&todo.uuid) else {
+                return (todo, Merge::Added);
+            };
+            let todo = Todo {
+                id: local.id,
+                ..todo
+            };
+
+            let conflicted = todo.title != local.title || todo.done != local.done;
+            let details = |todo: &Todo| {
+                (
+                    todo.priority,
+                    todo.due,
+                    todo.note.clone(),
+                    todo.label,
+                    todo.parent.clone(),
+                )
+            };
+            if !conflicted && details(&todo) == details(&local) {
+                return (todo, Merge::Skipped);
+            }
+
+            let kept_local = match prefer {
+                Prefer::Local => true,
+                Prefer::Remote => false,
+                // The imported todo wins without both timestamps, like before they existed.
+                Prefer::Newer => match (todo.updated_at, local.updated_at) {
+                    (Some(remote), Some(local)) => local > remote,
+                    _ => false,
+                },
+            };
+            let merge = match (conflicted, kept_local) {
+                (true, kept_local) => Merge::Conflicted { kept_local },
+                (false, true) => Merge::Skipped,
+                (false, false) => Merge::Updated,
+            };
+            (todo, merge)
+        })
+        .collect()
+}
+
+// Extra fields of `print --json` like the index are ignored.
+fn read_imported(
+    input: &mut dyn Read,
+    list: &str,
+    dedup: bool,
+) -> Result<Vec<Todo>, ImportCommandError> {
+    let imported: Vec<TodoDto> = serde_json::from_reader(input)?;
+    let todos = imported
+        .into_iter()
+        .map(|dto| {
+            Todo::try_from(TodoDto {
+                list: dto.list.clone().or_else(|| Some(list.into())),
+                ..dto
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if dedup { dedup_imported(todos) } else { todos })
+}
+
+// Titles match ignoring case and spacing. A done duplicate takes the place of a pending first
+// one, so a todo finished in one of the merged sources doesn't come back.
+fn dedup_imported(todos: Vec<Todo>) -> Vec<Todo> {
+    let mut kept: Vec<Todo> = Vec::new();
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+    for todo in todos {
+        let key = (
+            todo.list.clone(),
+            normalize_title(&todo.title).to_lowercase(),
+        );
+        match seen.get(&key) {
+            Some(&index) => {
+                if todo.done && !kept[index].done {
+                    kept[index] = todo;
+                }
+            }
+            None => {
+                seen.insert(key, kept.len());
+                kept.push(todo);
+            }
         }
     }
+    kept
+}
 
-    Ok(())
+// Only the list's todos go to the trash. An imported UUID still used in another list is replaced
+// by a new one, or adding it would clash. Returns how many were removed and the todos added, none
+// on a dry run.
+pub fn import_replace_command(
+    connection: &mut Connection,
+    list: &str,
+    input: &mut dyn Read,
+    dry_run: bool,
+    dedup: bool,
+    confirm: &mut dyn Confirm,
+    trash_limit: Option<usize>,
+) -> Result<(usize, Vec<Todo>), ImportCommandError> {
+    let todos = read_imported(input, list, dedup)?;
+
+    in_savepoint(connection, |connection| {
+        let removed: Vec<usize> = get_todos(connection, list)?
+            .into_iter()
+            .map(|todo| todo.id)
+            .collect();
+        let uuids = todos.iter().map(|todo| todo.uuid.clone()).collect();
+        let elsewhere = get_todos_by_uuid(connection, uuids)?;
+        let todos: Vec<Todo> = todos
+            .into_iter()
+            .map(|todo| match elsewhere.get(&todo.uuid) {
+                Some(existing) if existing.list != list => Todo {
+                    uuid: Uuid::new_v4().to_string(),
+                    ..todo
+                },
+                _ => todo,
+            })
+            .collect();
+        let (removed_count, imported_count) = (removed.len(), todos.len());
+
+        println!(
+            "{} existing todos will be removed, {} will be imported",
+            removed_count, imported_count
+        );
+        if dry_run {
+            println!("Nothing was written, this was a dry run");
+            return Ok((removed_count, Vec::new()));
+        }
+        confirm_above_threshold(
+            confirm,
+            0,
+            removed_count,
+            &format!(
+                "Replace {} todos with {} imported?",
+                removed_count, imported_count
+            ),
+        )?;
+
+        trash_todos(connection, removed, trash_limit)?;
+        let added = add_todos(connection, todos)?;
+        record_history(
+            connection,
+            list,
+            &format!(
+                "replaced {} with {} imported",
+                removed_count, imported_count
+            ),
+        )?;
+        Ok((removed_count, added))
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::db::create_table;
-    use rusqlite::Connection;
+pub fn import_command(
+    connection: &mut Connection,
+    list: &str,
+    input: &mut dyn Read,
+    prefer: Prefer,
+    dry_run: bool,
+    no_fsync: bool,
+    dedup: bool,
+) -> Result<Vec<(Todo, Merge)>, ImportCommandError> {
+    let todos = read_imported(input, list, dedup)?;
 
-    #[test]
-    fn test_add_command() {
-        let mut connection = Connection::open_in_memory().unwrap();
-        create_table(&mut connection).unwrap();
+    // Todos seen before, on this machine or another, are merged instead of duplicated.
+    let import = |connection: &mut Connection| {
+        in_savepoint(connection, |connection| {
+            let uuids = todos.iter().map(|todo| todo.uuid.clone()).collect();
+            let existing = get_todos_by_uuid(connection, uuids)?;
+            let plan = plan_import(todos, existing, prefer);
+            if dry_run {
+                return Ok::<_, ImportCommandError>(plan);
+            }
 
-        let titles = vec!["title1".to_string(), "title2".to_string()];
-        add_command(&mut connection, titles).unwrap();
+            let select = |wanted: fn(Merge) -> bool| -> Vec<Todo> {
+                plan.iter()
+                    .filter(|(_, merge)| wanted(*merge))
+                    .map(|(todo, _)| todo.clone())
+                    .collect()
+            };
+            let new = select(|merge| merge == Merge::Added);
+            let known = select(|merge| merge != Merge::Added && merge.writes());
+            let (added, updated) = (new.len(), known.len());
 
-        let todos = get_todos(&connection).unwrap();
-        assert_eq!(todos.len(), 2);
-        assert_eq!(todos[0].title, "title1");
-        assert_eq!(todos[1].title, "title2");
+            update_todos(connection, known)?;
+            // The added todos come back with their ids, in the order of the plan.
+            let mut new = add_todos(connection, new)?.into_iter();
+            let plan = plan
+                .into_iter()
+                .map(|(todo, merge)| match merge {
+                    Merge::Added => (new.next().unwrap_or(todo), merge),
+                    _ => (todo, merge),
+                })
+                .collect();
+            record_history(
+                connection,
+                list,
+                &format!("imported {}, updated {}", added, updated),
+            )?;
+            Ok(plan)
+        })
+    };
+    let plan = if no_fsync && !dry_run {
+        without_fsync(connection, import)?
+    } else {
+        import(connection)?
+    };
+
+    if dry_run {
+        for (todo, merge) in &plan {
+            let action = match merge {
+                Merge::Added => "add",
+                Merge::Updated => "update",
+                Merge::Skipped => "skip",
+                Merge::Conflicted { kept_local: true } => "keep local",
+                Merge::Conflicted { kept_local: false } => "take imported",
+            };
+            println!("{:<14}{}", action, escape_control(&todo.title));
+        }
+        println!();
     }
 
-    #[test]
-    fn test_set_done_command() {
-        let mut connection = Connection::open_in_memory().unwrap();
-        create_table(&mut connection).unwrap();
+    let count = |wanted: fn(&Merge) -> bool| plan.iter().filter(|(_, merge)| wanted(merge)).count();
+    for (label, count) in [
+        ("Added", count(|merge| *merge == Merge::Added)),
+        ("Updated", count(|merge| *merge == Merge::Updated)),
+        ("Skipped", count(|merge| *merge == Merge::Skipped)),
+        (
+            "Conflicted",
+            count(|merge| matches!(merge, Merge::Conflicted { .. })),
+        ),
+    ] {
+        println!("{:<12}{}", label, count);
+    }
+    if dry_run {
+        println!("Nothing was written, this was a dry run");
+    }
 
-        let titles = vec!["title1".to_string(), "title2".to_string()];
-        add_command(&mut connection, titles).unwrap();
+    Ok(plan)
+}
 
-        let todos = get_todos(&connection).unwrap();
-        assert_eq!(todos.len(), 2);
-        assert_eq!(todos[0].done, false);
-        assert_eq!(todos[1].done, false);
+#[derive(thiserror::Error, Debug)]
+pub enum DemoCommandError {
+    #[error("The database already has todos, pass --force to add the demo todos anyway")]
+    NotEmpty,
 
-        set_done_command(&mut connection, vec![0], true).unwrap();
+    #[error(transparent)]
+    CountTodos(#[from] GetTodosError),
 
-        let todos = get_todos(&connection).unwrap();
-        assert_eq!(todos.len(), 2);
-        assert_eq!(todos[0].done, true);
-        assert_eq!(todos[1].done, false);
-    }
+    #[error(transparent)]
+    AddTodos(#[from] AddTodosError),
 
-    #[test]
-    fn test_remove_command() {
-        let mut connection = Connection::open_in_memory().unwrap();
-        create_table(&mut connection).unwrap();
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to add the demo todos atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
 
-        let titles = vec!["title1".to_string(), "title2".to_string()];
-        add_command(&mut connection, titles).unwrap();
+// (title, done, tags, priority, due in days from today, note), all in the current list
+// except the last two, which show off a second list.
+type Sample = (
+    &'static str,
+    bool,
+    &'static [&'static str],
+    Priority,
+    Option<i64>,
+    Option<&'static str>,
+);
 
-        let todos = get_todos(&connection).unwrap();
-        assert_eq!(todos.len(), 2);
+const DEMO_TODOS: &[Sample] = &[
+    ("Buy milk", false, &["errands"], Priority::Medium, Some(0), None),
+    ("Renew passport", false, &["admin"], Priority::High, Some(-3), Some("Photos are in the top drawer")),
+    ("Call mom", true, &[], Priority::Medium, None, None),
+    ("Book dentist appointment", false, &["health"], Priority::Low, Some(1), None),
+    (
+        "Read through the whole quarterly planning document and leave comments before the review meeting",
+        false,
+        &["work", "reading"],
+        Priority::Medium,
+        Some(5),
+        None,
+    ),
+    ("Café ☕ with Zoë", false, &["friends"], Priority::Low, Some(2), None),
+    ("学习日本語 — 20 minutes", true, &["learning"], Priority::Low, None, None),
+    ("Water the plants 🌱", true, &["home"], Priority::Medium, Some(-1), None),
+    ("Pay rent", false, &["home", "bills"], Priority::High, Some(6), Some("Transfer by the 1st")),
+    ("Try out every command", false, &[], Priority::Medium, None, Some("See todo-cli --help")),
+    ("Fix the flaky CI build", false, &["ci"], Priority::High, Some(0), None),
+    ("Write the release notes", true, &[], Priority::Medium, Some(-2), None),
+];
+const DEMO_OTHER_LIST: &str = "work";
+const DEMO_OTHER_COUNT: usize = 2;
+
+pub fn demo_command(
+    connection: &mut Connection,
+    list: &str,
+    force: bool,
+    today: NaiveDate,
+) -> Result<Vec<Todo>, DemoCommandError> {
+    if !force && count_todos(connection, &TodoQuery::default())? > 0 {
+        return Err(DemoCommandError::NotEmpty);
+    }
+
+    let other_from = DEMO_TODOS.len() - DEMO_OTHER_COUNT;
+    let todos = DEMO_TODOS
+        .iter()
+        .enumerate()
+        .map(|(i, &(title, done, tags, priority, due, note))| Todo {
+            done,
+            list: if i < other_from {
+                list
+            } else {
+                DEMO_OTHER_LIST
+            }
+            .into(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            priority,
+            due: due.and_then(|days| today.checked_add_signed(chrono::Duration::days(days))),
+            note: note.map(Into::into),
+            ..Todo::new(title.into())
+        })
+        .collect();
 
-        remove_command(&connection, vec![0]).unwrap();
+    in_savepoint(connection, |connection| {
+        let added = add_todos(connection, todos)?;
+        record_history(
+            connection,
+            list,
+            &format!("added {} demo todos", added.len()),
+        )?;
+        Ok(added)
+    })
+}
 
-        let todos = get_todos(&connection).unwrap();
-        assert_eq!(todos.len(), 1);
-        assert_eq!(todos[0].title, "title2");
+#[derive(thiserror::Error, Debug)]
+pub enum SetDoneCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error("No pending todo to mark as done")]
+    NoPendingTodo,
+
+    #[error("No done todo to mark as pending")]
+    NoDoneTodo,
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    UpdateTodos(#[from] UpdateTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error("Fail to mark todos atomically")]
+    Savepoint(#[from] rusqlite::Error),
+
+    #[error("Fail to append to the archive file")]
+    Archive(#[source] std::io::Error),
+}
+
+pub fn set_done_command(
+    connection: &mut Connection,
+    list: &str,
+    ids: Vec<usize>,
+    done: bool,
+    archive: Option<&Path>,
+) -> Result<Vec<Todo>, SetDoneCommandError> {
+    // The change and its history entry are written together or not at all.
+    in_savepoint(connection, |connection| {
+        let todos = set_done(connection, list, ids, done)?;
+        // Last, so a failure to write it rolls the todos back rather than leaving them unlogged.
+        archive_done(archive, &todos)?;
+        Ok(todos)
+    })
+}
+
+// Marks the todos and records it in the history. The caller holds the savepoint and writes the
+// archive file once the rest of its changes went through.
+fn set_done(
+    connection: &mut Connection,
+    list: &str,
+    ids: Vec<usize>,
+    done: bool,
+) -> Result<Vec<Todo>, SetDoneCommandError> {
+    // Without indexes it's about the todo just added, or just done when undoing.
+    let ids = if ids.is_empty() {
+        let latest = get_latest_index(connection, list, !done)?.ok_or(if done {
+            SetDoneCommandError::NoPendingTodo
+        } else {
+            SetDoneCommandError::NoDoneTodo
+        })?;
+        vec![latest]
+    } else {
+        ids
+    };
+
+    // Todos already in that state are left out, they're neither written nor reported.
+    let selected: Vec<Todo> = select_by_indexes(get_todos(connection, list)?, &ids)?
+        .into_iter()
+        .filter(|todo| todo.done != done)
+        .collect();
+    let todos: Vec<Todo> = selected
+        .into_iter()
+        .map(|todo| Todo { done, ..todo })
+        .collect();
+
+    update_todos(connection, todos.clone())?;
+    if !todos.is_empty() {
+        let state = if done { "done" } else { "pending" };
+        record_history(
+            connection,
+            list,
+            &format!("marked {} {}", todos.len(), state),
+        )?;
+    }
+    Ok(todos)
+}
+
+// Only the todos marked done get a line.
+fn archive_done(archive: Option<&Path>, todos: &[Todo]) -> Result<(), SetDoneCommandError> {
+    let completed: Vec<&Todo> = todos.iter().filter(|todo| todo.done).collect();
+    append_archive(archive, &completed).map_err(SetDoneCommandError::Archive)
+}
+
+// One write in append mode, so concurrent runs don't interleave within a line.
+fn append_archive(archive: Option<&Path>, todos: &[&Todo]) -> std::io::Result<()> {
+    match archive {
+        Some(archive) if !todos.is_empty() => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(archive)
+            .and_then(|mut file| file.write_all(archive_lines(todos, Local::now()).as_bytes())),
+        _ => Ok(()),
+    }
+}
+
+fn archive_lines(todos: &[&Todo], at: DateTime<Local>) -> String {
+    todos
+        .iter()
+        .map(|todo| {
+            let title = todo.title.split_whitespace().collect::<Vec<_>>().join(" ");
+            format!(
+                "{}\t{}\t{}\n",
+                at.to_rfc3339_opts(SecondsFormat::Secs, false),
+                todo.list,
+                title
+            )
+        })
+        .collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RemoveCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error(transparent)]
+    Confirm(#[from] ConfirmError),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    TrashTodos(#[from] TrashTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to remove atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+pub fn remove_command(
+    connection: &mut Connection,
+    list: &str,
+    indexes: Vec<usize>,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+    trash_limit: Option<usize>,
+) -> Result<Vec<Todo>, RemoveCommandError> {
+    let todos = select_by_indexes(get_todos(connection, list)?, &indexes)?;
+    confirm_above_threshold(
+        confirm,
+        confirm_threshold,
+        todos.len(),
+        &format!("Remove {} todos?", todos.len()),
+    )?;
+
+    in_savepoint(connection, |connection| {
+        trash_todos(
+            connection,
+            todos.iter().map(|todo| todo.id).collect(),
+            trash_limit,
+        )?;
+        if !todos.is_empty() {
+            record_history(connection, list, &format!("removed {}", todos.len()))?;
+        }
+        Ok(todos)
+    })
+}
+
+// The filter is narrowed down to the list, whatever list it names.
+pub fn remove_matching_command(
+    connection: &mut Connection,
+    list: &str,
+    filter: &TodoQuery,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+    trash_limit: Option<usize>,
+) -> Result<Vec<Todo>, RemoveCommandError> {
+    let query = TodoQuery {
+        list: Some(list.into()),
+        ..filter.clone()
+    };
+    let todos: Vec<Todo> = query_todos(connection, &query)?
+        .into_iter()
+        .map(|(_, todo)| todo)
+        .collect();
+    confirm_above_threshold(
+        confirm,
+        confirm_threshold,
+        todos.len(),
+        &format!("Remove {} matching todos?", todos.len()),
+    )?;
+
+    in_savepoint(connection, |connection| {
+        trash_todos(
+            connection,
+            todos.iter().map(|todo| todo.id).collect(),
+            trash_limit,
+        )?;
+        if !todos.is_empty() {
+            record_history(
+                connection,
+                list,
+                &format!("removed {} matching", todos.len()),
+            )?;
+        }
+        Ok(todos)
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TrashCommandError {
+    #[error(transparent)]
+    Confirm(#[from] ConfirmError),
+
+    #[error(transparent)]
+    GetTrash(#[from] GetTrashError),
+
+    #[error(transparent)]
+    RestoreTodos(#[from] RestoreTodosError),
+
+    #[error(transparent)]
+    EmptyTrash(#[from] EmptyTrashError),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to restore atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+pub fn trash_command(connection: &Connection, style: &Style) -> Result<(), TrashCommandError> {
+    let trash = get_trash(connection)?;
+    if trash.is_empty() {
+        println!("The trash is empty");
+        return Ok(());
+    }
+    print!("{}", format_trash(&trash, style));
+    Ok(())
+}
+
+// Numbered by their id in the trash, which is what restore takes and doesn't change as others
+// come and go.
+fn format_trash(trash: &[TrashedTodo], style: &Style) -> String {
+    let width = trash
+        .iter()
+        .map(|trashed| trashed.id.to_string().len())
+        .max();
+    trash
+        .iter()
+        .map(|trashed| {
+            let title = list_title(&trashed.todo.title, style);
+            format!(
+                "{:>width$} {}  [{}] removed {}\n",
+                trashed.id,
+                if trashed.todo.done {
+                    style.strikethrough(&title)
+                } else {
+                    title
+                },
+                escape_control(&trashed.todo.list),
+                style.date_format.timestamp(trashed.removed_at),
+                width = width.unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+// Nothing to purge asks nothing, anything to purge always asks since it can't be undone.
+pub fn empty_trash_command(
+    connection: &Connection,
+    confirm: &mut dyn Confirm,
+) -> Result<usize, TrashCommandError> {
+    let count = get_trash(connection)?.len();
+    confirm_above_threshold(
+        confirm,
+        0,
+        count,
+        &format!("Permanently delete {} trashed todos?", count),
+    )?;
+    Ok(empty_trash(connection)?)
+}
+
+pub fn restore_command(
+    connection: &mut Connection,
+    ids: &[usize],
+) -> Result<Vec<Todo>, TrashCommandError> {
+    in_savepoint(connection, |connection| {
+        let restored = restore_todos(connection, ids)?;
+        let mut todos: Vec<Todo> = query_todos(connection, &TodoQuery::default())?
+            .into_iter()
+            .map(|(_, todo)| todo)
+            .filter(|todo| restored.contains(&todo.id))
+            .collect();
+
+        let mut lists: BTreeMap<&str, usize> = BTreeMap::new();
+        for todo in &todos {
+            *lists.entry(&todo.list).or_default() += 1;
+        }
+        for (list, count) in lists {
+            record_history(connection, list, &format!("restored {}", count))?;
+        }
+
+        todos.sort_by_key(|todo| todo.id);
+        Ok(todos)
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ClearCommandError {
+    #[error(transparent)]
+    Confirm(#[from] ConfirmError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to clear atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+pub fn clear_command(
+    connection: &mut Connection,
+    list: &str,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+) -> Result<Vec<Todo>, ClearCommandError> {
+    in_savepoint(connection, |connection| {
+        let todos = clear_done(connection, list, confirm, confirm_threshold)?;
+        if !todos.is_empty() {
+            record_history(connection, list, &format!("cleared {} done", todos.len()))?;
+        }
+        Ok(todos)
+    })
+}
+
+fn clear_done(
+    repository: &mut dyn TodoRepository,
+    list: &str,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+) -> Result<Vec<Todo>, ClearCommandError> {
+    let todos: Vec<Todo> = repository
+        .all(list)?
+        .done()
+        .map(|(_, todo)| todo.clone())
+        .collect();
+    confirm_above_threshold(
+        confirm,
+        confirm_threshold,
+        todos.len(),
+        &format!("Clear {} done todos?", todos.len()),
+    )?;
+
+    repository.remove(todos.iter().map(|todo| todo.id).collect())?;
+    Ok(todos)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CleanCommandError {
+    #[error(
+        "Reviewing the done todos one at a time needs a terminal, use clear to remove them all"
+    )]
+    NotTerminal,
+
+    #[error("Fail to ask about a todo")]
+    Prompt(#[from] std::io::Error),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    RemoveTodos(#[from] RemoveTodoError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error("Fail to append to the archive file")]
+    Archive(#[source] std::io::Error),
+
+    #[error("Fail to clean atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CleanChoice {
+    Keep,
+    Delete,
+    Archive,
+}
+
+impl CleanChoice {
+    fn name(self) -> &'static str {
+        match self {
+            CleanChoice::Keep => "keep",
+            CleanChoice::Delete => "delete",
+            CleanChoice::Archive => "archive",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CleanSummary {
+    pub deleted: Vec<Todo>,
+    pub archived: Vec<Todo>,
+    pub kept: usize,
+}
+
+// Nothing is written until every done todo has an answer, so quitting halfway, or the input
+// ending, leaves the list as it was and gives None. Enter repeats the previous choice.
+pub fn clean_command(
+    connection: &mut Connection,
+    list: &str,
+    archive: Option<&Path>,
+    input: &mut dyn BufRead,
+    prompt: &mut dyn Write,
+) -> Result<Option<CleanSummary>, CleanCommandError> {
+    let todos: Vec<Todo> = get_todos(connection, list)?
+        .done()
+        .map(|(_, todo)| todo.clone())
+        .collect();
+    let archive_option = if archive.is_some() { ", a(r)chive" } else { "" };
+
+    let mut choice = CleanChoice::Keep;
+    let mut all = false;
+    let mut summary = CleanSummary::default();
+    for (i, todo) in todos.iter().enumerate() {
+        while !all {
+            write!(
+                prompt,
+                "[{}/{}] {} - (k)eep, (d)elete{}, (a)ll the rest, (q)uit [{}] ",
+                i + 1,
+                todos.len(),
+                escape_control(&normalize_title(&todo.title)),
+                archive_option,
+                choice.name()
+            )?;
+            prompt.flush()?;
+
+            let mut answer = String::new();
+            if input.read_line(&mut answer)? == 0 {
+                return Ok(None);
+            }
+            match answer.trim().to_lowercase().as_str() {
+                "" => {}
+                "k" | "keep" => choice = CleanChoice::Keep,
+                "d" | "delete" => choice = CleanChoice::Delete,
+                "r" | "archive" if archive.is_some() => choice = CleanChoice::Archive,
+                "a" | "all" => all = true,
+                "q" | "quit" => return Ok(None),
+                other => {
+                    writeln!(prompt, "Unknown answer '{}'", other)?;
+                    continue;
+                }
+            }
+            break;
+        }
+
+        match choice {
+            CleanChoice::Keep => summary.kept += 1,
+            CleanChoice::Delete => summary.deleted.push(todo.clone()),
+            CleanChoice::Archive => summary.archived.push(todo.clone()),
+        }
+    }
+
+    let removed: Vec<usize> = summary
+        .deleted
+        .iter()
+        .chain(&summary.archived)
+        .map(|todo| todo.id)
+        .collect();
+    if removed.is_empty() {
+        return Ok(Some(summary));
+    }
+
+    in_savepoint(connection, |connection| {
+        remove_todos(connection, removed.clone())?;
+        record_history(connection, list, &format!("cleaned {} done", removed.len()))?;
+
+        // Last, so a failure to write it rolls the todos back rather than losing them.
+        let archived: Vec<&Todo> = summary.archived.iter().collect();
+        append_archive(archive, &archived).map_err(CleanCommandError::Archive)?;
+        Ok::<_, CleanCommandError>(())
+    })?;
+
+    Ok(Some(summary))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DoneAndClearCommandError {
+    #[error(transparent)]
+    SetDone(#[from] SetDoneCommandError),
+
+    #[error(transparent)]
+    Clear(#[from] ClearCommandError),
+
+    #[error("Fail to run the operation atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+pub fn done_and_clear_command(
+    connection: &mut Connection,
+    list: &str,
+    ids: Vec<usize>,
+    archive: Option<&Path>,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+) -> Result<(Vec<Todo>, Vec<Todo>), DoneAndClearCommandError> {
+    in_savepoint(connection, |connection| {
+        let updated = set_done(connection, list, ids, true)?;
+        let cleared = clear_command(connection, list, confirm, confirm_threshold)?;
+        // Only once the clear went through, so a declined or failed one logs nothing.
+        archive_done(archive, &updated)?;
+        Ok((updated, cleared))
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoveTarget {
+    Index(usize),
+    Top,
+    Bottom,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum MoveCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    ReorderTodos(#[from] ReorderTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to move atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+pub fn move_command(
+    connection: &mut Connection,
+    list: &str,
+    index: usize,
+    target: MoveTarget,
+) -> Result<Todo, MoveCommandError> {
+    let mut todos = get_todos(connection, list)?;
+    if index >= todos.len() {
+        return Err(InvalidIndexError(index).into());
+    }
+
+    let to = match target {
+        MoveTarget::Index(to) if to >= todos.len() => return Err(InvalidIndexError(to).into()),
+        MoveTarget::Index(to) => to,
+        MoveTarget::Top => 0,
+        MoveTarget::Bottom => todos.len() - 1,
+    };
+
+    let todo = todos.remove(index);
+    todos.insert(to, todo.clone());
+    if index != to {
+        in_savepoint(connection, |connection| {
+            reorder_todos(connection, todos.iter().map(|todo| todo.id).collect())?;
+            record_history(connection, list, &format!("moved {} to {}", index, to))?;
+            Ok::<_, MoveCommandError>(())
+        })?;
+    }
+    Ok(todo)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransferCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    MoveToList(#[from] MoveToListError),
+
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+    #[error("Fail to transfer todos atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+// The todos keep their order and go to the bottom of the target list.
+pub fn move_to_command(
+    connection: &mut Connection,
+    list: &str,
+    target: &str,
+    indexes: Vec<usize>,
+) -> Result<Vec<Todo>, TransferCommandError> {
+    let todos = select_by_indexes(get_todos(connection, list)?, &indexes)?;
+    in_savepoint(connection, |connection| {
+        move_to_list(
+            connection,
+            todos.iter().map(|todo| todo.id).collect(),
+            target,
+        )?;
+        record_history(
+            connection,
+            list,
+            &format!("moved {} to {}", todos.len(), target),
+        )?;
+        Ok(todos)
+    })
+}
+
+// The copies are new todos with UUIDs of their own, so they're no longer subtasks either.
+pub fn copy_to_command(
+    connection: &mut Connection,
+    list: &str,
+    target: &str,
+    indexes: Vec<usize>,
+    keep_done: bool,
+) -> Result<Vec<Todo>, TransferCommandError> {
+    in_savepoint(connection, |connection| {
+        let copied = copy_todos(connection, list, target, indexes, keep_done)?;
+        record_history(
+            connection,
+            list,
+            &format!("copied {} to {}", copied.len(), target),
+        )?;
+        Ok(copied)
+    })
+}
+
+// Copies are new todos, with UUIDs and timestamps of their own.
+fn copy_todos(
+    repository: &mut dyn TodoRepository,
+    list: &str,
+    target: &str,
+    indexes: Vec<usize>,
+    keep_done: bool,
+) -> Result<Vec<Todo>, TransferCommandError> {
+    let copies = select_by_indexes(repository.all(list)?, &indexes)?
+        .into_iter()
+        .map(|todo| Todo {
+            list: target.into(),
+            done: keep_done && todo.done,
+            tags: todo.tags,
+            priority: todo.priority,
+            due: todo.due,
+            note: todo.note,
+            label: todo.label,
+            ..Todo::new(todo.title)
+        })
+        .collect();
+    Ok(repository.add(copies)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListsCommandError {
+    #[error(transparent)]
+    Confirm(#[from] ConfirmError),
+
+    #[error("List name can't be empty")]
+    EmptyName,
+
+    #[error("There is no list named '{0}'")]
+    UnknownList(String),
+
+    #[error("A list named '{0}' already exists, pass --merge to move the todos into it")]
+    ListExists(String),
+
+    #[error(transparent)]
+    GetLists(#[from] GetCandidatesError),
+
+    #[error(transparent)]
+    GetStats(#[from] GetStatsError),
+
+    #[error("Fail to serialize the lists")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    MoveToList(#[from] MoveToListError),
+
+    #[error(transparent)]
+    RenameList(#[from] RenameListError),
+
+    #[error(transparent)]
+    TrashTodos(#[from] TrashTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error("Fail to change the list atomically")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+// The active and default lists show up even before they have todos, so there's always a line.
+pub fn lists_command(
+    connection: &Connection,
+    active: &str,
+    default: &str,
+    json: bool,
+) -> Result<(), ListsCommandError> {
+    let mut stats = get_list_stats(connection)?;
+    for name in [active, default] {
+        if !stats.iter().any(|list| list.name == name) {
+            stats.push(ListStats {
+                name: name.into(),
+                pending: 0,
+                done: 0,
+            });
+        }
+    }
+    stats.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if json {
+        println!("{}", serde_json::to_string(&stats)?);
+    } else {
+        print!("{}", format_lists(&stats, active, default));
+    }
+    Ok(())
+}
+
+fn format_lists(stats: &[ListStats], active: &str, default: &str) -> String {
+    let width = stats.iter().map(|list| list.name.chars().count()).max();
+    stats
+        .iter()
+        .map(|list| {
+            format!(
+                "{} {:<width$}  {} pending, {} done{}\n",
+                if list.name == active { "*" } else { " " },
+                escape_control(&list.name),
+                list.pending,
+                list.done,
+                if list.name == default {
+                    " (default)"
+                } else {
+                    ""
+                },
+                width = width.unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+// Nothing else refers to a list, so once its todos are in the trash so is the list. Restoring them
+// brings it back.
+pub fn delete_list_command(
+    connection: &mut Connection,
+    list: &str,
+    confirm: &mut dyn Confirm,
+    confirm_threshold: usize,
+    trash_limit: Option<usize>,
+) -> Result<Vec<Todo>, ListsCommandError> {
+    let todos = get_todos(connection, list)?.into_inner();
+    if todos.is_empty() {
+        return Err(ListsCommandError::UnknownList(list.into()));
+    }
+    confirm_above_threshold(
+        confirm,
+        confirm_threshold,
+        todos.len(),
+        &format!("Delete {} with {} todos?", list, todos.len()),
+    )?;
+
+    in_savepoint(connection, |connection| {
+        trash_todos(
+            connection,
+            todos.iter().map(|todo| todo.id).collect(),
+            trash_limit,
+        )?;
+        record_history(
+            connection,
+            list,
+            &format!("deleted the list with {} todos", todos.len()),
+        )?;
+        Ok(todos)
+    })
+}
+
+// Merged todos go to the bottom of the target list, as with move-to.
+pub fn rename_list_command(
+    connection: &mut Connection,
+    from: &str,
+    to: &str,
+    merge: bool,
+) -> Result<usize, ListsCommandError> {
+    let to = to.trim();
+    if to.is_empty() {
+        return Err(ListsCommandError::EmptyName);
+    }
+    let lists = get_list_names(connection)?;
+    if !lists.iter().any(|list| list == from) {
+        return Err(ListsCommandError::UnknownList(from.into()));
+    }
+    if from == to {
+        return Ok(0);
+    }
+    let exists = lists.iter().any(|list| list == to);
+    if exists && !merge {
+        return Err(ListsCommandError::ListExists(to.into()));
+    }
+
+    in_savepoint(connection, |connection| {
+        let renamed = if exists {
+            let ids: Vec<usize> = get_todos(connection, from)?
+                .iter()
+                .map(|todo| todo.id)
+                .collect();
+            let moved = move_to_list(connection, ids, to)?.len();
+            rename_list(connection, from, to)?;
+            moved
+        } else {
+            rename_list(connection, from, to)?
+        };
+        record_history(connection, to, &format!("renamed {} to {}", from, to))?;
+        Ok(renamed)
+    })
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PostponeCommandError {
+    #[error(transparent)]
+    InvalidIndex(#[from] InvalidIndexError),
+
+    #[error("Fail to postpone '{0}' that far")]
+    OutOfRange(String),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    UpdateTodos(#[from] UpdateTodosError),
+
+    #[error(transparent)]
+    RecordHistory(#[from] RecordHistoryError),
+
+    #[error("Fail to postpone todos together")]
+    Savepoint(#[from] rusqlite::Error),
+}
+
+struct Postponed {
+    index: usize,
+    from: Option<NaiveDate>,
+    todo: Todo,
+}
+
+// Todos without a due date are counted from today.
+fn plan_postpone(
+    todos: Vec<(usize, Todo)>,
+    span: Span,
+    today: NaiveDate,
+) -> Result<Vec<Postponed>, PostponeCommandError> {
+    todos
+        .into_iter()
+        .map(|(index, todo)| {
+            let due = span
+                .after(todo.due.unwrap_or(today))
+                .ok_or_else(|| PostponeCommandError::OutOfRange(todo.title.clone()))?;
+            Ok(Postponed {
+                index,
+                from: todo.due,
+                todo: Todo {
+                    due: Some(due),
+                    ..todo
+                },
+            })
+        })
+        .collect()
+}
+
+pub fn postpone_command(
+    connection: &mut Connection,
+    list: &str,
+    ids: Vec<usize>,
+    overdue: bool,
+    span: Span,
+    dry_run: bool,
+    style: &Style,
+) -> Result<usize, PostponeCommandError> {
+    let today = Local::now().date_naive();
+    let query = TodoQuery::list(list);
+    let todos = if overdue {
+        query_overdue_todos(connection, &query, today)?
+    } else {
+        select_by_indexes(query_todos(connection, &query)?, &ids)?
+    };
+    let postponed = plan_postpone(todos, span, today)?;
+
+    if postponed.is_empty() {
+        println!("Nothing to postpone");
+        return Ok(0);
+    }
+
+    if !dry_run {
+        in_savepoint(connection, |connection| {
+            let todos = postponed.iter().map(|postponed| postponed.todo.clone());
+            update_todos(connection, todos.collect())?;
+            record_history(connection, list, &format!("postponed {}", postponed.len()))?;
+            Ok::<_, PostponeCommandError>(())
+        })?;
+    }
+
+    let verb = if dry_run {
+        "Would postpone"
+    } else {
+        "Postponed"
+    };
+    match postponed.len() {
+        1 => println!("{} 1 todo", verb),
+        count => println!("{} {} todos", verb, count),
+    }
+    for Postponed { index, from, todo } in &postponed {
+        let from = from.map_or_else(
+            || "no date".to_string(),
+            |from| style.date_format.date(from),
+        );
+        let to = todo.due.expect("postponed todos have a due date");
+        let to = style.date_format.date(to);
+        let index = index + style.line_numbers_from;
+        let title = list_title(&todo.title, style);
+        println!("{}: {} ({} -> {})", index, title, from, to);
+    }
+
+    Ok(postponed.len())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PrintCommandError {
+    #[error(transparent)]
+    CreateTable(#[from] CreateTableError),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error("Fail to serialize todos")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Fail to print the todos")]
+    Write(#[from] std::io::Error),
+}
+
+#[derive(Serialize)]
+pub struct IndexedTodo {
+    index: usize,
+
+    #[serde(flatten)]
+    todo: TodoDto,
+}
+
+fn format_todos(
+    todos: &[(usize, Todo)],
+    show_list: bool,
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) -> Vec<String> {
+    if style.accessible {
+        return format_spoken(todos, show_list, options, style, today);
+    }
+
+    let anchor = relative_anchor(todos, options);
+    todos
+        .iter()
+        .map(|(i, todo)| {
+            let i = index_label(*i, anchor, style.line_numbers_from);
+            let new = match (options.new_since, todo.created_at) {
+                (Some(since), Some(created_at)) if created_at > since => {
+                    format!("{} ", style.bold("*"))
+                }
+                _ => String::new(),
+            };
+            // Kept out of the line so an overdue line colored red doesn't reset midway.
+            let label = todo
+                .label
+                .map(|label| format!("{} ", style.label(label)))
+                .unwrap_or_default();
+            let list = if show_list {
+                format!("[{}] ", escape_control(&todo.list))
+            } else {
+                String::new()
+            };
+            let id = if options.show_id {
+                format!(" (id {})", todo.id)
+            } else {
+                String::new()
+            };
+            let tags = format_tags(&todo.tags);
+            let title = list_title(&todo.title, style);
+            // The priority shows in how the title is painted or marked instead.
+            let details = format_details(todo, false, &style.date_format);
+            let details = match (&todo.note, options.show_note) {
+                (Some(note), true) => {
+                    format!("{} - {}", details, preview(note, options.note_width, style))
+                }
+                _ => details,
+            };
+
+            let line = if todo.done {
+                format!(
+                    "{}{}{}: {}{}{}",
+                    list,
+                    i,
+                    id,
+                    style.strikethrough(&title),
+                    tags,
+                    details
+                )
+            } else {
+                let overdue = todo.is_overdue(today);
+                let due_today = todo.is_due_today(today);
+                // A line colored as a whole keeps its color, the marks work either way.
+                let title = if style.color && (overdue || due_today) {
+                    title
+                } else {
+                    style.priority(todo.priority, &title)
+                };
+                let line = format!("{}{}{}: {}{}{}", list, i, id, title, tags, details);
+
+                if overdue {
+                    if style.color {
+                        style.red(&line)
+                    } else {
+                        format!("! {}", line)
+                    }
+                } else if due_today {
+                    style.yellow(&line)
+                } else {
+                    line
+                }
+            };
+            format!("{}{}{}", new, label, line)
+        })
+        .collect()
+}
+
+// Like format_todos, with what the marks and colors mean said in words before the title, as in
+// "3. pending, overdue: call mom".
+fn format_spoken(
+    todos: &[(usize, Todo)],
+    show_list: bool,
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) -> Vec<String> {
+    let anchor = relative_anchor(todos, options);
+    todos
+        .iter()
+        .map(|(i, todo)| {
+            let i = index_label(*i, anchor, style.line_numbers_from);
+            let mut status = vec![if todo.done { "done" } else { "pending" }.to_string()];
+            if todo.is_overdue(today) {
+                status.push("overdue".into());
+            } else if todo.is_due_today(today) {
+                status.push("due today".into());
+            }
+            if let (Some(since), Some(created_at)) = (options.new_since, todo.created_at) {
+                if created_at > since {
+                    status.push("new".into());
+                }
+            }
+            if let Some(label) = todo.label {
+                status.push(format!("{} label", label));
+            }
+
+            let list = if show_list {
+                format!("list {}, ", escape_control(&todo.list))
+            } else {
+                String::new()
+            };
+            let id = if options.show_id {
+                format!(" (id {})", todo.id)
+            } else {
+                String::new()
+            };
+            let tags = format_tags(&todo.tags);
+            let details = format_details(todo, true, &style.date_format);
+            let details = match (&todo.note, options.show_note) {
+                (Some(note), true) => {
+                    format!("{} - {}", details, preview(note, options.note_width, style))
+                }
+                _ => details,
+            };
+
+            format!(
+                "{}{}{}. {}: {}{}{}",
+                list,
+                i,
+                id,
+                status.join(", "),
+                list_title(&todo.title, style),
+                tags,
+                details
+            )
+        })
+        .collect()
+}
+
+fn format_tags(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!(" #{}", escape_control(tag)))
+        .collect()
+}
+
+// A title with line breaks would break the layout, so lists show its first line and mark the
+// rest as cut. Show prints it whole.
+fn list_title(title: &str, style: &Style) -> String {
+    let title = title.trim_end_matches(['\r', '\n']);
+    match title.split_once(['\r', '\n']) {
+        Some((first, _)) => format!("{}{}", escape_control(first.trim_end()), style.ellipsis()),
+        None => escape_control(title),
+    }
+}
+
+// The index of the first pending todo, which --relative counts from. Done and remove still
+// take absolute indexes, so that one is shown as is to work them out from.
+fn relative_anchor(todos: &[(usize, Todo)], options: &PrintOptions) -> Option<usize> {
+    todos
+        .iter()
+        .find(|(_, todo)| options.relative && !todo.done)
+        .map(|(i, _)| *i)
+}
+
+fn index_label(index: usize, anchor: Option<usize>, base: usize) -> String {
+    match anchor {
+        Some(anchor) if index < anchor => format!("-{}", anchor - index),
+        Some(anchor) if index > anchor => format!("+{}", index - anchor),
+        _ => (index + base).to_string(),
+    }
+}
+
+fn preview(note: &str, width: usize, style: &Style) -> String {
+    let mut lines = note.trim().lines();
+    let first_line = escape_control(lines.next().unwrap_or_default().trim_end());
+
+    if first_line.chars().count() > width {
+        let kept: String = first_line.chars().take(width.saturating_sub(1)).collect();
+        format!("{}{}", kept.trim_end(), style.ellipsis())
+    } else if lines.next().is_some() {
+        format!("{}{}", first_line, style.ellipsis())
+    } else {
+        first_line
+    }
+}
+
+// Control characters like newlines in a title become spaces, so each todo stays on one line
+// and the index is always the first word.
+fn format_oneline(todos: &[(usize, Todo)], base: usize) -> Vec<String> {
+    todos
+        .iter()
+        .map(|(i, todo)| {
+            let title: String = todo
+                .title
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            let done = if todo.done { " (done)" } else { "" };
+            format!("{} {}{}", i + base, title, done)
+        })
+        .collect()
+}
+
+// Todos whose parent isn't among them, like when it was removed, show at the top level. Every
+// todo is drawn once, so a cycle from an import is cut where it was first reached.
+fn format_tree(
+    todos: &[(usize, Todo)],
+    show_list: bool,
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) -> Vec<String> {
+    let lines = format_todos(todos, show_list, options, style, today);
+    let positions: HashMap<&str, usize> = todos
+        .iter()
+        .enumerate()
+        .map(|(position, (_, todo))| (todo.uuid.as_str(), position))
+        .collect();
+    let mut children = vec![Vec::new(); todos.len()];
+    let mut roots = Vec::new();
+    for (position, (_, todo)) in todos.iter().enumerate() {
+        match todo
+            .parent
+            .as_deref()
+            .and_then(|parent| positions.get(parent))
+        {
+            Some(&parent) if parent != position => children[parent].push(position),
+            _ => roots.push(position),
+        }
+    }
+
+    let (branch, last) = (style.marker("├─ ", "|- "), style.marker("└─ ", "`- "));
+    let (pipe, space) = (style.marker("│  ", "|  "), "   ");
+    let mut visited = vec![false; todos.len()];
+    let mut tree = Vec::with_capacity(todos.len());
+
+    // Whatever the roots don't reach hangs off a cycle.
+    for root in roots.into_iter().chain(0..todos.len()) {
+        let mut stack = vec![(root, String::new(), String::new())];
+        while let Some((position, guide, indent)) = stack.pop() {
+            if visited[position] {
+                continue;
+            }
+            visited[position] = true;
+            tree.push(format!("{}{}", guide, lines[position]));
+
+            let unvisited: Vec<usize> = children[position]
+                .iter()
+                .copied()
+                .filter(|&child| !visited[child])
+                .collect();
+            for (i, &child) in unvisited.iter().enumerate().rev() {
+                let (guide, next) = if i + 1 == unvisited.len() {
+                    (last, space)
+                } else {
+                    (branch, pipe)
+                };
+                stack.push((
+                    child,
+                    format!("{}{}", indent, guide),
+                    format!("{}{}", indent, next),
+                ));
+            }
+        }
+    }
+
+    tree
+}
+
+fn render_todos(
+    todos: &[(usize, Todo)],
+    show_list: bool,
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) -> Vec<String> {
+    if options.oneline {
+        return format_oneline(todos, style.line_numbers_from);
+    }
+    if options.tree {
+        return format_tree(todos, show_list, options, style, today);
+    }
+    if !options.table && options.columns.is_empty() {
+        return format_todos(todos, show_list, options, style, today);
+    }
+
+    let columns = if options.columns.is_empty() {
+        let mut columns = vec![Column::Index];
+        if options.show_id {
+            columns.push(Column::Id);
+        }
+        if show_list {
+            columns.push(Column::List);
+        }
+        columns.extend([
+            Column::Done,
+            Column::Priority,
+            Column::Due,
+            Column::Title,
+            Column::Tags,
+        ]);
+        if options.show_note {
+            columns.push(Column::Note);
+        }
+        columns
+    } else {
+        options.columns.clone()
+    };
+
+    format_table(todos, &columns, options, style, today)
+}
+
+fn format_table(
+    todos: &[(usize, Todo)],
+    columns: &[Column],
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) -> Vec<String> {
+    let header: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let (name, _) = COLUMNS.iter().find(|(_, c)| c == column).unwrap();
+            name.to_uppercase()
+        })
+        .collect();
+    let rows: Vec<Vec<String>> = todos
+        .iter()
+        .map(|(i, todo)| {
+            columns
+                .iter()
+                .map(|column| match column {
+                    Column::Index => (i + style.line_numbers_from).to_string(),
+                    Column::Id => todo.id.to_string(),
+                    Column::List => escape_control(&todo.list),
+                    Column::Done => if todo.done { "x" } else { "" }.into(),
+                    Column::Priority => todo.priority.to_string(),
+                    Column::Due => todo
+                        .due
+                        .map(|due| style.date_format.date(due))
+                        .unwrap_or_default(),
+                    Column::Title => list_title(&todo.title, style),
+                    Column::Tags => format_tags(&todo.tags).trim_start().to_string(),
+                    Column::Note => todo
+                        .note
+                        .as_deref()
+                        .map(|note| preview(note, options.note_width, style))
+                        .unwrap_or_default(),
+                    Column::Label => todo
+                        .label
+                        .map(|label| label.to_string())
+                        .unwrap_or_default(),
+                })
+                .collect()
+        })
+        .collect();
+
+    let widths: Vec<usize> = (0..columns.len())
+        .map(|column| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let format_row = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        cells.join("  ").trim_end().to_string()
+    };
+
+    std::iter::once(format_row(&header))
+        .chain(rows.iter().zip(todos).map(|(row, (_, todo))| {
+            let line = format_row(row);
+            if todo.is_overdue(today) {
+                style.red(&line)
+            } else if todo.is_due_today(today) {
+                style.yellow(&line)
+            } else {
+                line
+            }
+        }))
+        .collect()
+}
+
+fn overdue_summary(todos: &[(usize, Todo)], style: &Style, today: NaiveDate) -> Option<String> {
+    let overdue = todos
+        .iter()
+        .filter(|(_, todo)| todo.is_overdue(today))
+        .count();
+
+    match overdue {
+        0 => None,
+        _ if style.accessible => Some(format!("{} overdue.", todos_are(overdue))),
+        1 => Some(style.red("1 item overdue")),
+        _ => Some(style.red(&format!("{} items overdue", overdue))),
+    }
+}
+
+fn format_details(todo: &Todo, with_priority: bool, date_format: &DateFormat) -> String {
+    let mut details = Vec::new();
+
+    if with_priority && todo.priority != Priority::Medium {
+        details.push(todo.priority.to_string());
+    }
+    if let Some(due) = todo.due {
+        details.push(format!("due {}", date_format.date(due)));
+    }
+
+    if details.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", details.join(", "))
+    }
+}
+
+// Reports whether anything matched, whether or not it was printed.
+pub fn search_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    quiet: bool,
+    options: &PrintOptions,
+    style: &Style,
+) -> Result<bool, PrintCommandError> {
+    let found = count_todos(connection, query)? > 0;
+    if !quiet {
+        print_command(
+            connection,
+            query,
+            options,
+            style,
+            &mut std::io::stdout().lock(),
+        )?;
+    }
+    Ok(found)
+}
+
+pub fn print_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    options: &PrintOptions,
+    style: &Style,
+    out: &mut dyn Write,
+) -> Result<(), PrintCommandError> {
+    // Written record by record as the rows are read, so a consumer can start on the first todo
+    // right away and the list never has to fit in memory. Tail and grouping need all of it.
+    let write_record = |out: &mut dyn Write, index, todo: Todo| -> Result<(), PrintCommandError> {
+        let record = IndexedTodo {
+            index,
+            todo: todo.into(),
+        };
+        serde_json::to_writer(&mut *out, &record)?;
+        writeln!(out)?;
+        Ok(())
+    };
+    if options.ndjson && options.tail.is_none() && !options.group_done_last {
+        return for_each_todo(connection, query, |index, todo| {
+            write_record(out, index, todo)
+        });
+    }
+
+    let mut todos = tail(query_todos(connection, query)?, options.tail);
+    if options.group_done_last {
+        todos = group_done_last(todos);
+    }
+
+    if options.ndjson {
+        for (index, todo) in todos {
+            write_record(out, index, todo)?;
+        }
+        return Ok(());
+    }
+
+    if options.json {
+        writeln!(out, "{}", serde_json::to_string(&indexed(&todos))?)?;
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    if let Some(summary) = overdue_summary(&todos, style, today).filter(|_| !options.oneline) {
+        writeln!(out, "{}", summary)?;
+    }
+    for line in render_todos(&todos, query.list.is_none(), options, style, today) {
+        writeln!(out, "{}", line)?;
+    }
+
+    if options.progress && !todos.is_empty() {
+        let done = todos.iter().filter(|(_, todo)| todo.done).count();
+        if style.accessible {
+            writeln!(out, "{} of {} done.", done, todos_are(todos.len()))?;
+        } else {
+            writeln!(
+                out,
+                "{} {}/{} done",
+                style.progress_bar(done, todos.len(), PROGRESS_WIDTH),
+                done,
+                todos.len()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+const PROGRESS_WIDTH: usize = 20;
+
+// The indexes come along with the todos, so they still name the same todos.
+fn tail(mut todos: Vec<(usize, Todo)>, count: Option<usize>) -> Vec<(usize, Todo)> {
+    if let Some(count) = count {
+        todos.drain(..todos.len().saturating_sub(count));
+    }
+    todos
+}
+
+// A stable partition, the indexes going along so done and remove still read the printed ones.
+fn group_done_last(todos: Vec<(usize, Todo)>) -> Vec<(usize, Todo)> {
+    let (pending, done): (Vec<_>, Vec<_>) = todos.into_iter().partition(|(_, todo)| !todo.done);
+    pending.into_iter().chain(done).collect()
+}
+
+fn todos_are(count: usize) -> String {
+    match count {
+        1 => "1 todo is".into(),
+        _ => format!("{} todos are", count),
+    }
+}
+
+#[derive(Serialize)]
+struct TodosEvent {
+    at: DateTime<Utc>,
+    todos: Vec<IndexedTodo>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum WriteJsonEventError {
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error("Fail to serialize todos")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Fail to write the event")]
+    Write(#[from] std::io::Error),
+}
+
+// One document per line, flushed right away so a reader sees each change as it happens.
+pub fn write_json_event(
+    connection: &Connection,
+    query: &TodoQuery,
+    out: &mut dyn Write,
+) -> Result<(), WriteJsonEventError> {
+    let todos = query_todos(connection, query)?;
+    let event = TodosEvent {
+        at: Utc::now(),
+        todos: indexed(&todos),
+    };
+    serde_json::to_writer(&mut *out, &event)?;
+    writeln!(out)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportCommandError {
+    #[error("Invalid range, {since} comes after {until}")]
+    InvertedRange { since: NaiveDate, until: NaiveDate },
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error("Fail to serialize todos")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Fail to write the todos")]
+    Write(#[from] std::io::Error),
+}
+
+// The days are local, both ends included.
+pub fn export_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    since: Option<NaiveDate>,
+    until: Option<NaiveDate>,
+    by: ExportBy,
+    out: &mut dyn Write,
+) -> Result<(), ExportCommandError> {
+    if let (Some(since), Some(until)) = (since, until) {
+        if since > until {
+            return Err(ExportCommandError::InvertedRange { since, until });
+        }
+    }
+
+    let from = since.map(start_of_day);
+    let before = until.and_then(|until| until.succ_opt()).map(start_of_day);
+    let query = match by {
+        ExportBy::Created => TodoQuery {
+            created_from: from,
+            created_before: before,
+            ..query.clone()
+        },
+        ExportBy::Completed => TodoQuery {
+            done_from: from,
+            done_before: before,
+            ..query.clone()
+        },
+    };
+
+    let todos: Vec<TodoDto> = query_todos(connection, &query)?
+        .into_iter()
+        .map(|(_, todo)| todo.into())
+        .collect();
+    serde_json::to_writer(&mut *out, &todos)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+// A midnight skipped by a DST change falls back to the first time the day has.
+fn start_of_day(day: NaiveDate) -> DateTime<Utc> {
+    let midnight = day.and_time(NaiveTime::MIN);
+    midnight
+        .and_local_timezone(Local)
+        .earliest()
+        .map_or_else(|| midnight.and_utc(), |at| at.with_timezone(&Utc))
+}
+
+pub fn next_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    count: Option<usize>,
+    options: &PrintOptions,
+    style: &Style,
+) -> Result<(), PrintCommandError> {
+    let todos = query_next_todos(connection, query, count.unwrap_or(1))?;
+
+    if options.json {
+        let mut todos = indexed(&todos).into_iter();
+        // A single todo, or null, unless several were asked for.
+        let json = match count {
+            Some(_) => serde_json::to_string(&todos.collect::<Vec<_>>())?,
+            None => serde_json::to_string(&todos.next())?,
+        };
+        println!("{}", json);
+        return Ok(());
+    }
+
+    if todos.is_empty() {
+        println!("all done{}", style.marker(" 🎉", ""));
+        return Ok(());
+    }
+
+    let today = Local::now().date_naive();
+    for line in render_todos(&todos, query.list.is_none(), options, style, today) {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+pub fn overdue_phrase(due: NaiveDate, today: NaiveDate) -> String {
+    match (today - due).num_days() {
+        1 => "1 day overdue".into(),
+        days => format!("{} days overdue", days),
+    }
+}
+
+pub fn overdue_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    options: &PrintOptions,
+    style: &Style,
+) -> Result<(), PrintCommandError> {
+    let today = Local::now().date_naive();
+    let todos = query_overdue_todos(connection, query, today)?;
+
+    if options.json {
+        println!("{}", serde_json::to_string(&indexed(&todos))?);
+        return Ok(());
+    }
+
+    // Only the default format gets the phrase, the others print the way they do for print.
+    if options.oneline || options.tree || options.table || !options.columns.is_empty() {
+        for line in render_todos(&todos, query.list.is_none(), options, style, today) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    let lines = format_todos(&todos, query.list.is_none(), options, style, today);
+    for (line, (_, todo)) in lines.iter().zip(&todos) {
+        let due = todo.due.expect("overdue todos have a due date");
+        println!("{} - {}", line, overdue_phrase(due, today));
+    }
+
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct Agenda {
+    overdue: Vec<IndexedTodo>,
+    due_today: Vec<IndexedTodo>,
+    high_priority: Vec<IndexedTodo>,
+}
+
+fn get_agenda(
+    connection: &Connection,
+    query: &TodoQuery,
+    today: NaiveDate,
+) -> Result<[Vec<(usize, Todo)>; 3], GetTodosError> {
+    let overdue = query_overdue_todos(connection, query, today)?;
+    let due_today = query_todos(
+        connection,
+        &TodoQuery {
+            done: Some(false),
+            due_on: Some(today),
+            ..query.clone()
+        },
+    )?;
+    // High priority todos already listed as overdue or due today aren't repeated.
+    let high_priority = query_todos(
+        connection,
+        &TodoQuery {
+            done: Some(false),
+            min_priority: Some(Priority::High),
+            ..query.clone()
+        },
+    )?
+    .into_iter()
+    .filter(|(_, todo)| !todo.is_overdue(today) && !todo.is_due_today(today))
+    .collect();
+
+    Ok([overdue, due_today, high_priority])
+}
+
+pub fn indexed(todos: &[(usize, Todo)]) -> Vec<IndexedTodo> {
+    todos
+        .iter()
+        .map(|(index, todo)| IndexedTodo {
+            index: *index,
+            todo: todo.clone().into(),
+        })
+        .collect()
+}
+
+pub fn today_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    options: &PrintOptions,
+    style: &Style,
+) -> Result<(), PrintCommandError> {
+    let today = Local::now().date_naive();
+    let sections = get_agenda(connection, query, today)?;
+
+    if options.json {
+        let [overdue, due_today, high_priority] = &sections;
+        let agenda = Agenda {
+            overdue: indexed(overdue),
+            due_today: indexed(due_today),
+            high_priority: indexed(high_priority),
+        };
+        println!("{}", serde_json::to_string(&agenda)?);
+        return Ok(());
+    }
+
+    if sections.iter().all(Vec::is_empty) {
+        println!("Nothing scheduled for today");
+        return Ok(());
+    }
+
+    let sections = ["Overdue", "Due today", "High priority"]
+        .iter()
+        .zip(&sections)
+        .filter(|(_, todos)| !todos.is_empty())
+        .map(|(title, todos)| (title.to_string(), todos.as_slice()));
+    print_sections(sections, query.list.is_none(), options, style, today);
+
+    Ok(())
+}
+
+fn print_sections<'a>(
+    sections: impl Iterator<Item = (String, &'a [(usize, Todo)])>,
+    show_list: bool,
+    options: &PrintOptions,
+    style: &Style,
+    today: NaiveDate,
+) {
+    for (i, (title, todos)) in sections.enumerate() {
+        if i > 0 {
+            println!();
+        }
+        if style.accessible {
+            println!("{}:", title);
+        } else {
+            println!("{}", style.bold(&title));
+        }
+        for line in render_todos(todos, show_list, options, style, today) {
+            println!("{}", line);
+        }
+    }
+}
+
+struct Week {
+    overdue: Vec<(usize, Todo)>,
+    days: Vec<(NaiveDate, Vec<(usize, Todo)>)>,
+    no_date: Option<Vec<(usize, Todo)>>,
+}
+
+const WEEK_DAYS: u64 = 7;
+
+fn get_week(
+    connection: &Connection,
+    query: &TodoQuery,
+    today: NaiveDate,
+    no_date: bool,
+) -> Result<Week, GetTodosError> {
+    let overdue = query_overdue_todos(connection, query, today)?;
+
+    let until = today + Days::new(WEEK_DAYS);
+    let mut scheduled = query_scheduled_todos(connection, query, today, until)?
+        .into_iter()
+        .peekable();
+    let days = today
+        .iter_days()
+        .take(WEEK_DAYS as usize)
+        .map(|day| {
+            let todos = std::iter::from_fn(|| scheduled.next_if(|(_, todo)| todo.due == Some(day)))
+                .collect();
+            (day, todos)
+        })
+        .collect();
+
+    let no_date = no_date
+        .then(|| {
+            query_todos(
+                connection,
+                &TodoQuery {
+                    done: Some(false),
+                    has_due: Some(false),
+                    ..query.clone()
+                },
+            )
+        })
+        .transpose()?;
+
+    Ok(Week {
+        overdue,
+        days,
+        no_date,
+    })
+}
+
+#[derive(Serialize)]
+struct WeekAgenda {
+    overdue: Vec<IndexedTodo>,
+    days: Vec<AgendaDay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    no_date: Option<Vec<IndexedTodo>>,
+}
+
+#[derive(Serialize)]
+struct AgendaDay {
+    date: NaiveDate,
+    todos: Vec<IndexedTodo>,
+}
+
+pub fn week_command(
+    connection: &Connection,
+    query: &TodoQuery,
+    no_date: bool,
+    show_empty: bool,
+    options: &PrintOptions,
+    style: &Style,
+) -> Result<(), PrintCommandError> {
+    // Due dates are calendar dates, so the week follows the local day rather than UTC.
+    let today = Local::now().date_naive();
+    let week = get_week(connection, query, today, no_date)?;
+    let days = week
+        .days
+        .iter()
+        .filter(|(_, todos)| show_empty || !todos.is_empty());
+
+    if options.json {
+        let agenda = WeekAgenda {
+            overdue: indexed(&week.overdue),
+            days: days
+                .map(|(date, todos)| AgendaDay {
+                    date: *date,
+                    todos: indexed(todos),
+                })
+                .collect(),
+            no_date: week.no_date.as_deref().map(indexed),
+        };
+        println!("{}", serde_json::to_string(&agenda)?);
+        return Ok(());
+    }
+
+    let overdue = (!week.overdue.is_empty()).then(|| ("Overdue".to_string(), &week.overdue[..]));
+    let days = days.map(|(date, todos)| (date.format("%a %-d %b").to_string(), &todos[..]));
+    let no_date = week
+        .no_date
+        .as_deref()
+        .filter(|todos| show_empty || !todos.is_empty())
+        .map(|todos| ("No date".to_string(), todos));
+    let mut sections = overdue.into_iter().chain(days).chain(no_date).peekable();
+
+    if sections.peek().is_none() {
+        println!("Nothing due this week");
+        return Ok(());
+    }
+    print_sections(sections, query.list.is_none(), options, style, today);
+
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct CountCommandError(#[from] GetTodosError);
+
+pub fn count_command(connection: &Connection, query: &TodoQuery) -> Result<(), CountCommandError> {
+    println!("{}", count_todos(connection, query)?);
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StatsCommandError {
+    #[error(transparent)]
+    GetStats(#[from] GetStatsError),
+
+    #[error("Fail to serialize stats")]
+    Serialize(#[from] serde_json::Error),
+}
+
+// The --json shape, meant for dashboards, so fields are only ever added.
+#[derive(Debug, Serialize)]
+struct StatsReport {
+    #[serde(flatten)]
+    stats: Stats,
+    days: Vec<DayStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recent: Option<RecentStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct RecentStats {
+    since: DateTime<Utc>,
+    completed: usize,
+}
+
+fn stats_report(
+    connection: &Connection,
+    list: &str,
+    today: NaiveDate,
+    days: u32,
+    utc_offset_minutes: i32,
+    recent_since: Option<DateTime<Utc>>,
+) -> Result<StatsReport, GetStatsError> {
+    let from = today - Days::new(days.saturating_sub(1).into());
+    Ok(StatsReport {
+        stats: get_stats(connection, list)?,
+        days: get_daily_stats(connection, list, from, today, utc_offset_minutes)?,
+        recent: recent_since
+            .map(|since| {
+                Ok::<_, GetStatsError>(RecentStats {
+                    since,
+                    completed: count_completed_since(connection, list, since)?,
+                })
+            })
+            .transpose()?,
+    })
+}
+
+// A span reaching back before the earliest representable time covers everything.
+fn recent_since(span: Span, now: DateTime<Utc>) -> DateTime<Utc> {
+    span.before(now).unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct CycleTime {
+    measured: usize,
+    excluded: usize,
+    average_seconds: Option<i64>,
+    median_seconds: Option<i64>,
+    slowest: Option<SlowestTodo>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct SlowestTodo {
+    title: String,
+    seconds: i64,
+}
+
+// From added to done. Without both times a todo can't be measured and is only counted as
+// excluded, a done time before the added time counts as no time at all.
+fn cycle_time(completions: Vec<Completion>) -> CycleTime {
+    let mut measured: Vec<(i64, String)> = Vec::new();
+    let mut excluded = 0;
+    for completion in completions {
+        match (completion.created_at, completion.done_at) {
+            (Some(created_at), Some(done_at)) => measured.push((
+                (done_at - created_at).num_seconds().max(0),
+                completion.title,
+            )),
+            _ => excluded += 1,
+        }
+    }
+
+    let mut seconds: Vec<i64> = measured.iter().map(|(seconds, _)| *seconds).collect();
+    seconds.sort_unstable();
+    let middle = seconds.len() / 2;
+    let median_seconds = match seconds.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((seconds[middle - 1] + seconds[middle]) / 2),
+        _ => Some(seconds[middle]),
+    };
+
+    CycleTime {
+        measured: measured.len(),
+        excluded,
+        average_seconds: (!seconds.is_empty())
+            .then(|| seconds.iter().sum::<i64>() / seconds.len() as i64),
+        median_seconds,
+        // The first of several equally slow ones.
+        slowest: measured
+            .into_iter()
+            .rev()
+            .max_by_key(|(seconds, _)| *seconds)
+            .map(|(seconds, title)| SlowestTodo { title, seconds }),
+    }
+}
+
+// The two largest units, like "1d 4h" or "35m".
+fn humane_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+fn format_cycle_time(cycle_time: &CycleTime, days: u32) -> String {
+    let window = Span::Days(days);
+    let mut lines = match (
+        cycle_time.average_seconds,
+        cycle_time.median_seconds,
+        &cycle_time.slowest,
+    ) {
+        (Some(average), Some(median), Some(slowest)) => vec![
+            format!(
+                "Cycle time of {} done in the last {}",
+                todos_count(cycle_time.measured),
+                window
+            ),
+            format!("Average: {}", humane_duration(average)),
+            format!("Median: {}", humane_duration(median)),
+            format!(
+                "Slowest: {}, {}",
+                humane_duration(slowest.seconds),
+                escape_control(slowest.title.lines().next().unwrap_or_default())
+            ),
+        ],
+        _ => vec![format!("No todos were done in the last {}", window)],
+    };
+    if cycle_time.excluded > 0 {
+        lines.push(format!(
+            "Left out {} done before added and done times were recorded",
+            todos_count(cycle_time.excluded)
+        ));
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+pub fn todos_count(count: usize) -> String {
+    match count {
+        1 => "1 todo".into(),
+        _ => format!("{} todos", count),
+    }
+}
+
+pub fn stats_command(
+    connection: &Connection,
+    list: &str,
+    by_tag: bool,
+    json: bool,
+    days: u32,
+    recent: Option<Span>,
+    cycle_time_only: bool,
+) -> Result<(), StatsCommandError> {
+    let since = recent.map(|span| recent_since(span, Utc::now()));
+
+    if cycle_time_only {
+        let since = recent_since(Span::Days(days), Utc::now());
+        let report = cycle_time(get_completions(connection, list, since)?);
+        if json {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            print!("{}", format_cycle_time(&report, days));
+        }
+    } else if by_tag {
+        let stats = get_tag_stats(connection, list)?;
+
+        if json {
+            println!("{}", serde_json::to_string(&stats)?);
+        } else {
+            for tag in stats {
+                println!(
+                    "{}: {} done, {} pending",
+                    escape_control(&tag.tag),
+                    tag.done,
+                    tag.pending
+                );
+            }
+        }
+    } else {
+        if json {
+            let now = Local::now();
+            let offset = now.offset().local_minus_utc() / 60;
+            let report = stats_report(connection, list, now.date_naive(), days, offset, since)?;
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            let stats = get_stats(connection, list)?;
+            println!("Total: {}", stats.total);
+            println!("Done: {}", stats.done);
+            println!("Pending: {}", stats.pending);
+            if let (Some(span), Some(since)) = (recent, since) {
+                println!(
+                    "Done in the last {}: {}",
+                    span,
+                    count_completed_since(connection, list, since)?
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn burndown_command(
+    connection: &Connection,
+    list: &str,
+    days: u32,
+    json: bool,
+    style: &Style,
+) -> Result<(), StatsCommandError> {
+    let now = Local::now();
+    let today = now.date_naive();
+    let from = today - Days::new(days.saturating_sub(1).into());
+    let offset = now.offset().local_minus_utc() / 60;
+    let counts = get_open_counts(connection, list, from, today, offset)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&counts)?);
+    } else {
+        let width = crossterm::terminal::size().map_or(80, |(columns, _)| columns.into());
+        for line in format_burndown(&counts, width, style) {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+// One row per day, the longest bar being the busiest day, so the chart fills the width it
+// has however many todos there are.
+fn format_burndown(counts: &[OpenCount], width: usize, style: &Style) -> Vec<String> {
+    let (Some(first), Some(last)) = (counts.first(), counts.last()) else {
+        return Vec::new();
+    };
+    let change = match last.open as i64 - first.open as i64 {
+        0 => "no change".to_string(),
+        change => format!("{:+}", change),
+    };
+    let mut lines = vec![format!(
+        "Open todos over {}: {} -> {} ({})",
+        Span::Days(counts.len() as u32),
+        first.open,
+        last.open,
+        change
+    )];
+
+    let dates: Vec<String> = counts
+        .iter()
+        .map(|count| style.date_format.date(count.date))
+        .collect();
+    if style.accessible {
+        for (date, count) in dates.iter().zip(counts) {
+            lines.push(format!("{}: {} open", date, count.open));
+        }
+        return lines;
+    }
+
+    let max = counts.iter().map(|count| count.open).max().unwrap_or(0);
+    let date_width = dates
+        .iter()
+        .map(|date| date.chars().count())
+        .max()
+        .unwrap_or(0);
+    let count_width = max.to_string().len();
+    let bar_width = width.saturating_sub(date_width + count_width + 3).max(10);
+
+    for (date, count) in dates.iter().zip(counts) {
+        let filled = if max == 0 {
+            0
+        } else {
+            ((count.open * bar_width) as f64 / max as f64).round() as usize
+        };
+        lines.push(
+            format!(
+                "{:>date_width$} {}{} {}",
+                date,
+                style.marker("│", "|"),
+                style
+                    .marker("█", "#")
+                    .repeat(filled.max(usize::from(count.open > 0))),
+                count.open
+            )
+            .trim_end()
+            .to_string(),
+        );
+    }
+    let indent = " ".repeat(date_width + 1);
+    lines.push(format!(
+        "{}{}{}",
+        indent,
+        style.marker("└", "+"),
+        style.marker("─", "-").repeat(bar_width)
+    ));
+    lines.push(format!("{} 0{:>2$}", indent, max, bar_width - 1));
+    lines
+}
+
+const STATUS_DONE: &str = "✔";
+const STATUS_PENDING: &str = "◻";
+const STATUS_OVERDUE: &str = "!";
+
+fn format_status(status: &Status, config: &StatusConfig, style: &Style) -> String {
+    if style.accessible {
+        let overdue = match status.overdue {
+            0 => String::new(),
+            overdue => format!(", {} overdue", overdue),
+        };
+        return format!(
+            "{} done, {} pending{}.",
+            status.done, status.pending, overdue
+        );
+    }
+
+    let glyph = |glyph: &Option<String>, default: &str| glyph.clone().unwrap_or(default.into());
+
+    let mut parts = vec![
+        style.green(&format!(
+            "{}{}",
+            glyph(&config.done, style.marker(STATUS_DONE, "x")),
+            status.done
+        )),
+        format!(
+            "{}{}",
+            glyph(&config.pending, style.marker(STATUS_PENDING, "o")),
+            status.pending
+        ),
+    ];
+    if status.overdue > 0 {
+        parts.push(style.red(&format!(
+            "{}{}",
+            glyph(&config.overdue, STATUS_OVERDUE),
+            status.overdue
+        )));
+    }
+
+    parts.join(" ")
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StatusCommandError {
+    #[error(transparent)]
+    GetStats(#[from] GetStatsError),
+
+    #[error("Fail to write the status")]
+    Write(#[from] std::io::Error),
+}
+
+// A missing database just means nothing to do yet, so the status never creates one.
+pub fn status_command(
+    connection: Option<&Connection>,
+    list: &str,
+    config: &StatusConfig,
+    style: &Style,
+) -> Result<(), StatusCommandError> {
+    let status = match connection {
+        Some(connection) => get_status(connection, list, Local::now().date_naive())?,
+        None => Status::default(),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    write!(stdout, "{}", format_status(&status, config, style))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BackupCommandError {
+    #[error(transparent)]
+    GetBackupDir(#[from] GetDbPathError),
+
+    #[error("Fail to create the backup directory")]
+    CreateDir(#[source] std::io::Error),
+
+    #[error(transparent)]
+    Backup(#[from] BackupError),
+}
+
+// Named by the time taken, so backups sort oldest first and never overwrite each other.
+pub fn backup_command(
+    connection: &Connection,
+    dir: Option<PathBuf>,
+    now: DateTime<Local>,
+) -> Result<PathBuf, BackupCommandError> {
+    let dir = get_backup_dir(dir)?;
+    std::fs::create_dir_all(&dir).map_err(BackupCommandError::CreateDir)?;
+    let path = dir.join(format!("todos-{}.db", now.format("%Y%m%d-%H%M%S")));
+    backup_database(connection, &path)?;
+    Ok(path)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error(transparent)]
+pub struct ReindexCommandError(#[from] ReindexTodosError);
+
+pub fn reindex_command(connection: &mut Connection) -> Result<(), ReindexCommandError> {
+    match reindex_todos(connection)? {
+        0 => println!("Ids are already contiguous"),
+        1 => println!("Renumbered 1 todo"),
+        renumbered => println!("Renumbered {} todos", renumbered),
+    }
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryCommandError {
+    #[error(transparent)]
+    GetHistory(#[from] GetHistoryError),
+
+    #[error("Fail to serialize the history")]
+    Serialize(#[from] serde_json::Error),
+}
+
+pub fn history_command(
+    connection: &Connection,
+    list: Option<&str>,
+    limit: usize,
+    json: bool,
+) -> Result<(), HistoryCommandError> {
+    let history = get_history(connection, list, limit)?;
+
+    if json {
+        println!("{}", serde_json::to_string(&history)?);
+        return Ok(());
+    }
+
+    for entry in history {
+        let at = entry.at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S");
+        let description = escape_control(&entry.description);
+        if list.is_none() {
+            println!("{}  [{}] {}", at, escape_control(&entry.list), description);
+        } else {
+            println!("{}  {}", at, description);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{parse_column, Args, Commands};
+    use crate::{
+        confirm::AssumeYes,
+        dates::ISO_DATE,
+        db::create_table,
+        terminal::DEFAULT_THEME,
+        todo::{Label, DEFAULT_LIST},
+    };
+    use chrono::NaiveDate;
+    use clap::Parser;
+    use rusqlite::Connection;
+
+    const PLAIN: Style = Style {
+        color: false,
+        accessible: false,
+        ascii: false,
+        date_format: ISO_DATE,
+        theme: DEFAULT_THEME,
+        line_numbers_from: 0,
+    };
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()
+    }
+
+    struct ScriptedConfirm {
+        answer: bool,
+        prompts: Vec<String>,
+    }
+
+    impl ScriptedConfirm {
+        fn new(answer: bool) -> Self {
+            Self {
+                answer,
+                prompts: Vec::new(),
+            }
+        }
+    }
+
+    impl Confirm for ScriptedConfirm {
+        fn confirm(&mut self, prompt: &str) -> std::io::Result<bool> {
+            self.prompts.push(prompt.to_string());
+            Ok(self.answer)
+        }
+    }
+
+    #[test]
+    fn test_add_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "title2".to_string()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].title, "title1");
+        assert_eq!(todos[1].title, "title2");
+    }
+
+    #[test]
+    fn test_add_command_at() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            ["a", "b", "c"].map(String::from).to_vec(),
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let at = |at| AddOptions {
+            at: Some(at),
+            ..AddOptions::default()
+        };
+
+        let added = add_command(&mut connection, DEFAULT_LIST, vec!["new".into()], &at(1)).unwrap();
+        assert_eq!(titles(&connection), vec!["a", "new", "b", "c"]);
+        assert_eq!(
+            added[0].id,
+            get_todos(&connection, DEFAULT_LIST).unwrap()[1].id
+        );
+
+        add_command(&mut connection, DEFAULT_LIST, vec!["end".into()], &at(4)).unwrap();
+        assert_eq!(titles(&connection), vec!["a", "new", "b", "c", "end"]);
+        assert!(matches!(
+            add_command(&mut connection, DEFAULT_LIST, vec!["x".into()], &at(6)),
+            Err(AddCommandError::InvalidPosition { at: 6, count: 5 })
+        ));
+        assert_eq!(titles(&connection).len(), 5);
+    }
+
+    #[test]
+    fn test_set_done_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "title2".to_string()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert!(!todos[0].done);
+        assert!(!todos[1].done);
+
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0], true, None).unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert!(todos[0].done);
+        assert!(!todos[1].done);
+    }
+
+    #[test]
+    fn test_remove_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "title2".to_string()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].title, "title2");
+        assert_eq!(get_trash(&connection).unwrap()[0].todo.title, "title1");
+    }
+
+    #[test]
+    fn test_restore_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let titles = ["title1", "title2", "title3"].map(String::from).to_vec();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 1],
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+
+        let trash = get_trash(&connection).unwrap();
+        let restored = restore_command(&mut connection, &[trash[1].id, trash[0].id]).unwrap();
+        assert_eq!(restored.len(), 2);
+
+        let titles: Vec<String> = get_todos(&connection, DEFAULT_LIST)
+            .unwrap()
+            .iter()
+            .map(|todo| todo.title.clone())
+            .collect();
+        assert_eq!(titles, vec!["title3", "title2", "title1"]);
+        assert!(get_trash(&connection).unwrap().is_empty());
+        assert_eq!(
+            get_history(&connection, Some(DEFAULT_LIST), 1).unwrap()[0].description,
+            "restored 2"
+        );
+
+        let error = restore_command(&mut connection, &[trash[0].id]).unwrap_err();
+        assert!(matches!(
+            error,
+            TrashCommandError::RestoreTodos(RestoreTodosError::NotInTrash(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_trash_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let mut confirm = ScriptedConfirm::new(false);
+
+        // An empty trash has nothing to ask about.
+        assert_eq!(empty_trash_command(&connection, &mut confirm).unwrap(), 0);
+        assert!(confirm.prompts.is_empty());
+
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["title1".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+
+        let error = empty_trash_command(&connection, &mut confirm).unwrap_err();
+        assert!(matches!(
+            error,
+            TrashCommandError::Confirm(ConfirmError::Declined)
+        ));
+        assert_eq!(confirm.prompts, vec!["Permanently delete 1 trashed todos?"]);
+        assert_eq!(get_trash(&connection).unwrap().len(), 1);
+
+        let mut confirm = ScriptedConfirm::new(true);
+        assert_eq!(empty_trash_command(&connection, &mut confirm).unwrap(), 1);
+        assert!(get_trash(&connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_format_trash() {
+        let removed_at = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+            .with_timezone(&Utc);
+        let trash = vec![
+            TrashedTodo {
+                id: 9,
+                removed_at,
+                todo: Todo::new("call\nthe vendor".into()),
+            },
+            TrashedTodo {
+                id: 10,
+                removed_at,
+                todo: Todo {
+                    list: "work".into(),
+                    ..Todo::new("book".into())
+                },
+            },
+        ];
+
+        assert_eq!(
+            format_trash(&trash, &PLAIN),
+            " 9 call…  [default] removed 2024-05-01\n10 book  [work] removed 2024-05-01\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_matching_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let titles = ["pending1", "done", "pending2"].map(String::from).to_vec();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+        add_command(
+            &mut connection,
+            "other",
+            vec!["elsewhere".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+
+        let state = |done| TodoQuery {
+            done: Some(done),
+            ..Default::default()
+        };
+        let removed = remove_matching_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &state(false),
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+        let titles: Vec<&str> = removed.iter().map(|todo| todo.title.as_str()).collect();
+        assert_eq!(titles, vec!["pending1", "pending2"]);
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].title, "done");
+        assert_eq!(get_todos(&connection, "other").unwrap().len(), 1);
+
+        remove_matching_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &state(true),
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+        assert!(get_todos(&connection, DEFAULT_LIST).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename_tag_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let options = AddOptions {
+            tags: vec!["wrk".into()],
+            ..Default::default()
+        };
+        add_command(&mut connection, DEFAULT_LIST, vec!["a".into()], &options).unwrap();
+
+        assert!(matches!(
+            rename_tag_command(&mut connection, "wrk", "  "),
+            Err(TagCommandError::EmptyTag)
+        ));
+        assert_eq!(
+            rename_tag_command(&mut connection, "wrk", " work ").unwrap(),
+            1
+        );
+        assert_eq!(
+            get_todos(&connection, DEFAULT_LIST).unwrap()[0].tags,
+            vec!["work"]
+        );
+        assert_eq!(remove_tag_command(&connection, "work").unwrap(), 1);
+        assert!(get_todos(&connection, DEFAULT_LIST).unwrap()[0]
+            .tags
+            .is_empty());
+    }
+
+    #[test]
+    fn test_backup_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_todos(&mut connection, vec![Todo::new("a".into())]).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("nested").join("backups");
+        let now = NaiveDate::from_ymd_opt(2024, 5, 1)
+            .unwrap()
+            .and_hms_opt(9, 30, 5)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap();
+
+        let path = backup_command(&connection, Some(backups.clone()), now).unwrap();
+        assert_eq!(path, backups.join("todos-20240501-093005.db"));
+        let backup = Connection::open(&path).unwrap();
+        assert_eq!(get_todos(&backup, DEFAULT_LIST).unwrap()[0].title, "a");
+
+        assert!(matches!(
+            backup_command(&connection, Some(backups), now),
+            Err(BackupCommandError::Backup(_))
+        ));
+    }
+
+    #[test]
+    fn test_rename_list_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todo = |title: &str, list: &str| Todo {
+            list: list.into(),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("a", DEFAULT_LIST),
+                todo("b", "work"),
+                todo("c", DEFAULT_LIST),
+            ],
+        )
+        .unwrap();
+        let titles = |connection: &Connection, list: &str| -> Vec<String> {
+            get_todos(connection, list)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+
+        assert!(matches!(
+            rename_list_command(&mut connection, DEFAULT_LIST, "work", false),
+            Err(ListsCommandError::ListExists(_))
+        ));
+        assert!(matches!(
+            rename_list_command(&mut connection, "missing", "home", false),
+            Err(ListsCommandError::UnknownList(_))
+        ));
+        assert!(matches!(
+            rename_list_command(&mut connection, "work", " ", false),
+            Err(ListsCommandError::EmptyName)
+        ));
+        assert_eq!(titles(&connection, "work"), vec!["b"]);
+
+        assert_eq!(
+            rename_list_command(&mut connection, "work", "job", false).unwrap(),
+            1
+        );
+        assert_eq!(
+            rename_list_command(&mut connection, DEFAULT_LIST, "job", true).unwrap(),
+            2
+        );
+        assert!(titles(&connection, DEFAULT_LIST).is_empty());
+        assert_eq!(titles(&connection, "job"), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_format_lists() {
+        let stats = |name: &str, pending, done| ListStats {
+            name: name.into(),
+            pending,
+            done,
+        };
+        let lists = [stats("default", 0, 0), stats("work", 2, 1)];
+
+        assert_eq!(
+            format_lists(&lists, "work", DEFAULT_LIST),
+            "  default  0 pending, 0 done (default)\n* work     2 pending, 1 done\n"
+        );
+        assert_eq!(
+            serde_json::to_string(&lists[1..]).unwrap(),
+            r#"[{"name":"work","pending":2,"done":1}]"#
+        );
+    }
+
+    #[test]
+    fn test_delete_list_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_todos(
+            &mut connection,
+            vec![
+                Todo::new("a".into()),
+                Todo {
+                    list: "work".into(),
+                    tags: vec!["t".into()],
+                    ..Todo::new("b".into())
+                },
+                Todo {
+                    list: "work".into(),
+                    ..Todo::new("c".into())
+                },
+            ],
+        )
+        .unwrap();
+
+        let mut confirm = ScriptedConfirm::new(false);
+        assert!(matches!(
+            delete_list_command(&mut connection, "work", &mut confirm, 1, None),
+            Err(ListsCommandError::Confirm(_))
+        ));
+        assert_eq!(confirm.prompts, vec!["Delete work with 2 todos?"]);
+        assert_eq!(get_todos(&connection, "work").unwrap().len(), 2);
+
+        let deleted =
+            delete_list_command(&mut connection, "work", &mut AssumeYes, 1, None).unwrap();
+        assert_eq!(deleted.len(), 2);
+        assert!(get_todos(&connection, "work").unwrap().is_empty());
+        assert_eq!(get_list_names(&connection).unwrap(), vec![DEFAULT_LIST]);
+        assert!(matches!(
+            delete_list_command(&mut connection, "work", &mut AssumeYes, 1, None),
+            Err(ListsCommandError::UnknownList(_))
+        ));
+
+        let trashed: Vec<usize> = get_trash(&connection)
+            .unwrap()
+            .iter()
+            .map(|trashed| trashed.id)
+            .collect();
+        assert_eq!(trashed.len(), 2);
+        restore_command(&mut connection, &trashed).unwrap();
+        assert_eq!(get_todos(&connection, "work").unwrap().len(), 2);
+        delete_list_command(&mut connection, "work", &mut AssumeYes, 1, None).unwrap();
+
+        delete_list_command(&mut connection, DEFAULT_LIST, &mut AssumeYes, 1, None).unwrap();
+        add_todos(&mut connection, vec![Todo::new("d".into())]).unwrap();
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap()[0].title, "d");
+    }
+
+    #[test]
+    fn test_move_and_copy_to_list() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let titles = ["a", "b", "c"].map(String::from).to_vec();
+        let options = AddOptions {
+            tags: vec!["t".into()],
+            ..Default::default()
+        };
+        add_command(&mut connection, DEFAULT_LIST, titles, &options).unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+        let titles = |connection: &Connection, list: &str| -> Vec<(String, bool)> {
+            get_todos(connection, list)
+                .unwrap()
+                .into_iter()
+                .map(|todo| (todo.title, todo.done))
+                .collect()
+        };
+
+        let copied =
+            copy_to_command(&mut connection, DEFAULT_LIST, "home", vec![1, 0], false).unwrap();
+        assert_eq!(copied.len(), 2);
+        assert_eq!(
+            titles(&connection, "home"),
+            vec![("a".into(), false), ("b".into(), false)]
+        );
+        assert_eq!(get_todos(&connection, "home").unwrap()[0].tags, vec!["t"]);
+        copy_to_command(&mut connection, DEFAULT_LIST, "home", vec![1], true).unwrap();
+        assert_eq!(titles(&connection, "home")[2], ("b".into(), true));
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 3);
+
+        let moved = move_to_command(&mut connection, DEFAULT_LIST, "home", vec![2, 0]).unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(titles(&connection, DEFAULT_LIST), vec![("b".into(), true)]);
+        let home: Vec<String> = titles(&connection, "home")
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect();
+        assert_eq!(home, vec!["a", "b", "b", "a", "c"]);
+
+        assert!(matches!(
+            move_to_command(&mut connection, DEFAULT_LIST, "home", vec![1]),
+            Err(TransferCommandError::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_template_command() {
+        use crate::config::TemplateTodoTable;
+
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let templates = BTreeMap::from([(
+            "groceries".to_string(),
+            TemplateConfig {
+                todos: vec![
+                    TemplateTodo::Title("  milk ".into()),
+                    TemplateTodo::Table(TemplateTodoTable {
+                        title: "eggs".into(),
+                        tags: vec!["dairy".into(), "shop".into()],
+                        priority: Some(Priority::High),
+                    }),
+                ],
+            },
+        )]);
+        let options = AddOptions {
+            tags: vec!["shop".into()],
+            priority: Some(Priority::Low),
+            ..Default::default()
+        };
+
+        let added =
+            add_template_command(&mut connection, "home", &templates, "groceries", &options)
+                .unwrap();
+        assert_eq!(added.len(), 2);
+        let todos = get_todos(&connection, "home").unwrap();
+        assert_eq!(todos[0].title, "milk");
+        assert_eq!(todos[0].tags, vec!["shop"]);
+        assert_eq!(todos[0].priority, Priority::Low);
+        assert_eq!(todos[1].tags, vec!["dairy", "shop"]);
+        assert_eq!(todos[1].priority, Priority::High);
+
+        let error = add_template_command(&mut connection, "home", &templates, "chores", &options)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unknown template 'chores', expected one of groceries"
+        );
+        assert_eq!(get_todos(&connection, "home").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_add_command_empty_title() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "  ".to_string()];
+        let error = add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, AddCommandError::EmptyTitle));
+
+        assert!(get_todos(&connection, DEFAULT_LIST).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_done_command_rolls_back_without_history() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["title1".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        connection.execute_batch("DROP TABLE history").unwrap();
+
+        let error =
+            set_done_command(&mut connection, DEFAULT_LIST, vec![0], true, None).unwrap_err();
+        assert!(matches!(error, SetDoneCommandError::RecordHistory(_)));
+        assert!(!get_todos(&connection, DEFAULT_LIST).unwrap()[0].done);
+    }
+
+    #[test]
+    fn test_changes_roll_back_without_history() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["title1".into(), "title2".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+        connection.execute_batch("DROP TABLE history").unwrap();
+        let titles = |connection: &Connection, list: &str| -> Vec<String> {
+            get_todos(connection, list)
+                .unwrap()
+                .iter()
+                .map(|todo| todo.title.clone())
+                .collect()
+        };
+
+        assert!(add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["title3".into()],
+            &AddOptions::default()
+        )
+        .is_err());
+        assert!(edit_command(
+            &mut connection,
+            DEFAULT_LIST,
+            0,
+            &EditOptions {
+                title: Some("edited".into()),
+                ..EditOptions::default()
+            }
+        )
+        .is_err());
+        assert!(remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            &mut AssumeYes,
+            10,
+            None
+        )
+        .is_err());
+        assert!(clear_command(&mut connection, DEFAULT_LIST, &mut AssumeYes, 10).is_err());
+        assert!(move_command(&mut connection, DEFAULT_LIST, 0, MoveTarget::Bottom).is_err());
+        assert!(move_to_command(&mut connection, DEFAULT_LIST, "work", vec![0]).is_err());
+        assert!(copy_to_command(&mut connection, DEFAULT_LIST, "work", vec![0], false).is_err());
+        assert!(
+            delete_list_command(&mut connection, DEFAULT_LIST, &mut AssumeYes, 10, None).is_err()
+        );
+
+        assert_eq!(titles(&connection, DEFAULT_LIST), vec!["title1", "title2"]);
+        assert!(titles(&connection, "work").is_empty());
+        assert!(get_trash(&connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_done_command_invalid_index() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let error =
+            set_done_command(&mut connection, DEFAULT_LIST, vec![0, 1], true, None).unwrap_err();
+        assert!(matches!(
+            error,
+            SetDoneCommandError::InvalidIndex(InvalidIndexError(1))
+        ));
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert!(!todos[0].done);
+    }
+
+    #[test]
+    fn test_remove_command_above_confirm_threshold() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "title2".to_string(), "title3".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let mut confirm = ScriptedConfirm::new(false);
+        let error = remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 1],
+            &mut confirm,
+            1,
+            None,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            RemoveCommandError::Confirm(ConfirmError::Declined)
+        ));
+        assert_eq!(confirm.prompts, vec!["Remove 2 todos?"]);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 3);
+
+        let mut confirm = ScriptedConfirm::new(true);
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 1],
+            &mut confirm,
+            1,
+            None,
+        )
+        .unwrap();
+        assert_eq!(confirm.prompts.len(), 1);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_remove_command_below_confirm_threshold() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".to_string(), "title2".to_string()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let mut confirm = ScriptedConfirm::new(false);
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            &mut confirm,
+            1,
+            None,
+        )
+        .unwrap();
+        assert!(confirm.prompts.is_empty());
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_print_all_lists() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_command(
+            &mut connection,
+            "work",
+            vec!["title1".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        add_command(
+            &mut connection,
+            "home",
+            vec!["title2".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        add_command(
+            &mut connection,
+            "work",
+            vec!["title3".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let todos = query_todos(&connection, &TodoQuery::list("work")).unwrap();
+        let options = PrintOptions::default();
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec!["0: title1", "1: title3"]
+        );
+
+        let todos = query_todos(&connection, &TodoQuery::default()).unwrap();
+        assert_eq!(
+            format_todos(&todos, true, &options, &PLAIN, today()),
+            vec!["[home] 0: title2", "[work] 0: title1", "[work] 1: title3"]
+        );
+    }
+
+    #[test]
+    fn test_search_keeps_indexes() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["buy milk".into(), "call mom".into(), "buy bread".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let query = TodoQuery {
+            search: vec!["BUY".into()],
+            ..TodoQuery::list(DEFAULT_LIST)
+        };
+        let todos = query_todos(&connection, &query).unwrap();
+        assert_eq!(
+            format_todos(&todos, false, &PrintOptions::default(), &PLAIN, today()),
+            vec!["0: buy milk", "2: buy bread"]
+        );
+        assert_eq!(count_todos(&connection, &query).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_print_show_id() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".into(), "title2".into(), "title3".into()];
+        let added = add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+        remove_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            &mut AssumeYes,
+            10,
+            None,
+        )
+        .unwrap();
+
+        let todos = query_todos(&connection, &TodoQuery::list(DEFAULT_LIST)).unwrap();
+        let options = PrintOptions {
+            show_id: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec![
+                format!("0 (id {}): title2", added[1].id),
+                format!("1 (id {}): title3", added[2].id),
+            ]
+        );
+    }
+
+    fn add_with(connection: &mut Connection, title: &str, priority: Priority, due: Option<&str>) {
+        let options = AddOptions {
+            priority: Some(priority),
+            due: due.map(|due| due.parse().unwrap()),
+            ..Default::default()
+        };
+        add_command(connection, DEFAULT_LIST, vec![title.into()], &options).unwrap();
+    }
+
+    fn next_titles(connection: &Connection, limit: usize) -> Vec<(usize, String)> {
+        query_next_todos(connection, &TodoQuery::list(DEFAULT_LIST), limit)
+            .unwrap()
+            .into_iter()
+            .map(|(index, todo)| (index, todo.title))
+            .collect()
+    }
+
+    #[test]
+    fn test_next_prefers_due_date_then_priority_then_age() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_with(&mut connection, "undated", Priority::High, None);
+        add_with(&mut connection, "later", Priority::High, Some("2024-03-01"));
+        add_with(&mut connection, "old", Priority::Medium, Some("2024-02-01"));
+        add_with(
+            &mut connection,
+            "urgent",
+            Priority::High,
+            Some("2024-02-01"),
+        );
+        add_with(
+            &mut connection,
+            "overdue",
+            Priority::Low,
+            Some("2020-01-01"),
+        );
+        add_with(&mut connection, "undated too", Priority::High, None);
+
+        assert_eq!(next_titles(&connection, 1), vec![(4, "overdue".into())]);
+        assert_eq!(
+            next_titles(&connection, 10),
+            vec![
+                (4, "overdue".into()),
+                (3, "urgent".into()),
+                (2, "old".into()),
+                (1, "later".into()),
+                (0, "undated".into()),
+                (5, "undated too".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_next_all_done() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        assert!(next_titles(&connection, 1).is_empty());
+
+        add_with(
+            &mut connection,
+            "title1",
+            Priority::High,
+            Some("2024-01-01"),
+        );
+        add_with(&mut connection, "title2", Priority::Low, None);
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0], true, None).unwrap();
+        assert_eq!(next_titles(&connection, 1), vec![(1, "title2".into())]);
+
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+        assert!(next_titles(&connection, 1).is_empty());
+    }
+
+    #[test]
+    fn test_add_command_normalizes_whitespace() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["  buy   milk \t".into(), "call\n mom".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos[0].title, "buy milk");
+        assert_eq!(todos[1].title, "call mom");
+    }
+
+    #[test]
+    fn test_add_command_no_trim() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let options = AddOptions {
+            no_trim: true,
+            ..Default::default()
+        };
+        let titles = vec!["  fn main()  {\n    todo!()\n}".into()];
+        add_command(&mut connection, DEFAULT_LIST, titles, &options).unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos[0].title, "  fn main()  {\n    todo!()\n}");
+
+        let error = add_command(&mut connection, DEFAULT_LIST, vec!["  ".into()], &options);
+        assert!(matches!(error, Err(AddCommandError::EmptyTitle)));
+    }
+
+    #[test]
+    fn test_format_custom_date_format() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let options = AddOptions {
+            due: Some(today()),
+            ..Default::default()
+        };
+        add_command(&mut connection, DEFAULT_LIST, vec!["a".into()], &options).unwrap();
+
+        let style = Style {
+            date_format: "%d.%m.%Y".to_string().try_into().unwrap(),
+            ..Default::default()
+        };
+        let todos = query_todos(&connection, &TodoQuery::list(DEFAULT_LIST)).unwrap();
+        assert_eq!(
+            format_todos(&todos, false, &PrintOptions::default(), &style, today()),
+            vec!["0: a (due 01.05.2024)"]
+        );
+
+        // Only the display changes, the stored date stays ISO.
+        let stored: String = connection
+            .query_row("SELECT due FROM todos", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stored, "2024-05-01");
+    }
+
+    #[test]
+    fn test_format_overdue_todos() {
+        let due = |title: &str, due: &str, done: bool| {
+            let todo = Todo {
+                done,
+                due: Some(due.parse().unwrap()),
+                ..Todo::new(title.into())
+            };
+            (0, todo)
+        };
+        let todos = vec![
+            due("late", "2024-04-30", false),
+            due("today", "2024-05-01", false),
+            due("later", "2024-05-02", false),
+            due("finished", "2024-04-01", true),
+        ];
+        let options = PrintOptions::default();
+
+        assert_eq!(
+            format_todos(&todos[..3], false, &options, &PLAIN, today()),
+            vec![
+                "! 0: late (due 2024-04-30)",
+                "0: today (due 2024-05-01)",
+                "0: later (due 2024-05-02)",
+            ]
+        );
+        assert_eq!(
+            overdue_summary(&todos, &PLAIN, today()).unwrap(),
+            "1 item overdue"
+        );
+
+        let style = Style {
+            color: true,
+            ..Default::default()
+        };
+        let lines = format_todos(&todos[..3], false, &options, &style, today());
+        assert_eq!(lines[0], "\x1b[31m0: late (due 2024-04-30)\x1b[0m");
+        assert_eq!(lines[1], "\x1b[33m0: today (due 2024-05-01)\x1b[0m");
+        assert_eq!(lines[2], "0: later (due 2024-05-02)");
+
+        assert!(overdue_summary(&todos[1..], &style, today()).is_none());
+    }
+
+    #[test]
+    fn test_history_lists_recent_operations() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".into(), "title2".into(), "title3".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0, 2], true, None).unwrap();
+        clear_command(&mut connection, DEFAULT_LIST, &mut AssumeYes, 10).unwrap();
+        add_command(
+            &mut connection,
+            "work",
+            vec!["title4".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let descriptions = |list: Option<&str>, limit: usize| -> Vec<String> {
+            get_history(&connection, list, limit)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.description)
+                .collect()
+        };
+
+        assert_eq!(
+            descriptions(Some(DEFAULT_LIST), 10),
+            vec!["cleared 2 done", "marked 2 done", "added 3"]
+        );
+        assert_eq!(descriptions(None, 2), vec!["added 1", "cleared 2 done"]);
+    }
+
+    #[test]
+    fn test_format_labels() {
+        let labeled = |title: &str, label, due: Option<&str>| {
+            let todo = Todo {
+                label,
+                due: due.map(|due| due.parse().unwrap()),
+                ..Todo::new(title.into())
+            };
+            (0, todo)
+        };
+        let todos = vec![
+            labeled("red", Some(Label::Red), None),
+            labeled("plain", None, None),
+            labeled("late", Some(Label::Blue), Some("2024-04-30")),
+        ];
+        let options = PrintOptions::default();
+
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec![
+                "[red] 0: red",
+                "0: plain",
+                "[blue] ! 0: late (due 2024-04-30)"
+            ]
+        );
+        assert_eq!(
+            format_todos(
+                &todos[2..],
+                false,
+                &options,
+                &Style {
+                    color: true,
+                    ..Default::default()
+                },
+                today()
+            ),
+            vec!["\x1b[34m●\x1b[0m \x1b[31m0: late (due 2024-04-30)\x1b[0m"]
+        );
+
+        let options = PrintOptions {
+            columns: vec![Column::Title, Column::Label],
+            ..Default::default()
+        };
+        let lines = render_todos(&todos[..2], false, &options, &PLAIN, today());
+        assert_eq!(lines[1].trim_end(), "red    red");
+        assert_eq!(lines[2].trim_end(), "plain");
+    }
+
+    #[test]
+    fn test_stats_report_json_shape() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0], true, None).unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET created_at = '2024-04-29 08:00:00.000';
+                UPDATE todos SET done_at = '2024-05-01 08:00:00.000' WHERE done;",
+            )
+            .unwrap();
+
+        let report = stats_report(&connection, DEFAULT_LIST, today(), 3, 0, None).unwrap();
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "total": 2,
+                "done": 1,
+                "pending": 1,
+                "days": [
+                    {"date": "2024-04-29", "added": 2, "completed": 0},
+                    {"date": "2024-04-30", "added": 0, "completed": 0},
+                    {"date": "2024-05-01", "added": 0, "completed": 1},
+                ]
+            })
+        );
+
+        let report = stats_report(&connection, DEFAULT_LIST, today(), 1, 0, None).unwrap();
+        assert_eq!(report.days.len(), 1);
+    }
+
+    fn completion(title: &str, hours: Option<i64>) -> Completion {
+        let done_at = today().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        Completion {
+            title: title.into(),
+            created_at: hours.map(|hours| done_at - Duration::hours(hours)),
+            done_at: hours.map(|_| done_at),
+        }
+    }
+
+    #[test]
+    fn test_cycle_time() {
+        let report = cycle_time(vec![
+            completion("quick", Some(2)),
+            completion("slow", Some(28)),
+            completion("untimed", None),
+            completion("medium", Some(6)),
+            completion("also slow", Some(28)),
+        ]);
+        assert_eq!(report.measured, 4);
+        assert_eq!(report.excluded, 1);
+        assert_eq!(report.average_seconds, Some(16 * 3600));
+        assert_eq!(report.median_seconds, Some(17 * 3600));
+        assert_eq!(
+            report.slowest,
+            Some(SlowestTodo {
+                title: "slow".into(),
+                seconds: 28 * 3600
+            })
+        );
+
+        let report = cycle_time(vec![completion("one", Some(3)), completion("two", Some(5))]);
+        assert_eq!(report.median_seconds, Some(4 * 3600));
+        let report = cycle_time(vec![completion("only", Some(3))]);
+        assert_eq!(report.median_seconds, Some(3 * 3600));
+
+        // Done before added, like after the clock was set back.
+        let report = cycle_time(vec![completion("backwards", Some(-2))]);
+        assert_eq!(report.average_seconds, Some(0));
+
+        let report = cycle_time(vec![completion("untimed", None)]);
+        assert_eq!(report.measured, 0);
+        assert_eq!(report.average_seconds, None);
+        assert_eq!(report.slowest, None);
+    }
+
+    #[test]
+    fn test_humane_duration() {
+        assert_eq!(humane_duration(0), "0m");
+        assert_eq!(humane_duration(59), "0m");
+        assert_eq!(humane_duration(35 * 60), "35m");
+        assert_eq!(humane_duration(3 * 3600 + 20 * 60), "3h 20m");
+        assert_eq!(humane_duration(28 * 3600 + 59 * 60), "1d 4h");
+        assert_eq!(humane_duration(30 * 24 * 3600), "30d 0h");
+    }
+
+    #[test]
+    fn test_format_burndown() {
+        let counts: Vec<OpenCount> = [4, 0, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, open)| OpenCount {
+                date: today() + Days::new(i as u64),
+                open,
+            })
+            .collect();
+        let ascii = Style {
+            ascii: true,
+            ..PLAIN
+        };
+
+        assert_eq!(
+            format_burndown(&counts, 30, &ascii),
+            vec![
+                "Open todos over 3 days: 4 -> 2 (-2)",
+                "2024-05-01 |################ 4",
+                "2024-05-02 | 0",
+                "2024-05-03 |######## 2",
+                "           +----------------",
+                "            0              4",
+            ]
+        );
+        // Narrow terminals still get a readable bar.
+        assert_eq!(
+            format_burndown(&counts, 5, &ascii)[1],
+            "2024-05-01 |########## 4"
+        );
+        assert_eq!(
+            format_burndown(&counts[1..2], 30, &PLAIN),
+            vec![
+                "Open todos over 1 day: 0 -> 0 (no change)",
+                "2024-05-02 │ 0",
+                "           └────────────────",
+                "            0              0",
+            ]
+        );
+        let accessible = Style {
+            accessible: true,
+            ..PLAIN
+        };
+        assert_eq!(
+            format_burndown(&counts[..2], 30, &accessible),
+            vec![
+                "Open todos over 2 days: 4 -> 0 (-4)",
+                "2024-05-01: 4 open",
+                "2024-05-02: 0 open",
+            ]
+        );
+        assert!(format_burndown(&[], 30, &PLAIN).is_empty());
+    }
+
+    #[test]
+    fn test_format_cycle_time() {
+        let report = cycle_time(vec![
+            completion("quick", Some(2)),
+            completion("slow\nwith notes", Some(28)),
+            completion("untimed", None),
+        ]);
+        assert_eq!(
+            format_cycle_time(&report, 30),
+            "Cycle time of 2 todos done in the last 30 days
+Average: 15h 0m
+Median: 15h 0m
+Slowest: 1d 4h, slow
+Left out 1 todo done before added and done times were recorded
+"
+        );
+
+        let report = cycle_time(Vec::new());
+        assert_eq!(
+            format_cycle_time(&report, 1),
+            "No todos were done in the last 1 day\n"
+        );
+    }
+
+    #[test]
+    fn test_stats_report_recent_completions() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            ["yesterday", "last week", "last month", "pending"]
+                .map(String::from)
+                .to_vec(),
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0, 1, 2], true, None).unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET done_at = '2024-04-30 12:00:00.000' WHERE title = 'yesterday';
+                UPDATE todos SET done_at = '2024-04-25 12:00:00.000' WHERE title = 'last week';
+                UPDATE todos SET done_at = '2024-04-01 12:00:00.000' WHERE title = 'last month';",
+            )
+            .unwrap();
+        let now = today().and_hms_opt(12, 0, 0).unwrap().and_utc();
+        let completed = |span| {
+            let since = recent_since(span, now);
+            stats_report(&connection, DEFAULT_LIST, today(), 1, 0, Some(since))
+                .unwrap()
+                .recent
+                .unwrap()
+                .completed
+        };
+
+        assert_eq!(completed(Span::Days(2)), 1);
+        assert_eq!(completed(Span::Days(7)), 2);
+        assert_eq!(completed(Span::Months(1)), 3);
+        assert_eq!(completed(Span::Days(0)), 0);
+
+        let since = recent_since(Span::Weeks(1), now);
+        let report = stats_report(&connection, DEFAULT_LIST, today(), 1, 0, Some(since)).unwrap();
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["recent"]["completed"], 2);
+        assert_eq!(json["recent"]["since"], "2024-04-24T12:00:00Z");
+        assert_eq!(json["done"], 3);
+    }
+
+    #[test]
+    fn test_mark_viewed_flags_new_todos() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["old".into(), "new".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        for (title, created_at) in [("old", "09:00:00"), ("new", "11:00:00")] {
+            connection
+                .execute(
+                    "UPDATE todos SET created_at = '2024-05-01 ' || ?1 WHERE title = ?2",
+                    rusqlite::params![created_at, title],
+                )
+                .unwrap();
+        }
+        let at = |time: &str| format!("2024-05-01T{}Z", time).parse().unwrap();
+
+        assert_eq!(
+            mark_viewed(&connection, "alice", at("10:00:00")).unwrap(),
+            None
+        );
+        assert_eq!(
+            mark_viewed(&connection, "bob", at("12:00:00")).unwrap(),
+            None
+        );
+        let since = mark_viewed(&connection, "alice", at("13:00:00")).unwrap();
+        assert_eq!(since, Some(at("10:00:00")));
+
+        let options = PrintOptions {
+            new_since: since,
+            ..Default::default()
+        };
+        let todos = query_todos(&connection, &TodoQuery::list(DEFAULT_LIST)).unwrap();
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec!["0: old", "* 1: new"]
+        );
+        assert_eq!(
+            format_todos(
+                &todos[1..],
+                false,
+                &options,
+                &Style {
+                    color: true,
+                    ..Default::default()
+                },
+                today()
+            ),
+            vec!["\x1b[1m*\x1b[0m 1: new"]
+        );
+
+        let options = PrintOptions {
+            new_since: Some(at("13:00:00")),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec!["0: old", "1: new"]
+        );
+    }
+
+    #[test]
+    fn test_export_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["a".into(), "b".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+
+        let query = TodoQuery::list(DEFAULT_LIST);
+        let date = |s: &str| Some(s.parse::<NaiveDate>().unwrap());
+        let export = |since, until, by| -> Vec<String> {
+            let mut out = Vec::new();
+            export_command(&connection, &query, since, until, by, &mut out).unwrap();
+            serde_json::from_slice::<Vec<TodoDto>>(&out)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+        let today = Some(Local::now().date_naive());
+
+        assert_eq!(export(None, None, ExportBy::Created), vec!["a", "b"]);
+        assert_eq!(export(today, None, ExportBy::Created), vec!["a", "b"]);
+        assert_eq!(export(None, today, ExportBy::Completed), vec!["b"]);
+        assert!(export(None, date("2000-01-01"), ExportBy::Created).is_empty());
+        assert!(export(date("2999-01-01"), None, ExportBy::Completed).is_empty());
+
+        let error = export_command(
+            &connection,
+            &query,
+            date("2024-03-31"),
+            date("2024-01-01"),
+            ExportBy::Created,
+            &mut Vec::new(),
+        );
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Invalid range, 2024-03-31 comes after 2024-01-01"
+        );
+    }
+
+    #[test]
+    fn test_relative_phrases() {
+        assert_eq!(age_phrase(Duration::hours(5)), "today");
+        assert_eq!(age_phrase(Duration::hours(30)), "1 day ago");
+        assert_eq!(age_phrase(Duration::days(12)), "12 days ago");
+        assert_eq!(age_phrase(Duration::minutes(-1)), "today");
+
+        assert_eq!(time_left_phrase(Duration::hours(3)), "due today");
+        assert_eq!(time_left_phrase(Duration::hours(47)), "1 day left");
+        assert_eq!(time_left_phrase(Duration::days(3)), "3 days left");
+        assert_eq!(time_left_phrase(Duration::minutes(-1)), "overdue");
+    }
+
+    #[test]
+    fn test_auto_prune() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["old".into(), "recent".into(), "pending".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0, 1], true, None).unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET done_at = '2024-03-01 12:00:00.000' WHERE title = 'old';
+                UPDATE todos SET done_at = '2024-04-20 12:00:00.000' WHERE title = 'recent';",
+            )
+            .unwrap();
+        let now: DateTime<Utc> = "2024-05-01T12:00:00Z".parse().unwrap();
+
+        assert_eq!(auto_prune(&connection, Span::Days(30), now).unwrap(), 1);
+        let titles: Vec<String> = get_todos(&connection, DEFAULT_LIST)
+            .unwrap()
+            .into_iter()
+            .map(|todo| todo.title)
+            .collect();
+        assert_eq!(titles, vec!["recent", "pending"]);
+
+        // Pruned already today, so nothing is even looked at.
+        let later = now + Duration::hours(2);
+        assert_eq!(auto_prune(&connection, Span::Days(1), later).unwrap(), 0);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 2);
+
+        let next_day = now + Duration::days(1);
+        assert_eq!(auto_prune(&connection, Span::Days(1), next_day).unwrap(), 1);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_bulk_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["a".into(), "b".into(), "c".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let titles = |connection: &Connection| -> Vec<String> {
+            get_todos(connection, DEFAULT_LIST)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+
+        let mut file = "2: third\n\n0:  first  todo\n".as_bytes();
+        assert_eq!(
+            bulk_command(&mut connection, DEFAULT_LIST, &mut file, 0).unwrap(),
+            2
+        );
+        assert_eq!(titles(&connection), vec!["first todo", "b", "third"]);
+
+        // Nothing is applied when any line is bad.
+        for (content, line) in [
+            ("1: x\nnot an edit\n", 2),
+            ("1: x\n\nx: y\n", 3),
+            ("1:  \n", 1),
+            ("1: x\n1: y\n", 2),
+        ] {
+            let error = bulk_command(&mut connection, DEFAULT_LIST, &mut content.as_bytes(), 0);
+            match error {
+                Err(BulkCommandError::Parse(error)) => assert_eq!(error.line, line, "{}", content),
+                _ => panic!("{} should not parse", content),
+            }
+        }
+        let error = bulk_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut "1: x\n3: y".as_bytes(),
+            0,
+        );
+        assert!(matches!(error, Err(BulkCommandError::InvalidIndex(_))));
+        assert_eq!(titles(&connection), vec!["first todo", "b", "third"]);
+        assert_eq!(
+            ParseBulkError {
+                line: 2,
+                reason: "the title is empty"
+            }
+            .to_string(),
+            "Malformed line 2, the title is empty"
+        );
+    }
+
+    #[test]
+    fn test_edit_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let options = AddOptions {
+            label: Some(Label::Green),
+            ..Default::default()
+        };
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            get_todos(&connection, DEFAULT_LIST).unwrap()[0].label,
+            Some(Label::Green)
+        );
+
+        let edit = |connection: &mut Connection, index, options| {
+            edit_command(connection, DEFAULT_LIST, index, &options)
+        };
+        let edited = edit(
+            &mut connection,
+            1,
+            EditOptions {
+                title: Some("  renamed  todo ".into()),
+                label: Some(Label::Red),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(edited.title, "renamed todo");
+
+        edit(
+            &mut connection,
+            0,
+            EditOptions {
+                priority: Some(Priority::High),
+                no_label: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!((todos[0].label, todos[0].priority), (None, Priority::High));
+        assert_eq!(todos[0].title, "first");
+        assert_eq!(todos[1].title, "renamed todo");
+        assert_eq!(
+            (todos[1].label, todos[1].priority),
+            (Some(Label::Red), Priority::Medium)
+        );
+
+        let empty = EditOptions {
+            title: Some(" ".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            edit(&mut connection, 0, empty),
+            Err(EditCommandError::EmptyTitle)
+        ));
+        assert!(matches!(
+            edit(&mut connection, 2, EditOptions::default()),
+            Err(EditCommandError::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_note_preview() {
+        let noted = |title: &str, note: &str| {
+            let todo = Todo {
+                note: Some(note.into()),
+                ..Todo::new(title.into())
+            };
+            (0, todo)
+        };
+        let todos = vec![
+            noted("single", "call before noon"),
+            noted("multi", "first line\nsecond line"),
+            noted("long", "ünïcödé characters all the way down"),
+        ];
+        let options = PrintOptions {
+            show_note: true,
+            note_width: 12,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec![
+                "0: single - call before…",
+                "0: multi - first line…",
+                "0: long - ünïcödé cha…",
+            ]
+        );
+
+        let options = PrintOptions {
+            note_width: 40,
+            ..options
+        };
+        assert_eq!(
+            format_todos(&todos[..1], false, &options, &PLAIN, today()),
+            vec!["0: single - call before noon"]
+        );
+        assert_eq!(
+            format_todos(
+                &todos[..1],
+                false,
+                &PrintOptions::default(),
+                &PLAIN,
+                today()
+            ),
+            vec!["0: single"]
+        );
+    }
+
+    #[test]
+    fn test_format_status() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_with(
+            &mut connection,
+            "late",
+            Priority::Medium,
+            Some("2024-04-01"),
+        );
+        add_with(
+            &mut connection,
+            "today",
+            Priority::Medium,
+            Some("2024-05-01"),
+        );
+        add_with(
+            &mut connection,
+            "done late",
+            Priority::Medium,
+            Some("2024-04-01"),
+        );
+        add_with(&mut connection, "undated", Priority::Medium, None);
+        set_done_command(&mut connection, DEFAULT_LIST, vec![2], true, None).unwrap();
+
+        let status = get_status(&connection, DEFAULT_LIST, today()).unwrap();
+        let config = StatusConfig::default();
+        assert_eq!(format_status(&status, &config, &PLAIN), "✔1 ◻3 !1");
+
+        let status = get_status(&connection, "empty", today()).unwrap();
+        assert_eq!(format_status(&status, &config, &PLAIN), "✔0 ◻0");
+
+        let config = StatusConfig {
+            done: Some("D:".into()),
+            pending: Some("P:".into()),
+            overdue: None,
+        };
+        let status = Status {
+            done: 2,
+            pending: 1,
+            overdue: 1,
+        };
+        assert_eq!(format_status(&status, &config, &PLAIN), "D:2 P:1 !1");
+
+        let accessible = Style {
+            accessible: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_status(&status, &config, &accessible),
+            "2 done, 1 pending, 1 overdue."
+        );
+    }
+
+    #[test]
+    fn test_format_without_emoji() {
+        let todos = vec![
+            (
+                0,
+                Todo {
+                    done: true,
+                    label: Some(Label::Magenta),
+                    ..Todo::new("buy milk".into())
+                },
+            ),
+            (
+                1,
+                Todo {
+                    label: Some(Label::Red),
+                    note: Some("a note longer than the preview is wide".into()),
+                    ..Todo::new("call mom".into())
+                },
+            ),
+        ];
+        let options = PrintOptions {
+            show_note: true,
+            note_width: 10,
+            ..Default::default()
+        };
+
+        for color in [false, true] {
+            let style = Style {
+                color,
+                ascii: true,
+                ..Default::default()
+            };
+            let mut lines = format_todos(&todos, false, &options, &style, today());
+            lines.push(style.progress_bar(1, 2, 10));
+            let status = Status {
+                done: 1,
+                pending: 1,
+                overdue: 0,
+            };
+            lines.push(format_status(&status, &StatusConfig::default(), &style));
+
+            assert!(lines.iter().all(|line| line.is_ascii()), "{:?}", lines);
+        }
+    }
+
+    #[test]
+    fn test_print_ndjson() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let query = TodoQuery::list(DEFAULT_LIST);
+        let print = |connection: &Connection, options: &PrintOptions| {
+            let mut out = Vec::new();
+            print_command(connection, &query, options, &PLAIN, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let ndjson = PrintOptions {
+            ndjson: true,
+            ..Default::default()
+        };
+        assert_eq!(print(&connection, &ndjson), "");
+
+        add_with(&mut connection, "a\nb", Priority::High, None);
+        add_with(&mut connection, "c", Priority::Medium, None);
+        let output = print(&connection, &ndjson);
+        assert!(output.ends_with("}\n"));
+
+        let records: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let json = PrintOptions {
+            json: true,
+            ..Default::default()
+        };
+        let array: serde_json::Value = serde_json::from_str(&print(&connection, &json)).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(serde_json::Value::Array(records), array);
+    }
+
+    #[test]
+    fn test_tail() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        for title in ["a", "b", "c", "d", "e"] {
+            add_with(&mut connection, title, Priority::Medium, None);
+        }
+        let todos = query_todos(&connection, &TodoQuery::list(DEFAULT_LIST)).unwrap();
+
+        assert_eq!(
+            format_todos(
+                &tail(todos.clone(), Some(2)),
+                false,
+                &PrintOptions::default(),
+                &PLAIN,
+                today()
+            ),
+            vec!["3: d", "4: e"]
+        );
+        assert_eq!(tail(todos.clone(), Some(10)).len(), 5);
+        assert!(tail(todos.clone(), Some(0)).is_empty());
+        assert_eq!(tail(todos, None).len(), 5);
+    }
+
+    #[test]
+    fn test_group_done_last() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let query = TodoQuery::list(DEFAULT_LIST);
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            ["a", "b", "c", "d", "e"].map(String::from).to_vec(),
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0, 2], true, None).unwrap();
+        let options = PrintOptions {
+            group_done_last: true,
+            oneline: true,
+            ..Default::default()
+        };
+        let print = |connection: &Connection| {
+            let mut out = Vec::new();
+            print_command(connection, &query, &options, &PLAIN, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            print(&connection),
+            "1 b\n3 d\n4 e\n0 a (done)\n2 c (done)\n"
+        );
+        // The printed index names the same todo for done, whatever line it's on.
+        set_done_command(&mut connection, DEFAULT_LIST, vec![3], true, None).unwrap();
+        assert_eq!(
+            print(&connection),
+            "1 b\n4 e\n0 a (done)\n2 c (done)\n3 d (done)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_relative_indexes() {
+        let todo = |title: &str, done| Todo {
+            done,
+            ..Todo::new(title.into())
+        };
+        let todos = vec![
+            (0, todo("a", true)),
+            (1, todo("b", true)),
+            (2, todo("c", false)),
+            (3, todo("d", true)),
+            (5, todo("e", false)),
+        ];
+        let options = PrintOptions {
+            relative: true,
+            ..Default::default()
+        };
+
+        let indexes = |todos: &[(usize, Todo)]| -> Vec<String> {
+            format_todos(todos, false, &options, &PLAIN, today())
+                .into_iter()
+                .map(|line| line.split(':').next().unwrap().to_string())
+                .collect()
+        };
+
+        assert_eq!(indexes(&todos), vec!["-2", "-1", "2", "+1", "+3"]);
+        // Without a pending todo there's nothing to count from.
+        assert_eq!(indexes(&todos[..2]), vec!["0", "1"]);
+    }
+
+    #[test]
+    fn test_format_priorities() {
+        let todo = |title: &str, priority| Todo {
+            priority,
+            ..Todo::new(title.into())
+        };
+        let todos = vec![
+            (0, todo("urgent", Priority::High)),
+            (1, todo("normal", Priority::Medium)),
+            (2, todo("someday", Priority::Low)),
+            (
+                3,
+                Todo {
+                    due: Some("2024-04-30".parse().unwrap()),
+                    ..todo("late", Priority::High)
+                },
+            ),
+        ];
+        let options = PrintOptions::default();
+
+        assert_eq!(
+            format_todos(&todos, false, &options, &PLAIN, today()),
+            vec![
+                "0: (!) urgent",
+                "1: normal",
+                "2: (·) someday",
+                "! 3: (!) late (due 2024-04-30)"
+            ]
+        );
+
+        let colored = Style {
+            color: true,
+            ..PLAIN
+        };
+        assert_eq!(
+            format_todos(&todos, false, &options, &colored, today()),
+            vec![
+                "0: \x1b[1;31murgent\x1b[0m",
+                "1: normal",
+                "2: \x1b[2msomeday\x1b[0m",
+                "\x1b[31m3: late (due 2024-04-30)\x1b[0m"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_tree() {
+        let todo = |uuid: &str, parent: Option<&str>, done: bool| Todo {
+            uuid: uuid.into(),
+            parent: parent.map(Into::into),
+            done,
+            ..Todo::new(uuid.into())
+        };
+        let todos: Vec<(usize, Todo)> = [
+            todo("a", None, false),
+            todo("b", Some("a"), false),
+            todo("c", Some("b"), true),
+            todo("d", Some("a"), false),
+            todo("e", Some("removed"), false),
+            // A cycle, which only an import can make.
+            todo("f", Some("g"), false),
+            todo("g", Some("f"), false),
+            todo("h", Some("h"), false),
+        ]
+        .into_iter()
+        .enumerate()
+        .collect();
+        let options = PrintOptions {
+            tree: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render_todos(&todos, false, &options, &PLAIN, today()),
+            vec![
+                "0: a",
+                "├─ 1: b",
+                "│  └─ 2: c̶",
+                "└─ 3: d",
+                "4: e",
+                "7: h",
+                "5: f",
+                "└─ 6: g",
+            ]
+        );
+
+        let ascii = Style {
+            ascii: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render_todos(&todos[..4], false, &options, &ascii, today()),
+            vec!["0: a", "|- 1: b", "|  `- 2: ~~c~~", "`- 3: d"]
+        );
+    }
+
+    #[test]
+    fn test_add_subtask() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["parent".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let options = AddOptions {
+            parent: Some(TodoRef::Index(0)),
+            ..Default::default()
+        };
+        let added = add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["child".into()],
+            &options,
+        );
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(added.unwrap()[0].parent.as_ref(), Some(&todos[0].uuid));
+        assert_eq!(todos[1].parent.as_ref(), Some(&todos[0].uuid));
+
+        let options = AddOptions {
+            parent: Some(TodoRef::Index(5)),
+            ..Default::default()
+        };
+        let error = add_command(&mut connection, DEFAULT_LIST, vec!["x".into()], &options);
+        assert!(matches!(error, Err(AddCommandError::InvalidParent(_))));
+    }
+
+    #[test]
+    fn test_format_accessible() {
+        let todos = vec![
+            (
+                3,
+                Todo {
+                    done: true,
+                    ..Todo::new("buy milk".into())
+                },
+            ),
+            (4, Todo::new("call mom".into())),
+            (
+                5,
+                Todo {
+                    due: Some("2024-04-30".parse().unwrap()),
+                    label: Some(Label::Red),
+                    ..Todo::new("late".into())
+                },
+            ),
+        ];
+        let style = Style {
+            accessible: true,
+            ..Default::default()
+        };
+
+        let lines = format_todos(&todos, false, &PrintOptions::default(), &style, today());
+        assert_eq!(
+            lines,
+            vec![
+                "3. done: buy milk",
+                "4. pending: call mom",
+                "5. pending, overdue, red label: late (due 2024-04-30)",
+            ]
+        );
+        assert!(lines.iter().all(|line| !line.contains('\x1b')));
+        assert_eq!(
+            overdue_summary(&todos, &style, today()).unwrap(),
+            "1 todo is overdue."
+        );
+    }
+
+    #[test]
+    fn test_done_and_clear_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = (0..4).map(|i| format!("title{}", i)).collect();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, None).unwrap();
+
+        let (updated, cleared) = done_and_clear_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 2],
+            None,
+            &mut AssumeYes,
+            10,
+        )
+        .unwrap();
+        assert_eq!(updated.len(), 2);
+        let cleared: Vec<String> = cleared.into_iter().map(|todo| todo.title).collect();
+        assert_eq!(cleared, vec!["title0", "title1", "title2"]);
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].title, "title3");
+        assert!(!todos[0].done);
+    }
+
+    fn add_done(connection: &mut Connection, count: usize) {
+        let titles = (0..count).map(|i| format!("title{}", i)).collect();
+        add_command(connection, DEFAULT_LIST, titles, &AddOptions::default()).unwrap();
+        add_command(
+            connection,
+            DEFAULT_LIST,
+            vec!["pending".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(connection, DEFAULT_LIST, (0..count).collect(), true, None).unwrap();
+    }
+
+    fn titles(connection: &Connection) -> Vec<String> {
+        get_todos(connection, DEFAULT_LIST)
+            .unwrap()
+            .iter()
+            .map(|todo| todo.title.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_done(&mut connection, 6);
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive.log");
+
+        // Enter repeats the previous choice, a applies it to the rest.
+        let mut prompts = Vec::new();
+        let summary = clean_command(
+            &mut connection,
+            DEFAULT_LIST,
+            Some(&archive),
+            &mut "k\nd\n\nr\na\n".as_bytes(),
+            &mut prompts,
+        )
+        .unwrap()
+        .unwrap();
+
+        let titles_of = |todos: &[Todo]| -> Vec<String> {
+            todos.iter().map(|todo| todo.title.clone()).collect()
+        };
+        assert_eq!(summary.kept, 1);
+        assert_eq!(titles_of(&summary.deleted), vec!["title1", "title2"]);
+        assert_eq!(
+            titles_of(&summary.archived),
+            vec!["title3", "title4", "title5"]
+        );
+        assert_eq!(titles(&connection), vec!["title0", "pending"]);
+
+        let prompts = String::from_utf8(prompts).unwrap();
+        assert!(prompts.starts_with(
+            "[1/6] title0 - (k)eep, (d)elete, a(r)chive, (a)ll the rest, (q)uit [keep] "
+        ));
+        assert!(prompts.contains(
+            "[3/6] title2 - (k)eep, (d)elete, a(r)chive, (a)ll the rest, (q)uit [delete] "
+        ));
+        assert!(!prompts.contains("[6/6]"));
+
+        let log = std::fs::read_to_string(&archive).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.ends_with("\tdefault\ttitle5\n"));
+    }
+
+    #[test]
+    fn test_clean_command_quit_changes_nothing() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_done(&mut connection, 3);
+
+        for input in ["d\nd\nq\n", "d\n"] {
+            let summary = clean_command(
+                &mut connection,
+                DEFAULT_LIST,
+                None,
+                &mut input.as_bytes(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+            assert!(summary.is_none(), "{:?}", input);
+            assert_eq!(titles(&connection).len(), 4);
+        }
+
+        // Without an archive file there's no archive choice to make.
+        let mut prompts = Vec::new();
+        let summary = clean_command(
+            &mut connection,
+            DEFAULT_LIST,
+            None,
+            &mut "r\nd\nk\n\n".as_bytes(),
+            &mut prompts,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(summary.deleted.len(), 1);
+        assert_eq!(summary.kept, 2);
+        assert_eq!(titles(&connection), vec!["title1", "title2", "pending"]);
+        let prompts = String::from_utf8(prompts).unwrap();
+        assert!(prompts.contains("Unknown answer 'r'"));
+        assert!(!prompts.contains("a(r)chive"));
+    }
+
+    #[test]
+    fn test_done_and_clear_command_declined_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive.log");
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = vec!["title1".into(), "title2".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let mut confirm = ScriptedConfirm::new(false);
+        let error = done_and_clear_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 1],
+            Some(&archive),
+            &mut confirm,
+            1,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            DoneAndClearCommandError::Clear(ClearCommandError::Confirm(ConfirmError::Declined))
+        ));
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert!(todos.iter().all(|todo| !todo.done));
+        assert!(!archive.exists());
+
+        done_and_clear_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            Some(&archive),
+            &mut AssumeYes,
+            1,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&archive).unwrap().lines().count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_overdue_todos_most_overdue_first() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_with(
+            &mut connection,
+            "a bit late",
+            Priority::High,
+            Some("2024-04-28"),
+        );
+        add_with(
+            &mut connection,
+            "on time",
+            Priority::Medium,
+            Some("2024-05-01"),
+        );
+        add_with(&mut connection, "undated", Priority::Medium, None);
+        add_with(
+            &mut connection,
+            "very late",
+            Priority::Low,
+            Some("2024-03-01"),
+        );
+        add_with(
+            &mut connection,
+            "done late",
+            Priority::Low,
+            Some("2024-03-01"),
+        );
+        add_with(
+            &mut connection,
+            "yesterday",
+            Priority::Low,
+            Some("2024-04-30"),
+        );
+        set_done_command(&mut connection, DEFAULT_LIST, vec![4], true, None).unwrap();
+
+        let todos =
+            query_overdue_todos(&connection, &TodoQuery::list(DEFAULT_LIST), today()).unwrap();
+        let overdue: Vec<(usize, String, String)> = todos
+            .into_iter()
+            .map(|(index, todo)| {
+                (
+                    index,
+                    todo.title,
+                    overdue_phrase(todo.due.unwrap(), today()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            overdue,
+            vec![
+                (3, "very late".into(), "61 days overdue".into()),
+                (0, "a bit late".into(), "3 days overdue".into()),
+                (5, "yesterday".into(), "1 day overdue".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_multiline_titles() {
+        let todos = vec![
+            (0, Todo::new("first\nsecond".into())),
+            (1, Todo::new("windows \r\nline".into())),
+            (2, Todo::new("trailing\n".into())),
+        ];
+
+        assert_eq!(
+            format_todos(&todos, false, &PrintOptions::default(), &PLAIN, today()),
+            vec!["0: first…", "1: windows…", "2: trailing"]
+        );
+        let table = PrintOptions {
+            columns: vec![Column::Title],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_todos(&todos[..1], false, &table, &PLAIN, today())
+                .last()
+                .unwrap()
+                .trim_end(),
+            "first…"
+        );
+
+        let json = serde_json::to_string(&indexed(&todos)).unwrap();
+        assert_eq!(json.lines().count(), 1);
+        assert!(json.contains(r#""title":"windows \r\nline""#));
+    }
+
+    #[test]
+    fn test_format_titles_with_control_characters() {
+        let todos = vec![
+            (0, Todo::new("tab\there".into())),
+            (1, Todo::new("\x1b[31mred\x1b[0m".into())),
+            (2, Todo::new("bell\x07\nnext \x1b[2J".into())),
+            (3, Todo::new("plain \"quoted\" é".into())),
+        ];
+
+        let lines = format_todos(&todos, false, &PrintOptions::default(), &PLAIN, today());
+        assert_eq!(
+            lines,
+            vec![
+                r"0: tab\there",
+                r"1: \u{1b}[31mred\u{1b}[0m",
+                r"2: bell\u{7}…",
+                "3: plain \"quoted\" é",
+            ]
+        );
+        assert!(lines.iter().all(|line| !line.contains(char::is_control)));
+
+        let table = PrintOptions {
+            columns: vec![Column::Index, Column::Title],
+            ..Default::default()
+        };
+        let rows = render_todos(&todos, false, &table, &PLAIN, today());
+        assert!(rows.iter().all(|row| !row.contains(char::is_control)));
+        assert_eq!(rows[1].trim_end(), r"0      tab\there");
+
+        let note = Todo {
+            note: Some("a\tb\x1b[1m".into()),
+            ..Todo::new("noted".into())
+        };
+        let options = PrintOptions {
+            show_note: true,
+            note_width: 40,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_todos(&[(0, note)], false, &options, &PLAIN, today())[0],
+            r"0: noted - a\tb\u{1b}[1m"
+        );
+
+        // JSON keeps the title as it is, escaped the JSON way.
+        let json = serde_json::to_string(&indexed(&todos[1..2])).unwrap();
+        assert!(json.contains(r#""title":"\u001b[31mred\u001b[0m""#));
+    }
+
+    #[test]
+    fn test_format_tags_and_lists_with_control_characters() {
+        let todos = vec![(
+            0,
+            Todo {
+                list: "w\tork".into(),
+                tags: vec!["x\x1b[2J".into(), "ok".into()],
+                ..Todo::new("title".into())
+            },
+        )];
+        let no_control =
+            |lines: &[String]| lines.iter().all(|line| !line.contains(char::is_control));
+
+        let lines = format_todos(&todos, true, &PrintOptions::default(), &PLAIN, today());
+        assert_eq!(lines, vec![r"[w\tork] 0: title #x\u{1b}[2J #ok"]);
+        let spoken = Style {
+            accessible: true,
+            ..PLAIN
+        };
+        let lines = format_todos(&todos, true, &PrintOptions::default(), &spoken, today());
+        assert!(no_control(&lines), "{:?}", lines);
+        let table = PrintOptions {
+            columns: vec![Column::List, Column::Tags],
+            ..Default::default()
+        };
+        let rows = render_todos(&todos, true, &table, &PLAIN, today());
+        assert_eq!(rows[1].trim_end(), r"w\tork  #x\u{1b}[2J #ok");
+
+        let stats = [ListStats {
+            name: "w\tork".into(),
+            pending: 1,
+            done: 0,
+        }];
+        assert_eq!(
+            format_lists(&stats, "default", "default"),
+            "  w\\tork  1 pending, 0 done\n"
+        );
+    }
+
+    #[test]
+    fn test_render_oneline() {
+        let todo = |index, title: &str, done| {
+            let todo = Todo {
+                done,
+                label: Some(Label::Red),
+                due: Some("2024-04-01".parse().unwrap()),
+                ..Todo::new(title.into())
+            };
+            (index, todo)
+        };
+        let todos = vec![
+            todo(0, "buy milk", false),
+            todo(1, "first line\nsecond\r\nthird\tend", false),
+            todo(3, "call mom", true),
+        ];
+        let options = PrintOptions {
+            oneline: true,
+            ..Default::default()
+        };
+
+        let lines = render_todos(
+            &todos,
+            true,
+            &options,
+            &Style {
+                color: true,
+                ..Default::default()
+            },
+            today(),
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "0 buy milk",
+                "1 first line second  third end",
+                "3 call mom (done)"
+            ]
+        );
+    }
+
+    fn table_todos() -> Vec<(usize, Todo)> {
+        let first = Todo {
+            id: 7,
+            priority: Priority::High,
+            tags: vec!["work".into()],
+            ..Todo::new("write report".into())
+        };
+        let second = Todo {
+            id: 9,
+            done: true,
+            due: Some("2024-06-01".parse().unwrap()),
+            ..Todo::new("call".into())
+        };
+        vec![(0, first), (1, second)]
+    }
+
+    #[test]
+    fn test_table_column_subset() {
+        let options = PrintOptions {
+            columns: vec![Column::Index, Column::Priority, Column::Title],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_todos(&table_todos(), false, &options, &PLAIN, today()),
+            vec![
+                "INDEX  PRIORITY  TITLE",
+                "0      High      write report",
+                "1      Medium    call",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_columns_keep_requested_order() {
+        let options = PrintOptions {
+            columns: vec![Column::Title, Column::Due, Column::Id, Column::Done],
+            ..Default::default()
+        };
+        assert_eq!(
+            render_todos(&table_todos(), false, &options, &PLAIN, today()),
+            vec![
+                "TITLE         DUE         ID  DONE",
+                "write report              7",
+                "call          2024-06-01  9   x",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_invalid_column() {
+        let args = Args::try_parse_from(["todo-cli", "print", "--columns", "index,size"]);
+        let error = args.err().unwrap().to_string();
+        assert!(error.contains("Unknown column 'size'"), "{}", error);
+        assert!(error.contains("index, id, list, done, priority, due, title, tags, note"));
+
+        assert_eq!(parse_column(" Title "), Ok(Column::Title));
+        assert!(parse_column("size").is_err());
+    }
+
+    #[test]
+    fn test_today_agenda() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let query = TodoQuery::list(DEFAULT_LIST);
+        assert!(get_agenda(&connection, &query, today())
+            .unwrap()
+            .iter()
+            .all(Vec::is_empty));
+
+        add_with(
+            &mut connection,
+            "later",
+            Priority::Medium,
+            Some("2024-06-01"),
+        );
+        add_with(
+            &mut connection,
+            "due today",
+            Priority::Low,
+            Some("2024-05-01"),
+        );
+        add_with(
+            &mut connection,
+            "late and urgent",
+            Priority::High,
+            Some("2024-04-01"),
+        );
+        add_with(&mut connection, "urgent", Priority::High, None);
+        add_with(&mut connection, "done urgent", Priority::High, None);
+        add_with(&mut connection, "someday", Priority::Low, None);
+        set_done_command(&mut connection, DEFAULT_LIST, vec![4], true, None).unwrap();
+
+        let titles: Vec<Vec<(usize, String)>> = get_agenda(&connection, &query, today())
+            .unwrap()
+            .into_iter()
+            .map(|todos| {
+                todos
+                    .into_iter()
+                    .map(|(index, todo)| (index, todo.title))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            titles,
+            vec![
+                vec![(2, "late and urgent".to_string())],
+                vec![(1, "due today".to_string())],
+                vec![(3, "urgent".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_week_groups_by_day() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_with(
+            &mut connection,
+            "friday",
+            Priority::Medium,
+            Some("2024-05-03"),
+        );
+        add_with(
+            &mut connection,
+            "late",
+            Priority::Medium,
+            Some("2024-04-30"),
+        );
+        add_with(
+            &mut connection,
+            "today",
+            Priority::Medium,
+            Some("2024-05-01"),
+        );
+        add_with(
+            &mut connection,
+            "next week",
+            Priority::Medium,
+            Some("2024-05-08"),
+        );
+        add_with(
+            &mut connection,
+            "also friday",
+            Priority::Low,
+            Some("2024-05-03"),
+        );
+        add_with(&mut connection, "undated", Priority::Medium, None);
+        add_with(
+            &mut connection,
+            "done today",
+            Priority::Medium,
+            Some("2024-05-01"),
+        );
+        set_done_command(&mut connection, DEFAULT_LIST, vec![6], true, None).unwrap();
+
+        let titles = |todos: &[(usize, Todo)]| -> Vec<String> {
+            todos.iter().map(|(_, todo)| todo.title.clone()).collect()
+        };
+        let query = TodoQuery::list(DEFAULT_LIST);
+
+        let week = get_week(&connection, &query, today(), false).unwrap();
+        assert_eq!(titles(&week.overdue), vec!["late"]);
+        assert_eq!(week.days.len(), 7);
+        assert_eq!(week.days[0].0.to_string(), "2024-05-01");
+        assert_eq!(titles(&week.days[0].1), vec!["today"]);
+        assert!(week.days[1].1.is_empty());
+        assert_eq!(titles(&week.days[2].1), vec!["friday", "also friday"]);
+        assert!(week.days[3..].iter().all(|(_, todos)| todos.is_empty()));
+        assert!(week.no_date.is_none());
+
+        let week = get_week(&connection, &query, today(), true).unwrap();
+        assert_eq!(titles(&week.no_date.unwrap()), vec!["undated"]);
+    }
+
+    #[test]
+    fn test_plan_postpone() {
+        let todo = |due: Option<&str>| Todo {
+            due: due.map(|due| due.parse().unwrap()),
+            ..Todo::new("title".into())
+        };
+        let todos = vec![(0, todo(Some("2024-04-30"))), (2, todo(None))];
+
+        let postponed = plan_postpone(todos, Span::Days(1), today()).unwrap();
+        let moves: Vec<(usize, Option<String>, String)> = postponed
+            .iter()
+            .map(|postponed| {
+                (
+                    postponed.index,
+                    postponed.from.map(|from| from.to_string()),
+                    postponed.todo.due.unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            moves,
+            vec![
+                (0, Some("2024-04-30".into()), "2024-05-01".into()),
+                (2, None, "2024-05-02".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_postpone_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_with(
+            &mut connection,
+            "dated",
+            Priority::Medium,
+            Some("2099-01-31"),
+        );
+        add_with(
+            &mut connection,
+            "untouched",
+            Priority::Medium,
+            Some("2099-01-31"),
+        );
+
+        let due = |connection: &Connection| -> Vec<Option<String>> {
+            get_todos(connection, DEFAULT_LIST)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.due.map(|due| due.to_string()))
+                .collect()
+        };
+
+        postpone_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            false,
+            Span::Months(1),
+            true,
+            &PLAIN,
+        )
+        .unwrap();
+        assert_eq!(due(&connection)[0].as_deref(), Some("2099-01-31"));
+
+        postpone_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            false,
+            Span::Months(1),
+            false,
+            &PLAIN,
+        )
+        .unwrap();
+        assert_eq!(
+            due(&connection),
+            vec![Some("2099-02-28".into()), Some("2099-01-31".into())]
+        );
+
+        assert!(matches!(
+            postpone_command(
+                &mut connection,
+                DEFAULT_LIST,
+                vec![5],
+                false,
+                Span::Days(1),
+                false,
+                &PLAIN
+            ),
+            Err(PostponeCommandError::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_move_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let titles = ["a", "b", "c", "d"].map(String::from).to_vec();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let order = |connection: &Connection| -> String {
+            get_todos(connection, DEFAULT_LIST)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+
+        move_command(&mut connection, DEFAULT_LIST, 2, MoveTarget::Top).unwrap();
+        assert_eq!(order(&connection), "cabd");
+
+        move_command(&mut connection, DEFAULT_LIST, 1, MoveTarget::Bottom).unwrap();
+        assert_eq!(order(&connection), "cbda");
+
+        move_command(&mut connection, DEFAULT_LIST, 0, MoveTarget::Index(2)).unwrap();
+        assert_eq!(order(&connection), "bdca");
+
+        assert!(matches!(
+            move_command(&mut connection, DEFAULT_LIST, 4, MoveTarget::Top),
+            Err(MoveCommandError::InvalidIndex(InvalidIndexError(4)))
+        ));
+        assert!(matches!(
+            move_command(&mut connection, DEFAULT_LIST, 0, MoveTarget::Index(4)),
+            Err(MoveCommandError::InvalidIndex(InvalidIndexError(4)))
+        ));
+    }
+
+    #[test]
+    fn test_write_json_event() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let titles = vec!["title1".into(), "title2".into()];
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            titles,
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        write_json_event(&connection, &TodoQuery::list(DEFAULT_LIST), &mut out).unwrap();
+        write_json_event(&connection, &TodoQuery::list("other"), &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        let events: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events.len(), 2);
+        assert!(events[0]["at"].is_string());
+        assert_eq!(events[0]["todos"][1]["index"], 1);
+        assert_eq!(events[0]["todos"][1]["title"], "title2");
+        assert_eq!(events[1]["todos"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_min_priority_filter() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_with(&mut connection, "low", Priority::Low, None);
+        add_with(&mut connection, "high", Priority::High, None);
+        add_with(&mut connection, "medium", Priority::Medium, None);
+
+        let titles = |level: &str| -> Vec<(usize, String)> {
+            let args = Args::parse_from(["todo-cli", "print", "--min-priority", level]);
+            let Some(Commands::Print { filter, .. }) = args.command else {
+                panic!("expected the print command");
+            };
+            let query = TodoQuery {
+                min_priority: filter.min_priority,
+                ..TodoQuery::list(DEFAULT_LIST)
+            };
+            query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(index, todo)| (index, todo.title))
+                .collect()
+        };
+
+        assert_eq!(titles("high"), vec![(1, "high".to_string())]);
+        assert_eq!(
+            titles("medium"),
+            vec![(1, "high".to_string()), (2, "medium".to_string())]
+        );
+        assert_eq!(titles("low").len(), 3);
+    }
+
+    fn import(
+        connection: &mut Connection,
+        input: &str,
+        prefer: Prefer,
+        dry_run: bool,
+    ) -> Vec<(Todo, Merge)> {
+        import_command(
+            connection,
+            DEFAULT_LIST,
+            &mut input.as_bytes(),
+            prefer,
+            dry_run,
+            false,
+            false,
+        )
+        .unwrap()
+    }
+
+    fn merges(plan: &[(Todo, Merge)]) -> Vec<Merge> {
+        plan.iter().map(|(_, merge)| *merge).collect()
+    }
+
+    #[test]
+    fn test_plan_import() {
+        let at = |time: &str| Some(format!("2024-05-01T{}Z", time).parse().unwrap());
+        let local = Todo {
+            id: 4,
+            updated_at: at("10:00:00"),
+            ..Todo::new("title".into())
+        };
+        let existing = HashMap::from([(local.uuid.clone(), local.clone())]);
+        let plan = |remote: Todo, prefer| {
+            plan_import(vec![remote], existing.clone(), prefer)
+                .pop()
+                .unwrap()
+        };
+
+        let (todo, merge) = plan(local.clone(), Prefer::Local);
+        assert_eq!((todo.id, merge), (4, Merge::Skipped));
+        assert_eq!(
+            plan(Todo::new("title".into()), Prefer::Local).1,
+            Merge::Added
+        );
+
+        let noted = Todo {
+            note: Some("note".into()),
+            updated_at: at("09:00:00"),
+            ..local.clone()
+        };
+        assert_eq!(plan(noted.clone(), Prefer::Remote).1, Merge::Updated);
+        assert_eq!(plan(noted.clone(), Prefer::Local).1, Merge::Skipped);
+        assert_eq!(plan(noted, Prefer::Newer).1, Merge::Skipped);
+
+        let done = Todo {
+            done: true,
+            updated_at: at("11:00:00"),
+            ..local.clone()
+        };
+        let kept = |kept_local| Merge::Conflicted { kept_local };
+        assert_eq!(plan(done.clone(), Prefer::Newer).1, kept(false));
+        assert_eq!(plan(done.clone(), Prefer::Local).1, kept(true));
+        let older = Todo {
+            updated_at: at("09:00:00"),
+            ..done.clone()
+        };
+        assert_eq!(plan(older.clone(), Prefer::Newer).1, kept(true));
+        assert_eq!(plan(older, Prefer::Remote).1, kept(false));
+        let unstamped = Todo {
+            updated_at: None,
+            ..done
+        };
+        assert_eq!(plan(unstamped, Prefer::Newer).1, kept(false));
+    }
+
+    #[test]
+    fn test_import_replace_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let mut kept = get_todos(&connection, DEFAULT_LIST).unwrap()[1].clone();
+        kept.title = "second, imported".into();
+        let imported = serde_json::to_string(&vec![
+            TodoDto::from(kept),
+            TodoDto::from(Todo::new("third".into())),
+        ])
+        .unwrap();
+        let titles = |connection: &Connection| -> Vec<String> {
+            get_todos(connection, DEFAULT_LIST)
+                .unwrap()
+                .into_iter()
+                .map(|todo| todo.title)
+                .collect()
+        };
+
+        let mut confirm = ScriptedConfirm::new(false);
+        let error = import_replace_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut imported.as_bytes(),
+            false,
+            false,
+            &mut confirm,
+            None,
+        );
+        assert!(matches!(
+            error,
+            Err(ImportCommandError::Confirm(ConfirmError::Declined))
+        ));
+        assert_eq!(confirm.prompts, vec!["Replace 2 todos with 2 imported?"]);
+        assert_eq!(titles(&connection), vec!["first", "second"]);
+
+        let mut confirm = ScriptedConfirm::new(true);
+        let counts = import_replace_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut imported.as_bytes(),
+            false,
+            false,
+            &mut confirm,
+            None,
+        );
+        let (removed, added) = counts.unwrap();
+        assert_eq!((removed, added.len()), (2, 2));
+        assert_eq!(titles(&connection), vec!["second, imported", "third"]);
+        assert_eq!(
+            added.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            get_todos(&connection, DEFAULT_LIST)
+                .unwrap()
+                .iter()
+                .map(|todo| todo.id)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_import_command_skips_and_dry_runs() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let exported: Vec<TodoDto> = get_todos(&connection, DEFAULT_LIST)
+            .unwrap()
+            .into_iter()
+            .map(TodoDto::from)
+            .collect();
+
+        let unchanged = serde_json::to_string(&exported).unwrap();
+        let plan = import(&mut connection, &unchanged, Prefer::Newer, false);
+        assert_eq!(merges(&plan), vec![Merge::Skipped, Merge::Skipped]);
+
+        let mut changed = exported.clone();
+        changed[0].done = true;
+        changed[1].priority = Priority::High;
+        changed.push(TodoDto::from(Todo::new("third".into())));
+        let changed = serde_json::to_string(&changed).unwrap();
+        let expected = vec![
+            Merge::Conflicted { kept_local: false },
+            Merge::Updated,
+            Merge::Added,
+        ];
+        assert_eq!(
+            merges(&import(&mut connection, &changed, Prefer::Newer, true)),
+            expected
+        );
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert!(!todos[0].done);
+
+        // Timestamps have millisecond resolution.
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(
+            merges(&import(&mut connection, &changed, Prefer::Newer, false)),
+            expected
+        );
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 3);
+        assert!(todos[0].done);
+        assert_eq!(todos[1].priority, Priority::High);
+        assert!(todos[0].updated_at > exported[0].updated_at);
+
+        // The local edits are newer now, so importing the old export again keeps them.
+        assert_eq!(
+            merges(&import(&mut connection, &unchanged, Prefer::Newer, false)),
+            vec![Merge::Conflicted { kept_local: true }, Merge::Skipped]
+        );
+        assert!(get_todos(&connection, DEFAULT_LIST).unwrap()[0].done);
+    }
+
+    #[test]
+    fn test_import_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["existing".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let input = r#"[
+            {"index": 0, "id": 7, "title": "full", "done": true, "list": "work",
+             "tags": ["a"], "priority": "high", "due": "2024-05-01", "note": "n"},
+            {"title": "minimal"}
+        ]"#;
+        let plan = import(&mut connection, input, Prefer::Newer, false);
+        assert_eq!(merges(&plan), vec![Merge::Added, Merge::Added]);
+
+        let work = get_todos(&connection, "work").unwrap();
+        assert_eq!(work[0].title, "full");
+        assert!(work[0].done);
+        assert_eq!(work[0].tags, vec!["a"]);
+        assert_eq!(work[0].priority, Priority::High);
+        assert_eq!(work[0].note.as_deref(), Some("n"));
+
+        let titles: Vec<String> = get_todos(&connection, DEFAULT_LIST)
+            .unwrap()
+            .into_iter()
+            .map(|todo| todo.title)
+            .collect();
+        assert_eq!(titles, vec!["existing", "minimal"]);
+
+        assert!(matches!(
+            import_command(
+                &mut connection,
+                DEFAULT_LIST,
+                &mut "[{\"title\": \" \"}]".as_bytes(),
+                Prefer::Newer,
+                false,
+                false,
+                false
+            ),
+            Err(ImportCommandError::EmptyTitle(_))
+        ));
+        assert!(matches!(
+            import_command(
+                &mut connection,
+                DEFAULT_LIST,
+                &mut "{".as_bytes(),
+                Prefer::Newer,
+                false,
+                false,
+                false
+            ),
+            Err(ImportCommandError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_command_dedup() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let input = r#"[
+            {"title": "milk", "note": "first"},
+            {"title": "eggs", "done": true},
+            {"title": " Milk ", "done": true, "note": "done one"},
+            {"title": "milk", "list": "work"},
+            {"title": "eggs"},
+            {"title": "MILK", "done": true, "note": "later done one"}
+        ]"#;
+
+        let plan = import_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut input.as_bytes(),
+            Prefer::Newer,
+            false,
+            false,
+            true,
+        )
+        .unwrap();
+        assert_eq!(plan.len(), 3);
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        let summary: Vec<(&str, bool, Option<&str>)> = todos
+            .iter()
+            .map(|todo| (todo.title.as_str(), todo.done, todo.note.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(" Milk ", true, Some("done one")), ("eggs", true, None)]
+        );
+        assert_eq!(get_todos(&connection, "work").unwrap().len(), 1);
+
+        let mut confirm = ScriptedConfirm::new(true);
+        import_replace_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut input.as_bytes(),
+            false,
+            true,
+            &mut confirm,
+            None,
+        )
+        .unwrap();
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_demo_command() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+
+        let added = demo_command(&mut connection, DEFAULT_LIST, false, today).unwrap();
+        assert_eq!(added.len(), DEMO_TODOS.len());
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), DEMO_TODOS.len() - DEMO_OTHER_COUNT);
+        assert!(todos.iter().any(|todo| todo.done));
+        assert!(todos.iter().any(|todo| todo.is_overdue(today)));
+        assert!(todos.iter().any(|todo| todo.is_due_today(today)));
+        assert!(todos.iter().any(|todo| !todo.title.is_ascii()));
+        assert_eq!(
+            get_todos(&connection, DEMO_OTHER_LIST).unwrap().len(),
+            DEMO_OTHER_COUNT
+        );
+
+        assert!(matches!(
+            demo_command(&mut connection, "other", false, today),
+            Err(DemoCommandError::NotEmpty)
+        ));
+        demo_command(&mut connection, "other", true, today).unwrap();
+        assert_eq!(
+            get_todos(&connection, DEMO_OTHER_LIST).unwrap().len(),
+            DEMO_OTHER_COUNT * 2
+        );
+    }
+
+    #[test]
+    fn test_search_command_reports_matches() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["deploy the app".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        let search = |text: &str| TodoQuery {
+            search: vec![text.into()],
+            ..TodoQuery::list(DEFAULT_LIST)
+        };
+        let options = PrintOptions::default();
+        assert!(search_command(&connection, &search("deploy"), true, &options, &PLAIN).unwrap());
+        assert!(!search_command(&connection, &search("release"), true, &options, &PLAIN).unwrap());
+    }
+
+    #[test]
+    fn test_prefix_filter() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_with(&mut connection, "Project/Design", Priority::High, None);
+        add_with(&mut connection, "Other/Thing", Priority::High, None);
+        add_with(&mut connection, "Project/Build", Priority::Low, None);
+        add_with(&mut connection, "100%_done", Priority::Low, None);
+
+        let titles = |args: &[&str]| -> Vec<String> {
+            let args = Args::parse_from(["todo-cli", "print"].iter().chain(args));
+            let Some(Commands::Print { filter, .. }) = args.command else {
+                panic!("expected the print command");
+            };
+            let query = TodoQuery {
+                min_priority: filter.min_priority,
+                prefix: filter.prefix,
+                ..TodoQuery::list(DEFAULT_LIST)
+            };
+            query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(&["--prefix", "Project/"]),
+            vec!["Project/Design", "Project/Build"]
+        );
+        assert_eq!(
+            titles(&["--prefix", "Project/", "--min-priority", "high"]),
+            vec!["Project/Design"]
+        );
+        assert_eq!(titles(&["--prefix", "100%"]), vec!["100%_done"]);
+        assert_eq!(titles(&["--prefix", "1_0"]), Vec::<String>::new());
+        assert_eq!(titles(&["--prefix", ""]).len(), 4);
+    }
+
+    #[test]
+    fn test_set_done_command_without_indexes() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        assert!(matches!(
+            set_done_command(&mut connection, DEFAULT_LIST, vec![], true, None),
+            Err(SetDoneCommandError::NoPendingTodo)
+        ));
+
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let updated = set_done_command(&mut connection, DEFAULT_LIST, vec![], true, None).unwrap();
+        assert_eq!(updated[0].title, "second");
+        // Completion times are kept to the millisecond.
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let updated = set_done_command(&mut connection, DEFAULT_LIST, vec![], true, None).unwrap();
+        assert_eq!(updated[0].title, "first");
+        assert!(matches!(
+            set_done_command(&mut connection, DEFAULT_LIST, vec![], true, None),
+            Err(SetDoneCommandError::NoPendingTodo)
+        ));
+
+        let updated = set_done_command(&mut connection, DEFAULT_LIST, vec![], false, None).unwrap();
+        assert_eq!(updated[0].title, "first");
+        assert!(!get_todos(&connection, DEFAULT_LIST).unwrap()[0].done);
+        set_done_command(&mut connection, DEFAULT_LIST, vec![], false, None).unwrap();
+        assert!(matches!(
+            set_done_command(&mut connection, DEFAULT_LIST, vec![], false, None),
+            Err(SetDoneCommandError::NoDoneTodo)
+        ));
+    }
+
+    #[test]
+    fn test_set_done_command_appends_to_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive.log");
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into(), "second".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        set_done_command(&mut connection, DEFAULT_LIST, vec![1], true, Some(&archive)).unwrap();
+        // Already done todos and undoing don't add lines.
+        set_done_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0, 1],
+            true,
+            Some(&archive),
+        )
+        .unwrap();
+        set_done_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            false,
+            Some(&archive),
+        )
+        .unwrap();
+
+        let log = std::fs::read_to_string(&archive).unwrap();
+        let lines: Vec<Vec<&str>> = log.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0][1..], ["default", "second"]);
+        assert_eq!(lines[1][1..], ["default", "first"]);
+        assert!(DateTime::parse_from_rfc3339(lines[0][0]).is_ok());
+
+        let at = DateTime::parse_from_rfc3339("2024-05-01T12:00:00+02:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let todo = Todo::new("two\nlines".into());
+        assert!(archive_lines(&[&todo], at).ends_with("\tdefault\ttwo lines\n"));
+    }
+
+    #[test]
+    fn test_read_titles() {
+        let mut input = "buy milk\n\n  \ncall mom\r\n".as_bytes();
+        assert_eq!(
+            read_titles(&mut input, None).unwrap(),
+            vec!["buy milk", "call mom"]
+        );
+
+        let mut prompt = Vec::new();
+        let mut input = "buy milk\ncall mom\n\nignored\n".as_bytes();
+        assert_eq!(
+            read_titles(&mut input, Some(&mut prompt)).unwrap(),
+            vec!["buy milk", "call mom"]
+        );
+        assert_eq!(prompt, b"> > > ");
+
+        // Ctrl-D ends the input like an empty line.
+        let mut input = "buy milk".as_bytes();
+        assert_eq!(
+            read_titles(&mut input, Some(&mut Vec::new())).unwrap(),
+            vec!["buy milk"]
+        );
+    }
+
+    #[test]
+    fn test_import_command_matches_by_uuid() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["same title".into(), "other".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let existing = get_todos(&connection, DEFAULT_LIST).unwrap();
+
+        // The title alone doesn't make it the same todo, the UUID does.
+        let input = serde_json::json!([
+            {"title": "same title"},
+            {"uuid": existing[1].uuid, "title": "renamed", "done": true},
+        ])
+        .to_string();
+        let plan = import(&mut connection, &input, Prefer::Newer, false);
+        assert_eq!(
+            merges(&plan),
+            vec![Merge::Added, Merge::Conflicted { kept_local: false }]
+        );
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        let titles: Vec<&str> = todos.iter().map(|todo| todo.title.as_str()).collect();
+        assert_eq!(titles, vec!["same title", "renamed", "same title"]);
+        assert_eq!(todos[1].uuid, existing[1].uuid);
+        assert!(todos[1].done);
+        assert_ne!(todos[2].uuid, existing[0].uuid);
+    }
+
+    #[test]
+    fn test_resolve_refs() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todos = ["aaaa1111", "aaaa2222", "bbbb1111"]
+            .iter()
+            .map(|uuid| Todo {
+                uuid: format!("{}-0000-4000-8000-000000000000", uuid),
+                ..Todo::new(uuid.to_string())
+            })
+            .collect();
+        add_todos(&mut connection, todos).unwrap();
+
+        let refs = vec![
+            TodoRef::Uuid("bbbb".into()),
+            TodoRef::Index(7),
+            TodoRef::Uuid("aaaa2".into()),
+        ];
+        assert_eq!(
+            resolve_refs(&connection, DEFAULT_LIST, refs, false, 0).unwrap(),
+            vec![2, 7, 1]
+        );
+        assert!(matches!(
+            resolve_refs(
+                &connection,
+                DEFAULT_LIST,
+                vec![TodoRef::Uuid("aaaa".into())],
+                false,
+                0
+            ),
+            Err(ResolveRefsError::Ambiguous(_))
+        ));
+        assert!(matches!(
+            resolve_refs(
+                &connection,
+                DEFAULT_LIST,
+                vec![TodoRef::Uuid("cccc".into())],
+                false,
+                0
+            ),
+            Err(ResolveRefsError::NoMatch(_))
+        ));
+        assert!(matches!(
+            resolve_refs(
+                &connection,
+                "other",
+                vec![TodoRef::Uuid("bbbb".into())],
+                false,
+                0
+            ),
+            Err(ResolveRefsError::NoMatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_line_numbers_from() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todos = ["a", "b", "c"]
+            .iter()
+            .map(|title| Todo::new(title.to_string()))
+            .collect();
+        add_todos(&mut connection, todos).unwrap();
+
+        let index = |refs: Vec<usize>, base| {
+            let refs = refs.into_iter().map(TodoRef::Index).collect();
+            resolve_refs(&connection, DEFAULT_LIST, refs, false, base)
+        };
+        assert_eq!(index(vec![0, 2], 0).unwrap(), vec![0, 2]);
+        assert_eq!(index(vec![1, 3], 1).unwrap(), vec![0, 2]);
+        for missing in [0, 4] {
+            assert!(matches!(
+                index(vec![missing], 1),
+                Err(ResolveRefsError::NoSuchIndex(index)) if index == missing
+            ));
+        }
+
+        let first = index(vec![1], 1).unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, first, true, None).unwrap();
+        assert_eq!(
+            bulk_command(&mut connection, DEFAULT_LIST, &mut "3: C".as_bytes(), 1).unwrap(),
+            1
+        );
+        assert!(matches!(
+            bulk_command(&mut connection, DEFAULT_LIST, &mut "0: x".as_bytes(), 1),
+            Err(BulkCommandError::InvalidIndex(InvalidIndexError(0)))
+        ));
+
+        let todos = query_todos(&connection, &TodoQuery::list(DEFAULT_LIST)).unwrap();
+        let zero_based = Style {
+            ascii: true,
+            ..PLAIN
+        };
+        let one_based = Style {
+            line_numbers_from: 1,
+            ..zero_based.clone()
+        };
+        let options = PrintOptions::default();
+        assert_eq!(
+            format_todos(&todos, false, &options, &zero_based, today()),
+            vec!["0: ~~a~~", "1: b", "2: C"]
+        );
+        assert_eq!(
+            format_todos(&todos, false, &options, &one_based, today()),
+            vec!["1: ~~a~~", "2: b", "3: C"]
+        );
+        let relative = PrintOptions {
+            relative: true,
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            format_todos(&todos, false, &relative, &one_based, today()),
+            vec!["-1: ~~a~~", "2: b", "+1: C"]
+        );
+        let table = PrintOptions {
+            columns: vec![Column::Index, Column::Title],
+            ..PrintOptions::default()
+        };
+        assert_eq!(
+            render_todos(&todos, false, &table, &one_based, today())[1..],
+            ["1      a", "2      b", "3      C"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_refs_by_id() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todos = ["first", "second", "third"]
+            .iter()
+            .map(|title| Todo::new(title.to_string()))
+            .collect();
+        let added = add_todos(&mut connection, todos).unwrap();
+        add_todos(
+            &mut connection,
+            vec![Todo {
+                list: "other".into(),
+                ..Todo::new("elsewhere".into())
+            }],
+        )
+        .unwrap();
+        remove_todos(&connection, vec![added[0].id]).unwrap();
+
+        // Removing the first todo shifted the indexes, the ids stay.
+        let refs = vec![
+            TodoRef::Index(added[2].id),
+            TodoRef::Uuid(added[1].uuid[..8].into()),
+        ];
+        assert_eq!(
+            resolve_refs(&connection, DEFAULT_LIST, refs, true, 0).unwrap(),
+            vec![1, 0]
+        );
+        for id in [added[0].id, 4, 99] {
+            assert!(matches!(
+                resolve_refs(&connection, DEFAULT_LIST, vec![TodoRef::Index(id)], true, 0),
+                Err(ResolveRefsError::NoSuchId(missing)) if missing == id
+            ));
+        }
+    }
+
+    // Todos kept in a Vec, so command logic can be checked without a database.
+    #[derive(Default)]
+    struct FakeRepository {
+        todos: Vec<Todo>,
+        next_id: usize,
+    }
+
+    impl FakeRepository {
+        fn with(todos: Vec<Todo>) -> Self {
+            let mut repository = Self::default();
+            repository.add(todos).unwrap();
+            repository
+        }
+    }
+
+    impl TodoRepository for FakeRepository {
+        fn all(&self, list: &str) -> Result<TodoList, RepositoryError> {
+            Ok(self
+                .todos
+                .iter()
+                .filter(|todo| todo.list == list)
+                .cloned()
+                .collect())
+        }
+
+        fn add(&mut self, todos: Vec<Todo>) -> Result<Vec<Todo>, RepositoryError> {
+            let added: Vec<Todo> = todos
+                .into_iter()
+                .map(|todo| {
+                    self.next_id += 1;
+                    Todo {
+                        id: self.next_id,
+                        ..todo
+                    }
+                })
+                .collect();
+            self.todos.extend(added.iter().cloned());
+            Ok(added)
+        }
+
+        fn update(&mut self, todos: Vec<Todo>) -> Result<usize, RepositoryError> {
+            let mut updated = 0;
+            for todo in todos {
+                if let Some(stored) = self.todos.iter_mut().find(|stored| stored.id == todo.id) {
+                    *stored = todo;
+                    updated += 1;
+                }
+            }
+            Ok(updated)
+        }
+
+        fn remove(&mut self, ids: Vec<usize>) -> Result<usize, RepositoryError> {
+            let before = self.todos.len();
+            self.todos.retain(|todo| !ids.contains(&todo.id));
+            Ok(before - self.todos.len())
+        }
+    }
+
+    #[test]
+    fn test_edit_todo_with_fake_repository() {
+        let mut repository = FakeRepository::with(vec![
+            Todo::new("first".into()),
+            Todo {
+                label: Some(Label::Red),
+                ..Todo::new("second".into())
+            },
+        ]);
+        let options = EditOptions {
+            title: Some("  new   title ".into()),
+            priority: Some(Priority::High),
+            ..Default::default()
+        };
+
+        let edited = edit_todo(&mut repository, DEFAULT_LIST, 1, &options).unwrap();
+        assert_eq!(edited.title, "new title");
+        assert_eq!(repository.todos[1].title, "new title");
+        assert_eq!(repository.todos[1].priority, Priority::High);
+        assert_eq!(repository.todos[1].label, Some(Label::Red));
+        assert_eq!(repository.todos[0].title, "first");
+
+        assert!(matches!(
+            edit_todo(&mut repository, DEFAULT_LIST, 2, &options),
+            Err(EditCommandError::InvalidIndex(_))
+        ));
+    }
+
+    #[test]
+    fn test_clear_done_with_fake_repository() {
+        let done = |title: &str, list: &str| Todo {
+            done: true,
+            list: list.into(),
+            ..Todo::new(title.into())
+        };
+        let mut repository = FakeRepository::with(vec![
+            done("done", DEFAULT_LIST),
+            Todo::new("pending".into()),
+            done("elsewhere", "work"),
+        ]);
+
+        let mut confirm = ScriptedConfirm::new(false);
+        let error = clear_done(&mut repository, DEFAULT_LIST, &mut confirm, 0).unwrap_err();
+        assert!(matches!(
+            error,
+            ClearCommandError::Confirm(ConfirmError::Declined)
+        ));
+        assert_eq!(repository.todos.len(), 3);
+
+        let cleared = clear_done(&mut repository, DEFAULT_LIST, &mut AssumeYes, 0).unwrap();
+        assert_eq!(cleared.len(), 1);
+        let titles: Vec<&str> = repository
+            .todos
+            .iter()
+            .map(|todo| todo.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["pending", "elsewhere"]);
+    }
+
+    #[test]
+    fn test_copy_todos_with_fake_repository() {
+        let original = Todo {
+            done: true,
+            tags: vec!["home".into()],
+            ..Todo::new("original".into())
+        };
+        let mut repository = FakeRepository::with(vec![original.clone()]);
+
+        let copied = copy_todos(&mut repository, DEFAULT_LIST, "work", vec![0], false).unwrap();
+        assert_eq!(copied.len(), 1);
+        assert_eq!(repository.all("work").unwrap().len(), 1);
+        let copy = &repository.all("work").unwrap()[0];
+        assert_eq!(copy.title, "original");
+        assert_eq!(copy.tags, vec!["home"]);
+        assert!(!copy.done);
+        assert_ne!(copy.uuid, original.uuid);
+        assert_eq!(repository.all(DEFAULT_LIST).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_import_replace_command_keeps_other_lists() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        add_command(
+            &mut connection,
+            "work",
+            vec!["elsewhere".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        let elsewhere = get_todos(&connection, "work").unwrap()[0].clone();
+        let imported = serde_json::to_string(&vec![TodoDto {
+            list: None,
+            ..TodoDto::from(elsewhere.clone())
+        }])
+        .unwrap();
+
+        let (removed, added) = import_replace_command(
+            &mut connection,
+            DEFAULT_LIST,
+            &mut imported.as_bytes(),
+            false,
+            false,
+            &mut ScriptedConfirm::new(true),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(removed, 1);
+        let work = get_todos(&connection, "work").unwrap();
+        assert_eq!(work.len(), 1);
+        assert_eq!(work[0].uuid, elsewhere.uuid);
+        assert_eq!(added[0].title, "elsewhere");
+        assert_ne!(added[0].uuid, elsewhere.uuid);
+        let trash = get_trash(&connection).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].todo.title, "first");
+    }
+
+    #[test]
+    fn test_set_done_command_rolls_back_when_archive_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec!["first".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+
+        // A directory can't be appended to.
+        let error = set_done_command(
+            &mut connection,
+            DEFAULT_LIST,
+            vec![0],
+            true,
+            Some(dir.path()),
+        );
+        assert!(matches!(error, Err(SetDoneCommandError::Archive(_))));
+
+        assert!(!get_todos(&connection, DEFAULT_LIST).unwrap()[0].done);
+        let history: usize = connection
+            .query_row(
+                "SELECT COUNT(*) FROM history WHERE description LIKE 'marked%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(history, 0);
     }
 }
@@ -0,0 +1,200 @@
+use clap::CommandFactory;
+use clap_complete::Shell;
+use rusqlite::Connection;
+
+use crate::{
+    args::{Args, CompletionKind},
+    db::{get_index_candidates, get_list_names, get_tag_names, GetCandidatesError},
+};
+
+const BASH_DYNAMIC: &str = r#"
+__NAME___dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" kind="" cmd="" i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        case "${COMP_WORDS[i]}" in
+            -l|--list|--confirm-threshold) ((i++)) ;;
+            -*) ;;
+            *) cmd="${COMP_WORDS[i]}"; break ;;
+        esac
+    done
+    case "$prev" in
+        -t|--tag) kind=tag ;;
+        -l|--list) kind=list ;;
+        *) case "$cmd" in done|undone|remove) [[ "$cur" != -* ]] && kind="$cmd" ;; esac ;;
+    esac
+    if [[ -n "$kind" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(__NAME__ __complete "$kind" "$cur" 2>/dev/null | cut -f1))
+        return 0
+    fi
+    __GENERATED__ "$@"
+}
+
+complete -F __NAME___dynamic -o bashdefault -o default __NAME__
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+__NAME___dynamic() {
+    local kind="" cmd="" i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "${words[i]}" in
+            -l|--list|--confirm-threshold) ((i++)) ;;
+            -*) ;;
+            *) cmd="${words[i]}"; break ;;
+        esac
+    done
+    case "${words[CURRENT-1]}" in
+        -t|--tag) kind=tag ;;
+        -l|--list) kind=list ;;
+        *) case "$cmd" in done|undone|remove) [[ "${words[CURRENT]}" != -* ]] && kind="$cmd" ;; esac ;;
+    esac
+    if [[ -n "$kind" ]]; then
+        local -a candidates
+        candidates=("${(@f)$(__NAME__ __complete "$kind" "${words[CURRENT]}" 2>/dev/null | sed $'s/\t/:/')}")
+        _describe "$kind" candidates
+        return
+    fi
+    __GENERATED__ "$@"
+}
+
+if [ "$funcstack[1]" = "__GENERATED__" ]; then
+    __NAME___dynamic "$@"
+else
+    compdef __NAME___dynamic __NAME__
+fi
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __NAME___dynamic
+    __NAME__ __complete $argv[1] (commandline -ct) 2>/dev/null
+end
+
+complete -c __NAME__ -n "__fish_seen_subcommand_from done" -f -a "(__NAME___dynamic done)"
+complete -c __NAME__ -n "__fish_seen_subcommand_from undone" -f -a "(__NAME___dynamic undone)"
+complete -c __NAME__ -n "__fish_seen_subcommand_from remove" -f -a "(__NAME___dynamic remove)"
+complete -c __NAME__ -n "__fish_seen_subcommand_from add" -s t -l tag -r -f -a "(__NAME___dynamic tag)"
+complete -c __NAME__ -s l -l list -r -f -a "(__NAME___dynamic list)"
+"#;
+
+pub fn completions_command(shell: Shell) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut command, &name, &mut script);
+    let mut script = String::from_utf8_lossy(&script).into_owned();
+
+    // The dynamic wrappers fall back to the generated completion function for everything else.
+    let dynamic = match shell {
+        Shell::Bash => generated_function(&script, "complete -F ", " ")
+            .map(|function| BASH_DYNAMIC.replace("__GENERATED__", &function)),
+        Shell::Zsh => {
+            let function = generated_function(&script, "if [ \"$funcstack[1]\" = \"", "\"");
+            // The generated dispatch would bypass the wrapper, which ships its own.
+            if let Some(dispatch) = script.rfind("if [ \"$funcstack[1]\"") {
+                script.truncate(dispatch);
+            }
+            function.map(|function| ZSH_DYNAMIC.replace("__GENERATED__", &function))
+        }
+        Shell::Fish => Some(FISH_DYNAMIC.to_string()),
+        _ => None,
+    };
+
+    print!(
+        "{}{}",
+        script,
+        dynamic.unwrap_or_default().replace("__NAME__", &name)
+    );
+}
+
+fn generated_function(script: &str, before: &str, after: &str) -> Option<String> {
+    let start = script.find(before)? + before.len();
+    let end = script[start..].find(after)?;
+    Some(script[start..start + end].to_string())
+}
+
+pub fn complete_candidates(
+    connection: &Connection,
+    list: &str,
+    kind: CompletionKind,
+    prefix: &str,
+) -> Result<Vec<String>, GetCandidatesError> {
+    let candidates = match kind {
+        CompletionKind::Done | CompletionKind::Undone | CompletionKind::Remove => {
+            let done = match kind {
+                CompletionKind::Done => Some(false),
+                CompletionKind::Undone => Some(true),
+                _ => None,
+            };
+
+            get_index_candidates(connection, list, done)?
+                .into_iter()
+                .map(|(index, title)| (index.to_string(), title))
+                .filter(|(index, _)| index.starts_with(prefix))
+                .map(|(index, title)| format!("{}\t{}", index, one_line(&title)))
+                .collect()
+        }
+        CompletionKind::Tag => get_tag_names(connection)?
+            .into_iter()
+            .filter(|tag| tag.starts_with(prefix))
+            .collect(),
+        CompletionKind::List => get_list_names(connection)?
+            .into_iter()
+            .filter(|list| list.starts_with(prefix))
+            .collect(),
+    };
+
+    Ok(candidates)
+}
+
+fn one_line(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        args::AddOptions,
+        commands::{add_command, set_done_command},
+        db::create_table,
+        todo::DEFAULT_LIST,
+    };
+
+    #[test]
+    fn test_complete_candidates() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let titles = (0..12).map(|i| format!("title{}", i)).collect();
+        let options = AddOptions {
+            tags: vec!["work".into()],
+            ..Default::default()
+        };
+        add_command(&mut connection, DEFAULT_LIST, titles, &options).unwrap();
+        add_command(
+            &mut connection,
+            "home",
+            vec!["other".into()],
+            &AddOptions::default(),
+        )
+        .unwrap();
+        set_done_command(&mut connection, DEFAULT_LIST, vec![0], true, None).unwrap();
+
+        let candidates =
+            complete_candidates(&connection, DEFAULT_LIST, CompletionKind::Done, "1").unwrap();
+        assert_eq!(candidates, vec!["1\ttitle1", "10\ttitle10", "11\ttitle11"]);
+
+        let candidates =
+            complete_candidates(&connection, DEFAULT_LIST, CompletionKind::Undone, "").unwrap();
+        assert_eq!(candidates, vec!["0\ttitle0"]);
+
+        let candidates =
+            complete_candidates(&connection, DEFAULT_LIST, CompletionKind::Tag, "w").unwrap();
+        assert_eq!(candidates, vec!["work"]);
+
+        let candidates =
+            complete_candidates(&connection, DEFAULT_LIST, CompletionKind::List, "").unwrap();
+        assert_eq!(candidates, vec!["default", "home"]);
+    }
+}
@@ -1,8 +1,132 @@
-use std::{fs::create_dir_all, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs::create_dir_all,
+    path::{Path, PathBuf},
+};
 
 use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dates::{parse_span, DateFormat, Span},
+    terminal::Theme,
+    todo::Priority,
+};
 
 const FILE_NAME: &str = "todos.db";
+const CONFIG_FILE_NAME: &str = "config.toml";
+const DB_PATH_ENV: &str = "TODO_DB";
+const CONFIG_PATH_ENV: &str = "TODO_CONFIG";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub default_list: Option<String>,
+    pub confirm_threshold: Option<usize>,
+    pub read_only: Option<bool>,
+    pub accessible: Option<bool>,
+    pub no_emoji: Option<bool>,
+    pub archive_file: Option<PathBuf>,
+    pub backup_dir: Option<PathBuf>,
+    pub trash_limit: Option<usize>,
+    pub line_numbers_from: Option<usize>,
+    pub date_format: DateFormat,
+    pub theme: Theme,
+    pub auto_prune_done_after: AutoPrune,
+    pub hooks: HooksConfig,
+    pub status: StatusConfig,
+    pub notify: NotifyConfig,
+    pub encryption: EncryptionConfig,
+    pub template: BTreeMap<String, TemplateConfig>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub post_add: Option<String>,
+    pub post_done: Option<String>,
+    pub post_undone: Option<String>,
+    pub post_remove: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusConfig {
+    pub done: Option<String>,
+    pub pending: Option<String>,
+    pub overdue: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    pub within: Option<Span>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub todos: Vec<TemplateTodo>,
+}
+
+// A bare title, or a table for a todo with tags or a priority.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TemplateTodo {
+    Title(String),
+    Table(TemplateTodoTable),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateTodoTable {
+    pub title: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub priority: Option<Priority>,
+}
+
+impl TemplateTodo {
+    pub fn title(&self) -> &str {
+        match self {
+            TemplateTodo::Title(title) | TemplateTodo::Table(TemplateTodoTable { title, .. }) => {
+                title
+            }
+        }
+    }
+}
+
+// How long done todos are kept before any run removes them on its own.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum AutoPrune {
+    #[default]
+    Never,
+    After(Span),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid auto_prune_done_after '{0}', expected never or a duration like 30d")]
+pub struct ParseAutoPruneError(String);
+
+impl TryFrom<String> for AutoPrune {
+    type Error = ParseAutoPruneError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.trim().eq_ignore_ascii_case("never") {
+            return Ok(AutoPrune::Never);
+        }
+        parse_span(&value)
+            .map(AutoPrune::After)
+            .map_err(|_| ParseAutoPruneError(value))
+    }
+}
 
 #[derive(thiserror::Error, Debug)]
 pub enum GetDbPathError {
@@ -13,13 +137,262 @@ pub enum GetDbPathError {
     CreateDir(#[from] std::io::Error),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbPathSource {
+    Env,
+    Default,
+}
+
 pub fn get_db_path() -> Result<PathBuf, GetDbPathError> {
+    resolve_db_path().map(|(path, _)| path)
+}
+
+pub fn resolve_db_path() -> Result<(PathBuf, DbPathSource), GetDbPathError> {
+    if let Some(path) = std::env::var_os(DB_PATH_ENV) {
+        return Ok((PathBuf::from(path), DbPathSource::Env));
+    }
+
     if let Some(project) = ProjectDirs::from("com", "dely", "todo") {
         let config_dir = project.config_dir();
         create_dir_all(config_dir)?;
-        return Ok(config_dir.join(FILE_NAME));
+        return Ok((config_dir.join(FILE_NAME), DbPathSource::Default));
     }
 
     Err(GetDbPathError::GetDbPath)
 }
 
+// Without a configured directory, backups sit next to the database.
+pub fn get_backup_dir(configured: Option<PathBuf>) -> Result<PathBuf, GetDbPathError> {
+    if let Some(dir) = configured {
+        return Ok(dir);
+    }
+    Ok(get_db_path()?
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LoadConfigError {
+    #[error("Failed to get the config path")]
+    GetConfigPath,
+
+    #[error("Failed to read the config file")]
+    Read(#[from] std::io::Error),
+
+    #[error("Failed to parse the config file")]
+    Parse(#[from] toml::de::Error),
+}
+
+pub fn get_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_PATH_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    ProjectDirs::from("com", "dely", "todo")
+        .map(|project| project.config_dir().join(CONFIG_FILE_NAME))
+}
+
+pub fn load_config() -> Result<Config, LoadConfigError> {
+    let path = get_config_path().ok_or(LoadConfigError::GetConfigPath)?;
+
+    if !path.exists() {
+        log::info!("No config at {}, using defaults", path.display());
+        return Ok(Config::default());
+    }
+
+    log::info!("Using config {}", path.display());
+    parse_config(&std::fs::read_to_string(path)?)
+}
+
+pub fn parse_config(content: &str) -> Result<Config, LoadConfigError> {
+    Ok(toml::from_str(content)?)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UpdateConfigError {
+    #[error(transparent)]
+    Load(#[from] LoadConfigError),
+
+    #[error("Failed to write the config file")]
+    Write(#[source] std::io::Error),
+}
+
+// Reports whether the config named the list as its default and was updated.
+pub fn rename_default_list(from: &str, to: &str) -> Result<bool, UpdateConfigError> {
+    let Some(path) = get_config_path().filter(|path| path.exists()) else {
+        return Ok(false);
+    };
+    let content = std::fs::read_to_string(&path).map_err(LoadConfigError::Read)?;
+    let Some(updated) = replace_default_list(&content, from, to) else {
+        return Ok(false);
+    };
+    std::fs::write(&path, updated).map_err(UpdateConfigError::Write)?;
+    log::info!("Updated the default list in {}", path.display());
+    Ok(true)
+}
+
+// Only the default_list line is rewritten, the rest of the file stays as written.
+fn replace_default_list(content: &str, from: &str, to: &str) -> Option<String> {
+    if parse_config(content).ok()?.default_list.as_deref() != Some(from) {
+        return None;
+    }
+
+    let mut in_table = false;
+    let mut updated: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let trimmed = line.trim_start();
+            in_table |= trimmed.starts_with('[');
+            let key = trimmed.split('=').next().unwrap_or_default().trim();
+            if !in_table && key == "default_list" {
+                format!("default_list = {}", toml::Value::String(to.into()))
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if content.ends_with('\n') {
+        updated.push(String::new());
+    }
+    Some(updated.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates::ISO_DATE;
+
+    #[test]
+    fn test_parse_config() {
+        let config = parse_config(
+            "[hooks]
+            post_add = \"git commit -am add\"
+            post_remove = \"sync.sh\"",
+        )
+        .unwrap();
+
+        assert_eq!(config.hooks.post_add.as_deref(), Some("git commit -am add"));
+        assert_eq!(config.hooks.post_done, None);
+        assert_eq!(config.hooks.post_remove.as_deref(), Some("sync.sh"));
+    }
+
+    #[test]
+    fn test_parse_empty_config() {
+        let config = parse_config("").unwrap();
+        assert!(config.hooks.post_add.is_none());
+    }
+
+    #[test]
+    fn test_parse_config_unknown_key() {
+        assert!(parse_config("[hooks]\npost_typo = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_date_format_config() {
+        assert_eq!(parse_config("").unwrap().date_format, ISO_DATE);
+        let config = parse_config("date_format = \"%d.%m.%Y\"").unwrap();
+        assert_eq!(
+            config.date_format,
+            "%d.%m.%Y".to_string().try_into().unwrap()
+        );
+        assert!(parse_config("date_format = \"%Y %Q\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_auto_prune_config() {
+        assert_eq!(
+            parse_config("").unwrap().auto_prune_done_after,
+            AutoPrune::Never
+        );
+        let parse = |value: &str| {
+            parse_config(&format!("auto_prune_done_after = \"{}\"", value))
+                .map(|config| config.auto_prune_done_after)
+        };
+        assert_eq!(parse("never").unwrap(), AutoPrune::Never);
+        assert_eq!(parse("30d").unwrap(), AutoPrune::After(Span::Days(30)));
+        assert!(parse("soon").is_err());
+    }
+
+    #[test]
+    fn test_backup_dir_config() {
+        let config = parse_config("backup_dir = \"/tmp/todo-backups\"").unwrap();
+
+        assert_eq!(
+            get_backup_dir(config.backup_dir).unwrap(),
+            PathBuf::from("/tmp/todo-backups")
+        );
+        assert_eq!(parse_config("").unwrap().backup_dir, None);
+    }
+
+    #[test]
+    fn test_replace_default_list() {
+        let content = "# lists\ndefault_list = \"work\"\n\n[hooks]\npost_add = \"x\"\n";
+        assert_eq!(
+            replace_default_list(content, "work", "job").unwrap(),
+            "# lists\ndefault_list = \"job\"\n\n[hooks]\npost_add = \"x\"\n"
+        );
+        assert_eq!(
+            parse_config(&replace_default_list(content, "work", "a \"b\"").unwrap())
+                .unwrap()
+                .default_list
+                .as_deref(),
+            Some("a \"b\"")
+        );
+        assert_eq!(replace_default_list(content, "home", "job"), None);
+        assert_eq!(replace_default_list("", "work", "job"), None);
+    }
+
+    #[test]
+    fn test_parse_template_config() {
+        let config = parse_config(
+            "[template.groceries]
+            todos = [\"milk\", { title = \"eggs\", tags = [\"dairy\"], priority = \"high\" }]
+
+            [template.release]
+            todos = [{ title = \"tag the release\" }]",
+        )
+        .unwrap();
+        assert_eq!(
+            config.template.keys().collect::<Vec<_>>(),
+            vec!["groceries", "release"]
+        );
+        assert_eq!(
+            config.template["groceries"].todos,
+            vec![
+                TemplateTodo::Title("milk".into()),
+                TemplateTodo::Table(TemplateTodoTable {
+                    title: "eggs".into(),
+                    tags: vec!["dairy".into()],
+                    priority: Some(Priority::High),
+                })
+            ]
+        );
+        assert_eq!(
+            config.template["release"].todos[0].title(),
+            "tag the release"
+        );
+
+        assert!(
+            parse_config("[template.x]\ntodos = [{ title = \"a\", due = \"today\" }]").is_err()
+        );
+        assert!(parse_config("[template.x]\nitems = [\"a\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_theme_config() {
+        assert_eq!(parse_config("").unwrap().theme, Theme::default());
+        let config = parse_config("[theme]\nlow = \"cyan\"").unwrap();
+        assert_eq!(config.theme.high, Theme::default().high);
+        assert_eq!(config.theme.low, "cyan".to_string().try_into().unwrap());
+        assert!(parse_config("[theme]\nhigh = \"loud\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_status_config() {
+        let config = parse_config("[status]\ndone = \"D\"").unwrap();
+        assert_eq!(config.status.done.as_deref(), Some("D"));
+        assert!(config.status.pending.is_none());
+    }
+}
@@ -0,0 +1,50 @@
+use std::io::{BufRead, Write};
+
+pub trait Confirm {
+    fn confirm(&mut self, prompt: &str) -> std::io::Result<bool>;
+}
+
+pub struct StdinConfirm;
+
+impl Confirm for StdinConfirm {
+    fn confirm(&mut self, prompt: &str) -> std::io::Result<bool> {
+        let mut stderr = std::io::stderr();
+        write!(stderr, "{} [y/N] ", prompt)?;
+        stderr.flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+}
+
+pub struct AssumeYes;
+
+impl Confirm for AssumeYes {
+    fn confirm(&mut self, _prompt: &str) -> std::io::Result<bool> {
+        Ok(true)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfirmError {
+    #[error("Fail to read the confirmation")]
+    Read(#[from] std::io::Error),
+
+    #[error("Aborted")]
+    Declined,
+}
+
+pub fn confirm_above_threshold(
+    confirm: &mut dyn Confirm,
+    threshold: usize,
+    count: usize,
+    prompt: &str,
+) -> Result<(), ConfirmError> {
+    if count <= threshold || confirm.confirm(prompt)? {
+        return Ok(());
+    }
+
+    Err(ConfirmError::Declined)
+}
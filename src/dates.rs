@@ -0,0 +1,372 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Write},
+};
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, Utc, Weekday};
+use serde::Deserialize;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ParseDueError {
+    #[error(
+        "Invalid due date '{0}', expected YYYY-MM-DD, today, tomorrow, a weekday like friday or \
+        next friday, in N days/weeks/months, or a month and day like jul 4"
+    )]
+    Unknown(String),
+
+    #[error("Invalid due date '{0}', there's no such day in the calendar")]
+    NoSuchDate(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Span {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+}
+
+impl Span {
+    pub fn after(self, date: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Span::Days(count) => date.checked_add_days(Days::new(count.into())),
+            Span::Weeks(count) => date.checked_add_days(Days::new(u64::from(count) * 7)),
+            Span::Months(count) => date.checked_add_months(Months::new(count)),
+        }
+    }
+
+    pub fn before(self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Span::Days(count) => at.checked_sub_days(Days::new(count.into())),
+            Span::Weeks(count) => at.checked_sub_days(Days::new(u64::from(count) * 7)),
+            Span::Months(count) => at.checked_sub_months(Months::new(count)),
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (count, unit) = match self {
+            Span::Days(count) => (count, "day"),
+            Span::Weeks(count) => (count, "week"),
+            Span::Months(count) => (count, "month"),
+        };
+        write!(
+            f,
+            "{} {}{}",
+            count,
+            unit,
+            if *count == 1 { "" } else { "s" }
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error(
+    "Invalid duration '{0}', expected a number of days, weeks or months like 1d, 2w or 3 months"
+)]
+pub struct ParseSpanError(String);
+
+impl TryFrom<String> for Span {
+    type Error = ParseSpanError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        parse_span(&value)
+    }
+}
+
+// How dates are shown, stored dates are always ISO. Only date fields are allowed, the pattern
+// formats bare due dates too.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DateFormat(Cow<'static, str>);
+
+pub const ISO_DATE: DateFormat = DateFormat(Cow::Borrowed("%Y-%m-%d"));
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        ISO_DATE
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid date format '{0}', expected a strftime pattern of date fields like %d.%m.%Y")]
+pub struct ParseDateFormatError(String);
+
+impl TryFrom<String> for DateFormat {
+    type Error = ParseDateFormatError;
+
+    // Formatting a sample date catches unknown specifiers and time fields alike, which chrono
+    // would otherwise only report by panicking halfway through printing.
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let sample = NaiveDate::from_ymd_opt(2024, 5, 1).expect("valid date");
+        if value.trim().is_empty() || write!(String::new(), "{}", sample.format(&value)).is_err() {
+            return Err(ParseDateFormatError(value));
+        }
+        Ok(DateFormat(value.into()))
+    }
+}
+
+impl DateFormat {
+    pub fn date(&self, date: NaiveDate) -> String {
+        date.format(&self.0).to_string()
+    }
+
+    // The local day the timestamp falls on.
+    pub fn timestamp(&self, at: DateTime<Utc>) -> String {
+        self.date(at.with_timezone(&Local).date_naive())
+    }
+}
+
+// The unit may be abbreviated and the space before it left out, so "3d" reads like "3 days".
+pub fn parse_span(input: &str) -> Result<Span, ParseSpanError> {
+    let error = || ParseSpanError(input.into());
+    let normalized = input.trim().to_lowercase();
+    let (count, unit) = normalized.split_at(
+        normalized
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(error)?,
+    );
+    let count: u32 = count.parse().map_err(|_| error())?;
+
+    match unit.trim_start() {
+        "d" | "day" | "days" => Ok(Span::Days(count)),
+        "w" | "week" | "weeks" => Ok(Span::Weeks(count)),
+        "m" | "mo" | "month" | "months" => Ok(Span::Months(count)),
+        _ => Err(error()),
+    }
+}
+
+pub fn parse_due_arg(input: &str) -> Result<NaiveDate, ParseDueError> {
+    parse_due(input, Local::now().date_naive())
+}
+
+// Relative phrases resolve against today, so what gets stored is always a concrete date.
+pub fn parse_due(input: &str, today: NaiveDate) -> Result<NaiveDate, ParseDueError> {
+    let error = || ParseDueError::Unknown(input.into());
+    let no_such_date = || ParseDueError::NoSuchDate(input.into());
+    let normalized = input.trim().to_lowercase();
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+
+    // Shaped like a date but not parsing means the month or the day is out of range. A short
+    // year would parse, as one from the first century.
+    if is_iso_shaped(&normalized) {
+        return NaiveDate::parse_from_str(&normalized, "%Y-%m-%d").map_err(|_| no_such_date());
+    }
+
+    let date = match words.as_slice() {
+        ["today"] => Some(today),
+        ["tomorrow"] => today.checked_add_days(Days::new(1)),
+        ["yesterday"] => today.checked_sub_days(Days::new(1)),
+        ["next", "week"] => today.checked_add_days(Days::new(7)),
+        ["next", "month"] => today.checked_add_months(Months::new(1)),
+        [weekday] | ["next", weekday] => parse_weekday(weekday).map(|weekday| {
+            let ahead = (weekday.days_since(today.weekday()) + 6) % 7 + 1;
+            today + Days::new(ahead.into())
+        }),
+        ["in", span @ ..] => parse_span(&span.join(" "))
+            .map_err(|_| error())?
+            .after(today),
+        [month, day] | [day, month] if parse_month(month).is_some() => {
+            let month = parse_month(month).ok_or_else(error)?;
+            let day: u32 = day.parse().map_err(|_| error())?;
+            // The next time that day comes around, today included.
+            let date = NaiveDate::from_ymd_opt(today.year(), month, day)
+                .filter(|date| *date >= today)
+                .or_else(|| NaiveDate::from_ymd_opt(today.year() + 1, month, day));
+            // Feb 29 is found within two years only when the next one is a leap year.
+            if date.is_none() && NaiveDate::from_ymd_opt(2024, month, day).is_none() {
+                return Err(no_such_date());
+            }
+            date
+        }
+        _ => None,
+    };
+
+    date.ok_or_else(error)
+}
+
+fn is_iso_shaped(input: &str) -> bool {
+    let parts: Vec<&str> = input.split('-').collect();
+    matches!(parts.as_slice(), [year, month, day]
+        if year.len() == 4 && (1..=2).contains(&month.len()) && (1..=2).contains(&day.len())
+            && parts.iter().all(|part| part.chars().all(|c| c.is_ascii_digit())))
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+// Names may be shortened down to three letters, like "fri" or "thurs".
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    WEEKDAYS
+        .iter()
+        .find(|(name, _)| word.len() >= 3 && name.starts_with(word))
+        .map(|(_, weekday)| *weekday)
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+// Like weekdays, "sep" and "sept" work as well as "september".
+fn parse_month(word: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .position(|month| word.len() >= 3 && month.starts_with(word))
+        .map(|index| index as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Wednesday.
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()
+    }
+
+    fn parse(input: &str) -> String {
+        parse_due(input, today()).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_due_dates() {
+        assert_eq!(parse("2024-06-30"), "2024-06-30");
+        assert_eq!(parse("today"), "2024-05-01");
+        assert_eq!(parse("Tomorrow"), "2024-05-02");
+        assert_eq!(parse("next week"), "2024-05-08");
+        assert_eq!(parse("in 3 days"), "2024-05-04");
+        assert_eq!(parse("in 1 week"), "2024-05-08");
+        assert_eq!(parse("in 2 months"), "2024-07-01");
+    }
+
+    #[test]
+    fn test_parse_due_weekdays() {
+        assert_eq!(parse("friday"), "2024-05-03");
+        assert_eq!(parse("next fri"), "2024-05-03");
+        assert_eq!(parse("tue"), "2024-05-07");
+        // The same weekday as today means next week.
+        assert_eq!(parse("wednesday"), "2024-05-08");
+    }
+
+    #[test]
+    fn test_parse_due_month_and_day() {
+        assert_eq!(parse("jul 4"), "2024-07-04");
+        assert_eq!(parse("4 July"), "2024-07-04");
+        assert_eq!(parse("may 1"), "2024-05-01");
+        assert_eq!(parse("apr 30"), "2025-04-30");
+    }
+
+    #[test]
+    fn test_parse_due_invalid() {
+        for input in [
+            "",
+            "someday",
+            "in x days",
+            "in 3 fortnights",
+            "jul",
+            "ju 4",
+            "2024-05",
+            "24-05-01",
+        ] {
+            assert_eq!(
+                parse_due(input, today()),
+                Err(ParseDueError::Unknown(input.into())),
+                "{}",
+                input
+            );
+        }
+        assert!(ParseDueError::Unknown("x".into())
+            .to_string()
+            .contains("YYYY-MM-DD"));
+    }
+
+    #[test]
+    fn test_parse_due_impossible_dates() {
+        for input in [
+            "2024-13-40",
+            "2024-02-30",
+            "2023-02-29",
+            "2024-00-10",
+            "feb 30",
+            "jul 32",
+        ] {
+            assert_eq!(
+                parse_due(input, today()),
+                Err(ParseDueError::NoSuchDate(input.into())),
+                "{}",
+                input
+            );
+        }
+        assert_eq!(parse("2024-02-29"), "2024-02-29");
+        assert_eq!(
+            ParseDueError::NoSuchDate("2024-13-40".into()).to_string(),
+            "Invalid due date '2024-13-40', there's no such day in the calendar"
+        );
+    }
+
+    #[test]
+    fn test_parse_span() {
+        assert_eq!(parse_span("1d"), Ok(Span::Days(1)));
+        assert_eq!(parse_span("3 days"), Ok(Span::Days(3)));
+        assert_eq!(parse_span("2W"), Ok(Span::Weeks(2)));
+        assert_eq!(parse_span("1 month"), Ok(Span::Months(1)));
+        assert_eq!(parse("in 2w"), "2024-05-15");
+        assert_eq!(Span::Days(7).to_string(), "7 days");
+        assert_eq!(Span::Months(1).to_string(), "1 month");
+
+        for input in ["", "d", "1", "1 fortnight", "-1d", "1.5d"] {
+            assert_eq!(
+                parse_span(input),
+                Err(ParseSpanError(input.into())),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_date_format() {
+        let format = |pattern: &str| DateFormat::try_from(pattern.to_string());
+
+        assert_eq!(ISO_DATE.date(today()), "2024-05-01");
+        assert_eq!(format("%d.%m.%Y").unwrap().date(today()), "01.05.2024");
+        assert_eq!(format("%b %-d, %Y").unwrap().date(today()), "May 1, 2024");
+
+        for pattern in ["", " ", "%Q", "%Y-%m-%d %H:%M", "%"] {
+            assert_eq!(
+                format(pattern),
+                Err(ParseDateFormatError(pattern.into())),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_span_after_clamps_month_end() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        assert_eq!(
+            Span::Months(1).after(date).unwrap().to_string(),
+            "2024-02-29"
+        );
+    }
+}
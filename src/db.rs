@@ -1,10 +1,27 @@
-use std::rc::Rc;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{ErrorKind, Read},
+    path::Path,
+    rc::Rc,
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
 
+#[cfg(feature = "encryption")]
+use crate::encryption::{get_passphrase, GetPassphraseError};
 use crate::{
     config::{get_db_path, GetDbPathError},
-    todo,
+    todo::{self, Label, Priority},
 };
-use rusqlite::{types::Value, Connection};
+#[cfg(feature = "encryption")]
+use rusqlite::ErrorCode;
+use rusqlite::{
+    params_from_iter,
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, Value, ValueRef},
+    Batch, Connection, OpenFlags, OptionalExtension, Statement, ToSql,
+};
+use serde::Serialize;
 
 const CREATE_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS todos (
     id INTEGER PRIMARY KEY,
@@ -12,26 +29,573 @@ const CREATE_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS todos (
     done BOOLEAN NOT NULL
 )";
 
+const CREATE_TAGS_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS tags (
+    todo_id INTEGER NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    PRIMARY KEY (todo_id, name)
+)";
+
+const CREATE_HISTORY_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY,
+    at TEXT NOT NULL,
+    list TEXT NOT NULL,
+    description TEXT NOT NULL
+)";
+
+const CREATE_METADATA_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS metadata (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+)";
+
+// Removed todos wait here, out of reach of every query on todos, until restored or purged.
+const CREATE_TRASH_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS trash (
+    id INTEGER PRIMARY KEY,
+    uuid TEXT NOT NULL,
+    title TEXT NOT NULL,
+    done BOOLEAN NOT NULL,
+    list TEXT NOT NULL,
+    priority INTEGER NOT NULL,
+    due TEXT,
+    note TEXT,
+    label TEXT,
+    parent TEXT,
+    tags TEXT NOT NULL,
+    created_at TEXT,
+    done_at TEXT,
+    updated_at TEXT,
+    removed_at TEXT NOT NULL
+)";
+
+// Keyed by the due date too, so postponing a todo makes it worth notifying about again.
+const CREATE_NOTIFICATIONS_TABLE_QUERY: &str = "CREATE TABLE IF NOT EXISTS notifications (
+    todo_id INTEGER NOT NULL REFERENCES todos(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    due TEXT NOT NULL,
+    notified_at TEXT NOT NULL,
+    PRIMARY KEY (todo_id, due)
+)";
+
+// A random version 4 UUID, for rows that didn't get one from the app.
+macro_rules! uuid_v4_sql {
+    () => {
+        "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' ||
+        substr(hex(randomblob(2)), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1) ||
+        substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)))"
+    };
+}
+
+// Each entry upgrades the schema by one version, tracked in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE todos ADD COLUMN list TEXT NOT NULL DEFAULT 'default'",
+    "ALTER TABLE todos ADD COLUMN priority INTEGER NOT NULL DEFAULT 1",
+    "ALTER TABLE todos ADD COLUMN due TEXT",
+    "ALTER TABLE todos ADD COLUMN note TEXT",
+    "ALTER TABLE todos ADD COLUMN created_at TEXT",
+    "ALTER TABLE todos ADD COLUMN done_at TEXT",
+    "ALTER TABLE todos ADD COLUMN uuid TEXT",
+    concat!(
+        "UPDATE todos SET uuid = ",
+        uuid_v4_sql!(),
+        " WHERE uuid IS NULL"
+    ),
+    "CREATE UNIQUE INDEX todos_uuid ON todos (uuid)",
+    // Rows inserted by other tools, like the sqlite3 shell, still get one.
+    concat!(
+        "CREATE TRIGGER todos_uuid AFTER INSERT ON todos WHEN NEW.uuid IS NULL BEGIN
+        UPDATE todos SET uuid = ",
+        uuid_v4_sql!(),
+        " WHERE id = NEW.id; END"
+    ),
+    "ALTER TABLE todos ADD COLUMN updated_at TEXT",
+    "UPDATE todos SET updated_at = COALESCE(done_at, created_at)",
+    "ALTER TABLE todos ADD COLUMN label TEXT",
+    // The UUID of the parent todo, left dangling when the parent is removed.
+    "ALTER TABLE todos ADD COLUMN parent TEXT",
+    // A migration rather than created up front, so read-only mode reports an older database as
+    // outdated instead of missing the table.
+    CREATE_TRASH_TABLE_QUERY,
+    // Lists are ordered by position, so moving and inserting todos leaves their ids alone.
+    "ALTER TABLE todos ADD COLUMN position INTEGER",
+    "UPDATE todos SET position = id",
+    "CREATE INDEX todos_position ON todos (list, position)",
+    // Every insert, ours or another tool's, goes to the bottom.
+    "CREATE TRIGGER todos_position AFTER INSERT ON todos WHEN NEW.position IS NULL BEGIN
+    UPDATE todos SET position = (SELECT COALESCE(MAX(position), 0) + 1 FROM todos)
+    WHERE id = NEW.id; END",
+];
+
+// Milliseconds keep todos added one after the other in order, ids alone can't since reindex
+// renumbers them.
+const NOW: &str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+
+pub const UNTAGGED: &str = "(untagged)";
+
+impl ToSql for Priority {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok((*self as i64).into())
+    }
+}
+
+impl FromSql for Priority {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_i64()? {
+            0 => Ok(Priority::Low),
+            1 => Ok(Priority::Medium),
+            2 => Ok(Priority::High),
+            other => Err(FromSqlError::OutOfRange(other)),
+        }
+    }
+}
+
+impl ToSql for Label {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.to_string().into())
+    }
+}
+
+impl FromSql for Label {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|error| FromSqlError::Other(Box::new(error)))
+    }
+}
+
+fn prepare<'a>(connection: &'a Connection, sql: &str) -> rusqlite::Result<Statement<'a>> {
+    let statement = connection.prepare(sql)?;
+    log::debug!(
+        "SQL ({} params): {}",
+        statement.parameter_count(),
+        sql.split_whitespace().collect::<Vec<_>>().join(" ")
+    );
+    Ok(statement)
+}
+
+// Numbers todos within their list, so filtered queries still report the display index.
+const INDEXED_TODOS_QUERY: &str = "SELECT id, title, done, list, priority, due, note, uuid,
+    updated_at, label, created_at, done_at, parent, ROW_NUMBER() OVER (PARTITION BY list ORDER BY position, id) - 1 AS row_index
+    FROM todos";
+
+#[derive(Debug, Default, Clone)]
+pub struct TodoQuery {
+    pub list: Option<String>,
+    pub search: Vec<String>,
+    pub tags: Vec<String>,
+    // Otherwise any one of the tags is enough.
+    pub all_tags: bool,
+    pub done: Option<bool>,
+    pub due_before: Option<NaiveDate>,
+    pub due_from: Option<NaiveDate>,
+    pub has_due: Option<bool>,
+    pub due_on: Option<NaiveDate>,
+    pub min_priority: Option<Priority>,
+    pub prefix: Option<String>,
+    pub uuids: Option<Vec<String>>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub done_from: Option<DateTime<Utc>>,
+    pub done_before: Option<DateTime<Utc>>,
+}
+
+impl TodoQuery {
+    pub fn list(list: &str) -> Self {
+        Self {
+            list: Some(list.into()),
+            ..Default::default()
+        }
+    }
+
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut params = Vec::new();
+
+        if let Some(list) = &self.list {
+            conditions.push("list = ?");
+            params.push(Value::from(list.clone()));
+        }
+
+        for search in &self.search {
+            conditions.push("title LIKE ? ESCAPE '\\'");
+            params.push(Value::from(format!("%{}%", escape_like(search))));
+        }
+
+        if self.all_tags {
+            for tag in &self.tags {
+                conditions.push("id IN (SELECT todo_id FROM tags WHERE name = ?)");
+                params.push(Value::from(tag.clone()));
+            }
+        } else if !self.tags.is_empty() {
+            conditions.push(
+                "id IN (SELECT todo_id FROM tags WHERE name IN (SELECT value FROM json_each(?)))",
+            );
+            params.push(Value::from(
+                serde_json::Value::from(self.tags.clone()).to_string(),
+            ));
+        }
+
+        if let Some(done) = self.done {
+            conditions.push("done = ?");
+            params.push(Value::from(done));
+        }
+
+        if let Some(due_before) = self.due_before {
+            conditions.push("due < ?");
+            params.push(Value::from(due_before.to_string()));
+        }
+
+        if let Some(due_from) = self.due_from {
+            conditions.push("due >= ?");
+            params.push(Value::from(due_from.to_string()));
+        }
+
+        if let Some(has_due) = self.has_due {
+            conditions.push(if has_due {
+                "due IS NOT NULL"
+            } else {
+                "due IS NULL"
+            });
+        }
+
+        if let Some(due_on) = self.due_on {
+            conditions.push("due = ?");
+            params.push(Value::from(due_on.to_string()));
+        }
+
+        if let Some(min_priority) = self.min_priority {
+            conditions.push("priority >= ?");
+            params.push(Value::from(min_priority as i64));
+        }
+
+        if let Some(prefix) = &self.prefix {
+            conditions.push("title LIKE ? ESCAPE '\\'");
+            params.push(Value::from(format!("{}%", escape_like(prefix))));
+        }
+
+        // A JSON array keeps this to a single parameter however many UUIDs there are.
+        if let Some(uuids) = &self.uuids {
+            conditions.push("uuid IN (SELECT value FROM json_each(?))");
+            params.push(Value::from(
+                serde_json::Value::from(uuids.clone()).to_string(),
+            ));
+        }
+
+        for (bound, condition) in [
+            (self.created_from, "created_at >= ?"),
+            (self.created_before, "created_at < ?"),
+            (self.done_from, "done_at >= ?"),
+            (self.done_before, "done_at < ?"),
+        ] {
+            if let Some(bound) = bound {
+                conditions.push(condition);
+                params.push(Value::from(format_timestamp(bound)));
+            }
+        }
+
+        if conditions.is_empty() {
+            return (String::new(), params);
+        }
+
+        (format!("WHERE {}", conditions.join(" AND ")), params)
+    }
+}
+
+// Formatted like NOW, so it compares with the stored text in time order.
+fn format_timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%d %H:%M:%S%.3f").to_string()
+}
+
+fn escape_like(pattern: &str) -> String {
+    pattern
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Fail to get a todo")]
 pub struct GetTodosError(#[from] rusqlite::Error);
 
-pub fn get_todos(connection: &Connection) -> Result<Vec<todo::Todo>, GetTodosError> {
-    let mut statement = connection.prepare("SELECT id, title, done FROM todos")?;
-    let todos = statement
-        .query_map([], |row| {
-            Ok(todo::Todo {
-                id: row.get(0)?,
-                title: row.get(1)?,
-                done: row.get(2)?,
-            })
-        })?
+pub fn query_todos(
+    connection: &Connection,
+    query: &TodoQuery,
+) -> Result<Vec<(usize, todo::Todo)>, GetTodosError> {
+    select_todos(connection, query, "list, row_index", None)
+}
+
+// Soonest due first, which puts overdue todos on top, then highest priority, then oldest.
+pub fn query_next_todos(
+    connection: &Connection,
+    query: &TodoQuery,
+    limit: usize,
+) -> Result<Vec<(usize, todo::Todo)>, GetTodosError> {
+    let query = TodoQuery {
+        done: Some(false),
+        ..query.clone()
+    };
+    select_todos(
+        connection,
+        &query,
+        "due IS NULL, due, priority DESC, id",
+        Some(limit),
+    )
+}
+
+// Most overdue first.
+pub fn query_overdue_todos(
+    connection: &Connection,
+    query: &TodoQuery,
+    today: NaiveDate,
+) -> Result<Vec<(usize, todo::Todo)>, GetTodosError> {
+    let query = TodoQuery {
+        done: Some(false),
+        due_before: Some(today),
+        ..query.clone()
+    };
+    select_todos(connection, &query, "due, list, row_index", None)
+}
+
+pub fn query_scheduled_todos(
+    connection: &Connection,
+    query: &TodoQuery,
+    from: NaiveDate,
+    until: NaiveDate,
+) -> Result<Vec<(usize, todo::Todo)>, GetTodosError> {
+    let query = TodoQuery {
+        done: Some(false),
+        due_from: Some(from),
+        due_before: Some(until),
+        ..query.clone()
+    };
+    select_todos(connection, &query, "due, list, row_index", None)
+}
+
+fn prepare_select<'a>(
+    connection: &'a Connection,
+    query: &TodoQuery,
+    order_by: &str,
+    limit: Option<usize>,
+) -> rusqlite::Result<(Statement<'a>, Vec<Value>)> {
+    let (where_clause, mut params) = query.where_clause();
+    // A negative limit means no limit in SQLite.
+    params.push(Value::from(limit.map_or(-1, |limit| limit as i64)));
+    let statement = prepare(
+        connection,
+        &format!(
+            "SELECT id, title, done, list, priority, due, note, uuid, updated_at, label, created_at,
+            done_at, parent, row_index FROM ({}) {}
+            ORDER BY {} LIMIT ?",
+            INDEXED_TODOS_QUERY, where_clause, order_by
+        ),
+    )?;
+    Ok((statement, params))
+}
+
+fn read_todo(row: &rusqlite::Row) -> rusqlite::Result<(usize, todo::Todo)> {
+    Ok((
+        row.get(13)?,
+        todo::Todo {
+            id: row.get(0)?,
+            uuid: row.get(7)?,
+            title: row.get(1)?,
+            done: row.get(2)?,
+            list: row.get(3)?,
+            tags: Vec::new(),
+            priority: row.get(4)?,
+            due: row.get(5)?,
+            note: row.get(6)?,
+            label: row.get(9)?,
+            created_at: row.get(10)?,
+            done_at: row.get(11)?,
+            parent: row.get(12)?,
+            updated_at: row.get(8)?,
+        },
+    ))
+}
+
+fn select_todos(
+    connection: &Connection,
+    query: &TodoQuery,
+    order_by: &str,
+    limit: Option<usize>,
+) -> Result<Vec<(usize, todo::Todo)>, GetTodosError> {
+    let (mut statement, params) = prepare_select(connection, query, order_by, limit)?;
+    let mut todos: Vec<(usize, todo::Todo)> = statement
+        .query_map(params_from_iter(params), read_todo)?
         .filter_map(Result::ok)
         .collect();
 
+    let mut tags = get_tags(connection)?;
+    for (_, todo) in &mut todos {
+        todo.tags = tags.remove(&todo.id).unwrap_or_default();
+    }
+
+    log::debug!("Fetched {} todos", todos.len());
     Ok(todos)
 }
 
+// Like query_todos, but each todo is handed over as it's read and the tags are looked up per
+// todo, so the whole result never has to be in memory.
+pub fn for_each_todo<E: From<GetTodosError>>(
+    connection: &Connection,
+    query: &TodoQuery,
+    mut each: impl FnMut(usize, todo::Todo) -> Result<(), E>,
+) -> Result<(), E> {
+    let (mut statement, params) =
+        prepare_select(connection, query, "list, row_index", None).map_err(GetTodosError)?;
+    let mut tags = prepare(
+        connection,
+        "SELECT name FROM tags WHERE todo_id = ?1 ORDER BY name",
+    )
+    .map_err(GetTodosError)?;
+
+    let mut rows = statement
+        .query(params_from_iter(params))
+        .map_err(GetTodosError)?;
+    while let Some(row) = rows.next().map_err(GetTodosError)? {
+        let Ok((index, mut todo)) = read_todo(row) else {
+            continue;
+        };
+        todo.tags = tags
+            .query_map([todo.id], |row| row.get(0))
+            .and_then(|names| names.collect())
+            .map_err(GetTodosError)?;
+        each(index, todo)?;
+    }
+    Ok(())
+}
+
+pub fn get_todos(connection: &Connection, list: &str) -> Result<todo::TodoList, GetTodosError> {
+    let todos = query_todos(connection, &TodoQuery::list(list))?
+        .into_iter()
+        .map(|(_, todo)| todo)
+        .collect();
+
+    Ok(todos)
+}
+
+pub fn count_todos(connection: &Connection, query: &TodoQuery) -> Result<usize, GetTodosError> {
+    let (where_clause, params) = query.where_clause();
+    let count = prepare(
+        connection,
+        &format!(
+            "SELECT COUNT(*) FROM ({}) {}",
+            INDEXED_TODOS_QUERY, where_clause
+        ),
+    )?
+    .query_row(params_from_iter(params), |row| row.get(0))?;
+
+    Ok(count)
+}
+
+fn get_tags(connection: &Connection) -> Result<HashMap<usize, Vec<String>>, rusqlite::Error> {
+    let mut statement = prepare(connection, "SELECT todo_id, name FROM tags ORDER BY name")?;
+    let mut tags: HashMap<usize, Vec<String>> = HashMap::new();
+
+    for (todo_id, name) in statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(Result::ok)
+    {
+        tags.entry(todo_id).or_default().push(name);
+    }
+
+    Ok(tags)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RunSelectError {
+    #[error("Only a single read-only query can be run")]
+    NotSelect,
+
+    #[error("Fail to run the query")]
+    Query(#[from] rusqlite::Error),
+}
+
+// Column names and rows rendered as text. SQLite's parser splits the statements and its own
+// read-only check decides what may run, so a `;` in a literal or a CTE is no trouble.
+pub fn run_select(
+    connection: &Connection,
+    sql: &str,
+) -> Result<(Vec<String>, Vec<Vec<String>>), RunSelectError> {
+    log::debug!(
+        "SQL: {}",
+        sql.split_whitespace().collect::<Vec<_>>().join(" ")
+    );
+    let mut batch = Batch::new(connection, sql);
+    let Some(mut statement) = batch.next()? else {
+        return Err(RunSelectError::NotSelect);
+    };
+    // Only the first statement would run, so anything after it is refused rather than dropped.
+    if batch.next()?.is_some() || !statement.readonly() {
+        return Err(RunSelectError::NotSelect);
+    }
+
+    let columns: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let rows = statement
+        .query_map([], |row| {
+            (0..columns.len())
+                .map(|i| {
+                    Ok(match row.get_ref(i)? {
+                        ValueRef::Null => "NULL".to_string(),
+                        ValueRef::Integer(value) => value.to_string(),
+                        ValueRef::Real(value) => value.to_string(),
+                        ValueRef::Text(text) => String::from_utf8_lossy(text).into_owned(),
+                        ValueRef::Blob(blob) => format!("<{} bytes>", blob.len()),
+                    })
+                })
+                .collect()
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok((columns, rows))
+}
+
+// Maps the UUIDs already in the database, in any list, to their todo ids.
+pub fn get_todos_by_uuid(
+    connection: &Connection,
+    uuids: Vec<String>,
+) -> Result<HashMap<String, todo::Todo>, GetTodosError> {
+    let query = TodoQuery {
+        uuids: Some(uuids),
+        ..Default::default()
+    };
+    Ok(query_todos(connection, &query)?
+        .into_iter()
+        .map(|(_, todo)| (todo.uuid.clone(), todo))
+        .collect())
+}
+
+// The index of the pending todo added last, or of the done todo completed last. Todos from
+// before the timestamps existed come after any that have one, newest id first.
+pub fn get_latest_index(
+    connection: &Connection,
+    list: &str,
+    done: bool,
+) -> Result<Option<usize>, GetTodosError> {
+    let order = if done { "done_at" } else { "created_at" };
+    let mut statement = prepare(
+        connection,
+        &format!(
+            "SELECT row_index FROM (
+                SELECT id, done, list, created_at, done_at,
+                ROW_NUMBER() OVER (PARTITION BY list ORDER BY position, id) - 1 AS row_index
+                FROM todos
+            )
+            WHERE list = ?1 AND done = ?2
+            ORDER BY {} DESC, id DESC
+            LIMIT 1",
+            order
+        ),
+    )?;
+
+    Ok(statement
+        .query_row(rusqlite::params![list, done], |row| row.get(0))
+        .optional()?)
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AddTodosError {
     #[error("Fail to create transaction")]
@@ -43,24 +607,67 @@ pub enum AddTodosError {
     #[error("Fail to insert todo")]
     InsertTodo(#[source] rusqlite::Error),
 
+    #[error("Fail to insert tag")]
+    InsertTag(#[source] rusqlite::Error),
+
     #[error("Fail to commit transaction")]
     CommitTransaction(#[source] rusqlite::Error),
 }
 
-pub fn add_todos(connection: &mut Connection, todos: Vec<todo::Todo>) -> Result<(), AddTodosError> {
+pub fn add_todos(
+    connection: &mut Connection,
+    todos: Vec<todo::Todo>,
+) -> Result<Vec<todo::Todo>, AddTodosError> {
+    // A savepoint rather than a transaction, so callers can nest it in a bigger operation.
     let transaction = connection
-        .transaction()
+        .savepoint()
         .map_err(AddTodosError::CreateTransaction)?;
 
+    let mut added = Vec::with_capacity(todos.len());
+
     {
-        let mut statement = transaction
-            .prepare("INSERT INTO todos (title, done) VALUES (?1, ?2)")
-            .map_err(AddTodosError::PrepareInsert)?;
+        let mut statement = prepare(
+            &transaction,
+            &format!(
+                "INSERT INTO todos
+                (title, done, list, priority, due, note, uuid, label, parent, created_at, updated_at,
+                done_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, {now}, {now}, CASE WHEN ?2 THEN {now} END)",
+                now = NOW
+            ),
+        )
+        .map_err(AddTodosError::PrepareInsert)?;
+        let mut tag_statement = prepare(
+            &transaction,
+            "INSERT OR IGNORE INTO tags (todo_id, name) VALUES (?1, ?2)",
+        )
+        .map_err(AddTodosError::PrepareInsert)?;
 
         for todo in todos {
-            statement
-                .execute(rusqlite::params![todo.title, todo.done])
+            let id = statement
+                .insert(rusqlite::params![
+                    todo.title,
+                    todo.done,
+                    todo.list,
+                    todo.priority,
+                    todo.due,
+                    todo.note,
+                    todo.uuid,
+                    todo.label,
+                    todo.parent
+                ])
                 .map_err(AddTodosError::InsertTodo)?;
+
+            for tag in &todo.tags {
+                tag_statement
+                    .execute(rusqlite::params![id, tag])
+                    .map_err(AddTodosError::InsertTag)?;
+            }
+
+            added.push(todo::Todo {
+                id: id as usize,
+                ..todo
+            });
         }
     }
 
@@ -68,7 +675,46 @@ pub fn add_todos(connection: &mut Connection, todos: Vec<todo::Todo>) -> Result<
         .commit()
         .map_err(AddTodosError::CommitTransaction)?;
 
-    Ok(())
+    log::debug!("Inserted {} todos", added.len());
+    Ok(added)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InsertTodosError {
+    #[error(transparent)]
+    AddTodos(#[from] AddTodosError),
+
+    #[error(transparent)]
+    Reorder(#[from] ReorderTodosError),
+
+    #[error("Fail to insert todos")]
+    Query(#[from] rusqlite::Error),
+}
+
+// Added at the bottom, then moved up to `index` with the todos that were there moving down, past
+// the end they're appended. All of them go to `list`, every id stays as it is.
+pub fn insert_todos(
+    connection: &mut Connection,
+    list: &str,
+    index: usize,
+    todos: Vec<todo::Todo>,
+) -> Result<Vec<todo::Todo>, InsertTodosError> {
+    in_savepoint(connection, |connection| {
+        let added = add_todos(connection, todos)?;
+        let added_ids: Vec<usize> = added.iter().map(|todo| todo.id).collect();
+        let mut ids: Vec<usize> = prepare(
+            connection,
+            "SELECT id FROM todos WHERE list = ?1 ORDER BY position, id",
+        )?
+        .query_map([list], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+        ids.retain(|id| !added_ids.contains(id));
+        let index = index.min(ids.len());
+        ids.splice(index..index, added_ids);
+        reorder_todos(connection, ids)?;
+        Ok(added)
+    })
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -86,22 +732,45 @@ pub enum UpdateTodosError {
     CommitTransaction(#[source] rusqlite::Error),
 }
 
+// Only todos that differ are written, the count is of those that changed.
 pub fn update_todos(
     connection: &mut Connection,
     todos: Vec<todo::Todo>,
-) -> Result<(), UpdateTodosError> {
+) -> Result<usize, UpdateTodosError> {
     let transaction = connection
-        .transaction()
+        .savepoint()
         .map_err(UpdateTodosError::CreateTransaction)?;
 
+    let mut updated = 0;
+
     {
-        let mut statement = transaction
-            .prepare("UPDATE todos SET title = ?1, done = ?2 WHERE id = ?3")
-            .map_err(UpdateTodosError::Statement)?;
+        let mut statement = prepare(
+            &transaction,
+            &format!(
+                "UPDATE todos SET title = ?1, done = ?2, priority = ?3, due = ?4, note = ?5,
+                label = ?7, parent = ?8,
+                done_at = CASE WHEN NOT ?2 THEN NULL WHEN done THEN done_at ELSE {now} END,
+                updated_at = {now}
+                WHERE id = ?6
+                    AND (title, done, priority, due, note, label, parent)
+                        IS NOT (?1, ?2, ?3, ?4, ?5, ?7, ?8)",
+                now = NOW
+            ),
+        )
+        .map_err(UpdateTodosError::Statement)?;
 
         for todo in todos {
-            statement
-                .execute(rusqlite::params![todo.title, todo.done, todo.id])
+            updated += statement
+                .execute(rusqlite::params![
+                    todo.title,
+                    todo.done,
+                    todo.priority,
+                    todo.due,
+                    todo.note,
+                    todo.id,
+                    todo.label,
+                    todo.parent
+                ])
                 .map_err(UpdateTodosError::UpdateTodo)?;
         }
     }
@@ -110,25 +779,959 @@ pub fn update_todos(
         .commit()
         .map_err(UpdateTodosError::CommitTransaction)?;
 
+    log::debug!("Updated {} todos", updated);
+
+    Ok(updated)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReindexTodosError {
+    #[error("Fail to create transaction")]
+    CreateTransaction(#[source] rusqlite::Error),
+
+    #[error("Fail to renumber todo ids")]
+    Renumber(#[source] rusqlite::Error),
+
+    #[error("Fail to commit transaction")]
+    CommitTransaction(#[source] rusqlite::Error),
+}
+
+// Renumbers ids to 1..N in list order and returns how many changed. The only place ids change,
+// which is why it's a command of its own.
+pub fn reindex_todos(connection: &mut Connection) -> Result<usize, ReindexTodosError> {
+    let transaction = connection
+        .savepoint()
+        .map_err(ReindexTodosError::CreateTransaction)?;
+
+    let renumbered = renumber(&transaction).map_err(ReindexTodosError::Renumber)?;
+
+    transaction
+        .commit()
+        .map_err(ReindexTodosError::CommitTransaction)?;
+
+    log::debug!("Renumbered {} todos", renumbered);
+    Ok(renumbered)
+}
+
+fn renumber(transaction: &Connection) -> rusqlite::Result<usize> {
+    let ids: Vec<usize> = prepare(transaction, "SELECT id FROM todos ORDER BY position, id")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let moves: Vec<(usize, usize)> = ids
+        .into_iter()
+        .zip(1..)
+        .filter(|(old, new)| old != new)
+        .collect();
+
+    reassign_ids(transaction, &moves)?;
+    prepare(transaction, "UPDATE todos SET position = id")?.execute([])?;
+    Ok(moves.len())
+}
+
+fn reassign_ids(transaction: &Connection, moves: &[(usize, usize)]) -> rusqlite::Result<()> {
+    // Tags point at the old ids until the end of the reassignment.
+    transaction.pragma_update(None, "defer_foreign_keys", true)?;
+
+    // Moving through negative ids first means no two todos ever share an id.
+    let mut move_todo = prepare(transaction, "UPDATE todos SET id = -?2 WHERE id = ?1")?;
+    let mut move_tags = prepare(
+        transaction,
+        "UPDATE tags SET todo_id = -?2 WHERE todo_id = ?1",
+    )?;
+    for (old, new) in moves {
+        move_todo.execute(rusqlite::params![old, new])?;
+        move_tags.execute(rusqlite::params![old, new])?;
+    }
+    prepare(transaction, "UPDATE todos SET id = -id WHERE id < 0")?.execute([])?;
+    prepare(
+        transaction,
+        "UPDATE tags SET todo_id = -todo_id WHERE todo_id < 0",
+    )?
+    .execute([])?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReorderTodosError {
+    #[error("Fail to create transaction")]
+    CreateTransaction(#[source] rusqlite::Error),
+
+    #[error("Fail to reorder todos")]
+    Reorder(#[source] rusqlite::Error),
+
+    #[error("Fail to commit transaction")]
+    CommitTransaction(#[source] rusqlite::Error),
+}
+
+// The positions the todos already take are handed out again following the new order.
+pub fn reorder_todos(
+    connection: &mut Connection,
+    ids: Vec<usize>,
+) -> Result<(), ReorderTodosError> {
+    let transaction = connection
+        .savepoint()
+        .map_err(ReorderTodosError::CreateTransaction)?;
+
+    let reordered = reposition(&transaction, ids).map_err(ReorderTodosError::Reorder)?;
+
+    transaction
+        .commit()
+        .map_err(ReorderTodosError::CommitTransaction)?;
+
+    log::debug!("Reordered {} todos", reordered);
     Ok(())
 }
 
+fn reposition(transaction: &Connection, ids: Vec<usize>) -> rusqlite::Result<usize> {
+    let rc = Rc::new(id_values(&ids));
+    let positions: Vec<i64> = prepare(
+        transaction,
+        "SELECT position FROM todos WHERE id IN rarray(?1) ORDER BY position",
+    )?
+    .query_map([rc], |row| row.get(0))?
+    .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = prepare(
+        transaction,
+        "UPDATE todos SET position = ?2 WHERE id = ?1 AND position IS NOT ?2",
+    )?;
+    let mut reordered = 0;
+    for (id, position) in ids.into_iter().zip(positions) {
+        reordered += statement.execute(rusqlite::params![id, position])?;
+    }
+    Ok(reordered)
+}
+
+// Ids fit an i64 the way SQLite stores them, a todo can't have one past that.
+fn id_values(ids: &[usize]) -> Vec<Value> {
+    ids.iter()
+        .map(|id| Value::from(i64::try_from(*id).unwrap_or(i64::MAX)))
+        .collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to move todos to another list")]
+pub struct MoveToListError(#[from] rusqlite::Error);
+
+// Positioned past every other todo, so they end up at the bottom of the list in the given order.
+pub fn move_to_list(
+    connection: &mut Connection,
+    ids: Vec<usize>,
+    list: &str,
+) -> Result<Vec<usize>, MoveToListError> {
+    let transaction = connection.savepoint()?;
+
+    let last: i64 = prepare(&transaction, "SELECT COALESCE(MAX(position), 0) FROM todos")?
+        .query_row([], |row| row.get(0))?;
+    let mut statement = prepare(
+        &transaction,
+        &format!(
+            "UPDATE todos SET list = ?1, position = ?3, updated_at = {} WHERE id = ?2",
+            NOW
+        ),
+    )?;
+    for (id, position) in id_values(&ids).into_iter().zip(last + 1..) {
+        statement.execute(rusqlite::params![list, id, position])?;
+    }
+    drop(statement);
+
+    transaction.commit()?;
+
+    log::debug!("Moved {} todos to {}", ids.len(), list);
+    Ok(ids)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to back up the database")]
+pub struct BackupError(#[from] rusqlite::Error);
+
+// Unlike copying the file, this gives a consistent copy while another process may be writing.
+pub fn backup_database(connection: &Connection, path: &Path) -> Result<(), BackupError> {
+    connection.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+    log::debug!("Backed up the database to {}", path.display());
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to rename the list")]
+pub struct RenameListError(#[from] rusqlite::Error);
+
+// The history goes along, so it keeps showing under the list's new name.
+pub fn rename_list(
+    connection: &mut Connection,
+    from: &str,
+    to: &str,
+) -> Result<usize, RenameListError> {
+    let transaction = connection.savepoint()?;
+    let renamed =
+        prepare(&transaction, "UPDATE todos SET list = ?2 WHERE list = ?1")?.execute([from, to])?;
+    prepare(&transaction, "UPDATE history SET list = ?2 WHERE list = ?1")?.execute([from, to])?;
+    transaction.commit()?;
+
+    log::debug!("Renamed list {} to {}", from, to);
+    Ok(renamed)
+}
+
+// Trades durability for speed: until the operation ends, a crash or power loss can corrupt the
+// database, so the previous mode is restored whether or not the operation succeeds.
+pub fn without_fsync<T, E: From<rusqlite::Error>>(
+    connection: &mut Connection,
+    operation: impl FnOnce(&mut Connection) -> Result<T, E>,
+) -> Result<T, E> {
+    let synchronous: i64 = connection.pragma_query_value(None, "synchronous", |row| row.get(0))?;
+    connection.pragma_update(None, "synchronous", "OFF")?;
+
+    let result = operation(connection);
+    connection.pragma_update(None, "synchronous", synchronous)?;
+    result
+}
+
+// Runs several operations atomically, each of them may open savepoints of its own.
+pub fn in_savepoint<T, E: From<rusqlite::Error>>(
+    connection: &mut Connection,
+    operation: impl FnOnce(&mut Connection) -> Result<T, E>,
+) -> Result<T, E> {
+    connection.execute_batch("SAVEPOINT operation")?;
+
+    match operation(connection) {
+        Ok(value) => {
+            connection.execute_batch("RELEASE operation")?;
+            Ok(value)
+        }
+        Err(error) => {
+            if let Err(rollback_error) =
+                connection.execute_batch("ROLLBACK TO operation; RELEASE operation")
+            {
+                log::warn!("Fail to roll back: {}", rollback_error);
+            }
+            Err(error)
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 #[error("Fail to remove todo")]
 pub struct RemoveTodoError(#[from] rusqlite::Error);
 
-pub fn remove_todos(connection: &Connection, ids: Vec<usize>) -> Result<(), RemoveTodoError> {
-    let ids: Vec<Value> = ids.into_iter().map(|id| Value::from(id as u32)).collect();
-    let rc = Rc::new(ids);
+pub fn remove_todos(connection: &Connection, ids: Vec<usize>) -> Result<usize, RemoveTodoError> {
+    let rc = Rc::new(id_values(&ids));
+
+    let removed = prepare(connection, "DELETE FROM todos WHERE id in rarray(?1)")?
+        .execute(rusqlite::params![rc])?;
+
+    log::debug!("Removed {} todos", removed);
+    Ok(removed)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to move todos to the trash")]
+pub struct TrashTodosError(#[from] rusqlite::Error);
+
+// Deleted in the same savepoint they're copied in, so a todo is never in both places or neither.
+// Beyond the limit the oldest trashed todos are purged.
+pub fn trash_todos(
+    connection: &mut Connection,
+    ids: Vec<usize>,
+    limit: Option<usize>,
+) -> Result<usize, TrashTodosError> {
+    let rc = Rc::new(id_values(&ids));
+    let transaction = connection.savepoint()?;
+
+    prepare(
+        &transaction,
+        &format!(
+            "INSERT INTO trash
+            (uuid, title, done, list, priority, due, note, label, parent, tags, created_at, done_at,
+            updated_at, removed_at)
+            SELECT uuid, title, done, list, priority, due, note, label, parent,
+            (SELECT json_group_array(name) FROM tags WHERE todo_id = todos.id), created_at, done_at,
+            updated_at, {}
+            FROM todos WHERE id IN rarray(?1) ORDER BY id",
+            NOW
+        ),
+    )?
+    .execute(rusqlite::params![rc])?;
+    let trashed =
+        prepare(&transaction, "DELETE FROM todos WHERE id IN rarray(?1)")?.execute([rc])?;
+
+    if let Some(limit) = limit {
+        let purged = prepare(
+            &transaction,
+            "DELETE FROM trash WHERE id NOT IN (SELECT id FROM trash ORDER BY id DESC LIMIT ?1)",
+        )?
+        .execute([limit])?;
+        log::debug!("Purged {} todos past the trash limit", purged);
+    }
+
+    transaction.commit()?;
+
+    log::debug!("Trashed {} todos", trashed);
+    Ok(trashed)
+}
+
+#[derive(Debug)]
+pub struct TrashedTodo {
+    pub id: usize,
+    pub removed_at: DateTime<Utc>,
+    pub todo: todo::Todo,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetTrashError {
+    #[error("Fail to get the trash")]
+    Query(#[from] rusqlite::Error),
+
+    #[error("Fail to read the tags of a trashed todo")]
+    Tags(#[from] serde_json::Error),
+}
+
+// Oldest first, in the order the todos were removed.
+pub fn get_trash(connection: &Connection) -> Result<Vec<TrashedTodo>, GetTrashError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT id, uuid, title, done, list, priority, due, note, label, parent, tags, created_at,
+        done_at, updated_at, removed_at FROM trash ORDER BY id",
+    )?;
+
+    let rows: Vec<(TrashedTodo, String)> = statement
+        .query_map([], |row| {
+            Ok((
+                TrashedTodo {
+                    id: row.get(0)?,
+                    removed_at: row.get(14)?,
+                    todo: todo::Todo {
+                        id: 0,
+                        uuid: row.get(1)?,
+                        title: row.get(2)?,
+                        done: row.get(3)?,
+                        list: row.get(4)?,
+                        tags: Vec::new(),
+                        priority: row.get(5)?,
+                        due: row.get(6)?,
+                        note: row.get(7)?,
+                        label: row.get(8)?,
+                        parent: row.get(9)?,
+                        created_at: row.get(11)?,
+                        done_at: row.get(12)?,
+                        updated_at: row.get(13)?,
+                    },
+                },
+                row.get(10)?,
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    rows.into_iter()
+        .map(|(mut trashed, tags)| {
+            trashed.todo.tags = serde_json::from_str(&tags)?;
+            Ok(trashed)
+        })
+        .collect()
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RestoreTodosError {
+    #[error("There's no todo {0} in the trash")]
+    NotInTrash(usize),
+
+    #[error("Fail to restore todos from the trash")]
+    Restore(#[from] rusqlite::Error),
+}
+
+// Numbered past every other todo, so they're back at the bottom of their list. A todo added with
+// the same UUID since, like by an import, leaves the restored one to get a new one.
+pub fn restore_todos(
+    connection: &mut Connection,
+    ids: &[usize],
+) -> Result<Vec<usize>, RestoreTodosError> {
+    let transaction = connection.savepoint()?;
+    let mut restored = Vec::with_capacity(ids.len());
+
+    {
+        let mut insert = prepare(
+            &transaction,
+            &format!(
+                "INSERT INTO todos
+                (uuid, title, done, list, priority, due, note, label, parent, created_at, done_at,
+                updated_at)
+                SELECT CASE WHEN uuid IN (SELECT uuid FROM todos) THEN NULL ELSE uuid END, title,
+                done, list, priority, due, note, label, parent, created_at, done_at, {}
+                FROM trash WHERE id = ?1",
+                NOW
+            ),
+        )?;
+        let mut tags = prepare(
+            &transaction,
+            "INSERT OR IGNORE INTO tags (todo_id, name)
+            SELECT ?1, value FROM json_each((SELECT tags FROM trash WHERE id = ?2))",
+        )?;
+        let mut delete = prepare(&transaction, "DELETE FROM trash WHERE id = ?1")?;
+
+        for &id in ids {
+            if insert.execute([id])? == 0 {
+                return Err(RestoreTodosError::NotInTrash(id));
+            }
+            let todo_id = transaction.last_insert_rowid() as usize;
+            tags.execute([todo_id, id])?;
+            delete.execute([id])?;
+            restored.push(todo_id);
+        }
+    }
+
+    transaction.commit()?;
+
+    log::debug!("Restored {} todos", restored.len());
+    Ok(restored)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to empty the trash")]
+pub struct EmptyTrashError(#[from] rusqlite::Error);
+
+pub fn empty_trash(connection: &Connection) -> Result<usize, EmptyTrashError> {
+    let purged = prepare(connection, "DELETE FROM trash")?.execute([])?;
+    log::debug!("Purged {} todos from the trash", purged);
+    Ok(purged)
+}
+
+// Done todos without a done time predate it and are kept.
+pub fn prune_done_todos(
+    connection: &Connection,
+    done_before: DateTime<Utc>,
+) -> Result<usize, RemoveTodoError> {
+    let pruned = prepare(connection, "DELETE FROM todos WHERE done AND done_at < ?1")?
+        .execute([format_timestamp(done_before)])?;
+
+    log::debug!("Pruned {} todos", pruned);
+    Ok(pruned)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RepositoryError {
+    #[error(transparent)]
+    Get(#[from] GetTodosError),
+
+    #[error(transparent)]
+    Add(#[from] AddTodosError),
+
+    #[error(transparent)]
+    Update(#[from] UpdateTodosError),
+
+    #[error(transparent)]
+    Remove(#[from] RemoveTodoError),
+}
+
+// The reads and writes of todos that command logic needs, so it can be tested against a fake
+// store instead of a database. History, stats and the rest still take a connection.
+pub trait TodoRepository {
+    // The todos of a list, in list order.
+    fn all(&self, list: &str) -> Result<todo::TodoList, RepositoryError>;
+
+    // Appended to their lists, returned with the ids they were given.
+    fn add(&mut self, todos: Vec<todo::Todo>) -> Result<Vec<todo::Todo>, RepositoryError>;
+
+    // Matched by id, the count is of the todos that changed.
+    fn update(&mut self, todos: Vec<todo::Todo>) -> Result<usize, RepositoryError>;
+
+    fn remove(&mut self, ids: Vec<usize>) -> Result<usize, RepositoryError>;
+}
+
+impl TodoRepository for Connection {
+    fn all(&self, list: &str) -> Result<todo::TodoList, RepositoryError> {
+        Ok(get_todos(self, list)?)
+    }
+
+    fn add(&mut self, todos: Vec<todo::Todo>) -> Result<Vec<todo::Todo>, RepositoryError> {
+        Ok(add_todos(self, todos)?)
+    }
+
+    fn update(&mut self, todos: Vec<todo::Todo>) -> Result<usize, RepositoryError> {
+        Ok(update_todos(self, todos)?)
+    }
+
+    fn remove(&mut self, ids: Vec<usize>) -> Result<usize, RepositoryError> {
+        Ok(remove_todos(self, ids)?)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to update the tags")]
+pub struct UpdateTagsError(#[from] rusqlite::Error);
+
+// Every list at once. A todo that already has the new tag keeps it once, the old one merged in.
+pub fn rename_tag(
+    connection: &mut Connection,
+    from: &str,
+    to: &str,
+) -> Result<usize, UpdateTagsError> {
+    let savepoint = connection.savepoint()?;
+    let tagged: usize = prepare(&savepoint, "SELECT COUNT(*) FROM tags WHERE name = ?1")?
+        .query_row([from], |row| row.get(0))?;
+
+    if from != to {
+        prepare(
+            &savepoint,
+            "INSERT OR IGNORE INTO tags (todo_id, name) SELECT todo_id, ?2 FROM tags WHERE name = ?1",
+        )?
+        .execute([from, to])?;
+        prepare(&savepoint, "DELETE FROM tags WHERE name = ?1")?.execute([from])?;
+    }
+    savepoint.commit()?;
+
+    log::debug!("Renamed tag on {} todos", tagged);
+    Ok(tagged)
+}
+
+pub fn remove_tag(connection: &Connection, tag: &str) -> Result<usize, UpdateTagsError> {
+    let removed = prepare(connection, "DELETE FROM tags WHERE name = ?1")?.execute([tag])?;
+
+    log::debug!("Removed tag from {} todos", removed);
+    Ok(removed)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Stats {
+    pub total: usize,
+    pub done: usize,
+    pub pending: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to get stats")]
+pub struct GetStatsError(#[from] rusqlite::Error);
+
+pub fn get_stats(connection: &Connection, list: &str) -> Result<Stats, GetStatsError> {
+    let (total, done) = prepare(
+        connection,
+        "SELECT COUNT(*), COALESCE(SUM(done), 0) FROM todos WHERE list = ?1",
+    )?
+    .query_row([list], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+    Ok(Stats {
+        total,
+        done,
+        pending: total - done,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayStats {
+    pub date: NaiveDate,
+    pub added: usize,
+    pub completed: usize,
+}
+
+// One entry per day from `from` to `until`, empty days included. The stored times are UTC, the
+// offset in minutes moves them to the local day they happened on.
+pub fn get_daily_stats(
+    connection: &Connection,
+    list: &str,
+    from: NaiveDate,
+    until: NaiveDate,
+    utc_offset_minutes: i32,
+) -> Result<Vec<DayStats>, GetStatsError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT day, SUM(added), SUM(completed) FROM (
+            SELECT date(created_at, ?2) AS day, 1 AS added, 0 AS completed
+            FROM todos WHERE list = ?1
+            UNION ALL
+            SELECT date(done_at, ?2), 0, 1 FROM todos WHERE list = ?1
+        )
+        WHERE day BETWEEN ?3 AND ?4
+        GROUP BY day",
+    )?;
+    let offset = format!("{} minutes", utc_offset_minutes);
+    let mut counts: HashMap<NaiveDate, (usize, usize)> = statement
+        .query_map(rusqlite::params![list, offset, from, until], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(from
+        .iter_days()
+        .take_while(|date| *date <= until)
+        .map(|date| {
+            let (added, completed) = counts.remove(&date).unwrap_or_default();
+            DayStats {
+                date,
+                added,
+                completed,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpenCount {
+    pub date: NaiveDate,
+    pub open: usize,
+}
+
+// How many todos were open at the end of each day from `from` to `until`. Todos without an
+// added time have always been there, done ones without a done time were done before any day.
+pub fn get_open_counts(
+    connection: &Connection,
+    list: &str,
+    from: NaiveDate,
+    until: NaiveDate,
+    utc_offset_minutes: i32,
+) -> Result<Vec<OpenCount>, GetStatsError> {
+    let offset = format!("{} minutes", utc_offset_minutes);
+    let spans: Vec<(Option<NaiveDate>, Option<NaiveDate>, bool)> = prepare(
+        connection,
+        "SELECT date(created_at, ?2), date(done_at, ?2), done FROM todos WHERE list = ?1",
+    )?
+    .query_map(rusqlite::params![list, offset], |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })?
+    .collect::<Result<_, _>>()?;
+
+    Ok(from
+        .iter_days()
+        .take_while(|date| *date <= until)
+        .map(|date| {
+            let open = spans
+                .iter()
+                .filter(|(added, completed, done)| {
+                    added.is_none_or(|added| added <= date)
+                        && match (done, completed) {
+                            (false, _) => true,
+                            (true, Some(completed)) => *completed > date,
+                            (true, None) => false,
+                        }
+                })
+                .count();
+            OpenCount { date, open }
+        })
+        .collect())
+}
+
+// Done todos without a done time predate it and aren't counted.
+pub fn count_completed_since(
+    connection: &Connection,
+    list: &str,
+    since: DateTime<Utc>,
+) -> Result<usize, GetStatsError> {
+    let count = prepare(
+        connection,
+        "SELECT COUNT(*) FROM todos WHERE list = ?1 AND done AND done_at >= ?2",
+    )?
+    .query_row(rusqlite::params![list, format_timestamp(since)], |row| {
+        row.get(0)
+    })?;
+
+    Ok(count)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub title: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub done_at: Option<DateTime<Utc>>,
+}
 
-    connection.execute(
-        "DELETE FROM todos WHERE id in rarray(?1)",
-        rusqlite::params![rc],
+// The todos done since then, along with those done without a done time, which could be from
+// any time and are for the caller to leave out.
+pub fn get_completions(
+    connection: &Connection,
+    list: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<Completion>, GetStatsError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT title, created_at, done_at FROM todos
+        WHERE list = ?1 AND done AND (done_at >= ?2 OR done_at IS NULL)
+        ORDER BY id",
     )?;
 
+    let completions = statement
+        .query_map(rusqlite::params![list, format_timestamp(since)], |row| {
+            Ok(Completion {
+                title: row.get(0)?,
+                created_at: row.get(1)?,
+                done_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(completions)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Status {
+    pub done: usize,
+    pub pending: usize,
+    pub overdue: usize,
+}
+
+pub fn get_status(
+    connection: &Connection,
+    list: &str,
+    today: NaiveDate,
+) -> Result<Status, GetStatsError> {
+    let status = prepare(
+        connection,
+        "SELECT COALESCE(SUM(done), 0), COALESCE(SUM(NOT done), 0),
+        COALESCE(SUM(NOT done AND due < ?2), 0)
+        FROM todos WHERE list = ?1",
+    )?
+    .query_row(rusqlite::params![list, today], |row| {
+        Ok(Status {
+            done: row.get(0)?,
+            pending: row.get(1)?,
+            overdue: row.get(2)?,
+        })
+    })?;
+
+    Ok(status)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ListStats {
+    pub name: String,
+    pub pending: usize,
+    pub done: usize,
+}
+
+pub fn get_list_stats(connection: &Connection) -> Result<Vec<ListStats>, GetStatsError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT list, SUM(NOT done), SUM(done) FROM todos GROUP BY list ORDER BY list",
+    )?;
+
+    let stats = statement
+        .query_map([], |row| {
+            Ok(ListStats {
+                name: row.get(0)?,
+                pending: row.get(1)?,
+                done: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(stats)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TagStats {
+    pub tag: String,
+    pub done: usize,
+    pub pending: usize,
+}
+
+pub fn get_tag_stats(connection: &Connection, list: &str) -> Result<Vec<TagStats>, GetStatsError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT tags.name, SUM(todos.done), SUM(NOT todos.done)
+        FROM todos LEFT JOIN tags ON tags.todo_id = todos.id
+        WHERE todos.list = ?1
+        GROUP BY tags.name
+        ORDER BY tags.name IS NULL, tags.name",
+    )?;
+
+    let stats: Vec<TagStats> = statement
+        .query_map([list], |row| {
+            Ok(TagStats {
+                tag: row
+                    .get::<_, Option<String>>(0)?
+                    .unwrap_or_else(|| UNTAGGED.into()),
+                done: row.get(1)?,
+                pending: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    log::debug!("Fetched {} tag stats", stats.len());
+    Ok(stats)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetNotifiableTodosError {
+    #[error("Fail to get past notifications")]
+    GetNotified(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+}
+
+pub fn get_notifiable_todos(
+    connection: &Connection,
+    until: NaiveDate,
+    force: bool,
+) -> Result<Vec<(usize, todo::Todo)>, GetNotifiableTodosError> {
+    let mut statement = prepare(connection, "SELECT todo_id, due FROM notifications")?;
+    let notified: HashSet<(usize, NaiveDate)> = statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let query = TodoQuery {
+        done: Some(false),
+        due_before: until.succ_opt(),
+        ..Default::default()
+    };
+    let todos = select_todos(connection, &query, "due, list, row_index", None)?
+        .into_iter()
+        .filter(|(_, todo)| {
+            force
+                || !todo
+                    .due
+                    .is_some_and(|due| notified.contains(&(todo.id, due)))
+        })
+        .collect();
+
+    Ok(todos)
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to access the metadata")]
+pub struct MetadataError(#[from] rusqlite::Error);
+
+pub fn get_metadata<T: FromSql>(
+    connection: &Connection,
+    key: &str,
+) -> Result<Option<T>, MetadataError> {
+    Ok(
+        prepare(connection, "SELECT value FROM metadata WHERE key = ?1")?
+            .query_row([key], |row| row.get(0))
+            .optional()?,
+    )
+}
+
+pub fn set_metadata(
+    connection: &Connection,
+    key: &str,
+    value: impl ToSql,
+) -> Result<(), MetadataError> {
+    prepare(
+        connection,
+        "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+    )?
+    .execute(rusqlite::params![key, value])?;
     Ok(())
 }
 
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to remember the notification")]
+pub struct RecordNotifiedError(#[from] rusqlite::Error);
+
+pub fn record_notified(
+    connection: &Connection,
+    todo: &todo::Todo,
+) -> Result<(), RecordNotifiedError> {
+    prepare(
+        connection,
+        "INSERT OR REPLACE INTO notifications (todo_id, due, notified_at) VALUES (?1, ?2, ?3)",
+    )?
+    .execute(rusqlite::params![todo.id, todo.due, Utc::now()])?;
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to get completion candidates")]
+pub struct GetCandidatesError(#[from] rusqlite::Error);
+
+pub fn get_index_candidates(
+    connection: &Connection,
+    list: &str,
+    done: Option<bool>,
+) -> Result<Vec<(usize, String)>, GetCandidatesError> {
+    let mut statement = prepare(
+        connection,
+        &format!(
+            "SELECT row_index, title FROM ({}) WHERE list = ?1 AND (?2 IS NULL OR done = ?2)
+            ORDER BY row_index",
+            INDEXED_TODOS_QUERY
+        ),
+    )?;
+
+    let candidates = statement
+        .query_map(rusqlite::params![list, done], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(candidates)
+}
+
+pub fn get_tag_names(connection: &Connection) -> Result<Vec<String>, GetCandidatesError> {
+    let mut statement = prepare(connection, "SELECT DISTINCT name FROM tags ORDER BY name")?;
+    let tags = statement
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(tags)
+}
+
+pub fn get_list_names(connection: &Connection) -> Result<Vec<String>, GetCandidatesError> {
+    let mut statement = prepare(connection, "SELECT DISTINCT list FROM todos ORDER BY list")?;
+    let lists = statement
+        .query_map([], |row| row.get(0))?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(lists)
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    pub list: String,
+    pub description: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to record the operation in the history")]
+pub struct RecordHistoryError(#[from] rusqlite::Error);
+
+pub fn record_history(
+    connection: &Connection,
+    list: &str,
+    description: &str,
+) -> Result<(), RecordHistoryError> {
+    prepare(
+        connection,
+        "INSERT INTO history (at, list, description) VALUES (?1, ?2, ?3)",
+    )?
+    .execute(rusqlite::params![Utc::now(), list, description])?;
+
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Fail to get the history")]
+pub struct GetHistoryError(#[from] rusqlite::Error);
+
+pub fn get_history(
+    connection: &Connection,
+    list: Option<&str>,
+    limit: usize,
+) -> Result<Vec<HistoryEntry>, GetHistoryError> {
+    let mut statement = prepare(
+        connection,
+        "SELECT at, list, description FROM history WHERE ?1 IS NULL OR list = ?1
+        ORDER BY id DESC LIMIT ?2",
+    )?;
+
+    let history = statement
+        .query_map(rusqlite::params![list, limit], |row| {
+            Ok(HistoryEntry {
+                at: row.get(0)?,
+                list: row.get(1)?,
+                description: row.get(2)?,
+            })
+        })?
+        .filter_map(Result::ok)
+        .collect();
+
+    Ok(history)
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum GetConnectionError {
     #[error("Fail to create and connect to a db")]
@@ -136,28 +1739,151 @@ pub enum GetConnectionError {
 
     #[error(transparent)]
     GetDbPath(#[from] GetDbPathError),
+
+    #[error("Fail to read the database header")]
+    ReadHeader(#[from] std::io::Error),
+
+    #[cfg(not(feature = "encryption"))]
+    #[error("The database is encrypted, rebuild with the encryption feature to open it")]
+    Encrypted,
+
+    #[cfg(feature = "encryption")]
+    #[error(
+        "The database is encrypted, set TODO_PASSPHRASE or a keyfile in the config to open it"
+    )]
+    Encrypted,
+
+    #[cfg(feature = "encryption")]
+    #[error(transparent)]
+    GetPassphrase(GetPassphraseError),
+
+    #[cfg(feature = "encryption")]
+    #[error("Fail to decrypt the database, check the passphrase")]
+    WrongPassphrase,
+}
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+// Plain databases start with a fixed header, while encrypted ones look like random bytes.
+pub fn is_encrypted(path: &Path) -> std::io::Result<bool> {
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    match File::open(path) {
+        Ok(file) => file
+            .take(SQLITE_HEADER.len() as u64)
+            .read_to_end(&mut header)?,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn unlock(_connection: &Connection, path: &Path) -> Result<(), GetConnectionError> {
+    if is_encrypted(path)? {
+        return Err(GetConnectionError::Encrypted);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+fn unlock(connection: &Connection, path: &Path) -> Result<(), GetConnectionError> {
+    if !is_encrypted(path)? {
+        return Ok(());
+    }
+
+    let passphrase = get_passphrase(false).map_err(|error| match error {
+        GetPassphraseError::Missing => GetConnectionError::Encrypted,
+        error => GetConnectionError::GetPassphrase(error),
+    })?;
+    connection.pragma_update(None, "key", passphrase)?;
+
+    // A wrong key only shows once the first page is read.
+    match connection.query_row("SELECT COUNT(*) FROM sqlite_master", [], |_| Ok(())) {
+        Err(rusqlite::Error::SqliteFailure(error, _)) if error.code == ErrorCode::NotADatabase => {
+            Err(GetConnectionError::WrongPassphrase)
+        }
+        result => Ok(result?),
+    }
 }
 
 pub fn get_connection() -> Result<Connection, GetConnectionError> {
-    let connection = Connection::open(get_db_path()?)?;
+    let path = get_db_path()?;
+    log::info!("Using database {}", path.display());
+    let connection = Connection::open(&path)?;
+    unlock(&connection, &path)?;
 
     Ok(connection)
 }
 
+pub fn get_existing_connection() -> Result<Option<Connection>, GetConnectionError> {
+    let path = get_db_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    log::info!("Using existing database {}", path.display());
+    Ok(Some(open_read_only(&path)?))
+}
+
+fn open_read_only(path: &Path) -> Result<Connection, GetConnectionError> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    unlock(&connection, path)?;
+    Ok(connection)
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum CreateTableError {
     #[error("Fail to load array module")]
     LoadArrayModule(#[source] rusqlite::Error),
 
+    #[error("Fail to enable foreign keys")]
+    EnableForeignKeys(#[source] rusqlite::Error),
+
     #[error("Fail to execute create table query")]
     ExecuteCreateTableQuery(#[source] rusqlite::Error),
+
+    #[error("Fail to migrate the database schema")]
+    Migrate(#[source] rusqlite::Error),
+}
+
+pub const SCHEMA_VERSION: usize = MIGRATIONS.len();
+
+pub fn get_schema_version(connection: &Connection) -> rusqlite::Result<usize> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
 }
 
 pub fn create_table(connection: &Connection) -> Result<(), CreateTableError> {
-    rusqlite::vtab::array::load_module(&connection).map_err(CreateTableError::LoadArrayModule)?;
+    rusqlite::vtab::array::load_module(connection).map_err(CreateTableError::LoadArrayModule)?;
     connection
-        .execute(CREATE_TABLE_QUERY, [])
-        .map_err(CreateTableError::ExecuteCreateTableQuery)?;
+        .pragma_update(None, "foreign_keys", true)
+        .map_err(CreateTableError::EnableForeignKeys)?;
+    for query in [
+        CREATE_TABLE_QUERY,
+        CREATE_TAGS_TABLE_QUERY,
+        CREATE_HISTORY_TABLE_QUERY,
+        CREATE_NOTIFICATIONS_TABLE_QUERY,
+        CREATE_METADATA_TABLE_QUERY,
+    ] {
+        prepare(connection, query)
+            .and_then(|mut statement| statement.execute([]))
+            .map_err(CreateTableError::ExecuteCreateTableQuery)?;
+    }
+    migrate(connection).map_err(CreateTableError::Migrate)?;
+    Ok(())
+}
+
+fn migrate(connection: &Connection) -> rusqlite::Result<()> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.unchecked_transaction()?;
+        prepare(&transaction, migration)?.execute([])?;
+        transaction.pragma_update(None, "user_version", i + 1)?;
+        transaction.commit()?;
+        log::info!("Migrated the database schema to version {}", i + 1);
+    }
+
     Ok(())
 }
 
@@ -168,6 +1894,9 @@ pub enum GetConnectionWithTableError {
 
     #[error(transparent)]
     CreateTable(#[from] CreateTableError),
+
+    #[error("The database schema is outdated, run once without --read-only to upgrade it")]
+    Outdated,
 }
 
 pub fn get_connection_with_table() -> Result<Connection, GetConnectionWithTableError> {
@@ -176,11 +1905,37 @@ pub fn get_connection_with_table() -> Result<Connection, GetConnectionWithTableE
     Ok(connection)
 }
 
+pub fn open_connection(read_only: bool) -> Result<Connection, GetConnectionWithTableError> {
+    if !read_only {
+        return get_connection_with_table();
+    }
+
+    let path = get_db_path().map_err(GetConnectionError::from)?;
+    log::info!("Using database {} read-only", path.display());
+    let connection = open_read_only(&path)?;
+    check_read_only_schema(&connection)?;
+    Ok(connection)
+}
+
+// Nothing may be written, so instead of migrating an old schema it is only reported.
+pub fn check_read_only_schema(connection: &Connection) -> Result<(), GetConnectionWithTableError> {
+    rusqlite::vtab::array::load_module(connection).map_err(CreateTableError::LoadArrayModule)?;
+
+    let version: usize = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(GetConnectionError::Open)?;
+    if version < MIGRATIONS.len() {
+        return Err(GetConnectionWithTableError::Outdated);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use self::todo::Todo;
+    use self::todo::{Todo, DEFAULT_LIST};
 
     use super::*;
+    use chrono::NaiveDateTime;
     use rusqlite::params;
 
     #[test]
@@ -200,12 +1955,62 @@ mod tests {
         assert_eq!(table_info[0], "todos");
     }
 
+    #[test]
+    fn test_query_todos_by_timestamps() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        connection
+            .execute_batch(
+                "INSERT INTO todos (title, created_at, done) VALUES
+                    ('old', '2024-01-31 23:59:59.999', 0),
+                    ('new', '2024-02-01 00:00:00.000', 0);
+                INSERT INTO todos (title, created_at, done, done_at) VALUES
+                    ('done', '2024-01-01 10:00:00.000', 1, '2024-02-15 12:00:00.000');",
+            )
+            .unwrap();
+        let at = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+                .unwrap()
+                .and_utc()
+        };
+        let titles = |query: TodoQuery| -> Vec<String> {
+            query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(TodoQuery {
+                created_from: Some(at("2024-02-01 00:00")),
+                ..Default::default()
+            }),
+            vec!["new"]
+        );
+        assert_eq!(
+            titles(TodoQuery {
+                created_before: Some(at("2024-02-01 00:00")),
+                ..Default::default()
+            }),
+            vec!["old", "done"]
+        );
+        assert_eq!(
+            titles(TodoQuery {
+                done_from: Some(at("2024-02-15 00:00")),
+                done_before: Some(at("2024-02-16 00:00")),
+                ..Default::default()
+            }),
+            vec!["done"]
+        );
+    }
+
     #[test]
     fn test_get_todos() {
         let connection = Connection::open_in_memory().unwrap();
         create_table(&connection).unwrap();
 
-        let todos = get_todos(&connection).unwrap();
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
         assert_eq!(todos.len(), 0);
 
         connection
@@ -221,13 +2026,13 @@ mod tests {
             )
             .unwrap();
 
-        let todos = get_todos(&connection).unwrap();
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
 
         assert_eq!(todos.len(), 2);
         assert_eq!(todos[0].title, "todo1");
-        assert_eq!(todos[0].done, false);
+        assert!(!todos[0].done);
         assert_eq!(todos[1].title, "todo2");
-        assert_eq!(todos[1].done, true);
+        assert!(todos[1].done);
     }
 
     #[test]
@@ -239,7 +2044,7 @@ mod tests {
 
         add_todos(&mut connection, expected_todos.clone()).unwrap();
 
-        let received_todos = get_todos(&connection).unwrap();
+        let received_todos = get_todos(&connection, DEFAULT_LIST).unwrap();
 
         assert_eq!(received_todos.len(), expected_todos.len());
 
@@ -267,26 +2072,30 @@ mod tests {
             )
             .unwrap();
 
-        let mut todos = get_todos(&connection).unwrap();
+        let mut todos = get_todos(&connection, DEFAULT_LIST).unwrap();
         todos[0].title = "new todo1".into();
         todos[0].done = true;
         todos[1].title = "new todo2".into();
         todos[1].done = false;
 
-        update_todos(&mut connection, todos).unwrap();
+        assert_eq!(update_todos(&mut connection, todos.to_vec()).unwrap(), 2);
+        assert_eq!(
+            update_todos(&mut connection, todos.into_inner()).unwrap(),
+            0
+        );
 
-        let received_todos = get_todos(&connection).unwrap();
+        let received_todos = get_todos(&connection, DEFAULT_LIST).unwrap();
 
         assert_eq!(received_todos.len(), 2);
         assert_eq!(received_todos[0].title, "new todo1");
-        assert_eq!(received_todos[0].done, true);
+        assert!(received_todos[0].done);
         assert_eq!(received_todos[1].title, "new todo2");
-        assert_eq!(received_todos[1].done, false);
+        assert!(!received_todos[1].done);
     }
 
     #[test]
     fn test_remove_todos() {
-        let mut connection = Connection::open_in_memory().unwrap();
+        let connection = Connection::open_in_memory().unwrap();
         create_table(&connection).unwrap();
 
         connection
@@ -302,12 +2111,1098 @@ mod tests {
             )
             .unwrap();
 
-        remove_todos(&mut connection, vec![0]).unwrap();
+        remove_todos(&connection, vec![0]).unwrap();
 
-        let todos = get_todos(&connection).unwrap();
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
 
         assert_eq!(todos.len(), 1);
         assert_eq!(todos[0].title, "todo2");
-        assert_eq!(todos[0].done, true);
+        assert!(todos[0].done);
+    }
+
+    #[test]
+    fn test_add_todos_with_tags() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = Todo {
+            tags: vec!["work".into(), "home".into()],
+            ..Todo::new("todo1".into())
+        };
+        add_todos(&mut connection, vec![todo, Todo::new("todo2".into())]).unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos[0].tags, vec!["home", "work"]);
+        assert!(todos[1].tags.is_empty());
+    }
+
+    #[test]
+    fn test_query_combines_filters() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = |title: &str, tags: &[&str], due: Option<&str>| Todo {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            due: due.map(|due| due.parse().unwrap()),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("deploy app", &["work", "ops"], Some("2024-05-02")),
+                todo("deploy docs", &["home"], Some("2024-05-02")),
+                todo("deploy site", &["work"], Some("2024-06-01")),
+                todo("write report", &["work"], Some("2024-05-02")),
+            ],
+        )
+        .unwrap();
+
+        let titles = |query: TodoQuery| -> Vec<String> {
+            query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.title)
+                .collect()
+        };
+        let query = TodoQuery {
+            done: Some(false),
+            tags: vec!["work".into()],
+            search: vec!["deploy".into()],
+            ..Default::default()
+        };
+        assert_eq!(titles(query.clone()), vec!["deploy app", "deploy site"]);
+        assert_eq!(
+            titles(TodoQuery {
+                due_before: Some("2024-05-03".parse().unwrap()),
+                ..query.clone()
+            }),
+            vec!["deploy app"]
+        );
+        assert_eq!(
+            titles(TodoQuery {
+                tags: vec!["work".into(), "ops".into()],
+                search: vec!["de".into(), "app".into()],
+                ..query
+            }),
+            vec!["deploy app"]
+        );
+    }
+
+    #[test]
+    fn test_query_todos_by_tags() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = |title: &str, tags: &[&str]| Todo {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("both", &["work", "ops"]),
+                todo("work", &["work"]),
+                todo("ops", &["ops", "home"]),
+                todo("home", &["home"]),
+                todo("untagged", &[]),
+            ],
+        )
+        .unwrap();
+
+        let titles = |tags: &[&str], all_tags| -> Vec<String> {
+            let query = TodoQuery {
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                all_tags,
+                ..Default::default()
+            };
+            query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.title)
+                .collect()
+        };
+        assert_eq!(titles(&["work", "ops"], false), vec!["both", "work", "ops"]);
+        assert_eq!(titles(&["work", "ops"], true), vec!["both"]);
+        assert_eq!(titles(&["ops", "home"], false), vec!["both", "ops", "home"]);
+        assert_eq!(titles(&["ops", "home"], true), vec!["ops"]);
+        assert_eq!(titles(&["work", "home"], true), Vec::<String>::new());
+        assert_eq!(titles(&["home"], false), titles(&["home"], true));
+        assert_eq!(titles(&[], false).len(), 5);
+    }
+
+    #[test]
+    fn test_for_each_todo() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_todos(
+            &mut connection,
+            vec![
+                Todo {
+                    tags: vec!["b".into(), "a".into()],
+                    ..Todo::new("one".into())
+                },
+                Todo {
+                    list: "other".into(),
+                    ..Todo::new("two".into())
+                },
+                Todo::new("three".into()),
+            ],
+        )
+        .unwrap();
+
+        for query in [TodoQuery::default(), TodoQuery::list(DEFAULT_LIST)] {
+            let mut streamed = Vec::new();
+            for_each_todo(&connection, &query, |index, todo| {
+                streamed.push((index, todo.title, todo.tags));
+                Ok::<_, GetTodosError>(())
+            })
+            .unwrap();
+            let queried: Vec<_> = query_todos(&connection, &query)
+                .unwrap()
+                .into_iter()
+                .map(|(index, todo)| (index, todo.title, todo.tags))
+                .collect();
+            assert_eq!(streamed, queried);
+        }
+    }
+
+    #[test]
+    fn test_rename_list() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        add_todos(
+            &mut connection,
+            vec![
+                Todo::new("a".into()),
+                Todo {
+                    list: "work".into(),
+                    ..Todo::new("b".into())
+                },
+            ],
+        )
+        .unwrap();
+        record_history(&connection, DEFAULT_LIST, "added a").unwrap();
+
+        assert_eq!(
+            rename_list(&mut connection, DEFAULT_LIST, "home").unwrap(),
+            1
+        );
+        assert!(get_todos(&connection, DEFAULT_LIST).unwrap().is_empty());
+        assert_eq!(get_todos(&connection, "home").unwrap()[0].title, "a");
+        assert_eq!(get_todos(&connection, "work").unwrap()[0].title, "b");
+        assert_eq!(get_history(&connection, Some("home"), 10).unwrap().len(), 1);
+        assert_eq!(rename_list(&mut connection, "missing", "home").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_move_to_list() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = |title: &str, list: &str| Todo {
+            tags: vec![title.into()],
+            list: list.into(),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("a", DEFAULT_LIST),
+                todo("b", DEFAULT_LIST),
+                todo("c", DEFAULT_LIST),
+                todo("x", "work"),
+            ],
+        )
+        .unwrap();
+        let ids: Vec<usize> = ["c", "a"]
+            .iter()
+            .map(|title| {
+                get_todos(&connection, DEFAULT_LIST)
+                    .unwrap()
+                    .into_iter()
+                    .find(|todo| todo.title == *title)
+                    .unwrap()
+                    .id
+            })
+            .collect();
+
+        assert_eq!(
+            move_to_list(&mut connection, ids, "work").unwrap(),
+            vec![3, 1]
+        );
+        let titles = |list: &str| -> Vec<(String, Vec<String>)> {
+            get_todos(&connection, list)
+                .unwrap()
+                .into_iter()
+                .map(|todo| (todo.title, todo.tags))
+                .collect()
+        };
+        assert_eq!(titles(DEFAULT_LIST), vec![("b".into(), vec!["b".into()])]);
+        assert_eq!(
+            titles("work"),
+            vec![
+                ("x".into(), vec!["x".into()]),
+                ("c".into(), vec!["c".into()]),
+                ("a".into(), vec!["a".into()])
+            ]
+        );
+        assert!(get_todos(&connection, "work").unwrap()[1]
+            .updated_at
+            .is_some());
+    }
+
+    #[test]
+    fn test_rename_and_remove_tag() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = |title: &str, tags: &[&str], list: &str| Todo {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            list: list.into(),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("a", &["wrk"], DEFAULT_LIST),
+                todo("b", &["wrk", "work"], DEFAULT_LIST),
+                todo("c", &["wrk", "home"], "other"),
+                todo("d", &["home"], DEFAULT_LIST),
+            ],
+        )
+        .unwrap();
+        let tags = |connection: &Connection| -> Vec<Vec<String>> {
+            query_todos(connection, &TodoQuery::default())
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.tags)
+                .collect()
+        };
+
+        assert_eq!(rename_tag(&mut connection, "wrk", "work").unwrap(), 3);
+        assert_eq!(
+            tags(&connection),
+            vec![
+                vec!["work"],
+                vec!["work"],
+                vec!["home"],
+                vec!["home", "work"]
+            ]
+        );
+        assert_eq!(rename_tag(&mut connection, "work", "work").unwrap(), 3);
+        assert_eq!(rename_tag(&mut connection, "missing", "work").unwrap(), 0);
+        assert_eq!(get_tag_names(&connection).unwrap(), vec!["home", "work"]);
+
+        assert_eq!(remove_tag(&connection, "work").unwrap(), 3);
+        let expected: Vec<Vec<String>> =
+            vec![vec![], vec![], vec!["home".into()], vec!["home".into()]];
+        assert_eq!(tags(&connection), expected);
+        assert_eq!(remove_tag(&connection, "work").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_remove_todos_removes_tags() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = Todo {
+            tags: vec!["work".into()],
+            ..Todo::new("todo1".into())
+        };
+        let added = add_todos(&mut connection, vec![todo]).unwrap();
+        remove_todos(&connection, vec![added[0].id]).unwrap();
+
+        let tags: usize = connection
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tags, 0);
+    }
+
+    #[test]
+    fn test_trash_and_restore_todos() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todo = Todo {
+            tags: vec!["home".into(), "work".into()],
+            done: true,
+            ..Todo::new("todo1".into())
+        };
+        let added = add_todos(
+            &mut connection,
+            vec![todo, Todo::new("todo2".into()), Todo::new("todo3".into())],
+        )
+        .unwrap();
+        assert_eq!(
+            trash_todos(&mut connection, vec![added[0].id, added[1].id], None).unwrap(),
+            2
+        );
+
+        let titles = |connection: &Connection| -> Vec<String> {
+            get_todos(connection, DEFAULT_LIST)
+                .unwrap()
+                .iter()
+                .map(|todo| todo.title.clone())
+                .collect()
+        };
+        assert_eq!(titles(&connection), vec!["todo3"]);
+        assert_eq!(count_todos(&connection, &TodoQuery::default()).unwrap(), 1);
+
+        let trash = get_trash(&connection).unwrap();
+        assert_eq!(trash.len(), 2);
+        assert_eq!(trash[0].todo.uuid, added[0].uuid);
+        assert_eq!(trash[0].todo.tags, vec!["home", "work"]);
+        assert!(trash[0].todo.done);
+        assert!(trash[1].todo.tags.is_empty());
+
+        let restored = restore_todos(&mut connection, &[trash[0].id]).unwrap();
+        assert_eq!(titles(&connection), vec!["todo3", "todo1"]);
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos[1].id, restored[0]);
+        assert_eq!(todos[1].uuid, added[0].uuid);
+        assert_eq!(todos[1].tags, vec!["home", "work"]);
+        assert_eq!(get_trash(&connection).unwrap().len(), 1);
+
+        // Nothing is restored when one of them isn't in the trash.
+        let error = restore_todos(&mut connection, &[trash[1].id, trash[0].id]).unwrap_err();
+        assert!(matches!(error, RestoreTodosError::NotInTrash(id) if id == trash[0].id));
+        assert_eq!(titles(&connection), vec!["todo3", "todo1"]);
+
+        assert_eq!(empty_trash(&connection).unwrap(), 1);
+        assert!(get_trash(&connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_trash_todos_keeps_the_newest_within_limit() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let added = add_todos(
+            &mut connection,
+            vec![
+                Todo::new("todo1".into()),
+                Todo::new("todo2".into()),
+                Todo::new("todo3".into()),
+            ],
+        )
+        .unwrap();
+        trash_todos(&mut connection, vec![added[0].id, added[1].id], Some(2)).unwrap();
+        trash_todos(&mut connection, vec![added[2].id], Some(2)).unwrap();
+
+        let titles: Vec<String> = get_trash(&connection)
+            .unwrap()
+            .into_iter()
+            .map(|trashed| trashed.todo.title)
+            .collect();
+        assert_eq!(titles, vec!["todo2", "todo3"]);
+    }
+
+    #[test]
+    fn test_restore_todos_gives_a_taken_uuid_a_new_one() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let added = add_todos(&mut connection, vec![Todo::new("todo1".into())]).unwrap();
+        trash_todos(&mut connection, vec![added[0].id], None).unwrap();
+        add_todos(&mut connection, added.clone()).unwrap();
+
+        let trash = get_trash(&connection).unwrap();
+        restore_todos(&mut connection, &[trash[0].id]).unwrap();
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].uuid, added[0].uuid);
+        assert_ne!(todos[1].uuid, added[0].uuid);
+    }
+
+    #[test]
+    fn test_get_stats() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        assert_eq!(
+            get_stats(&connection, DEFAULT_LIST).unwrap(),
+            Stats {
+                total: 0,
+                done: 0,
+                pending: 0
+            }
+        );
+
+        let done = Todo {
+            done: true,
+            ..Todo::new("todo1".into())
+        };
+        add_todos(&mut connection, vec![done, Todo::new("todo2".into())]).unwrap();
+
+        assert_eq!(
+            get_stats(&connection, DEFAULT_LIST).unwrap(),
+            Stats {
+                total: 2,
+                done: 1,
+                pending: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_get_daily_stats() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let done = Todo {
+            done: true,
+            ..Todo::new("done".into())
+        };
+        let other = Todo {
+            list: "other".into(),
+            ..Todo::new("other".into())
+        };
+        add_todos(
+            &mut connection,
+            vec![done, Todo::new("pending".into()), other],
+        )
+        .unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET created_at = '2024-04-29 23:30:00.000';
+                UPDATE todos SET done_at = '2024-05-01 12:00:00.000' WHERE done;",
+            )
+            .unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 4, day).unwrap();
+        let day = |date, added, completed| DayStats {
+            date,
+            added,
+            completed,
+        };
+
+        let may_1 = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(
+            get_daily_stats(&connection, DEFAULT_LIST, date(28), may_1, 0).unwrap(),
+            vec![
+                day(date(28), 0, 0),
+                day(date(29), 2, 0),
+                day(date(30), 0, 0),
+                day(may_1, 0, 1),
+            ]
+        );
+        // An hour east of UTC the todos were added just after midnight.
+        assert_eq!(
+            get_daily_stats(&connection, DEFAULT_LIST, date(29), date(30), 60).unwrap(),
+            vec![day(date(29), 0, 0), day(date(30), 2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_get_open_counts() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todo = |title: &str, done| Todo {
+            done,
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("old", false),
+                todo("added", false),
+                todo("finished", true),
+                todo("long done", true),
+            ],
+        )
+        .unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET created_at = NULL WHERE title = 'old';
+                UPDATE todos SET created_at = '2024-04-29 23:30:00.000' WHERE title <> 'old';
+                UPDATE todos SET done_at = '2024-04-30 12:00:00.000' WHERE title = 'finished';
+                UPDATE todos SET done_at = NULL WHERE title = 'long done';",
+            )
+            .unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 4, day).unwrap();
+        let open = |counts: Vec<OpenCount>| -> Vec<usize> {
+            counts.into_iter().map(|count| count.open).collect()
+        };
+
+        let counts = get_open_counts(&connection, DEFAULT_LIST, date(28), date(30), 0).unwrap();
+        assert_eq!(counts[0].date, date(28));
+        assert_eq!(open(counts), vec![1, 3, 2]);
+        // An hour east of UTC the todos were added on the 30th, the day one was done.
+        assert_eq!(
+            open(get_open_counts(&connection, DEFAULT_LIST, date(29), date(30), 60).unwrap()),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_count_completed_since() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let done = |title: &str| Todo {
+            done: true,
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                done("inside"),
+                done("edge"),
+                done("outside"),
+                done("no time"),
+                Todo::new("pending".into()),
+            ],
+        )
+        .unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET done_at = '2024-04-30 09:00:00.000' WHERE title = 'inside';
+                UPDATE todos SET done_at = '2024-04-24 12:00:00.000' WHERE title = 'edge';
+                UPDATE todos SET done_at = '2024-04-20 12:00:00.000' WHERE title = 'outside';
+                UPDATE todos SET done_at = NULL WHERE title = 'no time';",
+            )
+            .unwrap();
+        let at = |day, hour| {
+            NaiveDate::from_ymd_opt(2024, 4, day)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+                .and_utc()
+        };
+
+        assert_eq!(
+            count_completed_since(&connection, DEFAULT_LIST, at(24, 12)).unwrap(),
+            2
+        );
+        assert_eq!(
+            count_completed_since(&connection, DEFAULT_LIST, at(24, 13)).unwrap(),
+            1
+        );
+        assert_eq!(
+            count_completed_since(&connection, "other", at(1, 0)).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_get_completions() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let done = |title: &str| Todo {
+            done: true,
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                done("recent"),
+                done("old"),
+                done("untimed"),
+                Todo::new("pending".into()),
+            ],
+        )
+        .unwrap();
+        connection
+            .execute_batch(
+                "UPDATE todos SET created_at = '2024-04-20 08:00:00.000';
+                UPDATE todos SET done_at = '2024-04-30 08:00:00.000' WHERE title = 'recent';
+                UPDATE todos SET done_at = '2024-03-01 08:00:00.000' WHERE title = 'old';
+                UPDATE todos SET created_at = NULL, done_at = NULL WHERE title = 'untimed';",
+            )
+            .unwrap();
+        let since = NaiveDate::from_ymd_opt(2024, 4, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+
+        let completions = get_completions(&connection, DEFAULT_LIST, since).unwrap();
+        let titles: Vec<&str> = completions.iter().map(|c| c.title.as_str()).collect();
+        assert_eq!(titles, vec!["recent", "untimed"]);
+        assert!(completions[0].created_at.is_some());
+        assert_eq!(completions[1].done_at, None);
+    }
+
+    #[test]
+    fn test_get_list_stats() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        assert!(get_list_stats(&connection).unwrap().is_empty());
+
+        let todo = |list: &str, done: bool| Todo {
+            list: list.into(),
+            done,
+            ..Todo::new("todo".into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("work", true),
+                todo("home", false),
+                todo("work", false),
+                todo("work", true),
+            ],
+        )
+        .unwrap();
+
+        let stats = |name: &str, pending, done| ListStats {
+            name: name.into(),
+            pending,
+            done,
+        };
+        assert_eq!(
+            get_list_stats(&connection).unwrap(),
+            vec![stats("home", 1, 0), stats("work", 1, 2)]
+        );
+    }
+
+    #[test]
+    fn test_get_tag_stats() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let tagged = |title: &str, done: bool, tags: &[&str]| Todo {
+            done,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                tagged("todo1", true, &["work"]),
+                tagged("todo2", false, &["work", "home"]),
+                tagged("todo3", false, &["work"]),
+                tagged("todo4", true, &[]),
+                tagged("todo5", false, &[]),
+                tagged("todo6", false, &[]),
+            ],
+        )
+        .unwrap();
+
+        let stats = get_tag_stats(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                TagStats {
+                    tag: "home".into(),
+                    done: 0,
+                    pending: 1
+                },
+                TagStats {
+                    tag: "work".into(),
+                    done: 1,
+                    pending: 2
+                },
+                TagStats {
+                    tag: UNTAGGED.into(),
+                    done: 1,
+                    pending: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_migrate_existing_database() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(CREATE_TABLE_QUERY, []).unwrap();
+        connection
+            .execute(
+                "INSERT INTO todos (title, done) VALUES (?1, ?2)",
+                params!["todo1", false],
+            )
+            .unwrap();
+
+        create_table(&connection).unwrap();
+        // Running it again must not re-apply migrations.
+        create_table(&connection).unwrap();
+
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].list, DEFAULT_LIST);
+    }
+
+    #[test]
+    fn test_uuids_are_backfilled_and_generated() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute(CREATE_TABLE_QUERY, []).unwrap();
+        let insert = |title: &str| {
+            connection
+                .execute(
+                    "INSERT INTO todos (title, done) VALUES (?1, 0)",
+                    params![title],
+                )
+                .unwrap();
+        };
+        insert("before");
+        insert("also before");
+        create_table(&connection).unwrap();
+        insert("after");
+
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos.len(), 3);
+        for todo in &todos {
+            let uuid = uuid::Uuid::parse_str(&todo.uuid).unwrap();
+            assert_eq!(uuid.get_version_num(), 4, "{}", todo.uuid);
+            assert_eq!(todo.uuid, todo.uuid.to_lowercase());
+        }
+        assert_ne!(todos[0].uuid, todos[1].uuid);
+
+        let uuids = vec![todos[2].uuid.clone(), "unknown".into()];
+        let by_uuid = get_todos_by_uuid(&connection, uuids).unwrap();
+        assert_eq!(by_uuid.len(), 1);
+        assert_eq!(by_uuid[&todos[2].uuid].title, "after");
+    }
+
+    #[test]
+    fn test_reindex_todos() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let todos = (0..5)
+            .map(|i| Todo {
+                tags: vec![format!("tag{}", i)],
+                list: if i % 2 == 0 { "even" } else { "odd" }.into(),
+                ..Todo::new(format!("todo{}", i))
+            })
+            .collect();
+        let added = add_todos(&mut connection, todos).unwrap();
+        remove_todos(&connection, vec![added[0].id, added[2].id]).unwrap();
+
+        assert_eq!(reindex_todos(&mut connection).unwrap(), 3);
+        assert_eq!(reindex_todos(&mut connection).unwrap(), 0);
+
+        let todos: Vec<(usize, String, String, Vec<String>)> =
+            query_todos(&connection, &TodoQuery::default())
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| (todo.id, todo.title, todo.list, todo.tags))
+                .collect();
+        assert_eq!(
+            todos,
+            vec![
+                (3, "todo4".into(), "even".into(), vec!["tag4".into()]),
+                (1, "todo1".into(), "odd".into(), vec!["tag1".into()]),
+                (2, "todo3".into(), "odd".into(), vec!["tag3".into()]),
+            ]
+        );
+
+        let violations: usize = connection
+            .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(violations, 0);
+    }
+
+    #[test]
+    fn test_notifiable_todos() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        let date = |date: &str| date.parse::<NaiveDate>().unwrap();
+        let todo = |title: &str, due: Option<&str>| Todo {
+            due: due.map(date),
+            ..Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("late", Some("2024-04-30")),
+                todo("today", Some("2024-05-01")),
+                todo("tomorrow", Some("2024-05-02")),
+                todo("undated", None),
+            ],
+        )
+        .unwrap();
+
+        let titles = |connection: &Connection, until: &str, force: bool| -> Vec<String> {
+            get_notifiable_todos(connection, date(until), force)
+                .unwrap()
+                .into_iter()
+                .map(|(_, todo)| todo.title)
+                .collect()
+        };
+
+        assert_eq!(
+            titles(&connection, "2024-05-01", false),
+            vec!["late", "today"]
+        );
+
+        let mut todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        record_notified(&connection, &todos[0]).unwrap();
+        record_notified(&connection, &todos[1]).unwrap();
+        assert!(titles(&connection, "2024-05-01", false).is_empty());
+        assert_eq!(titles(&connection, "2024-05-02", false), vec!["tomorrow"]);
+        assert_eq!(
+            titles(&connection, "2024-05-01", true),
+            vec!["late", "today"]
+        );
+
+        // Postponed todos are due again on their new date.
+        todos[0].due = Some(date("2024-05-01"));
+        update_todos(&mut connection, vec![todos[0].clone()]).unwrap();
+        assert_eq!(titles(&connection, "2024-05-01", false), vec!["late"]);
+    }
+
+    #[test]
+    fn test_is_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todos.db");
+        assert!(!is_encrypted(&path).unwrap());
+
+        std::fs::write(&path, "").unwrap();
+        assert!(!is_encrypted(&path).unwrap());
+
+        create_table(&Connection::open(&path).unwrap()).unwrap();
+        assert!(!is_encrypted(&path).unwrap());
+
+        std::fs::write(&path, [0x9f; 64]).unwrap();
+        assert!(is_encrypted(&path).unwrap());
+    }
+
+    #[test]
+    fn test_without_fsync_restores_synchronous() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut connection = Connection::open(dir.path().join("todos.db")).unwrap();
+        create_table(&connection).unwrap();
+
+        let synchronous = |connection: &Connection| -> i64 {
+            connection
+                .pragma_query_value(None, "synchronous", |row| row.get(0))
+                .unwrap()
+        };
+        let before = synchronous(&connection);
+        assert_ne!(before, 0);
+
+        let during = without_fsync(&mut connection, |connection| {
+            add_todos(connection, vec![Todo::new("todo".into())]).unwrap();
+            Ok::<_, rusqlite::Error>(synchronous(connection))
+        })
+        .unwrap();
+        assert_eq!(during, 0);
+        assert_eq!(synchronous(&connection), before);
+
+        let failed: Result<(), rusqlite::Error> =
+            without_fsync(&mut connection, |_| Err(rusqlite::Error::InvalidQuery));
+        assert!(failed.is_err());
+        assert_eq!(synchronous(&connection), before);
+    }
+
+    #[test]
+    fn test_insert_todos() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todo = |title: &str, list: &str| todo::Todo {
+            list: list.into(),
+            ..todo::Todo::new(title.into())
+        };
+        add_todos(
+            &mut connection,
+            vec![
+                todo("a", DEFAULT_LIST),
+                todo("other", "other"),
+                todo("b", DEFAULT_LIST),
+            ],
+        )
+        .unwrap();
+        let new = vec![
+            todo::Todo {
+                tags: vec!["x".into()],
+                ..todo("new1", DEFAULT_LIST)
+            },
+            todo("new2", DEFAULT_LIST),
+        ];
+
+        let added = insert_todos(&mut connection, DEFAULT_LIST, 1, new).unwrap();
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        let titles: Vec<&str> = todos.iter().map(|todo| todo.title.as_str()).collect();
+        assert_eq!(titles, vec!["a", "new1", "new2", "b"]);
+        assert_eq!(
+            added.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![todos[1].id, todos[2].id]
+        );
+        // The todos that were there keep their ids.
+        assert_eq!(
+            todos.iter().map(|todo| todo.id).collect::<Vec<_>>(),
+            vec![1, 4, 5, 3]
+        );
+        assert_eq!(todos[1].tags, vec!["x"]);
+        assert_eq!(get_todos(&connection, "other").unwrap()[0].title, "other");
+
+        insert_todos(
+            &mut connection,
+            DEFAULT_LIST,
+            9,
+            vec![todo("last", DEFAULT_LIST)],
+        )
+        .unwrap();
+        let todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        assert_eq!(todos[4].title, "last");
+    }
+
+    #[test]
+    fn test_get_latest_index() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        assert_eq!(
+            get_latest_index(&connection, DEFAULT_LIST, false).unwrap(),
+            None
+        );
+
+        let titles = ["first", "second", "third"];
+        add_todos(
+            &mut connection,
+            titles
+                .iter()
+                .map(|title| todo::Todo::new(title.to_string()))
+                .collect(),
+        )
+        .unwrap();
+        // Pretend they were added a second apart, the second one last, then move it to the top.
+        for (time, title) in ["00:00:01", "00:00:03", "00:00:02"].iter().zip(titles) {
+            connection
+                .execute(
+                    "UPDATE todos SET created_at = '2024-05-01 ' || ?1 WHERE title = ?2",
+                    params![time, title],
+                )
+                .unwrap();
+        }
+        reorder_todos(&mut connection, vec![2, 1, 3]).unwrap();
+        assert_eq!(
+            get_latest_index(&connection, DEFAULT_LIST, false).unwrap(),
+            Some(0)
+        );
+        assert_eq!(
+            get_latest_index(&connection, DEFAULT_LIST, true).unwrap(),
+            None
+        );
+
+        let mut todos = get_todos(&connection, DEFAULT_LIST).unwrap();
+        todos[2].done = true;
+        update_todos(&mut connection, vec![todos[2].clone()]).unwrap();
+        connection
+            .execute("UPDATE todos SET done_at = '2024-05-01 00:00:00'", [])
+            .unwrap();
+        todos[1].done = true;
+        update_todos(&mut connection, vec![todos[1].clone()]).unwrap();
+        assert_eq!(
+            get_latest_index(&connection, DEFAULT_LIST, true).unwrap(),
+            Some(1)
+        );
+
+        // Marking pending again forgets when it was done.
+        todos[1].done = false;
+        update_todos(&mut connection, vec![todos[1].clone()]).unwrap();
+        assert_eq!(
+            get_latest_index(&connection, DEFAULT_LIST, true).unwrap(),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_run_select() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        add_todos(
+            &mut connection,
+            vec![
+                todo::Todo::new("first".into()),
+                todo::Todo {
+                    note: Some("note".into()),
+                    ..todo::Todo::new("second".into())
+                },
+            ],
+        )
+        .unwrap();
+
+        let (columns, rows) = run_select(
+            &connection,
+            "  select id, title, note, x'00ff' AS data FROM todos ORDER BY id",
+        )
+        .unwrap();
+        assert_eq!(columns, vec!["id", "title", "note", "data"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "first", "NULL", "<2 bytes>"],
+                vec!["2", "second", "note", "<2 bytes>"],
+            ]
+        );
+
+        for sql in [
+            "DELETE FROM todos",
+            "UPDATE todos SET done = 1",
+            "WITH t AS (SELECT 1) DELETE FROM todos",
+            "SELECT 1; DELETE FROM todos",
+            "SELECT 1; SELECT 2",
+            "",
+            " ; ",
+        ] {
+            assert!(
+                matches!(run_select(&connection, sql), Err(RunSelectError::NotSelect)),
+                "{}",
+                sql
+            );
+        }
+        for sql in ["SELECT nope FROM todos", "selectx 1"] {
+            assert!(
+                matches!(run_select(&connection, sql), Err(RunSelectError::Query(_))),
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(
+            run_select(&connection, "SELECT 1; -- done").unwrap().1,
+            vec![vec!["1"]]
+        );
+        assert_eq!(
+            run_select(
+                &connection,
+                "SELECT title FROM todos WHERE title NOT LIKE '%;%'"
+            )
+            .unwrap()
+            .1,
+            vec![vec!["first"], vec!["second"]]
+        );
+        assert_eq!(
+            run_select(&connection, "SELECT ';' || title FROM todos WHERE id = 1")
+                .unwrap()
+                .1,
+            vec![vec![";first"]]
+        );
+        assert_eq!(
+            run_select(
+                &connection,
+                "WITH done AS (SELECT id FROM todos WHERE done) \
+                 SELECT count(*) FROM todos WHERE id NOT IN done"
+            )
+            .unwrap()
+            .1,
+            vec![vec!["2"]]
+        );
+        assert_eq!(count_todos(&connection, &TodoQuery::default()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_remove_todos_does_not_truncate_ids() {
+        let connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+
+        connection
+            .execute(
+                "INSERT INTO todos (id, title, done) VALUES (?1, ?2, ?3)",
+                params![1, "todo1", false],
+            )
+            .unwrap();
+
+        let removed = remove_todos(&connection, vec![(1 << 32) + 1]).unwrap();
+
+        assert_eq!(removed, 0);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 1);
     }
 }
@@ -0,0 +1,202 @@
+use std::{
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+use rusqlite::{Connection, DatabaseName};
+
+use crate::{
+    config::{get_db_path, load_config, GetDbPathError, LoadConfigError},
+    db::is_encrypted,
+};
+
+const PASSPHRASE_ENV: &str = "TODO_PASSPHRASE";
+
+#[derive(thiserror::Error, Debug)]
+pub enum GetPassphraseError {
+    #[error(
+        "No passphrase given, set TODO_PASSPHRASE, a keyfile in the config or run in a terminal"
+    )]
+    Missing,
+
+    #[error("Fail to read the keyfile")]
+    ReadKeyfile(#[source] std::io::Error),
+
+    #[error("Fail to read the passphrase")]
+    Prompt(#[source] std::io::Error),
+
+    #[error("The passphrases don't match")]
+    Mismatch,
+
+    #[error(transparent)]
+    LoadConfig(#[from] LoadConfigError),
+}
+
+// Only encrypted databases need the config, so it is loaded here rather than threaded through.
+pub fn get_passphrase(repeat: bool) -> Result<String, GetPassphraseError> {
+    if let Some(passphrase) = std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()) {
+        return Ok(passphrase);
+    }
+
+    if let Some(keyfile) = load_config()?.encryption.keyfile {
+        let passphrase =
+            std::fs::read_to_string(keyfile).map_err(GetPassphraseError::ReadKeyfile)?;
+        return non_empty(passphrase.trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return Err(GetPassphraseError::Missing);
+    }
+
+    let passphrase =
+        rpassword::prompt_password("Passphrase: ").map_err(GetPassphraseError::Prompt)?;
+    if repeat
+        && rpassword::prompt_password("Repeat the passphrase: ")
+            .map_err(GetPassphraseError::Prompt)?
+            != passphrase
+    {
+        return Err(GetPassphraseError::Mismatch);
+    }
+    non_empty(passphrase)
+}
+
+fn non_empty(passphrase: String) -> Result<String, GetPassphraseError> {
+    if passphrase.is_empty() {
+        return Err(GetPassphraseError::Missing);
+    }
+    Ok(passphrase)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConvertCommandError {
+    #[error("The database is already encrypted")]
+    AlreadyEncrypted,
+
+    #[error("The database isn't encrypted")]
+    NotEncrypted,
+
+    #[error(transparent)]
+    GetDbPath(#[from] GetDbPathError),
+
+    #[error(transparent)]
+    GetPassphrase(#[from] GetPassphraseError),
+
+    #[error("Fail to copy the database")]
+    Export(#[from] rusqlite::Error),
+
+    #[error("Fail to replace the database with its copy")]
+    Replace(#[from] std::io::Error),
+}
+
+pub fn encrypt_command() -> Result<(), ConvertCommandError> {
+    let path = get_db_path()?;
+    if is_encrypted(&path)? {
+        return Err(ConvertCommandError::AlreadyEncrypted);
+    }
+
+    let passphrase = get_passphrase(true)?;
+    convert(&path, None, &passphrase)?;
+    println!("Encrypted {}", path.display());
+    Ok(())
+}
+
+pub fn decrypt_command() -> Result<(), ConvertCommandError> {
+    let path = get_db_path()?;
+    if !is_encrypted(&path)? {
+        return Err(ConvertCommandError::NotEncrypted);
+    }
+
+    let passphrase = get_passphrase(false)?;
+    convert(&path, Some(&passphrase), "")?;
+    println!("Decrypted {}", path.display());
+    Ok(())
+}
+
+// Exporting into a copy and renaming it over the original never leaves a half-converted file.
+fn convert(path: &Path, from: Option<&str>, to: &str) -> Result<(), ConvertCommandError> {
+    let copy = copy_path(path);
+    if copy.exists() {
+        std::fs::remove_file(&copy)?;
+    }
+
+    let exported = export(path, &copy, from, to);
+    if let Err(error) = exported {
+        std::fs::remove_file(&copy).ok();
+        return Err(error.into());
+    }
+
+    std::fs::rename(&copy, path)?;
+    Ok(())
+}
+
+fn export(path: &Path, copy: &Path, from: Option<&str>, to: &str) -> rusqlite::Result<()> {
+    let connection = Connection::open(path)?;
+    if let Some(passphrase) = from {
+        connection.pragma_update(None, "key", passphrase)?;
+    }
+
+    // The schema version is not part of the export and has to be carried over by hand.
+    let version: i64 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    connection.execute(
+        "ATTACH DATABASE ?1 AS export KEY ?2",
+        rusqlite::params![copy.to_string_lossy(), to],
+    )?;
+    connection.query_row("SELECT sqlcipher_export('export')", [], |_| Ok(()))?;
+    connection.pragma_update(
+        Some(DatabaseName::Attached("export")),
+        "user_version",
+        version,
+    )?;
+    connection.execute_batch("DETACH DATABASE export")?;
+
+    Ok(())
+}
+
+fn copy_path(path: &Path) -> PathBuf {
+    let mut copy = path.as_os_str().to_owned();
+    copy.push(".converting");
+    PathBuf::from(copy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{add_todos, create_table, get_todos};
+    use crate::todo::{Todo, DEFAULT_LIST};
+
+    #[test]
+    fn test_convert_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todos.db");
+        {
+            let mut connection = Connection::open(&path).unwrap();
+            create_table(&connection).unwrap();
+            add_todos(&mut connection, vec![Todo::new("secret".into())]).unwrap();
+        }
+
+        convert(&path, None, "hunter2").unwrap();
+        assert!(is_encrypted(&path).unwrap());
+        assert!(!copy_path(&path).exists());
+
+        let connection = Connection::open(&path).unwrap();
+        assert!(get_todos(&connection, DEFAULT_LIST).is_err());
+        connection.pragma_update(None, "key", "hunter2").unwrap();
+        assert_eq!(
+            get_todos(&connection, DEFAULT_LIST).unwrap()[0].title,
+            "secret"
+        );
+        drop(connection);
+
+        assert!(convert(&path, Some("wrong"), "").is_err());
+        assert!(is_encrypted(&path).unwrap());
+
+        convert(&path, Some("hunter2"), "").unwrap();
+        assert!(!is_encrypted(&path).unwrap());
+        let connection = Connection::open(&path).unwrap();
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert!(version > 0);
+        assert_eq!(get_todos(&connection, DEFAULT_LIST).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    io::Write,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use crate::{
+    config::HooksConfig,
+    todo::{Todo, TodoDto},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    Add,
+    Done,
+    Undone,
+    Remove,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::Add => "post_add",
+            Hook::Done => "post_done",
+            Hook::Undone => "post_undone",
+            Hook::Remove => "post_remove",
+        }
+    }
+
+    fn command(self, hooks: &HooksConfig) -> Option<&str> {
+        match self {
+            Hook::Add => hooks.post_add.as_deref(),
+            Hook::Done => hooks.post_done.as_deref(),
+            Hook::Undone => hooks.post_undone.as_deref(),
+            Hook::Remove => hooks.post_remove.as_deref(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RunHookError {
+    #[error("Fail to serialize todos for the {0} hook")]
+    Serialize(&'static str, #[source] serde_json::Error),
+
+    #[error("Fail to spawn the {0} hook")]
+    Spawn(&'static str, #[source] std::io::Error),
+
+    #[error("Fail to write todos to the {0} hook")]
+    WriteStdin(&'static str, #[source] std::io::Error),
+
+    #[error("Fail to wait for the {0} hook")]
+    Wait(&'static str, #[source] std::io::Error),
+
+    #[error("The {0} hook exited with {1}")]
+    ExitStatus(&'static str, ExitStatus),
+}
+
+pub fn run_hook(hooks: &HooksConfig, hook: Hook, todos: &[Todo]) -> Result<(), RunHookError> {
+    let Some(command) = hook.command(hooks) else {
+        return Ok(());
+    };
+
+    let name = hook.name();
+    let todos: Vec<TodoDto> = todos.iter().cloned().map(Into::into).collect();
+    let input = serde_json::to_vec(&todos).map_err(|e| RunHookError::Serialize(name, e))?;
+
+    let mut child = shell(command)
+        .env("TODO_HOOK", name)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| RunHookError::Spawn(name, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A hook that doesn't read its stdin closes the pipe early, which isn't an error.
+        if let Err(e) = stdin.write_all(&input) {
+            if e.kind() != std::io::ErrorKind::BrokenPipe {
+                return Err(RunHookError::WriteStdin(name, e));
+            }
+        }
+    }
+
+    let status = child.wait().map_err(|e| RunHookError::Wait(name, e))?;
+
+    if !status.success() {
+        return Err(RunHookError::ExitStatus(name, status));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_hook_passes_todos_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.json");
+        let hooks = HooksConfig {
+            post_add: Some(format!("cat > {}", output.display())),
+            ..Default::default()
+        };
+
+        let todos = vec![Todo::new("title1".into())];
+        run_hook(&hooks, Hook::Add, &todos).unwrap();
+
+        let received: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(output).unwrap()).unwrap();
+        assert_eq!(received[0]["title"], "title1");
+        assert_eq!(received[0]["done"], false);
+    }
+
+    #[test]
+    fn test_run_hook_without_command() {
+        let hooks = HooksConfig::default();
+        run_hook(&hooks, Hook::Done, &[]).unwrap();
+    }
+
+    #[test]
+    fn test_run_hook_non_zero_exit() {
+        let hooks = HooksConfig {
+            post_remove: Some("exit 3".into()),
+            ..Default::default()
+        };
+
+        let error = run_hook(&hooks, Hook::Remove, &[]).unwrap_err();
+        assert!(matches!(error, RunHookError::ExitStatus("post_remove", _)));
+    }
+}
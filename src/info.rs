@@ -0,0 +1,205 @@
+use std::path::PathBuf;
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::{
+    config::{resolve_db_path, DbPathSource, GetDbPathError},
+    db::{
+        count_todos, get_existing_connection, get_schema_version, is_encrypted, GetConnectionError,
+        GetTodosError, TodoQuery, SCHEMA_VERSION,
+    },
+};
+
+#[derive(Serialize)]
+struct Info {
+    path: PathBuf,
+    source: DbPathSource,
+    exists: bool,
+    encrypted: bool,
+    size: u64,
+    schema_version: Option<usize>,
+    latest_schema_version: usize,
+    todos: Option<Counts>,
+    backend: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Counts {
+    total: usize,
+    pending: usize,
+    done: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InfoCommandError {
+    #[error(transparent)]
+    GetDbPath(#[from] GetDbPathError),
+
+    #[error(transparent)]
+    GetConnection(#[from] GetConnectionError),
+
+    #[error("Fail to read the database details")]
+    Metadata(#[from] std::io::Error),
+
+    #[error("Fail to get the schema version")]
+    GetSchemaVersion(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error("Fail to serialize the database details")]
+    Serialize(#[from] serde_json::Error),
+}
+
+// Like status, info must not create a database just by looking for one.
+pub fn info_command(json: bool) -> Result<(), InfoCommandError> {
+    let (path, source) = resolve_db_path()?;
+    let exists = path.exists();
+    let size = if exists { path.metadata()?.len() } else { 0 };
+    let encrypted = is_encrypted(&path)?;
+
+    // Without the passphrase an encrypted database still shows where it is.
+    let connection = match get_existing_connection() {
+        Err(GetConnectionError::Encrypted) => None,
+        result => result?,
+    };
+    let schema_version = connection.as_ref().map(get_schema_version).transpose()?;
+    let todos = match &connection {
+        Some(connection) if schema_version.is_some_and(|version| version > 0) => {
+            Some(count(connection)?)
+        }
+        _ => None,
+    };
+
+    let info = Info {
+        path,
+        source,
+        exists,
+        encrypted,
+        size,
+        schema_version,
+        latest_schema_version: SCHEMA_VERSION,
+        todos,
+        backend: backend(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string(&info)?);
+        return Ok(());
+    }
+
+    let source = match info.source {
+        DbPathSource::Env => "from TODO_DB",
+        DbPathSource::Default => "default location",
+    };
+    println!("Database: {} ({})", info.path.display(), source);
+    if !info.exists {
+        println!("Not created yet");
+    } else {
+        let encrypted = if info.encrypted { ", encrypted" } else { "" };
+        println!("Size: {}{}", format_size(info.size), encrypted);
+    }
+    if let Some(version) = info.schema_version {
+        println!(
+            "Schema version: {} of {}",
+            version, info.latest_schema_version
+        );
+    }
+    if let Some(Counts {
+        total,
+        pending,
+        done,
+    }) = info.todos
+    {
+        println!("Todos: {} ({} pending, {} done)", total, pending, done);
+    }
+    println!("Backend: {}", info.backend);
+
+    Ok(())
+}
+
+fn count(connection: &Connection) -> Result<Counts, GetTodosError> {
+    let total = count_todos(connection, &TodoQuery::default())?;
+    let done = count_todos(
+        connection,
+        &TodoQuery {
+            done: Some(true),
+            ..Default::default()
+        },
+    )?;
+
+    Ok(Counts {
+        total,
+        pending: total - done,
+        done,
+    })
+}
+
+fn backend() -> String {
+    let cipher = if cfg!(feature = "encryption") {
+        " with SQLCipher"
+    } else {
+        ""
+    };
+    format!("SQLite {}{}", rusqlite::version(), cipher)
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        db::{add_todos, create_table},
+        todo::Todo,
+    };
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GiB");
+    }
+
+    #[test]
+    fn test_count() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let todos = vec![
+            Todo::new("pending".into()),
+            Todo {
+                done: true,
+                list: "other".into(),
+                ..Todo::new("done".into())
+            },
+        ];
+        add_todos(&mut connection, todos).unwrap();
+
+        assert_eq!(
+            count(&connection).unwrap(),
+            Counts {
+                total: 2,
+                pending: 1,
+                done: 1
+            }
+        );
+        assert_eq!(get_schema_version(&connection).unwrap(), SCHEMA_VERSION);
+    }
+}
@@ -1,16 +1,73 @@
-use args::{Args, Commands};
+use args::{Args, Commands, FilterOptions, PrintFormat, PrintOptions, TagLogic};
+use chrono::{Local, Utc};
+use clipboard::{read_clipboard_titles, ReadClipboardError};
 use commands::{
-    add_command, clear_command, print_command, remove_command, set_done_command, AddCommandError,
-    ClearCommandError, PrintCommandError, RemoveCommandError, SetDoneCommandError,
+    add_command, add_template_command, auto_prune, backup_command, bulk_command, burndown_command,
+    clean_command, clear_command, copy_to_command, count_command, delete_list_command,
+    demo_command, done_and_clear_command, edit_command, empty_trash_command, export_command,
+    history_command, import_command, import_replace_command, lists_command, mark_viewed,
+    move_command, move_to_command, next_command, overdue_command, postpone_command, print_command,
+    read_titles, reindex_command, remove_command, remove_matching_command, remove_tag_command,
+    rename_list_command, rename_tag_command, resolve_refs, restore_command, search_command,
+    set_done_command, show_command, sql_command, stats_command, status_command, templates_command,
+    today_command, todos_count, trash_command, week_command, AddCommandError, AutoPruneError,
+    BackupCommandError, BulkCommandError, CleanCommandError, CleanSummary, ClearCommandError,
+    CountCommandError, DemoCommandError, DoneAndClearCommandError, EditCommandError,
+    ExportCommandError, HistoryCommandError, ImportCommandError, ListsCommandError, Merge,
+    MoveCommandError, MoveTarget, PostponeCommandError, PrintCommandError, ReindexCommandError,
+    RemoveCommandError, ResolveRefsError, SetDoneCommandError, ShowCommandError, StatsCommandError,
+    StatusCommandError, TagCommandError, TransferCommandError, TrashCommandError,
 };
-use db::{get_connection_with_table, GetConnectionWithTableError};
+use completions::{complete_candidates, completions_command};
+use config::{
+    load_config, rename_default_list, AutoPrune, Config, LoadConfigError, UpdateConfigError,
+};
+use confirm::{AssumeYes, Confirm, StdinConfirm};
+use dates::Span;
+use db::{
+    check_read_only_schema, create_table, get_existing_connection, open_connection,
+    CreateTableError, GetCandidatesError, GetConnectionError, GetConnectionWithTableError,
+    MetadataError, RestoreTodosError, RunSelectError, TodoQuery,
+};
+#[cfg(feature = "encryption")]
+use encryption::{decrypt_command, encrypt_command, ConvertCommandError};
+use hooks::{run_hook, Hook, RunHookError};
+use info::{info_command, InfoCommandError};
+use man::{man_command, ManCommandError};
+use notify::{notify_command, NotifyCommandError};
+use rusqlite::{Connection, ErrorCode};
+use serve::{serve_command, ServeCommandError, Server};
+use shell::{shell_command, ShellCommandError};
+use std::{
+    fs::File,
+    io::{IsTerminal, Read, Write},
+    time::{Duration, Instant},
+};
+use terminal::{escape_control, Style};
+use todo::{Todo, TodoRef, DEFAULT_LIST};
+use tui::{tui_command, TuiCommandError};
+use watch::{watch_command, watch_json_command, WatchCommandError};
 
 pub mod args;
+mod clipboard;
 mod commands;
+mod completions;
 mod config;
+mod confirm;
+mod dates;
 mod db;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod hooks;
+mod info;
+mod man;
+mod notify;
+mod serve;
+mod shell;
 mod terminal;
 mod todo;
+mod tui;
+mod watch;
 
 #[derive(thiserror::Error, Debug)]
 #[error(transparent)]
@@ -27,40 +84,1112 @@ pub enum RunCommandError {
     #[error(transparent)]
     ClearCommand(#[from] ClearCommandError),
 
+    #[error(transparent)]
+    DoneAndClearCommand(#[from] DoneAndClearCommandError),
+
+    #[error(transparent)]
+    DemoCommand(#[from] DemoCommandError),
+
+    #[error(transparent)]
+    ResolveRefs(#[from] ResolveRefsError),
+
+    #[error(transparent)]
+    ShowCommand(#[from] ShowCommandError),
+
+    #[error(transparent)]
+    EditCommand(#[from] EditCommandError),
+
+    #[error(transparent)]
+    BulkCommand(#[from] BulkCommandError),
+
+    #[error(transparent)]
+    TagCommand(#[from] TagCommandError),
+
+    #[error(transparent)]
+    AutoPrune(#[from] AutoPruneError),
+
+    #[error(transparent)]
+    ExportCommand(#[from] ExportCommandError),
+
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+
+    #[error(transparent)]
+    SqlCommand(#[from] RunSelectError),
+
+    #[error(transparent)]
+    ImportCommand(#[from] ImportCommandError),
+
+    #[error(transparent)]
+    MoveCommand(#[from] MoveCommandError),
+
+    #[error(transparent)]
+    TransferCommand(#[from] TransferCommandError),
+
+    #[error(transparent)]
+    ListsCommand(#[from] ListsCommandError),
+
+    #[error(transparent)]
+    UpdateConfig(#[from] UpdateConfigError),
+
+    #[error("No todo was changed")]
+    NothingAffected,
+
+    #[error(transparent)]
+    PostponeCommand(#[from] PostponeCommandError),
+
     #[error(transparent)]
     PrintAllCommand(#[from] PrintCommandError),
 
+    #[error(transparent)]
+    CountCommand(#[from] CountCommandError),
+
+    #[error(transparent)]
+    StatusCommand(#[from] StatusCommandError),
+
+    #[error(transparent)]
+    TuiCommand(#[from] TuiCommandError),
+
+    #[error(transparent)]
+    ShellCommand(#[from] ShellCommandError),
+
+    #[error(transparent)]
+    ServeCommand(#[from] ServeCommandError),
+
+    #[error(transparent)]
+    WatchCommand(#[from] WatchCommandError),
+
+    #[error(transparent)]
+    ReindexCommand(#[from] ReindexCommandError),
+
+    #[error(transparent)]
+    BackupCommand(#[from] BackupCommandError),
+
+    #[error(transparent)]
+    TrashCommand(#[from] TrashCommandError),
+
+    #[error(transparent)]
+    CleanCommand(#[from] CleanCommandError),
+
+    #[error(transparent)]
+    ReadClipboard(#[from] ReadClipboardError),
+
+    #[error(transparent)]
+    HistoryCommand(#[from] HistoryCommandError),
+
+    #[error(transparent)]
+    StatsCommand(#[from] StatsCommandError),
+
+    #[error(transparent)]
+    NotifyCommand(#[from] NotifyCommandError),
+
+    #[cfg(feature = "encryption")]
+    #[error(transparent)]
+    ConvertCommand(#[from] ConvertCommandError),
+
+    #[error(transparent)]
+    InfoCommand(#[from] InfoCommandError),
+
+    #[error(transparent)]
+    ManCommand(#[from] ManCommandError),
+
+    #[error(transparent)]
+    GetCandidates(#[from] GetCandidatesError),
+
+    #[error(transparent)]
+    GetConnection(#[from] GetConnectionError),
+
     #[error(transparent)]
     GetConnectionWithTable(#[from] GetConnectionWithTableError),
+
+    #[error(transparent)]
+    CreateTable(#[from] CreateTableError),
+
+    #[error(transparent)]
+    LoadConfig(#[from] LoadConfigError),
+
+    #[error(transparent)]
+    RunHook(#[from] RunHookError),
+
+    #[error("Can't change todos in read-only mode")]
+    ReadOnly,
 }
 
-pub fn run_command(args: Args) -> Result<(), RunCommandError> {
-    let mut connection = get_connection_with_table()?;
+const DEFAULT_CONFIRM_THRESHOLD: usize = 10;
+const JSON_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_NOTIFY_WITHIN: Span = Span::Days(0);
+
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_NO_MATCH: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_DB_BUSY: i32 = 3;
+pub const EXIT_IO: i32 = 4;
+// Apart from 1, so a script can tell a failed search or --fail-empty from a broken one.
+pub const EXIT_FAILURE: i32 = 5;
 
-    match args.command {
-        Some(Commands::Add { titles }) => {
-            add_command(&mut connection, titles)?;
-            print_command(&connection)?;
+// Like grep, a search that finds nothing isn't an error but still tells the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    NoMatch,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => EXIT_SUCCESS,
+            Outcome::NoMatch => EXIT_NO_MATCH,
         }
-        Some(Commands::Done { ids }) => {
-            set_done_command(&mut connection, ids, true)?;
-            print_command(&connection)?;
+    }
+}
+
+impl RunCommandError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunCommandError::AddCommand(
+                AddCommandError::EmptyTitle
+                | AddCommandError::UnknownTemplate { .. }
+                | AddCommandError::ResolveParent(_)
+                | AddCommandError::InvalidParent(_)
+                | AddCommandError::InvalidPosition { .. },
+            )
+            | RunCommandError::SetDoneCommand(
+                SetDoneCommandError::InvalidIndex(_)
+                | SetDoneCommandError::NoPendingTodo
+                | SetDoneCommandError::NoDoneTodo,
+            )
+            | RunCommandError::RemoveCommand(RemoveCommandError::InvalidIndex(_))
+            | RunCommandError::MoveCommand(MoveCommandError::InvalidIndex(_))
+            | RunCommandError::TransferCommand(TransferCommandError::InvalidIndex(_))
+            | RunCommandError::ShowCommand(ShowCommandError::InvalidIndex(_))
+            | RunCommandError::SqlCommand(RunSelectError::NotSelect)
+            | RunCommandError::TagCommand(TagCommandError::EmptyTag)
+            | RunCommandError::ListsCommand(
+                ListsCommandError::EmptyName
+                | ListsCommandError::UnknownList(_)
+                | ListsCommandError::ListExists(_),
+            )
+            | RunCommandError::ExportCommand(ExportCommandError::InvertedRange { .. })
+            | RunCommandError::BulkCommand(
+                BulkCommandError::Parse(_) | BulkCommandError::InvalidIndex(_),
+            )
+            | RunCommandError::EditCommand(
+                EditCommandError::EmptyTitle | EditCommandError::InvalidIndex(_),
+            )
+            | RunCommandError::ResolveRefs(
+                ResolveRefsError::NoMatch(_)
+                | ResolveRefsError::Ambiguous(_)
+                | ResolveRefsError::NoSuchId(_)
+                | ResolveRefsError::NoSuchIndex(_),
+            )
+            | RunCommandError::TrashCommand(TrashCommandError::RestoreTodos(
+                RestoreTodosError::NotInTrash(_),
+            ))
+            | RunCommandError::CleanCommand(CleanCommandError::NotTerminal)
+            | RunCommandError::TuiCommand(TuiCommandError::NotTerminal)
+            | RunCommandError::DemoCommand(DemoCommandError::NotEmpty)
+            | RunCommandError::ImportCommand(
+                ImportCommandError::EmptyTitle(_) | ImportCommandError::Parse(_),
+            )
+            | RunCommandError::PostponeCommand(PostponeCommandError::InvalidIndex(_))
+            | RunCommandError::DoneAndClearCommand(DoneAndClearCommandError::SetDone(
+                SetDoneCommandError::InvalidIndex(_) | SetDoneCommandError::NoPendingTodo,
+            ))
+            | RunCommandError::LoadConfig(LoadConfigError::Parse(_))
+            | RunCommandError::ReadOnly => EXIT_USAGE,
+            RunCommandError::LoadConfig(_)
+            | RunCommandError::ManCommand(_)
+            | RunCommandError::ReadClipboard(ReadClipboardError::Unavailable)
+            | RunCommandError::BackupCommand(
+                BackupCommandError::GetBackupDir(_) | BackupCommandError::CreateDir(_),
+            )
+            | RunCommandError::GetConnectionWithTable(
+                GetConnectionWithTableError::GetConnection(GetConnectionError::GetDbPath(_)),
+            ) => EXIT_IO,
+            RunCommandError::RunHook(_) => EXIT_FAILURE,
+            RunCommandError::NothingAffected => EXIT_NO_MATCH,
+            _ => match self.sqlite_error_code() {
+                Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked) => EXIT_DB_BUSY,
+                Some(ErrorCode::CannotOpen | ErrorCode::SystemIoFailure) => EXIT_IO,
+                _ => EXIT_FAILURE,
+            },
+        }
+    }
+
+    fn sqlite_error_code(&self) -> Option<ErrorCode> {
+        let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+
+        while let Some(error) = source {
+            if let Some(rusqlite::Error::SqliteFailure(error, _)) = error.downcast_ref() {
+                return Some(error.code);
+            }
+            source = error.source();
+        }
+
+        None
+    }
+}
+
+pub fn run_command(args: Args) -> Result<Outcome, RunCommandError> {
+    if let Some(result) = run_without_config(&args) {
+        return result.map(|()| Outcome::Success);
+    }
+
+    if args.ephemeral {
+        let mut connection = Connection::open_in_memory().map_err(GetConnectionError::Open)?;
+        return run_command_with_connection(args, &mut connection);
+    }
+
+    let (config, read_only) = load_checked_config(&args)?;
+    let mut connection = match &args.command {
+        // Completion and status lines run constantly, so they must never create the database.
+        Some(Commands::Complete { .. } | Commands::Status) => get_existing_connection()?,
+        // Watching reconnects on every change.
+        Some(Commands::Watch { .. } | Commands::Print { watch: true, .. }) => None,
+        _ => {
+            let started = Instant::now();
+            let connection = open_connection(read_only)?;
+            log::info!("Opened database in {:?}", started.elapsed());
+            Some(connection)
+        }
+    };
+
+    run(args, config, read_only, connection.as_mut())
+}
+
+pub fn run_command_with_connection(
+    args: Args,
+    connection: &mut Connection,
+) -> Result<Outcome, RunCommandError> {
+    if let Some(result) = run_without_config(&args) {
+        return result.map(|()| Outcome::Success);
+    }
+
+    let (config, read_only) = load_checked_config(&args)?;
+    if read_only {
+        check_read_only_schema(connection)?;
+    } else {
+        // The schema is created idempotently, so any caller's connection works as is.
+        create_table(connection)?;
+    }
+    run(args, config, read_only, Some(connection))
+}
+
+// These don't need the connection, converting even replaces the database file and opens it
+// itself. It still reads the config first, which may make it read-only.
+fn run_without_config(args: &Args) -> Option<Result<(), RunCommandError>> {
+    match &args.command {
+        Some(Commands::Completions { shell }) => {
+            completions_command(*shell);
+            Some(Ok(()))
+        }
+        Some(Commands::Man { output }) => Some(man_command(output.as_deref()).map_err(Into::into)),
+        Some(Commands::Info { json }) => Some(info_command(*json).map_err(Into::into)),
+        #[cfg(feature = "encryption")]
+        Some(Commands::Encrypt) => {
+            Some(load_checked_config(args).and_then(|_| Ok(encrypt_command()?)))
+        }
+        #[cfg(feature = "encryption")]
+        Some(Commands::Decrypt) => {
+            Some(load_checked_config(args).and_then(|_| Ok(decrypt_command()?)))
+        }
+        _ => None,
+    }
+}
+
+fn load_checked_config(args: &Args) -> Result<(Config, bool), RunCommandError> {
+    let started = Instant::now();
+    let config = load_config()?;
+    log::info!("Loaded config in {:?}", started.elapsed());
+
+    let read_only = args.read_only || config.read_only.unwrap_or(false);
+    if read_only && mutates(&args.command) {
+        return Err(RunCommandError::ReadOnly);
+    }
+    Ok((config, read_only))
+}
+
+fn is_dry_run(command: &Option<Commands>) -> bool {
+    matches!(
+        command,
+        Some(Commands::Postpone { dry_run: true, .. } | Commands::Import { dry_run: true, .. })
+    )
+}
+
+// Each filter given narrows the query further, clap rejects --done with --pending.
+fn filter_query(filter: FilterOptions, query: TodoQuery) -> TodoQuery {
+    let done = match (filter.done, filter.pending) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => query.done,
+    };
+    let mut search = query.search;
+    search.extend(filter.search);
+    let mut tags = query.tags;
+    tags.extend(filter.tag);
+    TodoQuery {
+        done,
+        search,
+        tags,
+        all_tags: filter.tag_logic == TagLogic::And,
+        due_before: filter.due_before.or(query.due_before),
+        min_priority: filter.min_priority.or(query.min_priority),
+        prefix: filter.prefix.or(query.prefix),
+        ..query
+    }
+}
+
+// Listed without a catch-all, so a new command has to decide whether read-only mode allows it.
+fn mutates(command: &Option<Commands>) -> bool {
+    let Some(command) = command else {
+        return false;
+    };
+
+    match command {
+        Commands::Add { .. }
+        | Commands::Demo { .. }
+        | Commands::Edit { .. }
+        | Commands::Bulk { .. }
+        | Commands::RenameTag { .. }
+        | Commands::RemoveTag { .. }
+        | Commands::Tui
+        | Commands::Done { .. }
+        | Commands::Undone { .. }
+        | Commands::Remove { .. }
+        | Commands::Clear
+        | Commands::Clean
+        | Commands::Move { .. }
+        | Commands::MoveTo { .. }
+        | Commands::CopyTo { .. }
+        | Commands::Reindex
+        | Commands::Restore { .. }
+        | Commands::Notify { .. } => true,
+        Commands::Trash { empty } => *empty,
+        Commands::Lists { rename, delete, .. } => rename.is_some() || delete.is_some(),
+        #[cfg(feature = "encryption")]
+        Commands::Encrypt | Commands::Decrypt => true,
+        Commands::Postpone { dry_run, .. } | Commands::Import { dry_run, .. } => !dry_run,
+        Commands::Print { since_last_run, .. } => *since_last_run,
+        Commands::Count { .. }
+        | Commands::Search { .. }
+        | Commands::Export { .. }
+        | Commands::Completions { .. }
+        | Commands::Man { .. }
+        | Commands::Complete { .. }
+        | Commands::Next { .. }
+        | Commands::Watch { .. }
+        | Commands::Overdue { .. }
+        | Commands::Today { .. }
+        | Commands::Week { .. }
+        | Commands::Status
+        | Commands::History { .. }
+        | Commands::Stats { .. }
+        | Commands::Shell
+        | Commands::Serve { .. }
+        | Commands::Burndown { .. }
+        | Commands::Show { .. }
+        | Commands::Sql { .. }
+        | Commands::Templates
+        | Commands::Backup
+        | Commands::Info { .. } => false,
+    }
+}
+
+fn run(
+    args: Args,
+    config: Config,
+    read_only: bool,
+    connection: Option<&mut Connection>,
+) -> Result<Outcome, RunCommandError> {
+    let list = args
+        .list
+        .clone()
+        .or(config.default_list.clone())
+        .unwrap_or_else(|| DEFAULT_LIST.into());
+
+    if let Some(Commands::Complete { kind, prefix }) = args.command {
+        if let Some(connection) = connection {
+            for candidate in complete_candidates(connection, &list, kind, &prefix)? {
+                println!("{}", candidate);
+            }
+        }
+        return Ok(Outcome::Success);
+    }
+
+    let style = Style::new(
+        args.color,
+        args.accessible || config.accessible.unwrap_or(false),
+        args.no_emoji || config.no_emoji.unwrap_or(false),
+        config.date_format.clone(),
+        config.theme.clone(),
+        args.line_numbers_from
+            .or(config.line_numbers_from)
+            .unwrap_or(0),
+    );
+
+    if let Some(Commands::Status) = args.command {
+        status_command(connection.as_deref(), &list, &config.status, &style)?;
+        return Ok(Outcome::Success);
+    }
+
+    let list_query = TodoQuery::list(&list);
+    let scoped_query = |all_lists: bool| {
+        if all_lists {
+            TodoQuery::default()
+        } else {
+            list_query.clone()
+        }
+    };
+
+    if let Some(Commands::Watch {
+        all_lists,
+        interval,
+        options,
+    }) = &args.command
+    {
+        let interval = Duration::from_secs(*interval);
+        watch_command(
+            &scoped_query(*all_lists),
+            options,
+            &style,
+            interval,
+            read_only,
+        )?;
+        return Ok(Outcome::Success);
+    }
+
+    if let Some(Commands::Print {
+        all_lists,
+        watch: true,
+        filter,
+        ..
+    }) = &args.command
+    {
+        let query = filter_query(filter.clone(), scoped_query(*all_lists));
+        watch_json_command(&query, JSON_WATCH_INTERVAL, read_only)?;
+        return Ok(Outcome::Success);
+    }
+
+    let connection = connection.expect("run_command opens the database for every other command");
+
+    // Each line is checked and run on its own, flags and all.
+    if let Some(Commands::Shell) = args.command {
+        let stdin = std::io::stdin();
+        let mut stderr = std::io::stderr();
+        let prompt = stdin.is_terminal().then_some(&mut stderr as &mut dyn Write);
+        shell_command(connection, &args, &mut stdin.lock(), prompt)?;
+        return Ok(Outcome::Success);
+    }
+
+    if let AutoPrune::After(after) = config.auto_prune_done_after {
+        if !read_only && !is_dry_run(&args.command) {
+            auto_prune(connection, after, Utc::now())?;
+        }
+    }
+
+    let mut confirm: Box<dyn Confirm> = if args.yes {
+        Box::new(AssumeYes)
+    } else {
+        Box::new(StdinConfirm)
+    };
+    let confirm_threshold = args
+        .confirm_threshold
+        .or(config.confirm_threshold)
+        .unwrap_or(DEFAULT_CONFIRM_THRESHOLD);
+
+    let archive_file = args.archive_file.clone().or(config.archive_file.clone());
+
+    // Changes print the list afterwards unless silenced globally or for that one command.
+    let print_after = |connection: &Connection, query: &TodoQuery, quiet: bool| {
+        if args.quiet || quiet {
+            return Ok(());
+        }
+        let options = PrintOptions::default();
+        if args.reprint_stderr {
+            print_command(connection, query, &options, &style, &mut std::io::stderr())
+        } else {
+            print_command(
+                connection,
+                query,
+                &options,
+                &style,
+                &mut std::io::stdout().lock(),
+            )
+        }
+    };
+    let reprint =
+        |connection: &Connection, quiet: bool| print_after(connection, &list_query, quiet);
+
+    let resolve = |connection: &Connection, refs| {
+        resolve_refs(connection, &list, refs, args.by_id, style.line_numbers_from)
+    };
+    // For indexes that don't name a todo yet, which resolve can't check.
+    let unbase = |index: usize| {
+        index
+            .checked_sub(style.line_numbers_from)
+            .ok_or(ResolveRefsError::NoSuchIndex(index))
+    };
+
+    let started = Instant::now();
+    let mut outcome = Outcome::Success;
+    // How many todos a change acted on, for --fail-empty.
+    let mut affected = None;
+    let hook_result = match args.command {
+        Some(Commands::Add {
+            titles,
+            template,
+            separate,
+            from_clipboard,
+            single,
+            quiet,
+            mut options,
+        }) => {
+            // Resolved here to honor --by-id, add_command only sees the index.
+            if let Some(parent) = options.parent.take() {
+                options.parent = Some(TodoRef::Index(resolve(connection, vec![parent])?[0]));
+            }
+            options.at = options.at.map(unbase).transpose()?;
+            let read = titles.is_empty() && template.is_none() && !from_clipboard;
+            let added = if let Some(template) = &template {
+                add_template_command(connection, &list, &config.template, template, &options)?
+            } else {
+                // Quotes shouldn't be needed for a title of several words.
+                let titles = if from_clipboard {
+                    read_clipboard_titles(single)?
+                } else if read {
+                    let stdin = std::io::stdin();
+                    let mut stderr = std::io::stderr();
+                    let prompt = stdin.is_terminal().then_some(&mut stderr as &mut dyn Write);
+                    read_titles(&mut stdin.lock(), prompt)?
+                } else if separate {
+                    titles
+                } else {
+                    vec![titles.join(" ")]
+                };
+                add_command(connection, &list, titles, &options)?
+            };
+            if read || template.is_some() || from_clipboard {
+                let from = match template {
+                    Some(template) => format!(" from {}", template),
+                    None if from_clipboard => " from the clipboard".into(),
+                    None => String::new(),
+                };
+                match added.len() {
+                    1 => println!("Added 1 todo{}", from),
+                    count => println!("Added {} todos{}", count, from),
+                }
+            }
+            let hook_result = run_hook(&config.hooks, Hook::Add, &added);
+            reprint(connection, quiet)?;
+            hook_result
+        }
+        // Writes are turned away by the server itself in read-only mode, reading still works.
+        Some(Commands::Serve { addr }) => {
+            let server = Server {
+                list: &list,
+                archive: archive_file.as_deref(),
+                hooks: &config.hooks,
+                read_only,
+            };
+            Ok(serve_command(connection, addr, &server)?)
+        }
+        Some(Commands::Tui) => Ok(tui_command(
+            connection,
+            &list,
+            archive_file.as_deref(),
+            config.trash_limit,
+            &config.hooks,
+        )?),
+        Some(Commands::Demo { force }) => {
+            demo_command(connection, &list, force, Local::now().date_naive())?;
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::Import {
+            file,
+            prefer,
+            dry_run,
+            replace,
+            no_fsync,
+            dedup,
+        }) => {
+            let mut input: Box<dyn Read> = if file.as_os_str() == "-" {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(File::open(&file).map_err(ImportCommandError::Read)?)
+            };
+            let added = if replace {
+                import_replace_command(
+                    connection,
+                    &list,
+                    input.as_mut(),
+                    dry_run,
+                    dedup,
+                    confirm.as_mut(),
+                    config.trash_limit,
+                )?
+                .1
+            } else {
+                import_command(
+                    connection,
+                    &list,
+                    input.as_mut(),
+                    prefer,
+                    dry_run,
+                    no_fsync,
+                    dedup,
+                )?
+                .into_iter()
+                .filter(|(_, merge)| *merge == Merge::Added && !dry_run)
+                .map(|(todo, _)| todo)
+                .collect()
+            };
+            if added.is_empty() {
+                Ok(())
+            } else {
+                run_hook(&config.hooks, Hook::Add, &added)
+            }
+        }
+        Some(Commands::Done {
+            ids,
+            and_clear: true,
+        }) => {
+            let ids = resolve(connection, ids)?;
+            let (updated, cleared) = done_and_clear_command(
+                connection,
+                &list,
+                ids,
+                archive_file.as_deref(),
+                confirm.as_mut(),
+                confirm_threshold,
+            )?;
+            // A todo done already still counts when the clear removes it.
+            affected = Some(updated.len() + cleared.len());
+            for todo in &cleared {
+                println!("Cleared: {}", escape_control(&todo.title));
+            }
+            let hook_result = run_hook(&config.hooks, Hook::Done, &updated).and(run_hook(
+                &config.hooks,
+                Hook::Remove,
+                &cleared,
+            ));
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Done { ids, .. }) => {
+            let ids = resolve(connection, ids)?;
+            let latest = ids.is_empty();
+            let updated = set_done_command(connection, &list, ids, true, archive_file.as_deref())?;
+            affected = Some(updated.len());
+            if latest {
+                println!("Done: {}", escape_control(&updated[0].title));
+            }
+            let hook_result = run_hook(&config.hooks, Hook::Done, &updated);
+            reprint(connection, false)?;
+            hook_result
         }
         Some(Commands::Undone { ids }) => {
-            set_done_command(&mut connection, ids, false)?;
-            print_command(&connection)?;
+            let ids = resolve(connection, ids)?;
+            let latest = ids.is_empty();
+            let updated = set_done_command(connection, &list, ids, false, None)?;
+            affected = Some(updated.len());
+            if latest {
+                println!("Pending again: {}", escape_control(&updated[0].title));
+            }
+            let hook_result = run_hook(&config.hooks, Hook::Undone, &updated);
+            reprint(connection, false)?;
+            hook_result
         }
-        Some(Commands::Remove { ids }) => {
-            remove_command(&connection, ids)?;
-            print_command(&connection)?;
+        Some(Commands::Remove { ids, filter }) => {
+            let removed = if !filter.is_empty() {
+                remove_matching_command(
+                    connection,
+                    &list,
+                    &filter_query(filter, TodoQuery::default()),
+                    confirm.as_mut(),
+                    confirm_threshold,
+                    config.trash_limit,
+                )?
+            } else {
+                let ids = resolve(connection, ids)?;
+                remove_command(
+                    connection,
+                    &list,
+                    ids,
+                    confirm.as_mut(),
+                    confirm_threshold,
+                    config.trash_limit,
+                )?
+            };
+            affected = Some(removed.len());
+            let hook_result = run_hook(&config.hooks, Hook::Remove, &removed);
+            reprint(connection, false)?;
+            hook_result
         }
         Some(Commands::Clear) => {
-            clear_command(&connection)?;
-            print_command(&connection)?;
+            let removed = clear_command(connection, &list, confirm.as_mut(), confirm_threshold)?;
+            affected = Some(removed.len());
+            let hook_result = run_hook(&config.hooks, Hook::Remove, &removed);
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Clean) => {
+            let stdin = std::io::stdin();
+            if !stdin.is_terminal() {
+                Err(CleanCommandError::NotTerminal)?;
+            }
+            let summary = clean_command(
+                connection,
+                &list,
+                archive_file.as_deref(),
+                &mut stdin.lock(),
+                &mut std::io::stderr(),
+            )?;
+            match &summary {
+                None => println!("Quit, nothing was changed"),
+                Some(CleanSummary {
+                    deleted,
+                    archived,
+                    kept: 0,
+                }) if deleted.is_empty() && archived.is_empty() => {
+                    println!("No done todos to clean")
+                }
+                Some(summary) => println!(
+                    "Deleted {}, archived {}, kept {}",
+                    todos_count(summary.deleted.len()),
+                    todos_count(summary.archived.len()),
+                    todos_count(summary.kept)
+                ),
+            }
+            let removed: Vec<Todo> = summary
+                .map(|summary| {
+                    summary
+                        .deleted
+                        .into_iter()
+                        .chain(summary.archived)
+                        .collect()
+                })
+                .unwrap_or_default();
+            affected = Some(removed.len());
+            let hook_result = run_hook(&config.hooks, Hook::Remove, &removed);
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Move { id, to, top, .. }) => {
+            // Clap requires one of them, so without an index or --top it's --bottom.
+            let target = match (to, top) {
+                (Some(to), _) => MoveTarget::Index(unbase(to)?),
+                (None, true) => MoveTarget::Top,
+                (None, false) => MoveTarget::Bottom,
+            };
+            let id = resolve(connection, vec![id])?[0];
+            move_command(connection, &list, id, target)?;
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::MoveTo { target, ids }) => {
+            let ids = resolve(connection, ids)?;
+            let moved = move_to_command(connection, &list, &target, ids)?;
+            println!("Moved {} to {}", todos_count(moved.len()), target);
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::CopyTo {
+            target,
+            ids,
+            keep_done,
+        }) => {
+            let ids = resolve(connection, ids)?;
+            let copied = copy_to_command(connection, &list, &target, ids, keep_done)?;
+            println!("Copied {} to {}", todos_count(copied.len()), target);
+            // The copies are new todos, the originals stay as they were.
+            let hook_result = run_hook(&config.hooks, Hook::Add, &copied);
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Postpone {
+            ids,
+            duration,
+            overdue,
+            dry_run,
+        }) => {
+            let ids = resolve(connection, ids)?;
+            let postponed =
+                postpone_command(connection, &list, ids, overdue, duration, dry_run, &style)?;
+            affected = Some(postponed);
+            Ok(())
+        }
+        Some(Commands::Edit { id, options }) => {
+            let index = resolve(connection, vec![id])?[0];
+            edit_command(connection, &list, index, &options)?;
+            Ok(reprint(connection, false)?)
         }
-        Some(Commands::Print) => print_command(&connection)?,
-        None => print_command(&connection)?,
+        Some(Commands::Bulk { file }) => {
+            let mut input: Box<dyn Read> = if file.as_os_str() == "-" {
+                Box::new(std::io::stdin().lock())
+            } else {
+                Box::new(File::open(&file).map_err(BulkCommandError::Read)?)
+            };
+            let updated = bulk_command(connection, &list, input.as_mut(), style.line_numbers_from)?;
+            affected = Some(updated);
+            println!("Updated {} todos", updated);
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::Templates) => {
+            templates_command(&config.template);
+            Ok(())
+        }
+        Some(Commands::Lists {
+            delete: Some(target),
+            ..
+        }) => {
+            let deleted = delete_list_command(
+                connection,
+                &target,
+                confirm.as_mut(),
+                confirm_threshold,
+                config.trash_limit,
+            )?;
+            println!("Deleted {} with {}", target, todos_count(deleted.len()));
+            if target == config.default_list.as_deref().unwrap_or(DEFAULT_LIST) {
+                eprintln!(
+                    "Warning: {} is the default list, which now has no todos",
+                    target
+                );
+            }
+            let hook_result = run_hook(&config.hooks, Hook::Remove, &deleted);
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Lists {
+            rename: None, json, ..
+        }) => Ok(lists_command(
+            connection,
+            &list,
+            config.default_list.as_deref().unwrap_or(DEFAULT_LIST),
+            json,
+        )?),
+        Some(Commands::Lists {
+            rename: Some(names),
+            merge,
+            ..
+        }) => {
+            let [from, to] = names.as_slice() else {
+                unreachable!("clap takes exactly two values for --rename");
+            };
+            let to = to.trim();
+            let renamed = rename_list_command(connection, from, to, merge)?;
+            println!("Renamed {} to {}, {}", from, to, todos_count(renamed));
+            if rename_default_list(from, to)? {
+                println!("Updated the default list in the config");
+            }
+
+            // The rest of the invocation follows the renamed list.
+            if *from == list {
+                Ok(print_after(connection, &TodoQuery::list(to), false)?)
+            } else {
+                Ok(reprint(connection, false)?)
+            }
+        }
+        Some(Commands::RenameTag { from, to }) => {
+            let renamed = rename_tag_command(connection, &from, &to)?;
+            affected = Some(renamed);
+            println!("Renamed the tag on {} todos", renamed);
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::RemoveTag { tag }) => {
+            let removed = remove_tag_command(connection, &tag)?;
+            affected = Some(removed);
+            println!("Removed the tag from {} todos", removed);
+            Ok(reprint(connection, false)?)
+        }
+        Some(Commands::Sql { query }) => Ok(sql_command(connection, &query)?),
+        Some(Commands::Show { id }) => {
+            let index = resolve(connection, vec![id])?[0];
+            Ok(show_command(connection, &list, index, &style.date_format)?)
+        }
+        Some(Commands::Print {
+            all_lists,
+            filter,
+            since_last_run,
+            tail,
+            group_done_last,
+            relative,
+            progress,
+            format,
+            mut options,
+            ..
+        }) => {
+            let query = filter_query(filter, scoped_query(all_lists));
+            options.tail = tail;
+            options.group_done_last = group_done_last;
+            options.relative = relative;
+            options.progress = progress;
+            match format {
+                Some(PrintFormat::Json) => options.json = true,
+                Some(PrintFormat::Ndjson) => options.ndjson = true,
+                None => {}
+            }
+            if since_last_run {
+                let user = std::env::var("USER")
+                    .or_else(|_| std::env::var("USERNAME"))
+                    .unwrap_or_default();
+                options.new_since = mark_viewed(connection, &user, Utc::now())?;
+            }
+            Ok(print_command(
+                connection,
+                &query,
+                &options,
+                &style,
+                &mut std::io::stdout().lock(),
+            )?)
+        }
+        Some(Commands::Count { all_lists }) => {
+            Ok(count_command(connection, &scoped_query(all_lists))?)
+        }
+        Some(Commands::Export {
+            since,
+            until,
+            by,
+            all_lists,
+            filter,
+        }) => Ok(export_command(
+            connection,
+            &filter_query(filter, scoped_query(all_lists)),
+            since,
+            until,
+            by,
+            &mut std::io::stdout().lock(),
+        )?),
+        Some(Commands::Search {
+            text,
+            all_lists,
+            quiet,
+            filter,
+            options,
+        }) => {
+            let query = TodoQuery {
+                search: vec![text],
+                ..scoped_query(all_lists)
+            };
+            let query = filter_query(filter, query);
+            if !search_command(connection, &query, quiet, &options, &style)? {
+                outcome = Outcome::NoMatch;
+            }
+            Ok(())
+        }
+        Some(Commands::Next { count, options }) => Ok(next_command(
+            connection,
+            &list_query,
+            count,
+            &options,
+            &style,
+        )?),
+        Some(Commands::Overdue { all_lists, options }) => Ok(overdue_command(
+            connection,
+            &scoped_query(all_lists),
+            &options,
+            &style,
+        )?),
+        Some(Commands::Today { all_lists, options }) => Ok(today_command(
+            connection,
+            &scoped_query(all_lists),
+            &options,
+            &style,
+        )?),
+        Some(Commands::Week {
+            all_lists,
+            no_date,
+            show_empty,
+            options,
+        }) => Ok(week_command(
+            connection,
+            &scoped_query(all_lists),
+            no_date,
+            show_empty,
+            &options,
+            &style,
+        )?),
+        Some(Commands::Notify { within, force }) => {
+            let within = within
+                .or(config.notify.within)
+                .unwrap_or(DEFAULT_NOTIFY_WITHIN);
+            Ok(notify_command(connection, within, force)?)
+        }
+        Some(Commands::Reindex) => Ok(reindex_command(connection)?),
+        Some(Commands::Trash { empty: false }) => Ok(trash_command(connection, &style)?),
+        Some(Commands::Trash { empty: true }) => {
+            let purged = empty_trash_command(connection, confirm.as_mut())?;
+            println!("Permanently deleted {} from the trash", todos_count(purged));
+            Ok(())
+        }
+        Some(Commands::Restore { ids }) => {
+            let restored = restore_command(connection, &ids)?;
+            affected = Some(restored.len());
+            println!("Restored {}", todos_count(restored.len()));
+            let hook_result = run_hook(&config.hooks, Hook::Add, &restored);
+            reprint(connection, false)?;
+            hook_result
+        }
+        Some(Commands::Backup) => {
+            let dir = args.backup_dir.clone().or(config.backup_dir.clone());
+            let path = backup_command(connection, dir, Local::now())?;
+            println!("Backed up to {}", path.display());
+            Ok(())
+        }
+        Some(Commands::History {
+            limit,
+            all_lists,
+            json,
+        }) => {
+            let list = (!all_lists).then_some(list.as_str());
+            Ok(history_command(connection, list, limit, json)?)
+        }
+        Some(Commands::Stats {
+            by_tag,
+            json,
+            days,
+            recent,
+            cycle_time,
+        }) => Ok(stats_command(
+            connection, &list, by_tag, json, days, recent, cycle_time,
+        )?),
+        Some(Commands::Burndown { days, json }) => {
+            Ok(burndown_command(connection, &list, days, json, &style)?)
+        }
+        Some(
+            Commands::Completions { .. }
+            | Commands::Man { .. }
+            | Commands::Info { .. }
+            | Commands::Complete { .. }
+            | Commands::Status
+            | Commands::Watch { .. }
+            | Commands::Shell,
+        ) => {
+            unreachable!("handled before dispatching on the database")
+        }
+        #[cfg(feature = "encryption")]
+        Some(Commands::Encrypt | Commands::Decrypt) => {
+            unreachable!("handled before dispatching on the database")
+        }
+        None => Ok(print_command(
+            connection,
+            &list_query,
+            &PrintOptions::default(),
+            &style,
+            &mut std::io::stdout().lock(),
+        )?),
     };
+    log::info!("Ran command in {:?}", started.elapsed());
+
+    if args.fail_empty && affected == Some(0) {
+        return Err(RunCommandError::NothingAffected);
+    }
+
+    // The change is already committed, so a failing hook only warns unless asked to be strict.
+    if let Err(error) = hook_result {
+        if args.strict_hooks {
+            return Err(error.into());
+        }
+        eprintln!("Warning: {}", error);
+    }
 
-    Ok(())
+    Ok(outcome)
 }
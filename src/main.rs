@@ -1,11 +1,23 @@
 use clap::Parser;
+use log::LevelFilter;
 use todo_cli::{args::Args, run_command};
 
 fn main() {
     let args = Args::parse();
 
-    run_command(args).unwrap_or_else(|e| {
+    let level = match args.verbose {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Info,
+        _ => LevelFilter::Debug,
+    };
+    env_logger::Builder::new()
+        .filter_module("todo_cli", level)
+        .format_timestamp(None)
+        .init();
+
+    let outcome = run_command(args).unwrap_or_else(|e| {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.exit_code());
     });
+    std::process::exit(outcome.exit_code());
 }
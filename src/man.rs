@@ -0,0 +1,125 @@
+use std::{fs::File, io::Write, path::Path};
+
+use clap::{Command, CommandFactory};
+use clap_mangen::Man;
+use roff::{bold, roman, Roff};
+
+use crate::args::{Args, EXAMPLES};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ManCommandError {
+    #[error("Fail to create the man page directory")]
+    CreateDir(#[source] std::io::Error),
+
+    #[error("Fail to write the man page")]
+    Write(#[source] std::io::Error),
+}
+
+pub fn man_command(output: Option<&Path>) -> Result<(), ManCommandError> {
+    let mut command = Args::command().disable_help_subcommand(true);
+    command.build();
+
+    let Some(output) = output else {
+        return render_page(command, EXAMPLES.to_vec(), &mut std::io::stdout().lock())
+            .map_err(ManCommandError::Write);
+    };
+
+    std::fs::create_dir_all(output).map_err(ManCommandError::CreateDir)?;
+
+    let subcommands: Vec<Command> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .cloned()
+        .collect();
+
+    let pages = std::iter::once((command, EXAMPLES.to_vec())).chain(subcommands.into_iter().map(
+        |subcommand| {
+            let examples = examples_of(subcommand.get_name());
+            (subcommand, examples)
+        },
+    ));
+
+    for (command, examples) in pages {
+        let path = output.join(Man::new(command.clone()).get_filename());
+        let mut file = File::create(&path).map_err(ManCommandError::Write)?;
+        render_page(command, examples, &mut file).map_err(ManCommandError::Write)?;
+        log::info!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn render_page(
+    command: Command,
+    examples: Vec<(&str, &str)>,
+    out: &mut dyn Write,
+) -> std::io::Result<()> {
+    let has_subcommands = command.has_subcommands();
+    let has_after_help = command.get_after_help().is_some();
+    let has_version = command.get_version().is_some();
+    let man = Man::new(command);
+
+    man.render_title(out)?;
+    man.render_name_section(out)?;
+    man.render_synopsis_section(out)?;
+    man.render_description_section(out)?;
+    man.render_options_section(out)?;
+    if has_subcommands {
+        man.render_subcommands_section(out)?;
+    }
+    if !examples.is_empty() {
+        render_examples_section(&examples, out)?;
+    }
+    if has_after_help {
+        man.render_extra_section(out)?;
+    }
+    if has_version {
+        man.render_version_section(out)?;
+    }
+
+    Ok(())
+}
+
+// Subcommand pages only show the examples invoking that subcommand.
+fn examples_of(subcommand: &str) -> Vec<(&'static str, &'static str)> {
+    EXAMPLES
+        .iter()
+        .filter(|(example, _)| example.split_whitespace().nth(1) == Some(subcommand))
+        .copied()
+        .collect()
+}
+
+fn render_examples_section(examples: &[(&str, &str)], out: &mut dyn Write) -> std::io::Result<()> {
+    let mut roff = Roff::new();
+    roff.control("SH", ["EXAMPLES"]);
+
+    for (example, description) in examples {
+        roff.control("TP", []);
+        roff.text([bold(*example)]);
+        roff.text([roman(*description)]);
+    }
+
+    roff.to_writer(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples_use_existing_subcommands() {
+        let command = Args::command();
+
+        for (example, _) in EXAMPLES {
+            if let Some(subcommand) = example.split_whitespace().nth(1) {
+                assert!(
+                    subcommand.starts_with('-') || command.find_subcommand(subcommand).is_some(),
+                    "unknown subcommand in example: {}",
+                    example
+                );
+            }
+        }
+
+        assert_eq!(examples_of("clear"), vec![EXAMPLES[6]]);
+    }
+}
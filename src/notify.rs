@@ -0,0 +1,75 @@
+use chrono::{Local, NaiveDate};
+use notify_rust::Notification;
+use rusqlite::Connection;
+
+use crate::{
+    commands::overdue_phrase,
+    dates::Span,
+    db::{get_notifiable_todos, record_notified, GetNotifiableTodosError, RecordNotifiedError},
+};
+
+#[derive(thiserror::Error, Debug)]
+pub enum NotifyCommandError {
+    #[error("Fail to notify that far ahead")]
+    OutOfRange,
+
+    #[error(transparent)]
+    GetNotifiableTodos(#[from] GetNotifiableTodosError),
+
+    #[error(transparent)]
+    RecordNotified(#[from] RecordNotifiedError),
+}
+
+pub fn notify_command(
+    connection: &Connection,
+    within: Span,
+    force: bool,
+) -> Result<(), NotifyCommandError> {
+    let today = Local::now().date_naive();
+    let until = within.after(today).ok_or(NotifyCommandError::OutOfRange)?;
+
+    for (_, todo) in get_notifiable_todos(connection, until, force)? {
+        let due = todo.due.expect("notifiable todos have a due date");
+        let body = due_phrase(due, today);
+
+        // Without a notification service, as on a headless box, the reminder lands in the log.
+        if let Err(error) = Notification::new()
+            .appname(env!("CARGO_PKG_NAME"))
+            .summary(&todo.title)
+            .body(&body)
+            .show()
+        {
+            log::warn!("Fail to show a desktop notification: {}", error);
+            eprintln!("{}: {}", todo.title, body);
+        }
+
+        record_notified(connection, &todo)?;
+    }
+
+    Ok(())
+}
+
+fn due_phrase(due: NaiveDate, today: NaiveDate) -> String {
+    if due < today {
+        overdue_phrase(due, today)
+    } else if due == today {
+        "due today".into()
+    } else {
+        format!("due {}", due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_phrase() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let date = |date: &str| date.parse().unwrap();
+
+        assert_eq!(due_phrase(date("2024-04-29"), today), "2 days overdue");
+        assert_eq!(due_phrase(date("2024-05-01"), today), "due today");
+        assert_eq!(due_phrase(date("2024-05-02"), today), "due 2024-05-02");
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use serde::Deserialize;
+
+use crate::{
+    args::AddOptions,
+    commands::{
+        add_command, indexed, resolve_refs, set_done_command, AddCommandError, ResolveRefsError,
+    },
+    config::HooksConfig,
+    db::{get_todos, query_todos, TodoQuery},
+    hooks::{run_hook, Hook},
+    todo::{Priority, Todo, TodoDto, TodoRef},
+};
+
+const MAX_BODY: usize = 64 * 1024;
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(thiserror::Error, Debug)]
+pub enum ServeCommandError {
+    #[error("Fail to listen on {0}")]
+    Bind(SocketAddr, #[source] std::io::Error),
+
+    #[error("Fail to accept a connection")]
+    Accept(#[source] std::io::Error),
+
+    #[error("Fail to handle Ctrl-C")]
+    SetHandler(#[from] ctrlc::Error),
+}
+
+// What the handlers need besides the connection, all of it settled before serving starts.
+pub struct Server<'a> {
+    pub list: &'a str,
+    pub archive: Option<&'a Path>,
+    pub hooks: &'a HooksConfig,
+    pub read_only: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: &impl serde::Serialize) -> Self {
+        match serde_json::to_string(value) {
+            Ok(body) => Self { status, body },
+            Err(error) => Self::error(500, &error.to_string()),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: serde_json::json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewTodo {
+    title: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    priority: Option<Priority>,
+    due: Option<NaiveDate>,
+    note: Option<String>,
+}
+
+// One request at a time on the one connection, which is plenty for a dashboard polling it.
+// Accepting doesn't block, so Ctrl-C is noticed between requests.
+pub fn serve_command(
+    connection: &mut Connection,
+    addr: SocketAddr,
+    server: &Server,
+) -> Result<(), ServeCommandError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.store(true, Ordering::SeqCst))?;
+    }
+
+    let listener = TcpListener::bind(addr).map_err(|e| ServeCommandError::Bind(addr, e))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| ServeCommandError::Bind(addr, e))?;
+    eprintln!("Serving the todos on http://{}, Ctrl-C to stop", addr);
+
+    while !stop.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if let Err(error) = respond(connection, server, stream) {
+                    log::warn!("Fail to answer {}: {}", peer, error);
+                }
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_INTERVAL);
+            }
+            Err(error) => return Err(ServeCommandError::Accept(error)),
+        }
+    }
+    Ok(())
+}
+
+fn respond(connection: &mut Connection, server: &Server, stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader)? {
+        Ok(request) => {
+            log::info!("{} {}", request.method, request.path);
+            handle(connection, server, &request)
+        }
+        Err(response) => response,
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+// Only what the API needs of HTTP/1.1: a request line, the headers for the body's length and
+// the body. A malformed request is answered rather than dropped.
+fn read_request(reader: &mut dyn BufRead) -> std::io::Result<Result<Request, Response>> {
+    let bad_request = |message| Ok(Err(Response::error(400, message)));
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return bad_request("Malformed request line");
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                let Ok(value) = value.trim().parse() else {
+                    return bad_request("Malformed Content-Length");
+                };
+                length = value;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Ok(Err(Response::error(413, "The body is too large")));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Ok(Request {
+        method,
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(name), percent_decode(value))
+            })
+            .collect(),
+        body,
+    }))
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => match text
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                None => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn handle(connection: &mut Connection, server: &Server, request: &Request) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let writes = request.method == "POST";
+    if writes && server.read_only {
+        return Response::error(403, "The database is opened read-only");
+    }
+
+    match (request.method.as_str(), &segments[..]) {
+        ("GET", ["todos"]) => list_todos(connection, server, request),
+        ("POST", ["todos"]) => add_todo(connection, server, request),
+        ("POST", ["todos", id, "done"]) => match id.parse() {
+            Ok(id) => done_todo(connection, server, id),
+            Err(_) => Response::error(404, "No such todo"),
+        },
+        (_, ["todos"] | ["todos", _, "done"]) => Response::error(405, "Method not allowed"),
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+fn list_todos(connection: &Connection, server: &Server, request: &Request) -> Response {
+    let mut query = TodoQuery::list(server.list);
+    for (name, value) in &request.query {
+        match (name.as_str(), value.as_str()) {
+            ("done", "true") => query.done = Some(true),
+            ("done", "false") => query.done = Some(false),
+            ("done", _) => return Response::error(400, "done is either true or false"),
+            ("tag", tag) => query.tags.push(tag.to_string()),
+            (name, _) => return Response::error(400, &format!("Unknown parameter {}", name)),
+        }
+    }
+
+    match query_todos(connection, &query) {
+        Ok(todos) => Response::json(200, &indexed(&todos)),
+        Err(error) => Response::error(500, &error.to_string()),
+    }
+}
+
+fn add_todo(connection: &mut Connection, server: &Server, request: &Request) -> Response {
+    let new: NewTodo = match serde_json::from_slice(&request.body) {
+        Ok(new) => new,
+        Err(error) => return Response::error(400, &error.to_string()),
+    };
+    let options = AddOptions {
+        tags: new.tags,
+        priority: new.priority,
+        due: new.due,
+        note: new.note,
+        ..AddOptions::default()
+    };
+
+    match add_command(connection, server.list, vec![new.title], &options) {
+        Ok(added) => {
+            after(server, Hook::Add, &added);
+            let added: Vec<TodoDto> = added.into_iter().map(Into::into).collect();
+            Response::json(201, &added[0])
+        }
+        Err(error @ AddCommandError::EmptyTitle) => Response::error(400, &error.to_string()),
+        Err(error) => Response::error(500, &error.to_string()),
+    }
+}
+
+// By database id, which unlike an index stays the same while the dashboard shows it. Marking a
+// done todo done again only answers with it, so a retried request still succeeds.
+fn done_todo(connection: &mut Connection, server: &Server, id: usize) -> Response {
+    let index = match resolve_refs(connection, server.list, vec![TodoRef::Index(id)], true, 0) {
+        Ok(indexes) => indexes[0],
+        Err(error @ ResolveRefsError::NoSuchId(_)) => {
+            return Response::error(404, &error.to_string())
+        }
+        Err(error) => return Response::error(500, &error.to_string()),
+    };
+
+    let updated = match set_done_command(connection, server.list, vec![index], true, server.archive)
+    {
+        Ok(updated) => updated,
+        Err(error) => return Response::error(500, &error.to_string()),
+    };
+    after(server, Hook::Done, &updated);
+    if let Some(todo) = updated.into_iter().next() {
+        return Response::json(200, &TodoDto::from(todo));
+    }
+
+    match get_todos(connection, server.list) {
+        Ok(todos) => match todos.into_iter().find(|todo| todo.id == id) {
+            Some(todo) => Response::json(200, &TodoDto::from(todo)),
+            None => Response::error(404, &ResolveRefsError::NoSuchId(id).to_string()),
+        },
+        Err(error) => Response::error(500, &error.to_string()),
+    }
+}
+
+// The change is made by then, so a failing hook is only logged.
+fn after(server: &Server, hook: Hook, todos: &[Todo]) {
+    if let Err(error) = run_hook(server.hooks, hook, todos) {
+        log::warn!("{}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::create_table, todo::DEFAULT_LIST};
+
+    fn request(method: &str, target: &str, body: &str) -> Request {
+        let raw = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\ncontent-length: {}\r\n\r\n{}",
+            method,
+            target,
+            body.len(),
+            body
+        );
+        read_request(&mut raw.as_bytes()).unwrap().unwrap()
+    }
+
+    fn titles(response: &Response) -> Vec<String> {
+        let todos: Vec<serde_json::Value> = serde_json::from_str(&response.body).unwrap();
+        todos
+            .iter()
+            .map(|todo| todo["title"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_request() {
+        assert_eq!(
+            request("GET", "/todos?tag=at%20home&done=false&tag=a+b", ""),
+            Request {
+                method: "GET".into(),
+                path: "/todos".into(),
+                query: vec![
+                    ("tag".into(), "at home".into()),
+                    ("done".into(), "false".into()),
+                    ("tag".into(), "a b".into()),
+                ],
+                body: Vec::new(),
+            }
+        );
+        assert_eq!(request("POST", "/todos", "{}").body, b"{}");
+        assert_eq!(percent_decode("100%25%zz%"), "100%%zz%");
+
+        let status = |raw: &str| {
+            read_request(&mut raw.as_bytes())
+                .unwrap()
+                .unwrap_err()
+                .status
+        };
+        assert_eq!(status("nonsense\r\n\r\n"), 400);
+        assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n"), 400);
+        assert_eq!(
+            status("POST / HTTP/1.1\r\nContent-Length: 999999\r\n\r\n"),
+            413
+        );
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let hooks = HooksConfig::default();
+        let server = Server {
+            list: DEFAULT_LIST,
+            archive: None,
+            hooks: &hooks,
+            read_only: false,
+        };
+        let mut send = |method: &str, target: &str, body: &str| {
+            handle(&mut connection, &server, &request(method, target, body))
+        };
+
+        let added = send(
+            "POST",
+            "/todos",
+            r#"{"title": " buy  milk ", "tags": ["shop"]}"#,
+        );
+        assert_eq!(added.status, 201);
+        let added: TodoDto = serde_json::from_str(&added.body).unwrap();
+        assert_eq!(added.title, "buy milk");
+        assert_eq!(send("POST", "/todos", r#"{"title": "call"}"#).status, 201);
+
+        assert_eq!(send("POST", "/todos", r#"{"title": " "}"#).status, 400);
+        assert_eq!(send("POST", "/todos", r#"{"name": "x"}"#).status, 400);
+        assert_eq!(send("POST", "/todos", "not json").status, 400);
+
+        let done = send("POST", &format!("/todos/{}/done", added.id), "");
+        assert_eq!(done.status, 200);
+        assert!(serde_json::from_str::<TodoDto>(&done.body).unwrap().done);
+        let again = send("POST", &format!("/todos/{}/done", added.id), "");
+        assert_eq!(again.status, 200);
+        let again: TodoDto = serde_json::from_str(&again.body).unwrap();
+        assert_eq!((again.id, again.done), (added.id, true));
+        assert_eq!(send("POST", "/todos/99/done", "").status, 404);
+        assert_eq!(send("POST", "/todos/x/done", "").status, 404);
+
+        let all = send("GET", "/todos", "");
+        assert_eq!(all.status, 200);
+        assert_eq!(titles(&all), vec!["buy milk", "call"]);
+        assert_eq!(
+            titles(&send("GET", "/todos?done=true", "")),
+            vec!["buy milk"]
+        );
+        assert_eq!(titles(&send("GET", "/todos?done=false", "")), vec!["call"]);
+        assert_eq!(
+            titles(&send("GET", "/todos?tag=shop", "")),
+            vec!["buy milk"]
+        );
+        assert_eq!(send("GET", "/todos?done=maybe", "").status, 400);
+        assert_eq!(send("GET", "/todos?sort=title", "").status, 400);
+
+        assert_eq!(send("DELETE", "/todos", "").status, 405);
+        assert_eq!(send("GET", "/todos/1/done", "").status, 405);
+        assert_eq!(send("GET", "/", "").status, 404);
+    }
+
+    #[test]
+    fn test_handle_read_only() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        create_table(&connection).unwrap();
+        let hooks = HooksConfig::default();
+        let server = Server {
+            list: DEFAULT_LIST,
+            archive: None,
+            hooks: &hooks,
+            read_only: true,
+        };
+
+        let add = request("POST", "/todos", r#"{"title": "a"}"#);
+        assert_eq!(handle(&mut connection, &server, &add).status, 403);
+        let list = request("GET", "/todos", "");
+        assert_eq!(handle(&mut connection, &server, &list).body, "[]");
+    }
+}
@@ -0,0 +1,207 @@
+use std::io::{BufRead, Write};
+
+use clap::Parser;
+use rusqlite::Connection;
+
+use crate::{
+    args::{Args, ColorChoice, Commands},
+    run_command_with_connection,
+};
+
+const PROMPT: &str = "todo> ";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ShellCommandError {
+    #[error("Fail to read the shell input")]
+    Read(#[from] std::io::Error),
+
+    #[error("{0} of the shell lines failed")]
+    Failed(usize),
+}
+
+enum ShellLine {
+    Empty,
+    Quit,
+    Run(Box<Args>),
+    Invalid(clap::Error),
+    Unquoted,
+    Nested,
+}
+
+// Split the way a shell would, so quoting works as it does on the command line.
+fn parse_line(line: &str) -> ShellLine {
+    let Some(words) = shlex::split(line) else {
+        return ShellLine::Unquoted;
+    };
+    match words.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => return ShellLine::Empty,
+        ["quit" | "exit"] => return ShellLine::Quit,
+        _ => {}
+    }
+    match Args::try_parse_from(std::iter::once("todo-cli".to_string()).chain(words)) {
+        Ok(args) if matches!(args.command, Some(Commands::Shell)) => ShellLine::Nested,
+        Ok(args) => ShellLine::Run(Box::new(args)),
+        Err(error) => ShellLine::Invalid(error),
+    }
+}
+
+// The global flags given to the shell itself apply to every line, unless the line gives its own.
+// Destructured in full, so a new global flag has to be handled here.
+fn inherit_globals(args: &mut Args, outer: &Args) {
+    let Args {
+        command: _,
+        list,
+        verbose,
+        yes,
+        confirm_threshold,
+        quiet,
+        fail_empty,
+        reprint_stderr,
+        by_id,
+        line_numbers_from,
+        ephemeral,
+        read_only,
+        archive_file,
+        backup_dir,
+        strict_hooks,
+        color,
+        accessible,
+        no_emoji,
+    } = outer;
+
+    args.list = args.list.take().or_else(|| list.clone());
+    args.verbose = args.verbose.max(*verbose);
+    args.yes |= yes;
+    args.confirm_threshold = args.confirm_threshold.or(*confirm_threshold);
+    args.quiet |= quiet;
+    args.fail_empty |= fail_empty;
+    args.reprint_stderr |= reprint_stderr;
+    args.by_id |= by_id;
+    args.line_numbers_from = args.line_numbers_from.or(*line_numbers_from);
+    args.ephemeral |= ephemeral;
+    args.read_only |= read_only;
+    args.archive_file = args.archive_file.take().or_else(|| archive_file.clone());
+    args.backup_dir = args.backup_dir.take().or_else(|| backup_dir.clone());
+    args.strict_hooks |= strict_hooks;
+    if matches!(args.color, ColorChoice::Auto) {
+        args.color = *color;
+    }
+    args.accessible |= accessible;
+    args.no_emoji |= no_emoji;
+}
+
+// Every line runs as a command of its own on the one connection, so a failing line only ends
+// itself. The prompt is only shown when someone is typing, otherwise failed lines fail the shell
+// like they would a script.
+pub fn shell_command(
+    connection: &mut Connection,
+    outer: &Args,
+    input: &mut dyn BufRead,
+    mut prompt: Option<&mut dyn Write>,
+) -> Result<(), ShellCommandError> {
+    if let Some(prompt) = prompt.as_deref_mut() {
+        writeln!(
+            prompt,
+            "Type commands as after todo-cli, quit or Ctrl-D to leave"
+        )?;
+    }
+
+    let mut failed = 0;
+    loop {
+        if let Some(prompt) = prompt.as_deref_mut() {
+            write!(prompt, "{}", PROMPT)?;
+            prompt.flush()?;
+        }
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            if let Some(prompt) = prompt.as_deref_mut() {
+                writeln!(prompt)?;
+            }
+            break;
+        }
+
+        let ok = match parse_line(&line) {
+            ShellLine::Empty => true,
+            ShellLine::Quit => break,
+            ShellLine::Run(mut args) => {
+                inherit_globals(&mut args, outer);
+                match run_command_with_connection(*args, connection) {
+                    Ok(_) => true,
+                    Err(error) => {
+                        eprintln!("Error: {}", error);
+                        false
+                    }
+                }
+            }
+            // Also how --help and --version print, which aren't failures.
+            ShellLine::Invalid(error) => {
+                error.print()?;
+                !error.use_stderr()
+            }
+            ShellLine::Unquoted => {
+                eprintln!("Error: A quote isn't closed");
+                false
+            }
+            ShellLine::Nested => {
+                eprintln!("Error: Already in the shell");
+                false
+            }
+        };
+        if !ok {
+            failed += 1;
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ if prompt.is_some() => Ok(()),
+        failed => Err(ShellCommandError::Failed(failed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        assert!(matches!(parse_line(" \n"), ShellLine::Empty));
+        assert!(matches!(parse_line("quit\n"), ShellLine::Quit));
+        assert!(matches!(parse_line(" exit "), ShellLine::Quit));
+        assert!(matches!(parse_line("add 'a b"), ShellLine::Unquoted));
+        assert!(matches!(parse_line("shell"), ShellLine::Nested));
+        assert!(matches!(
+            parse_line("no-such-command"),
+            ShellLine::Invalid(_)
+        ));
+        assert!(matches!(parse_line("add quit"), ShellLine::Run(_)));
+
+        let ShellLine::Run(args) = parse_line("-q add 'buy milk' \"and eggs\"\n") else {
+            panic!("should run");
+        };
+        assert!(args.quiet);
+        let Some(Commands::Add { titles, .. }) = args.command else {
+            panic!("should add");
+        };
+        assert_eq!(titles, vec!["buy milk", "and eggs"]);
+    }
+
+    #[test]
+    fn test_inherit_globals() {
+        let outer = Args::parse_from(["todo-cli", "-l", "work", "--read-only", "--by-id", "shell"]);
+
+        let ShellLine::Run(mut args) = parse_line("-l home add x") else {
+            panic!("should run");
+        };
+        inherit_globals(&mut args, &outer);
+        assert_eq!(args.list.as_deref(), Some("home"));
+        assert!(args.read_only && args.by_id && !args.yes);
+
+        let ShellLine::Run(mut args) = parse_line("--yes clear") else {
+            panic!("should run");
+        };
+        inherit_globals(&mut args, &outer);
+        assert_eq!(args.list.as_deref(), Some("work"));
+        assert!(args.read_only && args.yes);
+    }
+}
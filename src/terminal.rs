@@ -1,3 +1,380 @@
-pub fn strikethrough(s: &str) -> String {
-    s.chars().map(|c| format!("{}\u{0336}", c)).collect()
+use std::{borrow::Cow, io::IsTerminal};
+
+use serde::Deserialize;
+
+use crate::{
+    args::ColorChoice,
+    dates::DateFormat,
+    todo::{Label, Priority},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl Color {
+    pub fn for_progress(ratio: f64) -> Self {
+        if ratio < 0.33 {
+            Color::Red
+        } else if ratio < 0.66 {
+            Color::Yellow
+        } else {
+            Color::Green
+        }
+    }
+
+    fn code(self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+            Color::Magenta => "35",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+// The SGR codes of a style written as names, like "red bold".
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Paint(Cow<'static, str>);
+
+const PAINT_NAMES: [(&str, &str); 10] = [
+    ("bold", "1"),
+    ("dim", "2"),
+    ("italic", "3"),
+    ("underline", "4"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+];
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid style '{0}', expected names like red, bold, dim or none")]
+pub struct ParsePaintError(String);
+
+impl TryFrom<String> for Paint {
+    type Error = ParsePaintError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let normalized = value.trim().to_lowercase();
+        if normalized == "none" {
+            return Ok(Paint(Cow::Borrowed("")));
+        }
+
+        let codes = normalized
+            .split_whitespace()
+            .map(|name| {
+                PAINT_NAMES
+                    .iter()
+                    .find(|(known, _)| *known == name)
+                    .map(|(_, code)| *code)
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|codes| !codes.is_empty())
+            .ok_or_else(|| ParsePaintError(value.clone()))?;
+        Ok(Paint(codes.join(";").into()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub high: Paint,
+    pub low: Paint,
+}
+
+pub const DEFAULT_THEME: Theme = Theme {
+    high: Paint(Cow::Borrowed("1;31")),
+    low: Paint(Cow::Borrowed("2")),
+};
+
+impl Default for Theme {
+    fn default() -> Self {
+        DEFAULT_THEME
+    }
+}
+
+impl From<Label> for Color {
+    fn from(label: Label) -> Self {
+        match label {
+            Label::Red => Color::Red,
+            Label::Yellow => Color::Yellow,
+            Label::Green => Color::Green,
+            Label::Blue => Color::Blue,
+            Label::Magenta => Color::Magenta,
+            Label::Cyan => Color::Cyan,
+        }
+    }
+}
+
+// Control characters are spelled out like in a Rust string, so a tab can't throw off the
+// alignment and an escape sequence in a title can't restyle the terminal.
+pub fn escape_control(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_control() {
+            escaped.extend(c.escape_default());
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Style {
+    pub color: bool,
+    // Spells out in words what colors, marks and bars convey, for screen readers.
+    pub accessible: bool,
+    // Every marker below falls back to plain ASCII, for terminals that draw emoji as boxes.
+    pub ascii: bool,
+    pub date_format: DateFormat,
+    pub theme: Theme,
+    // What the first todo is numbered, in what's printed and in the numbers commands read back.
+    pub line_numbers_from: usize,
+}
+
+impl Style {
+    pub fn new(
+        choice: ColorChoice,
+        accessible: bool,
+        ascii: bool,
+        date_format: DateFormat,
+        theme: Theme,
+        line_numbers_from: usize,
+    ) -> Self {
+        let color = !accessible
+            && match choice {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => {
+                    std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+                }
+            };
+
+        Self {
+            color,
+            accessible,
+            ascii,
+            date_format,
+            theme,
+            line_numbers_from,
+        }
+    }
+
+    pub fn marker(&self, emoji: &'static str, ascii: &'static str) -> &'static str {
+        if self.ascii {
+            ascii
+        } else {
+            emoji
+        }
+    }
+
+    pub fn ellipsis(&self) -> &'static str {
+        self.marker("…", "...")
+    }
+
+    pub fn strikethrough(&self, s: &str) -> String {
+        if self.ascii {
+            format!("~~{}~~", s)
+        } else {
+            s.chars().map(|c| format!("{}\u{0336}", c)).collect()
+        }
+    }
+
+    pub fn bold(&self, s: &str) -> String {
+        self.paint(s, "1")
+    }
+
+    pub fn red(&self, s: &str) -> String {
+        self.paint(s, Color::Red.code())
+    }
+
+    pub fn green(&self, s: &str) -> String {
+        self.paint(s, Color::Green.code())
+    }
+
+    pub fn yellow(&self, s: &str) -> String {
+        self.paint(s, Color::Yellow.code())
+    }
+
+    // Painted after the theme, or marked in text when there's no color.
+    pub fn priority(&self, priority: Priority, title: &str) -> String {
+        match (priority, self.color) {
+            (Priority::Medium, _) => title.to_string(),
+            (Priority::High, true) => self.paint(title, &self.theme.high.0),
+            (Priority::Low, true) => self.paint(title, &self.theme.low.0),
+            (Priority::High, false) => format!("(!) {}", title),
+            (Priority::Low, false) => format!("{} {}", self.marker("(·)", "(.)"), title),
+        }
+    }
+
+    // A dot in the label's color, or its name when there's no color or dot to show.
+    pub fn label(&self, label: Label) -> String {
+        let mark = if self.color && !self.ascii {
+            "●".to_string()
+        } else {
+            format!("[{}]", label)
+        };
+        self.paint(&mark, Color::from(label).code())
+    }
+
+    // Without color the bar falls back to ASCII, which reads fine in logs and pipes.
+    pub fn progress_bar(&self, done: usize, total: usize, width: usize) -> String {
+        let ratio = if total == 0 {
+            1.0
+        } else {
+            done as f64 / total as f64
+        };
+        let filled = ((ratio * width as f64).round() as usize).min(width);
+
+        let bar = if self.color && !self.ascii {
+            format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+        } else {
+            format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+        };
+        self.paint(&bar, Color::for_progress(ratio).code())
+    }
+
+    fn paint(&self, s: &str, code: &str) -> String {
+        if self.color && !code.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, s)
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_color_buckets() {
+        assert_eq!(Color::for_progress(0.0), Color::Red);
+        assert_eq!(Color::for_progress(0.32), Color::Red);
+        assert_eq!(Color::for_progress(0.33), Color::Yellow);
+        assert_eq!(Color::for_progress(0.5), Color::Yellow);
+        assert_eq!(Color::for_progress(0.66), Color::Green);
+        assert_eq!(Color::for_progress(1.0), Color::Green);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let plain = Style::default();
+        assert_eq!(plain.progress_bar(0, 4, 8), "[--------]");
+        assert_eq!(plain.progress_bar(2, 4, 8), "[####----]");
+        assert_eq!(plain.progress_bar(4, 4, 8), "[########]");
+
+        let colored = Style {
+            color: true,
+            ..Default::default()
+        };
+        assert_eq!(colored.progress_bar(1, 4, 4), "\x1b[31m█░░░\x1b[0m");
+        assert_eq!(colored.progress_bar(2, 4, 4), "\x1b[33m██░░\x1b[0m");
+        assert_eq!(colored.progress_bar(4, 4, 4), "\x1b[32m████\x1b[0m");
+    }
+
+    #[test]
+    fn test_accessible_disables_color() {
+        let style = |accessible| {
+            Style::new(
+                ColorChoice::Always,
+                accessible,
+                false,
+                DateFormat::default(),
+                Theme::default(),
+                0,
+            )
+        };
+        assert!(!style(true).color);
+        assert_eq!(style(true).red("late"), "late");
+        assert!(style(false).color);
+    }
+
+    #[test]
+    fn test_escape_control() {
+        assert_eq!(
+            escape_control("a\tb\r\n\x1b[0m\x7f"),
+            r"a\tb\r\n\u{1b}[0m\u{7f}"
+        );
+        assert_eq!(
+            escape_control("naïve 'quotes' \"too\" ✔"),
+            "naïve 'quotes' \"too\" ✔"
+        );
+    }
+
+    #[test]
+    fn test_label() {
+        let colored = Style {
+            color: true,
+            ..Default::default()
+        };
+        assert_eq!(Style::default().label(Label::Red), "[red]");
+        assert_eq!(colored.label(Label::Cyan), "\x1b[36m●\x1b[0m");
+    }
+
+    #[test]
+    fn test_priority() {
+        let plain = Style::default();
+        assert_eq!(plain.priority(Priority::High, "a"), "(!) a");
+        assert_eq!(plain.priority(Priority::Medium, "a"), "a");
+        assert_eq!(plain.priority(Priority::Low, "a"), "(·) a");
+        let ascii = Style {
+            ascii: true,
+            ..Default::default()
+        };
+        assert_eq!(ascii.priority(Priority::Low, "a"), "(.) a");
+
+        let colored = Style {
+            color: true,
+            ..Default::default()
+        };
+        assert_eq!(colored.priority(Priority::High, "a"), "\x1b[1;31ma\x1b[0m");
+        assert_eq!(colored.priority(Priority::Medium, "a"), "a");
+        assert_eq!(colored.priority(Priority::Low, "a"), "\x1b[2ma\x1b[0m");
+
+        let themed = Style {
+            color: true,
+            theme: Theme {
+                high: "Magenta underline".to_string().try_into().unwrap(),
+                low: "none".to_string().try_into().unwrap(),
+            },
+            ..Default::default()
+        };
+        assert_eq!(themed.priority(Priority::High, "a"), "\x1b[35;4ma\x1b[0m");
+        assert_eq!(themed.priority(Priority::Low, "a"), "a");
+
+        for value in ["", "pink", "red sparkly"] {
+            assert_eq!(
+                Paint::try_from(value.to_string()),
+                Err(ParsePaintError(value.into()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_ascii_markers() {
+        let ascii = Style {
+            color: true,
+            ascii: true,
+            ..Default::default()
+        };
+        assert_eq!(ascii.label(Label::Cyan), "\x1b[36m[cyan]\x1b[0m");
+        assert_eq!(ascii.progress_bar(2, 4, 4), "\x1b[33m[##--]\x1b[0m");
+        assert_eq!(ascii.strikethrough("milk"), "~~milk~~");
+        assert_eq!(ascii.ellipsis(), "...");
+        assert_eq!(Style::default().ellipsis(), "…");
+    }
 }
@@ -1,8 +1,112 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    str::FromStr,
+};
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const DEFAULT_LIST: &str = "default";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low = 0,
+    #[default]
+    Medium = 1,
+    High = 2,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Priority::Low => "Low",
+            Priority::Medium => "Medium",
+            Priority::High => "High",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid priority '{0}', expected high, medium or low")]
+pub struct ParsePriorityError(String);
+
+impl FromStr for Priority {
+    type Err = ParsePriorityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "l" | "low" => Ok(Priority::Low),
+            "m" | "medium" => Ok(Priority::Medium),
+            "h" | "high" => Ok(Priority::High),
+            _ => Err(ParsePriorityError(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Label {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+const LABELS: [(&str, Label); 6] = [
+    ("red", Label::Red),
+    ("yellow", Label::Yellow),
+    ("green", Label::Green),
+    ("blue", Label::Blue),
+    ("magenta", Label::Magenta),
+    ("cyan", Label::Cyan),
+];
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = LABELS.iter().find(|(_, label)| label == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid label '{0}', expected red, yellow, green, blue, magenta or cyan")]
+pub struct ParseLabelError(String);
+
+impl FromStr for Label {
+    type Err = ParseLabelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        LABELS
+            .iter()
+            .find(|(label, _)| *label == name)
+            .map(|(_, label)| *label)
+            .ok_or_else(|| ParseLabelError(s.into()))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Todo {
     pub id: usize,
+    pub uuid: String,
     pub title: String,
     pub done: bool,
+    pub list: String,
+    pub tags: Vec<String>,
+    pub priority: Priority,
+    pub due: Option<NaiveDate>,
+    pub note: Option<String>,
+    pub label: Option<Label>,
+    pub parent: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub done_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
 }
 
 impl Todo {
@@ -11,6 +115,418 @@ impl Todo {
             title,
             done: false,
             id: 0,
+            uuid: Uuid::new_v4().to_string(),
+            list: DEFAULT_LIST.into(),
+            tags: Vec::new(),
+            priority: Priority::default(),
+            due: None,
+            note: None,
+            label: None,
+            parent: None,
+            created_at: None,
+            done_at: None,
+            updated_at: None,
+        }
+    }
+
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        !self.done && self.due.is_some_and(|due| due < today)
+    }
+
+    pub fn is_due_today(&self, today: NaiveDate) -> bool {
+        !self.done && self.due == Some(today)
+    }
+
+    pub fn age<Tz: TimeZone>(&self, now: DateTime<Tz>) -> Option<Duration> {
+        self.created_at
+            .map(|created_at| now.with_timezone(&Utc) - created_at)
+    }
+
+    // Counted to the end of the due day in the time zone of now, so like is_overdue it only
+    // turns negative the day after.
+    pub fn time_to_due<Tz: TimeZone>(&self, now: DateTime<Tz>) -> Option<Duration> {
+        let deadline = self
+            .due?
+            .succ_opt()?
+            .and_time(NaiveTime::MIN)
+            .and_local_timezone(now.timezone())
+            .earliest()?;
+        Some(deadline - now)
+    }
+}
+
+// The todos of one list in id order, so a todo's position is the index commands refer to it by.
+#[derive(Debug, Clone, Default)]
+pub struct TodoList(pub Vec<Todo>);
+
+impl TodoList {
+    pub fn into_inner(self) -> Vec<Todo> {
+        self.0
+    }
+
+    pub fn by_index(&self, index: usize) -> Option<&Todo> {
+        self.0.get(index)
+    }
+
+    // Filtering keeps the list indexes, which a position in the result wouldn't match.
+    pub fn pending(&self) -> impl Iterator<Item = (usize, &Todo)> {
+        self.indexed().filter(|(_, todo)| !todo.done)
+    }
+
+    pub fn done(&self) -> impl Iterator<Item = (usize, &Todo)> {
+        self.indexed().filter(|(_, todo)| todo.done)
+    }
+
+    pub fn indexed(&self) -> impl Iterator<Item = (usize, &Todo)> {
+        self.0.iter().enumerate()
+    }
+}
+
+impl Deref for TodoList {
+    type Target = Vec<Todo>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for TodoList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<Vec<Todo>> for TodoList {
+    fn from(todos: Vec<Todo>) -> Self {
+        Self(todos)
+    }
+}
+
+impl From<TodoList> for Vec<Todo> {
+    fn from(list: TodoList) -> Self {
+        list.0
+    }
+}
+
+impl FromIterator<Todo> for TodoList {
+    fn from_iter<I: IntoIterator<Item = Todo>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for TodoList {
+    type Item = Todo;
+    type IntoIter = std::vec::IntoIter<Todo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TodoList {
+    type Item = &'a Todo;
+    type IntoIter = std::slice::Iter<'a, Todo>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+// Commands take list indexes, or UUID prefixes to point at a todo no matter how the list moved.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TodoRef {
+    Index(usize),
+    Uuid(String),
+}
+
+const MIN_UUID_PREFIX: usize = 4;
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Invalid todo '{0}', expected an index or at least 4 characters of a UUID")]
+pub struct ParseTodoRefError(String);
+
+impl FromStr for TodoRef {
+    type Err = ParseTodoRefError;
+
+    // A prefix made only of digits reads as an index, a longer prefix disambiguates.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(index) = s.parse() {
+            return Ok(TodoRef::Index(index));
+        }
+
+        let prefix = s.to_lowercase();
+        if prefix.len() >= MIN_UUID_PREFIX
+            && prefix.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+        {
+            return Ok(TodoRef::Uuid(prefix));
+        }
+        Err(ParseTodoRefError(s.into()))
+    }
+}
+
+// What todos look like in JSON output, hooks and imports, so the struct can change without
+// breaking that format. Only the title is required when reading it back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoDto {
+    #[serde(default)]
+    pub id: usize,
+    pub uuid: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub done: bool,
+    pub list: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    pub due: Option<NaiveDate>,
+    pub note: Option<String>,
+    pub label: Option<Label>,
+    pub parent: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Todo> for TodoDto {
+    fn from(todo: Todo) -> Self {
+        Self {
+            id: todo.id,
+            uuid: Some(todo.uuid),
+            title: todo.title,
+            done: todo.done,
+            list: Some(todo.list),
+            tags: todo.tags,
+            priority: todo.priority,
+            due: todo.due,
+            note: todo.note,
+            label: todo.label,
+            parent: todo.parent,
+            updated_at: todo.updated_at,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Todo title can't be empty")]
+pub struct EmptyTitleError;
+
+impl TryFrom<TodoDto> for Todo {
+    type Error = EmptyTitleError;
+
+    fn try_from(dto: TodoDto) -> Result<Self, Self::Error> {
+        if dto.title.trim().is_empty() {
+            return Err(EmptyTitleError);
+        }
+
+        let new = Todo::new(dto.title);
+        Ok(Self {
+            id: dto.id,
+            uuid: dto.uuid.unwrap_or(new.uuid),
+            done: dto.done,
+            list: dto.list.unwrap_or_else(|| DEFAULT_LIST.into()),
+            tags: dto.tags,
+            priority: dto.priority,
+            due: dto.due,
+            note: dto.note,
+            label: dto.label,
+            parent: dto.parent,
+            updated_at: dto.updated_at,
+            ..new
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo_list() -> TodoList {
+        ["a", "b", "c"]
+            .into_iter()
+            .map(|title| Todo {
+                done: title == "b",
+                ..Todo::new(title.into())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_todo_list_filters_keep_indexes() {
+        let list = todo_list();
+        let titles = |todos: Vec<(usize, &Todo)>| -> Vec<(usize, String)> {
+            todos
+                .into_iter()
+                .map(|(index, todo)| (index, todo.title.clone()))
+                .collect()
+        };
+
+        assert_eq!(
+            titles(list.pending().collect()),
+            vec![(0, "a".into()), (2, "c".into())]
+        );
+        assert_eq!(titles(list.done().collect()), vec![(1, "b".into())]);
+        assert!(TodoList::default().pending().next().is_none());
+    }
+
+    #[test]
+    fn test_todo_list_by_index() {
+        let list = todo_list();
+
+        assert_eq!(list.by_index(2).unwrap().title, "c");
+        assert!(list.by_index(3).is_none());
+        assert_eq!(list.len(), 3);
+        let titles: Vec<String> = list.into_iter().map(|todo| todo.title).collect();
+        assert_eq!(titles, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_parse_priority() {
+        assert_eq!("low".parse(), Ok(Priority::Low));
+        assert_eq!("medium".parse(), Ok(Priority::Medium));
+        assert_eq!("high".parse(), Ok(Priority::High));
+    }
+
+    #[test]
+    fn test_parse_priority_ignores_case() {
+        assert_eq!("HIGH".parse(), Ok(Priority::High));
+        assert_eq!("Medium".parse(), Ok(Priority::Medium));
+        assert_eq!(" lOw ".parse(), Ok(Priority::Low));
+    }
+
+    #[test]
+    fn test_parse_priority_shorthand() {
+        assert_eq!("h".parse(), Ok(Priority::High));
+        assert_eq!("M".parse(), Ok(Priority::Medium));
+        assert_eq!("l".parse(), Ok(Priority::Low));
+    }
+
+    #[test]
+    fn test_parse_invalid_priority() {
+        let error = "urgent".parse::<Priority>().unwrap_err();
+        assert_eq!(error, ParsePriorityError("urgent".into()));
+        assert_eq!(
+            error.to_string(),
+            "Invalid priority 'urgent', expected high, medium or low"
+        );
+        assert!("".parse::<Priority>().is_err());
+    }
+
+    #[test]
+    fn test_priority_round_trips_through_display() {
+        for priority in [Priority::Low, Priority::Medium, Priority::High] {
+            assert_eq!(priority.to_string().parse(), Ok(priority));
+        }
+    }
+
+    #[test]
+    fn test_todo_round_trips_through_dto() {
+        let todo = Todo {
+            id: 3,
+            done: true,
+            list: "work".into(),
+            tags: vec!["a".into(), "b".into()],
+            priority: Priority::High,
+            due: NaiveDate::from_ymd_opt(2024, 5, 1),
+            note: Some("note".into()),
+            label: Some(Label::Blue),
+            parent: Some("4f2a9c1e".into()),
+            ..Todo::new("title".into())
+        };
+
+        let dto = TodoDto::from(todo);
+        let json = serde_json::to_string(&dto).unwrap();
+        let parsed: TodoDto = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, dto);
+        assert_eq!(TodoDto::from(Todo::try_from(parsed).unwrap()), dto);
+    }
+
+    #[test]
+    fn test_dto_without_optional_fields() {
+        let dto: TodoDto = serde_json::from_str(r#"{"title": "title"}"#).unwrap();
+        let todo = Todo::try_from(dto).unwrap();
+        assert_eq!(todo.title, "title");
+        assert!(!todo.done);
+        assert_eq!(todo.list, DEFAULT_LIST);
+        assert!(todo.tags.is_empty());
+        assert_eq!(todo.priority, Priority::Medium);
+        assert_eq!(todo.due, None);
+        assert_eq!(todo.note, None);
+        assert_eq!(todo.label, None);
+        assert_eq!(Uuid::parse_str(&todo.uuid).unwrap().get_version_num(), 4);
+
+        let dto: TodoDto = serde_json::from_str(r#"{"title": " "}"#).unwrap();
+        assert_eq!(Todo::try_from(dto).unwrap_err(), EmptyTitleError);
+        assert!(serde_json::from_str::<TodoDto>(r#"{"done": true}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_label() {
+        for (name, label) in LABELS {
+            assert_eq!(name.parse(), Ok(label));
+            assert_eq!(label.to_string(), name);
+        }
+        assert_eq!(" Red ".parse(), Ok(Label::Red));
+        assert_eq!("pink".parse::<Label>(), Err(ParseLabelError("pink".into())));
+    }
+
+    #[test]
+    fn test_age() {
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let todo = Todo {
+            created_at: Some(at("2024-05-01T10:00:00Z")),
+            ..Todo::new("title".into())
+        };
+
+        assert_eq!(
+            todo.age(at("2024-05-03T12:00:00Z")),
+            Some(Duration::hours(50))
+        );
+        assert_eq!(
+            todo.age(at("2024-05-01T09:00:00Z")),
+            Some(Duration::hours(-1))
+        );
+        assert_eq!(Todo::new("title".into()).age(Utc::now()), None);
+    }
+
+    #[test]
+    fn test_time_to_due() {
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let todo = Todo {
+            due: NaiveDate::from_ymd_opt(2024, 5, 3),
+            ..Todo::new("title".into())
+        };
+
+        assert_eq!(
+            todo.time_to_due(at("2024-05-01T12:00:00Z")),
+            Some(Duration::hours(60))
+        );
+        assert_eq!(
+            todo.time_to_due(at("2024-05-03T23:00:00Z")),
+            Some(Duration::hours(1))
+        );
+        assert_eq!(
+            todo.time_to_due(at("2024-05-05T00:00:00Z")),
+            Some(Duration::days(-1))
+        );
+        assert_eq!(Todo::new("title".into()).time_to_due(Utc::now()), None);
+    }
+
+    #[test]
+    fn test_parse_todo_ref() {
+        assert_eq!("3".parse(), Ok(TodoRef::Index(3)));
+        assert_eq!("1234".parse(), Ok(TodoRef::Index(1234)));
+        assert_eq!("4F2a".parse(), Ok(TodoRef::Uuid("4f2a".into())));
+        assert_eq!(
+            "4f2a9c1e-8b".parse(),
+            Ok(TodoRef::Uuid("4f2a9c1e-8b".into()))
+        );
+
+        for input in ["", "abc", "-1", "milk", "4f2g"] {
+            assert_eq!(
+                input.parse::<TodoRef>(),
+                Err(ParseTodoRefError(input.into())),
+                "{}",
+                input
+            );
         }
     }
 }
@@ -0,0 +1,509 @@
+use std::{io::IsTerminal, path::Path};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::{
+    layout::{Constraint, Layout, Position},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use rusqlite::Connection;
+
+use crate::{
+    args::AddOptions,
+    commands::{
+        add_command, remove_command, set_done_command, AddCommandError, RemoveCommandError,
+        SetDoneCommandError,
+    },
+    config::HooksConfig,
+    confirm::AssumeYes,
+    db::{get_todos, GetTodosError},
+    hooks::{run_hook, Hook},
+    todo::{Todo, TodoList},
+};
+
+const HELP: &str = "↑/↓ move  space toggle done  d delete  a add  / filter  q quit";
+
+#[derive(thiserror::Error, Debug)]
+pub enum TuiCommandError {
+    #[error("The full-screen view needs a terminal, use print to list the todos")]
+    NotTerminal,
+
+    #[error("Fail to draw on the terminal")]
+    Terminal(#[from] std::io::Error),
+
+    #[error(transparent)]
+    GetTodos(#[from] GetTodosError),
+
+    #[error(transparent)]
+    SetDone(#[from] SetDoneCommandError),
+
+    #[error(transparent)]
+    Remove(#[from] RemoveCommandError),
+
+    #[error(transparent)]
+    Add(#[from] AddCommandError),
+}
+
+#[derive(Debug, Default, PartialEq)]
+enum Mode {
+    #[default]
+    Browse,
+    ConfirmRemove,
+    Add(String),
+    Filter,
+}
+
+#[derive(Default)]
+struct Model {
+    todos: TodoList,
+    // Into the todos the filter shows, not into the whole list.
+    selected: usize,
+    filter: String,
+    mode: Mode,
+}
+
+impl Model {
+    // The list indexes of the todos whose title has the filter in it, ignoring case.
+    fn visible(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        self.todos
+            .iter()
+            .enumerate()
+            .filter(|(_, todo)| todo.title.to_lowercase().contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.visible().get(self.selected).copied()
+    }
+
+    fn clamp_selection(&mut self) {
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+    }
+}
+
+// What the database has to be told about after a key press. Indexes are into the whole list,
+// as the commands read them.
+#[derive(Debug, PartialEq)]
+enum Effect {
+    SetDone(usize, bool),
+    Remove(usize),
+    Add(String),
+    Quit,
+}
+
+// Applies the key to the model right away, the returned effect is persisted afterwards.
+fn update(model: &mut Model, key: KeyCode) -> Option<Effect> {
+    match &mut model.mode {
+        Mode::Browse => browse(model, key),
+        Mode::ConfirmRemove => {
+            model.mode = Mode::Browse;
+            if key != KeyCode::Char('y') {
+                return None;
+            }
+            let index = model.selected_index()?;
+            model.todos.remove(index);
+            model.clamp_selection();
+            Some(Effect::Remove(index))
+        }
+        Mode::Add(title) => match key {
+            KeyCode::Char(c) => {
+                title.push(c);
+                None
+            }
+            KeyCode::Backspace => {
+                title.pop();
+                None
+            }
+            KeyCode::Enter => {
+                let title = title.trim().to_string();
+                model.mode = Mode::Browse;
+                (!title.is_empty()).then_some(Effect::Add(title))
+            }
+            KeyCode::Esc => {
+                model.mode = Mode::Browse;
+                None
+            }
+            _ => None,
+        },
+        Mode::Filter => {
+            match key {
+                KeyCode::Char(c) => model.filter.push(c),
+                KeyCode::Backspace => {
+                    model.filter.pop();
+                }
+                KeyCode::Enter => model.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    model.filter.clear();
+                    model.mode = Mode::Browse;
+                }
+                _ => {}
+            }
+            model.clamp_selection();
+            None
+        }
+    }
+}
+
+fn browse(model: &mut Model, key: KeyCode) -> Option<Effect> {
+    match key {
+        // Escape first takes back a filter, so it isn't a surprise exit while searching.
+        KeyCode::Esc if !model.filter.is_empty() => {
+            model.filter.clear();
+            model.clamp_selection();
+            None
+        }
+        KeyCode::Char('q') | KeyCode::Esc => Some(Effect::Quit),
+        KeyCode::Up | KeyCode::Char('k') => {
+            model.selected = model.selected.saturating_sub(1);
+            None
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            if model.selected + 1 < model.visible().len() {
+                model.selected += 1;
+            }
+            None
+        }
+        KeyCode::Char(' ') => {
+            let index = model.selected_index()?;
+            let todo = model.todos.get_mut(index)?;
+            todo.done = !todo.done;
+            Some(Effect::SetDone(index, todo.done))
+        }
+        KeyCode::Char('d') => {
+            model.selected_index()?;
+            model.mode = Mode::ConfirmRemove;
+            None
+        }
+        KeyCode::Char('a') => {
+            model.mode = Mode::Add(String::new());
+            None
+        }
+        KeyCode::Char('/') => {
+            model.mode = Mode::Filter;
+            None
+        }
+        _ => None,
+    }
+}
+
+pub fn tui_command(
+    connection: &mut Connection,
+    list: &str,
+    archive: Option<&Path>,
+    trash_limit: Option<usize>,
+    hooks: &HooksConfig,
+) -> Result<(), TuiCommandError> {
+    if !std::io::stdout().is_terminal() {
+        return Err(TuiCommandError::NotTerminal);
+    }
+    let mut model = Model {
+        todos: get_todos(connection, list)?,
+        ..Model::default()
+    };
+
+    // Also installs a panic hook that restores the terminal before the message is printed.
+    let mut terminal = ratatui::try_init()?;
+    let result = run(
+        &mut terminal,
+        connection,
+        list,
+        archive,
+        trash_limit,
+        hooks,
+        &mut model,
+    );
+    ratatui::try_restore()?;
+    result
+}
+
+fn run(
+    terminal: &mut DefaultTerminal,
+    connection: &mut Connection,
+    list: &str,
+    archive: Option<&Path>,
+    trash_limit: Option<usize>,
+    hooks: &HooksConfig,
+    model: &mut Model,
+) -> Result<(), TuiCommandError> {
+    loop {
+        terminal.draw(|frame| draw(frame, list, model))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match update(model, key.code) {
+            Some(Effect::Quit) => return Ok(()),
+            Some(Effect::SetDone(index, done)) => {
+                let updated = set_done_command(connection, list, vec![index], done, archive)?;
+                let hook = if done { Hook::Done } else { Hook::Undone };
+                after(hooks, hook, &updated);
+            }
+            // Already confirmed in the view, there's nothing to ask on top.
+            Some(Effect::Remove(index)) => {
+                let removed = remove_command(
+                    connection,
+                    list,
+                    vec![index],
+                    &mut AssumeYes,
+                    1,
+                    trash_limit,
+                )?;
+                after(hooks, Hook::Remove, &removed);
+            }
+            // Read back rather than pushed, so the todo shows as the database stored it.
+            Some(Effect::Add(title)) => {
+                let added = add_command(connection, list, vec![title], &AddOptions::default())?;
+                after(hooks, Hook::Add, &added);
+                model.todos = get_todos(connection, list)?;
+                model.selected = model.visible().len().saturating_sub(1);
+            }
+            None => {}
+        }
+    }
+}
+
+// The change is made by then, so a failing hook is only logged.
+fn after(hooks: &HooksConfig, hook: Hook, todos: &[Todo]) {
+    if let Err(error) = run_hook(hooks, hook, todos) {
+        log::warn!("{}", error);
+    }
+}
+
+fn draw(frame: &mut Frame, list: &str, model: &Model) {
+    let input_height = if matches!(model.mode, Mode::Add(_)) {
+        3
+    } else {
+        0
+    };
+    let [todos_area, input_area, help_area] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(input_height),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let items: Vec<ListItem> = model
+        .visible()
+        .into_iter()
+        .map(|index| {
+            let todo = &model.todos[index];
+            let style = if todo.done {
+                Style::new().add_modifier(Modifier::CROSSED_OUT | Modifier::DIM)
+            } else {
+                Style::new()
+            };
+            ListItem::new(Line::styled(todo.title.as_str(), style))
+        })
+        .collect();
+
+    let title = if model.filter.is_empty() {
+        format!(" {} ", list)
+    } else {
+        format!(" {} /{} ", list, model.filter)
+    };
+    let todos = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ListState::default().with_selected(Some(model.selected));
+    frame.render_stateful_widget(todos, todos_area, &mut state);
+
+    let help = match &model.mode {
+        Mode::Browse => HELP.to_string(),
+        Mode::ConfirmRemove => {
+            let title = model
+                .selected_index()
+                .map_or("", |index| model.todos[index].title.as_str());
+            format!("Remove \"{}\"? y/n", title)
+        }
+        Mode::Add(title) => {
+            frame.render_widget(
+                Paragraph::new(title.as_str()).block(Block::bordered().title(" Add ")),
+                input_area,
+            );
+            let typed = u16::try_from(title.chars().count()).unwrap_or(u16::MAX);
+            frame.set_cursor_position(Position::new(
+                input_area.x.saturating_add(1).saturating_add(typed),
+                input_area.y + 1,
+            ));
+            "enter add  esc cancel".to_string()
+        }
+        Mode::Filter => {
+            let typed = u16::try_from(model.filter.chars().count()).unwrap_or(u16::MAX);
+            frame.set_cursor_position(Position::new(
+                help_area.x.saturating_add(1).saturating_add(typed),
+                help_area.y,
+            ));
+            format!("/{}", model.filter)
+        }
+    };
+    frame.render_widget(Paragraph::new(help), help_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::Todo;
+
+    fn model(titles: &[&str]) -> Model {
+        Model {
+            todos: titles
+                .iter()
+                .map(|title| Todo::new(title.to_string()))
+                .collect(),
+            ..Model::default()
+        }
+    }
+
+    fn titles(model: &Model) -> Vec<&str> {
+        model
+            .visible()
+            .into_iter()
+            .map(|index| model.todos[index].title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_update_moves_within_bounds() {
+        let mut model = model(&["a", "b"]);
+
+        assert_eq!(update(&mut model, KeyCode::Up), None);
+        assert_eq!(model.selected, 0);
+        update(&mut model, KeyCode::Down);
+        update(&mut model, KeyCode::Char('j'));
+        assert_eq!(model.selected, 1);
+        update(&mut model, KeyCode::Char('k'));
+        assert_eq!(model.selected, 0);
+    }
+
+    #[test]
+    fn test_update_toggles_done() {
+        let mut model = model(&["a", "b"]);
+        model.selected = 1;
+
+        assert_eq!(
+            update(&mut model, KeyCode::Char(' ')),
+            Some(Effect::SetDone(1, true))
+        );
+        assert!(model.todos[1].done);
+        assert_eq!(
+            update(&mut model, KeyCode::Char(' ')),
+            Some(Effect::SetDone(1, false))
+        );
+        assert!(!model.todos[1].done);
+    }
+
+    #[test]
+    fn test_update_deletes_once_confirmed() {
+        let mut model = model(&["a", "b", "c"]);
+        model.selected = 2;
+
+        assert_eq!(update(&mut model, KeyCode::Char('d')), None);
+        assert_eq!(model.mode, Mode::ConfirmRemove);
+        assert_eq!(update(&mut model, KeyCode::Char('n')), None);
+        assert_eq!(model.mode, Mode::Browse);
+        assert_eq!(model.todos.len(), 3);
+
+        update(&mut model, KeyCode::Char('d'));
+        assert_eq!(
+            update(&mut model, KeyCode::Char('y')),
+            Some(Effect::Remove(2))
+        );
+        assert_eq!(titles(&model), vec!["a", "b"]);
+        assert_eq!(model.selected, 1);
+
+        model.selected = 0;
+        update(&mut model, KeyCode::Char('d'));
+        assert_eq!(
+            update(&mut model, KeyCode::Char('y')),
+            Some(Effect::Remove(0))
+        );
+        assert_eq!(titles(&model), vec!["b"]);
+        assert_eq!(model.selected, 0);
+
+        update(&mut model, KeyCode::Char('d'));
+        update(&mut model, KeyCode::Char('y'));
+        assert!(model.todos.is_empty());
+        assert_eq!(update(&mut model, KeyCode::Char('d')), None);
+        assert_eq!(model.mode, Mode::Browse);
+        assert_eq!(update(&mut model, KeyCode::Char(' ')), None);
+    }
+
+    #[test]
+    fn test_update_adds_from_the_input() {
+        let mut model = model(&[]);
+
+        update(&mut model, KeyCode::Char('a'));
+        for key in [
+            KeyCode::Char(' '),
+            KeyCode::Char('q'),
+            KeyCode::Char('x'),
+            KeyCode::Backspace,
+        ] {
+            assert_eq!(update(&mut model, key), None);
+        }
+        assert_eq!(
+            update(&mut model, KeyCode::Enter),
+            Some(Effect::Add("q".into()))
+        );
+        assert_eq!(model.mode, Mode::Browse);
+
+        update(&mut model, KeyCode::Char('a'));
+        assert_eq!(update(&mut model, KeyCode::Enter), None);
+        update(&mut model, KeyCode::Char('a'));
+        update(&mut model, KeyCode::Char('x'));
+        assert_eq!(update(&mut model, KeyCode::Esc), None);
+        assert_eq!(model.mode, Mode::Browse);
+    }
+
+    #[test]
+    fn test_update_filters_and_acts_on_the_list_index() {
+        let mut model = model(&["Buy milk", "call mom", "buy bread"]);
+        model.selected = 2;
+
+        update(&mut model, KeyCode::Char('/'));
+        for c in "buy".chars() {
+            update(&mut model, KeyCode::Char(c));
+        }
+        assert_eq!(titles(&model), vec!["Buy milk", "buy bread"]);
+        assert_eq!(model.selected, 1);
+        update(&mut model, KeyCode::Enter);
+
+        assert_eq!(
+            update(&mut model, KeyCode::Char(' ')),
+            Some(Effect::SetDone(2, true))
+        );
+        update(&mut model, KeyCode::Char('d'));
+        assert_eq!(
+            update(&mut model, KeyCode::Char('y')),
+            Some(Effect::Remove(2))
+        );
+        assert_eq!(titles(&model), vec!["Buy milk"]);
+        assert_eq!(model.selected, 0);
+
+        assert_eq!(update(&mut model, KeyCode::Esc), None);
+        assert_eq!(titles(&model), vec!["Buy milk", "call mom"]);
+        update(&mut model, KeyCode::Char('/'));
+        update(&mut model, KeyCode::Char('x'));
+        assert!(titles(&model).is_empty());
+        update(&mut model, KeyCode::Esc);
+        assert_eq!(titles(&model).len(), 2);
+    }
+
+    #[test]
+    fn test_update_quits() {
+        let mut model = model(&[]);
+
+        assert_eq!(update(&mut model, KeyCode::Char('q')), Some(Effect::Quit));
+        assert_eq!(update(&mut model, KeyCode::Esc), Some(Effect::Quit));
+        assert_eq!(update(&mut model, KeyCode::Char('x')), None);
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use chrono::Utc;
+
+use crate::{
+    args::PrintOptions,
+    commands::{print_command, write_json_event},
+    config::{get_db_path, GetDbPathError},
+    db::{open_connection, TodoQuery},
+    terminal::Style,
+};
+
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const POLL_STEP: Duration = Duration::from_millis(100);
+
+#[derive(thiserror::Error, Debug)]
+pub enum WatchCommandError {
+    #[error(transparent)]
+    GetDbPath(#[from] GetDbPathError),
+
+    #[error("Fail to handle Ctrl-C")]
+    SetHandler(#[from] ctrlc::Error),
+}
+
+pub fn watch_command(
+    query: &TodoQuery,
+    options: &PrintOptions,
+    style: &Style,
+    interval: Duration,
+    read_only: bool,
+) -> Result<(), WatchCommandError> {
+    watch(interval, |path| {
+        print!("{}", CLEAR_SCREEN);
+        let rendered = open_connection(read_only)
+            .map_err(|error| error.to_string())
+            .and_then(|connection| {
+                print_command(
+                    &connection,
+                    query,
+                    options,
+                    style,
+                    &mut std::io::stdout().lock(),
+                )
+                .map_err(|error| error.to_string())
+            });
+        if let Err(error) = rendered {
+            println!("Error: {}", error);
+        }
+        println!("\nWatching {}, Ctrl-C to exit", path.display());
+        std::io::stdout().flush().ok();
+    })
+}
+
+pub fn watch_json_command(
+    query: &TodoQuery,
+    interval: Duration,
+    read_only: bool,
+) -> Result<(), WatchCommandError> {
+    watch(interval, |_| {
+        let mut stdout = std::io::stdout().lock();
+        let written = open_connection(read_only)
+            .map_err(|error| error.to_string())
+            .and_then(|connection| {
+                write_json_event(&connection, query, &mut stdout).map_err(|error| error.to_string())
+            });
+        // Consumers keep reading lines, so a failure is reported as an event of its own.
+        if let Err(error) = written {
+            let event = serde_json::json!({ "at": Utc::now(), "error": error });
+            writeln!(stdout, "{}", event).ok();
+            stdout.flush().ok();
+        }
+    })
+}
+
+fn watch(interval: Duration, mut render: impl FnMut(&Path)) -> Result<(), WatchCommandError> {
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        ctrlc::set_handler(move || stop.store(true, Ordering::SeqCst))?;
+    }
+
+    let path = get_db_path()?;
+    let mut rendered_stamp = None;
+
+    while !stop.load(Ordering::SeqCst) {
+        // A fresh connection per render follows a database that was replaced by a restore.
+        if rendered_stamp != Some(file_stamp(&path)) {
+            render(&path);
+
+            // Opening may have created the database, so take the stamp after rendering.
+            rendered_stamp = Some(file_stamp(&path));
+        }
+
+        let mut waited = Duration::ZERO;
+        while waited < interval && !stop.load(Ordering::SeqCst) {
+            std::thread::sleep(POLL_STEP);
+            waited += POLL_STEP;
+        }
+    }
+
+    Ok(())
+}
+
+// The size catches writes that land within the resolution of the modification time.
+fn file_stamp(path: &Path) -> Option<(Option<SystemTime>, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
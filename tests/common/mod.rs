@@ -0,0 +1,26 @@
+// Each test binary uses its own share of these.
+#![allow(dead_code)]
+
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+// The binary with its database and config kept in `dir`.
+pub fn command(dir: &TempDir) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_todo-cli"));
+    command
+        .env("TODO_DB", dir.path().join("todos.db"))
+        .env("TODO_CONFIG", dir.path().join("config.toml"))
+        .env("NO_COLOR", "1");
+    command
+}
+
+pub fn todo(dir: &TempDir, args: &[&str]) -> Output {
+    command(dir).args(args).output().unwrap()
+}
+
+pub fn todo_ok(dir: &TempDir, args: &[&str]) -> Output {
+    let output = todo(dir, args);
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
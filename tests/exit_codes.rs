@@ -0,0 +1,139 @@
+mod common;
+
+use common::todo;
+use rusqlite::Connection;
+
+#[test]
+fn test_success_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = todo(&dir, &["add", "title1"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_usage_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert_eq!(todo(&dir, &["add", " "]).status.code(), Some(2));
+    assert_eq!(todo(&dir, &["done", "5"]).status.code(), Some(2));
+    assert_eq!(todo(&dir, &["remove", "5"]).status.code(), Some(2));
+    // Without a terminal to ask on.
+    assert_eq!(todo(&dir, &["clean"]).status.code(), Some(2));
+    assert_eq!(todo(&dir, &["ui"]).status.code(), Some(2));
+    assert_eq!(todo(&dir, &["no-such-command"]).status.code(), Some(2));
+}
+
+#[test]
+fn test_fail_empty_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(todo(&dir, &["add", "title1"]).status.code(), Some(0));
+
+    assert_eq!(
+        todo(&dir, &["done", "--fail-empty", "0"]).status.code(),
+        Some(0)
+    );
+    let output = todo(&dir, &["done", "--fail-empty", "0"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("No todo was changed"));
+    assert_eq!(todo(&dir, &["done", "0"]).status.code(), Some(0));
+
+    assert_eq!(
+        todo(&dir, &["--fail-empty", "clear", "-y"]).status.code(),
+        Some(0)
+    );
+    assert_eq!(
+        todo(&dir, &["--fail-empty", "clear"]).status.code(),
+        Some(1)
+    );
+    assert_eq!(
+        todo(&dir, &["remove-tag", "--fail-empty", "x"])
+            .status
+            .code(),
+        Some(1)
+    );
+
+    assert_eq!(todo(&dir, &["add", "title2"]).status.code(), Some(0));
+    assert_eq!(todo(&dir, &["done", "0"]).status.code(), Some(0));
+    let output = todo(&dir, &["done", "--and-clear", "--fail-empty", "0"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("Cleared: title2"));
+}
+
+#[test]
+fn test_invalid_config_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("config.toml"), "not toml").unwrap();
+
+    assert_eq!(todo(&dir, &["print"]).status.code(), Some(2));
+}
+
+#[test]
+fn test_db_busy_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("todos.db");
+    assert_eq!(todo(&dir, &["add", "title1"]).status.code(), Some(0));
+
+    let connection = Connection::open(&db).unwrap();
+    connection.execute_batch("BEGIN EXCLUSIVE").unwrap();
+
+    let output = todo(&dir, &["add", "title2"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn test_io_exit_code() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("missing").join("todos.db");
+
+    let output = common::command(&dir)
+        .env("TODO_DB", db)
+        .arg("print")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+}
+
+#[test]
+fn test_search_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("todos.db");
+    assert_eq!(
+        todo(&dir, &["add", "deploy the app"]).status.code(),
+        Some(0)
+    );
+
+    let output = todo(&dir, &["search", "deploy"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!output.stdout.is_empty());
+
+    let output = todo(&dir, &["search", "release"]);
+    assert_eq!(output.status.code(), Some(1));
+
+    for text in ["deploy", "release"] {
+        let output = todo(&dir, &["search", "-q", text]);
+        assert!(output.stdout.is_empty());
+        assert!(output.stderr.is_empty());
+    }
+    assert_eq!(
+        todo(&dir, &["search", "-q", "deploy"]).status.code(),
+        Some(0)
+    );
+    assert_eq!(
+        todo(&dir, &["search", "--quiet", "release"]).status.code(),
+        Some(1)
+    );
+
+    // A real error isn't mistaken for no match.
+    std::fs::write(
+        &db,
+        "not a database, but long enough that sqlite reads a header",
+    )
+    .unwrap();
+    let output = todo(&dir, &["search", "-q", "deploy"]);
+    assert_eq!(output.status.code(), Some(5));
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use common::todo_ok as todo;
+use tempfile::TempDir;
+
+// Each run of the hook appends its JSON input as a line of its own.
+fn set_add_hook(dir: &TempDir) {
+    let log = dir.path().join("added.log");
+    std::fs::write(
+        dir.path().join("config.toml"),
+        format!(
+            "[hooks]\npost_add = \"cat >> '{0}'; echo >> '{0}'\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+}
+
+// The titles given to each run of the hook.
+fn hook_runs(dir: &TempDir) -> Vec<Vec<String>> {
+    std::fs::read_to_string(dir.path().join("added.log"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| {
+            let todos: Vec<serde_json::Value> = serde_json::from_str(line).unwrap();
+            todos
+                .iter()
+                .map(|todo| todo["title"].as_str().unwrap().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_import_runs_the_add_hook() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("import.json");
+    std::fs::write(&file, r#"[{"title": "a"}, {"title": "b"}]"#).unwrap();
+    set_add_hook(&dir);
+
+    todo(&dir, &["import", "--dry-run", file.to_str().unwrap()]);
+    assert!(hook_runs(&dir).is_empty());
+    todo(&dir, &["import", file.to_str().unwrap()]);
+    todo(&dir, &["-y", "import", "--replace", file.to_str().unwrap()]);
+
+    assert_eq!(hook_runs(&dir), vec![vec!["a", "b"], vec!["a", "b"]]);
+}
+
+#[test]
+fn test_restore_and_copy_to_run_the_add_hook() {
+    let dir = tempfile::tempdir().unwrap();
+    todo(&dir, &["-q", "add", "--separate", "a", "b"]);
+    set_add_hook(&dir);
+
+    todo(&dir, &["-q", "copy-to", "work", "0"]);
+    todo(&dir, &["-q", "remove", "1"]);
+    todo(&dir, &["-q", "restore", "1"]);
+
+    assert_eq!(hook_runs(&dir), vec![vec!["a"], vec!["b"]]);
+}
@@ -0,0 +1,62 @@
+mod common;
+
+use std::{os::unix::fs::PermissionsExt, path::Path};
+
+use common::todo;
+
+fn set_read_only(path: &Path) {
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o444)).unwrap();
+}
+
+#[test]
+fn test_read_only_rejects_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(todo(&dir, &["add", "title1"]).status.success());
+    let before = std::fs::read(dir.path().join("todos.db")).unwrap();
+
+    let output = todo(&dir, &["--read-only", "add", "title2"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("read-only mode"));
+    assert_eq!(
+        todo(&dir, &["--read-only", "done", "0"]).status.code(),
+        Some(2)
+    );
+
+    assert_eq!(std::fs::read(dir.path().join("todos.db")).unwrap(), before);
+}
+
+#[test]
+fn test_read_only_reads_unwritable_database() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(todo(&dir, &["add", "title1"]).status.success());
+    std::fs::write(dir.path().join("config.toml"), "read_only = true").unwrap();
+    set_read_only(&dir.path().join("todos.db"));
+    set_read_only(dir.path());
+
+    let print = todo(&dir, &["print"]);
+    let search = todo(&dir, &["search", "title"]);
+    let stats = todo(&dir, &["stats"]);
+    let add = todo(&dir, &["add", "title2"]);
+    std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&print.stdout), "0: title1\n");
+    assert_eq!(String::from_utf8_lossy(&search.stdout), "0: title1\n");
+    assert!(stats.status.success());
+    assert_eq!(add.status.code(), Some(2));
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_read_only_rejects_encrypting() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(todo(&dir, &["add", "title1"]).status.success());
+    let before = std::fs::read(dir.path().join("todos.db")).unwrap();
+
+    let output = todo(&dir, &["--read-only", "encrypt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("read-only mode"));
+    std::fs::write(dir.path().join("config.toml"), "read_only = true").unwrap();
+    assert_eq!(todo(&dir, &["decrypt"]).status.code(), Some(2));
+
+    assert_eq!(std::fs::read(dir.path().join("todos.db")).unwrap(), before);
+}
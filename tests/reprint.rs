@@ -0,0 +1,79 @@
+mod common;
+
+use common::todo_ok as run;
+use tempfile::TempDir;
+
+fn todo(dir: &TempDir, args: &[&str]) -> String {
+    String::from_utf8(run(dir, args).stdout).unwrap()
+}
+
+#[test]
+fn test_add_reprints_list() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert_eq!(todo(&dir, &["add", "title1"]), "0: title1\n");
+}
+
+#[test]
+fn test_reprint_to_stderr() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = run(&dir, &["add", "--reprint-stderr", "title1"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "0: title1\n");
+
+    let output = run(&dir, &["--reprint-stderr", "rename-tag", "a", "b"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Renamed the tag on 0 todos\n"
+    );
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "0: title1\n");
+
+    // Only the reprint moves, printing on purpose still goes to stdout.
+    let output = run(&dir, &["print", "--reprint-stderr"]);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0: title1\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_add_quiet_skips_reprint() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert_eq!(todo(&dir, &["add", "--quiet", "title1"]), "");
+    assert_eq!(todo(&dir, &["--quiet", "add", "title2"]), "");
+    assert_eq!(todo(&dir, &["--quiet", "done", "0"]), "");
+    assert_eq!(todo(&dir, &["print"]).lines().count(), 2);
+}
+
+#[test]
+fn test_print_numbers_from_line_numbers_base() {
+    let dir = tempfile::tempdir().unwrap();
+    todo(&dir, &["add", "--separate", "a", "b"]);
+
+    assert_eq!(todo(&dir, &["print"]), "0: a\n1: b\n");
+    assert_eq!(
+        todo(&dir, &["--line-numbers-from", "1", "print"]),
+        "1: a\n2: b\n"
+    );
+    std::fs::write(dir.path().join("config.toml"), "line_numbers_from = 1\n").unwrap();
+    assert_eq!(todo(&dir, &["print", "--oneline"]), "1 a\n2 b\n");
+    assert_eq!(
+        todo(&dir, &["--line-numbers-from", "0", "print", "--oneline"]),
+        "0 a\n1 b\n"
+    );
+}
+
+#[test]
+fn test_overdue_uses_print_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    run(&dir, &["-q", "add", "-d", "2000-01-01", "late"]);
+    run(&dir, &["-q", "add", "soon"]);
+
+    assert!(todo(&dir, &["overdue"]).ends_with(" days overdue\n"));
+    assert_eq!(todo(&dir, &["overdue", "--oneline"]), "0 late\n");
+    let table = todo(&dir, &["overdue", "--columns", "index,title"]);
+    assert_eq!(
+        table.lines().map(str::trim_end).collect::<Vec<_>>(),
+        vec!["INDEX  TITLE", "0      late"]
+    );
+}
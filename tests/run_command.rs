@@ -0,0 +1,126 @@
+use clap::Parser;
+use rusqlite::Connection;
+use todo_cli::{args::Args, run_command_with_connection};
+
+fn run(connection: &mut Connection, args: &[&str]) {
+    let args = Args::parse_from(std::iter::once("todo-cli").chain(args.iter().copied()));
+    run_command_with_connection(args, connection).unwrap();
+}
+
+fn todos(connection: &Connection) -> Vec<(String, bool)> {
+    let mut statement = connection
+        .prepare("SELECT title, done FROM todos ORDER BY id")
+        .unwrap();
+    statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn test_add_done_print_in_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TODO_CONFIG", dir.path().join("config.toml"));
+    let mut connection = Connection::open_in_memory().unwrap();
+
+    run(&mut connection, &["add", "--separate", "title1", "title2"]);
+    run(&mut connection, &["done", "1"]);
+    run(&mut connection, &["print", "--json"]);
+
+    assert_eq!(
+        todos(&connection),
+        vec![("title1".to_string(), false), ("title2".to_string(), true)]
+    );
+}
+
+#[test]
+fn test_add_joins_words_unless_separate() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TODO_CONFIG", dir.path().join("config.toml"));
+    let mut connection = Connection::open_in_memory().unwrap();
+
+    run(&mut connection, &["add", "buy", "milk"]);
+    run(&mut connection, &["add", "call mom", "today"]);
+    run(
+        &mut connection,
+        &["add", "-s", "walk the dog", "water", "plants"],
+    );
+    run(&mut connection, &["add", "--separate", "one"]);
+
+    let titles: Vec<String> = todos(&connection)
+        .into_iter()
+        .map(|(title, _)| title)
+        .collect();
+    assert_eq!(
+        titles,
+        vec![
+            "buy milk",
+            "call mom today",
+            "walk the dog",
+            "water",
+            "plants",
+            "one"
+        ]
+    );
+}
+
+#[test]
+fn test_done_reads_indexes_from_line_numbers_base() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TODO_CONFIG", dir.path().join("config.toml"));
+    let mut connection = Connection::open_in_memory().unwrap();
+
+    run(&mut connection, &["add", "--separate", "a", "b", "c"]);
+    run(&mut connection, &["done", "1"]);
+    run(&mut connection, &["--line-numbers-from", "1", "done", "1"]);
+    run(&mut connection, &["--line-numbers-from", "0", "done", "2"]);
+
+    assert_eq!(
+        todos(&connection),
+        vec![
+            ("a".to_string(), true),
+            ("b".to_string(), true),
+            ("c".to_string(), true)
+        ]
+    );
+}
+
+#[test]
+fn test_ids_stay_stable_when_inserting_and_moving() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TODO_CONFIG", dir.path().join("config.toml"));
+    let mut connection = Connection::open_in_memory().unwrap();
+
+    run(&mut connection, &["-q", "add", "a b c"]);
+    run(&mut connection, &["-q", "add", "--at", "0", "z"]);
+    run(&mut connection, &["-q", "move", "1", "0"]);
+    run(&mut connection, &["-q", "--by-id", "done", "1"]);
+
+    assert_eq!(
+        todos(&connection),
+        vec![("a b c".to_string(), true), ("z".to_string(), false)]
+    );
+}
+
+#[test]
+fn test_indexes_below_line_numbers_base_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("TODO_CONFIG", dir.path().join("config.toml"));
+    let mut connection = Connection::open_in_memory().unwrap();
+    run(&mut connection, &["-q", "add", "--separate", "a", "b"]);
+
+    for args in [
+        &["--line-numbers-from", "1", "add", "--at", "0", "z"][..],
+        &["--line-numbers-from", "1", "move", "2", "0"],
+    ] {
+        let args = Args::parse_from(std::iter::once("todo-cli").chain(args.iter().copied()));
+        let error = run_command_with_connection(args, &mut connection).unwrap_err();
+        assert_eq!(error.to_string(), "There is no todo at index 0");
+        assert_eq!(error.exit_code(), 2);
+    }
+    assert_eq!(
+        todos(&connection),
+        vec![("a".to_string(), false), ("b".to_string(), false)]
+    );
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+    io::Write,
+    process::{Output, Stdio},
+};
+
+use tempfile::TempDir;
+
+fn shell(dir: &TempDir, input: &str) -> Output {
+    shell_with(dir, &[], input)
+}
+
+fn shell_with(dir: &TempDir, flags: &[&str], input: &str) -> Output {
+    let mut child = common::command(dir)
+        .args(flags)
+        .arg("shell")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_shell_runs_each_line() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = shell(
+        &dir,
+        "-q add 'buy milk'\n\n-q add -s a b\ndone 9\nbogus\n-q done 1\nprint --oneline\nquit\nadd never\n",
+    );
+    // Piped in like a script, so the failed lines fail the shell too.
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0 buy milk\n1 a (done)\n2 b\n"
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("There is no todo at index 9"), "{}", stderr);
+    assert!(
+        stderr.contains("unrecognized subcommand 'bogus'"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("2 of the shell lines failed"), "{}", stderr);
+}
+
+#[test]
+fn test_shell_lines_use_the_outer_list() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = shell_with(&dir, &["-l", "work"], "-q add x\n-l home -q add y\n");
+    assert!(output.status.success());
+    let list = |flags: &[&str]| {
+        String::from_utf8(shell_with(&dir, flags, "print --oneline").stdout).unwrap()
+    };
+    assert_eq!(list(&["-l", "work"]), "0 x\n");
+    assert_eq!(list(&["-l", "home"]), "0 y\n");
+    assert_eq!(list(&[]), "");
+}
+
+#[test]
+fn test_shell_lines_stay_read_only() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(shell(&dir, "-q add a").status.success());
+
+    let output = shell_with(&dir, &["--read-only"], "add x\nprint --oneline\n");
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0 a\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("Can't change todos in read-only mode"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_shell_ends_at_end_of_input() {
+    let dir = tempfile::tempdir().unwrap();
+
+    assert!(shell(&dir, "-q add a").status.success());
+    let output = shell(&dir, "print --oneline");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0 a\n");
+}